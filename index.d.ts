@@ -7,11 +7,903 @@ export interface BatchResponse {
   keys: Array<string>
   values?: Array<any>
 }
-export declare function startLogger(): void
-export declare function initCouchbase(): void
-export declare function getDocuments(key: string, withCas: boolean, bucketName: string): Promise<any>
-export declare function addDocument(key: string, value: any, bucketName: string): Promise<boolean>
-export declare function replaceDocument(key: string, value: any, withCas: bigint | null | undefined, bucketName: string): Promise<boolean>
-export declare function removeDocument(key: string, bucketName: string): Promise<string>
-export declare function getBatchDocuments(keys: Array<string>, withCas: boolean, bucketName: string): Promise<any>
-export declare function getBatchDocumentsV2(keys: Array<string>, withCas: boolean, bucketName: string): Promise<any>
+export interface Timings {
+  queueMs: number
+  networkMs: number
+  deserializeMs: number
+  attempts: number
+}
+export interface GetResult {
+  value: any
+  timings?: Timings
+  servedBy?: string
+  stale?: boolean
+}
+export interface GetResultWithCas {
+  value: any
+  cas: bigint
+  timings?: Timings
+  servedBy?: string
+  stale?: boolean
+}
+export interface DocumentWithMeta {
+  value: any
+  cas: bigint
+  expiry?: number
+  expiresInMs?: number
+}
+export interface MutationToken {
+  bucketName: string
+  partitionId: number
+  partitionUuid: bigint
+  sequenceNumber: bigint
+}
+export interface MutationResult {
+  cas: bigint
+  mutationToken?: MutationToken
+  timings?: Timings
+  attempts: number
+  retryDelayMs: number
+}
+export interface BatchDocumentsV2 {
+  docs: Record<string, any | GetResultWithCas>
+  errors: Record<string, any>
+  sources: Record<string, string>
+}
+export interface QueryColumns {
+  columns: Array<string>
+  rows: Array<Array<any>>
+}
+export interface ListKeysResult {
+  keys: Array<string>
+  nextStartAfter?: string
+}
+export interface ReplaySummary {
+  applied: number
+  skipped: number
+  failed: number
+}
+export interface QueuedWrite {
+  id: string
+  key: string
+  bucket: string
+  scope?: string
+  collection?: string
+  operation: string
+  attempts: number
+  enqueuedAt: number
+  nextAttemptAt: number
+  lastError: string
+  failed: boolean
+}
+export interface RetryQueueSummary {
+  applied: number
+  retrying: number
+  failed: number
+}
+export interface ClusterFeatures {
+  collections: boolean
+  rangeScan: boolean
+  transactions: boolean
+  search: boolean
+}
+export interface ClusterInfo {
+  version: string
+  services: Array<string>
+  nodes: number
+  features: ClusterFeatures
+}
+export interface EventSignature {
+  keyId: string
+  timestamp: number
+  signature: string
+}
+export interface CloudEventMessage {
+  key: string
+  value: any
+}
+export interface ExistsResult {
+  exists: boolean
+  cas?: bigint
+}
+export interface CasSnapshotResult {
+  cas: Record<string, bigint>
+  missing: Array<string>
+  errors: Record<string, string>
+}
+export interface BlobResult {
+  hash: string
+  size: number
+  refs: number
+  deduplicated: boolean
+}
+export interface ProcessOnceResult {
+  first: boolean
+  result: any
+}
+export interface BatchProgress {
+  processed: number
+  succeeded: number
+  failed: number
+  total?: number
+  etaMs?: number
+  done: boolean
+}
+export interface PreloadCacheResult {
+  loaded: number
+  missing: number
+  skipped: number
+}
+export interface DeleteByPrefixResult {
+  matched: number
+  deleted: number
+  failed: number
+  sampleKeys: Array<string>
+  confirmationToken?: string
+}
+export interface ExportResult {
+  outputPath: string
+  written: number
+  failed: number
+}
+export interface RetryStat {
+  bucket: string
+  operation: string
+  retries: number
+  retriesExhausted: number
+  deadlineExhausted: number
+  causes: Record<string, number>
+}
+export interface RetryStats {
+  windowSecs: number
+  operations: Array<RetryStat>
+}
+export interface LogEntry {
+  time: string
+  level: string
+  message: string
+  modulePath?: string
+  file?: string
+  line?: number
+}
+export interface ReadRepairStats {
+  repaired: number
+  alreadyPresent: number
+  failed: number
+}
+export interface HotKey {
+  key: string
+  keyspace: string
+  count: number
+}
+export interface MaintenanceEvent {
+  window: string
+  phase: string
+  at: number
+  inFlight: number
+}
+export interface DocumentChange {
+  key: string
+  value?: any
+  cas?: bigint
+  deleted: boolean
+}
+export interface ExpiryBucket {
+  from: number
+  to: number
+  count: number
+}
+export interface ExpiryReport {
+  total: number
+  withoutExpiry: number
+  expired: number
+  buckets: Array<ExpiryBucket>
+  later: number
+}
+export interface SearchHit {
+  id: string
+  document: any
+}
+export interface TimeSeriesPoint {
+  timestamp: number
+  value: any
+}
+export interface MultiGetItem {
+  key: string
+  value: any
+  source: string
+}
+export interface BucketCallOptions {
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export const enum StoreSemantics {
+  Replace = 0,
+  Upsert = 1,
+  Insert = 2
+}
+export interface OperationContext {
+  requestId?: string
+  tenantId?: string
+  actor?: string
+}
+export interface GetDocumentOptions {
+  key: string
+  bucket: string
+  scope?: string
+  collection?: string
+  timings?: boolean
+  transcoder?: string
+  readPreference?: string
+  cached?: boolean
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface AddDocumentOptions {
+  key: string
+  value: any
+  bucket: string
+  scope?: string
+  collection?: string
+  retry?: number
+  geoFields?: Array<string>
+  timings?: boolean
+  queueOnFailure?: boolean
+  transcoder?: string
+  ttlSecs?: number
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface UpsertDocumentOptions {
+  key: string
+  value: any
+  bucket: string
+  scope?: string
+  collection?: string
+  retry?: number
+  geoFields?: Array<string>
+  timings?: boolean
+  queueOnFailure?: boolean
+  transcoder?: string
+  ttlSecs?: number
+  preserveExpiry?: boolean
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface ReplaceDocumentOptions {
+  key: string
+  value: any
+  cas?: bigint
+  bucket: string
+  scope?: string
+  collection?: string
+  retry?: number
+  geoFields?: Array<string>
+  timings?: boolean
+  queueOnFailure?: boolean
+  transcoder?: string
+  ttlSecs?: number
+  preserveExpiry?: boolean
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface RemoveDocumentOptions {
+  key: string
+  cas?: bigint
+  bucket: string
+  scope?: string
+  collection?: string
+  timings?: boolean
+  queueOnFailure?: boolean
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export const enum MergeStrategy {
+  LastWriteWins = 0,
+  ArrayUnion = 1,
+  NumericSum = 2
+}
+export interface MergeDocumentOptions {
+  key: string
+  partial: any
+  strategy?: MergeStrategy
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface ScheduleMutationOptions {
+  key: string
+  operation: string
+  value?: any
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface CoalesceWriteOptions {
+  key: string
+  value?: any
+  patch?: any
+  windowMs?: number
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export const enum MutateInOperation {
+  Insert = 0,
+  Upsert = 1,
+  Replace = 2,
+  Remove = 3,
+  ArrayAppend = 4,
+  ArrayPrepend = 5,
+  ArrayAddUnique = 6,
+  Increment = 7,
+  Decrement = 8
+}
+export interface MutateInSpec {
+  operation: MutateInOperation
+  path: string
+  value?: any
+  createPath?: boolean
+  xattr?: boolean
+}
+export interface LookupInOptions {
+  key: string
+  paths: Array<string>
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface MutateInOptions {
+  key: string
+  specs: Array<MutateInSpec>
+  cas?: bigint
+  storeSemantics?: StoreSemantics
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface QueryOptions {
+  parameters?: Array<any>
+  columnar?: boolean
+  cacheTtlMs?: number
+  bypassCache?: boolean
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface GetBatchOptions {
+  keys: Array<string>
+  bucket: string
+  scope?: string
+  collection?: string
+  fallbackBucket?: string
+  readRepair?: boolean
+  withCas?: boolean
+  partialOnTimeout?: boolean
+  transcoder?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface ListKeysOptions {
+  prefix: string
+  bucket: string
+  scope?: string
+  collection?: string
+  limit?: number
+  startAfter?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface GetAndTouchOptions {
+  key: string
+  ttlSecs: number
+  bucket: string
+  scope?: string
+  collection?: string
+  withCas?: boolean
+  transcoder?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface CounterOptions {
+  key: string
+  delta?: bigint
+  initial?: bigint
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface TouchDocumentOptions {
+  key: string
+  ttlSecs: number
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface CasSnapshotOptions {
+  keys: Array<string>
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface DeleteByPrefixOptions {
+  prefix: string
+  bucket: string
+  scope?: string
+  collection?: string
+  dryRun?: boolean
+  maxDocs?: number
+  rateLimitPerSec?: number
+  confirmationToken?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface PreloadCacheOptions {
+  keys?: Array<string>
+  prefix?: string
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface CloudEventOptions {
+  key: string
+  operation: string
+  bucket: string
+  scope?: string
+  collection?: string
+  cas?: bigint
+  value?: any
+}
+export interface WatchOptions {
+  scope?: string
+  collection?: string
+  pollIntervalMs?: number
+}
+export interface BlobOptions {
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface ProcessOnceOptions {
+  messageId: string
+  ttlSecs: number
+  result: any
+  keyPrefix?: string
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface ExportDocumentsOptions {
+  keys?: Array<string>
+  prefix?: string
+  outputPath: string
+  progressEvery?: number
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export const enum TimeSeriesGranularity {
+  Hour = 0,
+  Day = 1
+}
+export const enum ConsoleLogging {
+  All = 0,
+  WarnOnly = 1,
+  Off = 2
+}
+export interface LoggerOptions {
+  console?: ConsoleLogging
+}
+export interface LogQueryOptions {
+  level?: string
+  since?: number
+  key?: string
+  operation?: string
+  limit?: number
+}
+export interface AppendTimeSeriesOptions {
+  seriesKey: string
+  timestamp?: number
+  value: any
+  granularity?: TimeSeriesGranularity
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface QueryTimeSeriesOptions {
+  seriesKey: string
+  from: number
+  to: number
+  granularity?: TimeSeriesGranularity
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface AnalyzeExpiryOptions {
+  prefix: string
+  bucket: string
+  scope?: string
+  collection?: string
+  intervalSecs?: number
+  horizonSecs?: number
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface EraseSubjectOptions {
+  subjectId: string
+  bucket: string
+  scope?: string
+  collection?: string
+  prefixes?: Array<string>
+  namedQuery?: string
+  fieldsToAnonymize?: Array<string>
+  auditBucket?: string
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface GeoPoint {
+  lon: number
+  lat: number
+}
+export interface SearchNearOptions {
+  indexName: string
+  field?: string
+  lon: number
+  lat: number
+  radiusMeters: number
+  bucket: string
+  scope?: string
+  collection?: string
+  limit?: number
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface SearchWithinPolygonOptions {
+  indexName: string
+  field?: string
+  points: Array<GeoPoint>
+  bucket: string
+  scope?: string
+  collection?: string
+  limit?: number
+  timeoutMs?: number
+  deadlineMs?: number
+  abortToken?: string
+  context?: OperationContext
+}
+export interface DatastructureOptions {
+  key: string
+  bucket: string
+  scope?: string
+  collection?: string
+  timeoutMs?: number
+}
+export interface SortedSetEntry {
+  member: string
+  score: number
+}
+export interface SessionStoreOptions {
+  bucket: string
+  scope?: string
+  collection?: string
+  keyPrefix?: string
+  ttlSecs?: number
+  timeoutMs?: number
+}
+export interface ConnectionOptions {
+  connectionString: string
+  username: string
+  password: string
+}
+export declare function startLogger(options?: LoggerOptions | undefined | null): void
+export declare function initCouchbase(buckets?: Array<string> | undefined | null, timeoutMs?: number | undefined | null): Promise<void>
+export declare function isReady(): boolean
+export declare function getDocuments(key: string, withCas: boolean, bucketName: string, abortToken?: string | undefined | null): Promise<any | GetResultWithCas>
+export declare function addDocument(key: string, value: any, bucketName: string, abortToken?: string | undefined | null): Promise<MutationResult>
+export declare function addDocumentWithTTL(key: string, value: any, bucketName: string, ttlSeconds: number, abortToken?: string | undefined | null): Promise<MutationResult>
+export declare function lookupIn(key: string, paths: Array<string>, bucketName: string, abortToken?: string | undefined | null): Promise<Record<string, any>>
+export declare function replaceDocument(key: string, value: any, cas: bigint | undefined | null, bucketName: string, abortToken?: string | undefined | null): Promise<MutationResult>
+export declare function removeDocument(key: string, bucketName: string, cas?: bigint | undefined | null, abortToken?: string | undefined | null): Promise<MutationResult>
+export declare function getBatchDocuments(keys: Array<string>, withCas: boolean, bucketName: string, abortToken?: string | undefined | null): Promise<Record<string, any | GetResultWithCas>>
+export declare function getBatchDocumentsV2(keys: Array<string>, withCas: boolean, bucketName: string, abortToken?: string | undefined | null): Promise<BatchDocumentsV2>
+export class AbortToken {
+  constructor()
+  get id(): string
+  get aborted(): boolean
+  abort(): boolean
+}
+export class BucketHandle {
+  get bucket(): string
+  get scope(): string | null
+  get collection(): string | null
+  get(key: string, options?: BucketCallOptions | undefined | null): Promise<GetResultWithCas>
+  insert(key: string, value: any, options?: BucketCallOptions | undefined | null): Promise<MutationResult>
+  replace(key: string, value: any, cas?: bigint | undefined | null, options?: BucketCallOptions | undefined | null): Promise<MutationResult>
+  remove(key: string, options?: BucketCallOptions | undefined | null): Promise<MutationResult>
+  query(statement: string, options?: QueryOptions | undefined | null): Promise<Array<any> | QueryColumns>
+}
+export class CouchbaseClient {
+  constructor(options: ConnectionOptions)
+  getDocument(options: GetDocumentOptions): Promise<GetResult>
+  getDocumentWithCas(options: GetDocumentOptions): Promise<GetResultWithCas>
+  getDocumentTyped(options: GetDocumentOptions, schema: string): Promise<GetResult>
+  getAndTouch(options: GetAndTouchOptions): Promise<any | GetResultWithCas>
+  incrementCounter(options: CounterOptions): Promise<bigint>
+  decrementCounter(options: CounterOptions): Promise<bigint>
+  touchDocument(options: TouchDocumentOptions): Promise<bigint>
+  documentExists(options: GetDocumentOptions): Promise<ExistsResult>
+  getDocumentExpiry(options: GetDocumentOptions): Promise<number | null>
+  getDocumentWithMeta(options: GetDocumentOptions): Promise<DocumentWithMeta>
+  addDocument(options: AddDocumentOptions): Promise<MutationResult>
+  upsertDocument(options: UpsertDocumentOptions): Promise<MutationResult>
+  replaceDocument(options: ReplaceDocumentOptions): Promise<MutationResult>
+  lookupIn(options: LookupInOptions): Promise<Record<string, any>>
+  mutateIn(options: MutateInOptions): Promise<MutationResult>
+  mergeDocument(options: MergeDocumentOptions): Promise<MutationResult>
+  coalesceWrite(options: CoalesceWriteOptions): Promise<MutationResult>
+  removeDocument(options: RemoveDocumentOptions): Promise<MutationResult>
+  getBatch(options: GetBatchOptions): Promise<BatchDocumentsV2>
+  getMulti(options: GetBatchOptions, transform: (item: MultiGetItem) => any): Promise<BatchDocumentsV2>
+  queryConsistentWith(tokens: Array<MutationToken>, statement: string, options?: QueryOptions | undefined | null): Promise<Array<any> | QueryColumns>
+  runNamedQuery(name: string, params?: any | undefined | null, options?: QueryOptions | undefined | null): Promise<Array<any> | QueryColumns>
+  listKeys(options: ListKeysOptions): Promise<ListKeysResult>
+  exportDocuments(options: ExportDocumentsOptions, onProgress?: (progress: BatchProgress) => void): Promise<ExportResult>
+  getCasSnapshot(options: CasSnapshotOptions): Promise<CasSnapshotResult>
+  deleteByPrefix(options: DeleteByPrefixOptions): Promise<DeleteByPrefixResult>
+  preloadCache(options: PreloadCacheOptions): Promise<PreloadCacheResult>
+  putBlob(bytes: Buffer, options: BlobOptions): Promise<BlobResult>
+  getBlob(hash: string, options: BlobOptions): Promise<Buffer>
+  releaseBlob(hash: string, options: BlobOptions): Promise<number>
+  processOnce(options: ProcessOnceOptions): Promise<ProcessOnceResult>
+  analyzeExpiry(options: AnalyzeExpiryOptions): Promise<ExpiryReport>
+  eraseSubjectData(options: EraseSubjectOptions): Promise<any>
+  searchNear(options: SearchNearOptions): Promise<Array<SearchHit>>
+  searchWithinPolygon(options: SearchWithinPolygonOptions): Promise<Array<SearchHit>>
+  appendTimeSeries(options: AppendTimeSeriesOptions): Promise<MutationResult>
+  queryTimeSeries(options: QueryTimeSeriesOptions): Promise<Array<TimeSeriesPoint>>
+  openBucket(bucketName: string, scope?: string | undefined | null, collection?: string | undefined | null): Promise<BucketHandle>
+  list(options: DatastructureOptions): CouchbaseList
+  set(options: DatastructureOptions): CouchbaseSet
+  map(options: DatastructureOptions): CouchbaseMap
+  queue(options: DatastructureOptions): CouchbaseQueue
+  sortedSet(options: DatastructureOptions): CouchbaseSortedSet
+  sessions(options: SessionStoreOptions): CouchbaseSessionStore
+  waitUntilReady(buckets?: Array<string> | undefined | null, timeoutMs?: number | undefined | null): Promise<void>
+  isReady(): boolean
+  buildHealthReport(): Promise<any>
+  getClusterInfo(): Promise<ClusterInfo>
+  replayJournal(path: string, toBucket: string, sinceMs?: number | undefined | null, onProgress?: (progress: BatchProgress) => void): Promise<ReplaySummary>
+  runRetention(dryRun?: boolean | undefined | null): Promise<any>
+  startRetention(intervalSecs?: number | undefined | null): Promise<void>
+  stopRetention(): boolean
+  scheduleMutation(atMs: number, op: ScheduleMutationOptions): Promise<string>
+  startJobExecutor(pollSecs?: number | undefined | null): Promise<void>
+  stopJobExecutor(): boolean
+  listQueuedWrites(): Array<QueuedWrite>
+  flushRetryQueue(): Promise<RetryQueueSummary>
+  discardQueuedWrite(id: string): boolean
+  startRetryWorker(pollSecs?: number | undefined | null): Promise<void>
+  stopRetryWorker(): boolean
+  setReadOnlyMode(enabled: boolean): void
+  isReadOnlyMode(): boolean
+  getReadRepairStats(): ReadRepairStats
+  getRetryStats(): RetryStats
+  getHotKeys(topN?: number | undefined | null): Array<HotKey>
+  startMaintenanceScheduler(): number
+  stopMaintenanceScheduler(): boolean
+  onMaintenanceEvent(callback: (event: MaintenanceEvent) => void): void
+  watchDocument(key: string, bucket: string, callback: (change: DocumentChange) => void, options?: WatchOptions | undefined | null): string
+  unwatchDocument(watchId: string): boolean
+  close(): Promise<void>
+  isClosed(): Promise<boolean>
+  signEventPayload(body: string): EventSignature
+  verifyEventSignature(body: string, signature: EventSignature): boolean
+  toCloudEvent(options: CloudEventOptions): CloudEventMessage
+}
+export class CouchbaseList {
+  constructor(options: DatastructureOptions)
+  push(value: any): Promise<void>
+  pop(): Promise<any | null>
+  get(index: number): Promise<any | null>
+  contains(value: any): Promise<boolean>
+  size(): Promise<number>
+  clear(): Promise<void>
+}
+export class CouchbaseSet {
+  constructor(options: DatastructureOptions)
+  add(value: any): Promise<boolean>
+  remove(value: any): Promise<boolean>
+  contains(value: any): Promise<boolean>
+  size(): Promise<number>
+  clear(): Promise<void>
+}
+export class CouchbaseMap {
+  constructor(options: DatastructureOptions)
+  set(field: string, value: any): Promise<void>
+  get(field: string): Promise<any | null>
+  remove(field: string): Promise<boolean>
+  contains(field: string): Promise<boolean>
+  size(): Promise<number>
+  clear(): Promise<void>
+}
+export class CouchbaseQueue {
+  constructor(options: DatastructureOptions)
+  push(value: any): Promise<void>
+  pop(): Promise<any | null>
+  size(): Promise<number>
+  clear(): Promise<void>
+}
+export class CouchbaseSortedSet {
+  constructor(options: DatastructureOptions)
+  zAdd(member: string, score: number): Promise<void>
+  zRange(start: number, stop: number, reverse?: boolean | undefined | null): Promise<Array<SortedSetEntry>>
+  zRank(member: string, reverse?: boolean | undefined | null): Promise<number | null>
+}
+export class CouchbaseSessionStore {
+  constructor(options: SessionStoreOptions)
+  createSession(data: any, ttlSecs?: number | undefined | null): Promise<string>
+  getSession(id: string): Promise<any | null>
+  destroySession(id: string): Promise<boolean>
+}
+export namespace v2 {
+  export function getDocument(options: GetDocumentOptions): Promise<GetResult>
+  export function getDocumentWithCas(options: GetDocumentOptions): Promise<GetResultWithCas>
+  export function getDocumentTyped(options: GetDocumentOptions, schema: string): Promise<GetResult>
+  export function registerSchema(name: string, definition: any): void
+  export function registerValidationRules(bucket: string, rules: any): void
+  export function setBucketTranscoder(bucket: string, transcoder: string): void
+  export function getAndTouch(options: GetAndTouchOptions): Promise<any | GetResultWithCas>
+  export function incrementCounter(options: CounterOptions): Promise<bigint>
+  export function decrementCounter(options: CounterOptions): Promise<bigint>
+  export function touchDocument(options: TouchDocumentOptions): Promise<bigint>
+  export function documentExists(options: GetDocumentOptions): Promise<ExistsResult>
+  export function getDocumentExpiry(options: GetDocumentOptions): Promise<number | null>
+  export function getDocumentWithMeta(options: GetDocumentOptions): Promise<DocumentWithMeta>
+  export function addDocument(options: AddDocumentOptions): Promise<MutationResult>
+  export function upsertDocument(options: UpsertDocumentOptions): Promise<MutationResult>
+  export function replaceDocument(options: ReplaceDocumentOptions): Promise<MutationResult>
+  export function lookupIn(options: LookupInOptions): Promise<Record<string, any>>
+  export function mutateIn(options: MutateInOptions): Promise<MutationResult>
+  export function coalesceWrite(options: CoalesceWriteOptions): Promise<MutationResult>
+  export function mergeDocument(options: MergeDocumentOptions): Promise<MutationResult>
+  export function removeDocument(options: RemoveDocumentOptions): Promise<MutationResult>
+  export function getBatch(options: GetBatchOptions): Promise<BatchDocumentsV2>
+  export function queryConsistentWith(tokens: Array<MutationToken>, statement: string, options?: QueryOptions | undefined | null): Promise<Array<any> | QueryColumns>
+  export function buildHealthReport(): Promise<any>
+  export function getClusterInfo(): Promise<ClusterInfo>
+  export function runRetention(dryRun?: boolean | undefined | null): Promise<any>
+  export function startRetention(intervalSecs?: number | undefined | null): Promise<void>
+  export function stopRetention(): boolean
+  export function scheduleMutation(atMs: number, op: ScheduleMutationOptions): Promise<string>
+  export function startJobExecutor(pollSecs?: number | undefined | null): Promise<void>
+  export function stopJobExecutor(): boolean
+  export function listQueuedWrites(): Array<QueuedWrite>
+  export function flushRetryQueue(): Promise<RetryQueueSummary>
+  export function discardQueuedWrite(id: string): boolean
+  export function startRetryWorker(pollSecs?: number | undefined | null): Promise<void>
+  export function stopRetryWorker(): boolean
+  export function setReadOnlyMode(enabled: boolean): void
+  export function isReadOnlyMode(): boolean
+  export function getReadRepairStats(): ReadRepairStats
+  export function getRetryStats(): RetryStats
+  export function getHotKeys(topN?: number | undefined | null): Array<HotKey>
+  export function startMaintenanceScheduler(): number
+  export function stopMaintenanceScheduler(): boolean
+  export function onMaintenanceEvent(callback: (event: MaintenanceEvent) => void): void
+  export function watchDocument(key: string, bucket: string, callback: (change: DocumentChange) => void, options?: WatchOptions | undefined | null): string
+  export function unwatchDocument(watchId: string): boolean
+  export function getMulti(options: GetBatchOptions, transform: (item: MultiGetItem) => any): Promise<BatchDocumentsV2>
+  export function openBucket(bucketName: string, scope?: string | undefined | null, collection?: string | undefined | null): Promise<BucketHandle>
+  export function listKeys(options: ListKeysOptions): Promise<ListKeysResult>
+  export function exportDocuments(options: ExportDocumentsOptions, onProgress?: (progress: BatchProgress) => void): Promise<ExportResult>
+  export function deleteByPrefix(options: DeleteByPrefixOptions): Promise<DeleteByPrefixResult>
+  export function putBlob(bytes: Buffer, options: BlobOptions): Promise<BlobResult>
+  export function getBlob(hash: string, options: BlobOptions): Promise<Buffer>
+  export function releaseBlob(hash: string, options: BlobOptions): Promise<number>
+  export function processOnce(options: ProcessOnceOptions): Promise<ProcessOnceResult>
+  export function getCasSnapshot(options: CasSnapshotOptions): Promise<CasSnapshotResult>
+  export function dumpEffectiveConfig(): any
+  export function encryptConfigValue(plaintext: string): string
+  export function registerKeyTemplate(name: string, template: string): void
+  export function buildKey(name: string, params: any): string
+  export function parseKey(name: string, key: string): Record<string, string>
+  export function toCloudEvent(options: CloudEventOptions): CloudEventMessage
+  export function signEventPayload(body: string): EventSignature
+  export function verifyEventSignature(body: string, signature: EventSignature): boolean
+  export function registerNamedQuery(name: string, definition: any): void
+  export function runNamedQuery(name: string, params?: any | undefined | null, options?: QueryOptions | undefined | null): Promise<Array<any> | QueryColumns>
+  export function clearQueryCache(): number
+  export function preloadCache(options: PreloadCacheOptions): Promise<PreloadCacheResult>
+  export function clearDocumentCache(): number
+  export function queryLocalLogs(options?: LogQueryOptions | undefined | null): Array<LogEntry>
+  export function appendTimeSeries(options: AppendTimeSeriesOptions): Promise<MutationResult>
+  export function queryTimeSeries(options: QueryTimeSeriesOptions): Promise<Array<TimeSeriesPoint>>
+  export function searchNear(options: SearchNearOptions): Promise<Array<SearchHit>>
+  export function searchWithinPolygon(options: SearchWithinPolygonOptions): Promise<Array<SearchHit>>
+  export function eraseSubjectData(options: EraseSubjectOptions): Promise<any>
+  export function analyzeExpiry(options: AnalyzeExpiryOptions): Promise<ExpiryReport>
+  export function replayJournal(path: string, toBucket: string, sinceMs?: number | undefined | null, onProgress?: (progress: BatchProgress) => void): Promise<ReplaySummary>
+}
@@ -310,13 +310,31 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { startLogger, initCouchbase, getDocuments, addDocument, replaceDocument, removeDocument, getBatchDocuments, getBatchDocumentsV2 } = nativeBinding
+const { AbortToken, BucketHandle, CouchbaseClient, CouchbaseList, CouchbaseSet, CouchbaseMap, CouchbaseQueue, CouchbaseSortedSet, CouchbaseSessionStore, StoreSemantics, MergeStrategy, MutateInOperation, TimeSeriesGranularity, ConsoleLogging, startLogger, initCouchbase, isReady, getDocuments, addDocument, addDocumentWithTTL, lookupIn, replaceDocument, removeDocument, getBatchDocuments, getBatchDocumentsV2, v2 } = nativeBinding
 
+module.exports.AbortToken = AbortToken
+module.exports.BucketHandle = BucketHandle
+module.exports.CouchbaseClient = CouchbaseClient
+module.exports.CouchbaseList = CouchbaseList
+module.exports.CouchbaseSet = CouchbaseSet
+module.exports.CouchbaseMap = CouchbaseMap
+module.exports.CouchbaseQueue = CouchbaseQueue
+module.exports.CouchbaseSortedSet = CouchbaseSortedSet
+module.exports.CouchbaseSessionStore = CouchbaseSessionStore
+module.exports.StoreSemantics = StoreSemantics
+module.exports.MergeStrategy = MergeStrategy
+module.exports.MutateInOperation = MutateInOperation
+module.exports.TimeSeriesGranularity = TimeSeriesGranularity
+module.exports.ConsoleLogging = ConsoleLogging
 module.exports.startLogger = startLogger
 module.exports.initCouchbase = initCouchbase
+module.exports.isReady = isReady
 module.exports.getDocuments = getDocuments
 module.exports.addDocument = addDocument
+module.exports.addDocumentWithTTL = addDocumentWithTTL
+module.exports.lookupIn = lookupIn
 module.exports.replaceDocument = replaceDocument
 module.exports.removeDocument = removeDocument
 module.exports.getBatchDocuments = getBatchDocuments
 module.exports.getBatchDocumentsV2 = getBatchDocumentsV2
+module.exports.v2 = v2
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use napi::bindgen_prelude::{BigInt, Either};
+use serde_json::Value;
+
+use crate::options::{operation_options, OperationContext, QueryOptions};
+use crate::utils::abort::run_abortable;
+use crate::utils::couchbase_db::{CouchbaseConnection, Keyspace, OperationOptions, QuerySettings};
+use crate::{
+  cas_from_bigint, cas_to_bigint, mutation_response, query_response, GetResultWithCas,
+  MutationResult, QueryColumns,
+};
+
+// Per call settings for BucketHandle methods, the keyspace is fixed by openBucket
+#[napi(object)]
+pub struct BucketCallOptions {
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+fn call_options(options: Option<BucketCallOptions>) -> (OperationOptions, Option<String>) {
+  match options {
+    Some(options) => (
+      operation_options(
+        options.timeout_ms,
+        options.deadline_ms,
+        options.context.as_ref(),
+      ),
+      options.abort_token,
+    ),
+    None => (OperationOptions::default(), None),
+  }
+}
+
+// A collection opened once and reused, so calls carry neither the bucket name nor a keyspace
+#[napi]
+pub struct BucketHandle {
+  connection: Arc<CouchbaseConnection>,
+  keyspace: Keyspace,
+}
+
+impl BucketHandle {
+  // Resolves the collection up front so a bad bucket fails at open time rather than on first use
+  pub async fn open(
+    connection: Arc<CouchbaseConnection>,
+    keyspace: Keyspace,
+  ) -> Result<Self, napi::Error> {
+    connection
+      .collection(keyspace.clone())
+      .await
+      .map_err(napi::Error::from_reason)?;
+    Ok(BucketHandle {
+      connection,
+      keyspace,
+    })
+  }
+}
+
+#[napi]
+impl BucketHandle {
+  #[napi(getter)]
+  pub fn bucket(&self) -> String {
+    self.keyspace.bucket.clone()
+  }
+
+  #[napi(getter)]
+  pub fn scope(&self) -> Option<String> {
+    self.keyspace.scope.clone()
+  }
+
+  #[napi(getter)]
+  pub fn collection(&self) -> Option<String> {
+    self.keyspace.collection.clone()
+  }
+
+  #[napi]
+  pub async fn get(
+    &self,
+    key: String,
+    options: Option<BucketCallOptions>,
+  ) -> Result<GetResultWithCas, napi::Error> {
+    let (operation_options, abort_token) = call_options(options);
+    let document = run_abortable(
+      abort_token,
+      self
+        .connection
+        .get_document(key, self.keyspace.clone(), operation_options),
+    )
+    .await
+    .map_err(napi::Error::from_reason)?;
+    Ok(GetResultWithCas {
+      value: document.value,
+      cas: cas_to_bigint(document.cas),
+      timings: None,
+      served_by: None,
+      stale: None,
+    })
+  }
+
+  #[napi]
+  pub async fn insert(
+    &self,
+    key: String,
+    value: Value,
+    options: Option<BucketCallOptions>,
+  ) -> Result<MutationResult, napi::Error> {
+    let (operation_options, abort_token) = call_options(options);
+    run_abortable(
+      abort_token,
+      self
+        .connection
+        .add_document(key, value, self.keyspace.clone(), operation_options, None),
+    )
+    .await
+    .map(mutation_response)
+    .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn replace(
+    &self,
+    key: String,
+    value: Value,
+    cas: Option<BigInt>,
+    options: Option<BucketCallOptions>,
+  ) -> Result<MutationResult, napi::Error> {
+    let cas = cas.map(cas_from_bigint).transpose()?;
+    let (operation_options, abort_token) = call_options(options);
+    run_abortable(
+      abort_token,
+      self.connection.replace_document(
+        key,
+        value,
+        cas,
+        self.keyspace.clone(),
+        operation_options,
+        None,
+      ),
+    )
+    .await
+    .map(mutation_response)
+    .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn remove(
+    &self,
+    key: String,
+    options: Option<BucketCallOptions>,
+  ) -> Result<MutationResult, napi::Error> {
+    let (operation_options, abort_token) = call_options(options);
+    run_abortable(
+      abort_token,
+      self
+        .connection
+        .delete_data(key, None, self.keyspace.clone(), operation_options),
+    )
+    .await
+    .map(mutation_response)
+    .map_err(napi::Error::from_reason)
+  }
+
+  // Unqualified keyspace names in the statement resolve against this handle's bucket and scope
+  #[napi]
+  pub async fn query(
+    &self,
+    statement: String,
+    options: Option<QueryOptions>,
+  ) -> Result<Either<Vec<Value>, QueryColumns>, napi::Error> {
+    let (parameters, operation_options, abort_token, columnar) = match options {
+      Some(options) => (
+        options.parameters.unwrap_or_default(),
+        operation_options(
+          options.timeout_ms,
+          options.deadline_ms,
+          options.context.as_ref(),
+        ),
+        options.abort_token,
+        options.columnar.unwrap_or(false),
+      ),
+      None => (Vec::new(), OperationOptions::default(), None, false),
+    };
+    run_abortable(
+      abort_token,
+      self.connection.query(
+        statement,
+        parameters,
+        Vec::new(),
+        QuerySettings {
+          query_context: Some(self.keyspace.query_context()),
+          ..Default::default()
+        },
+        operation_options,
+      ),
+    )
+    .await
+    .map(|rows| query_response(rows, columnar))
+    .map_err(napi::Error::from_reason)
+  }
+}
@@ -0,0 +1,2167 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+use napi::bindgen_prelude::{BigInt, Buffer, Either};
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::Env;
+use serde_json::{json, Value};
+
+use crate::bucket::BucketHandle;
+use crate::datastructures::{
+  CouchbaseList, CouchbaseMap, CouchbaseQueue, CouchbaseSet, CouchbaseSortedSet,
+  DatastructureOptions,
+};
+use crate::options::{
+  operation_options, AddDocumentOptions, AnalyzeExpiryOptions, AppendTimeSeriesOptions,
+  BlobOptions, CasSnapshotOptions, CloudEventOptions, CoalesceWriteOptions, CounterOptions,
+  DeleteByPrefixOptions, EraseSubjectOptions, ExportDocumentsOptions, GetAndTouchOptions,
+  GetBatchOptions, GetDocumentOptions, KeyspaceOptions, ListKeysOptions, LookupInOptions,
+  MergeDocumentOptions, MutateInOptions, PreloadCacheOptions, ProcessOnceOptions, QueryOptions,
+  QueryTimeSeriesOptions, RemoveDocumentOptions, ReplaceDocumentOptions, ScheduleMutationOptions,
+  SearchNearOptions, SearchWithinPolygonOptions, TouchDocumentOptions, UpsertDocumentOptions,
+  WatchOptions,
+};
+use crate::sessions::{CouchbaseSessionStore, SessionStoreOptions};
+use crate::utils::abort::run_abortable;
+use crate::utils::blobs::{get_blob, put_blob, release_blob};
+use crate::utils::bulk_delete::{delete_by_prefix, DeleteByPrefixRequest};
+use crate::utils::cloud_events::mutation_event;
+use crate::utils::cluster_info::ClusterFeature;
+use crate::utils::coalescer::coalesce_write;
+use crate::utils::couchbase_db::{
+  batch_concurrency, default_connection, BatchDocuments, CasDocument, CouchbaseConnParams,
+  CouchbaseConnection, CounterChange, Keyspace, OperationOptions, QuerySettings,
+};
+use crate::utils::document_cache::{preload_cache, PreloadSource};
+use crate::utils::erasure::{erase_subject_data, ErasureRequest};
+use crate::utils::event_signing::{sign_event, verify_event, EventSignature as SignedEvent};
+use crate::utils::export::{export_documents, ExportSource};
+use crate::utils::geo::{near_query, polygon_query, validate_geo_fields};
+use crate::utils::hot_keys::hot_keys;
+use crate::utils::idempotency::process_once;
+use crate::utils::journal::{replay_journal, JournalOp};
+use crate::utils::maintenance::{
+  on_maintenance_event, start_maintenance_scheduler, stop_maintenance_scheduler,
+};
+use crate::utils::named_queries::named_query;
+use crate::utils::payload::log_preview;
+use crate::utils::read_only::{is_read_only, set_read_only};
+use crate::utils::read_preference::{read_document, ReadPreference, ReadSource};
+use crate::utils::read_repair::{read_repair_stats, repair_in_background};
+use crate::utils::retention::{run_retention, start_retention, stop_retention};
+use crate::utils::retry_queue::{
+  discard_queued_write, queued_writes, retry_queued_writes, start_retry_worker, stop_retry_worker,
+};
+use crate::utils::retry_stats::{retry_stats, retry_window};
+use crate::utils::scheduled_jobs::{schedule_mutation, start_job_executor, stop_job_executor};
+use crate::utils::schema::validate_document;
+use crate::utils::timings::OperationTimings;
+use crate::utils::watch::{start_watch, stop_watch};
+use crate::{
+  cas_from_bigint, cas_to_bigint, document_response, mutation_response, mutation_token_from_js,
+  progress_reporter, query_response, timings_response, BatchDocumentsV2, BatchProgress, BlobResult,
+  CasSnapshotResult, CloudEventMessage, ClusterFeatures, ClusterInfo, DeleteByPrefixResult,
+  DocumentChange, DocumentWithMeta, EventSignature, ExistsResult, ExpiryBucket, ExpiryReport,
+  ExportResult, GetResult, GetResultWithCas, HotKey, ListKeysResult, MaintenanceEvent,
+  MultiGetItem, MutationResult, MutationToken, PreloadCacheResult, ProcessOnceResult, QueryColumns,
+  QueuedWrite, ReadRepairStats, ReplaySummary, RetryQueueSummary, RetryStat, RetryStats, SearchHit,
+  TimeSeriesPoint, Timings, DEFAULT_INIT_TIMEOUT_MS,
+};
+
+#[napi(object)]
+pub struct ConnectionOptions {
+  pub connection_string: String,
+  pub username: String,
+  pub password: String,
+}
+
+#[napi]
+pub struct CouchbaseClient {
+  connection: Arc<CouchbaseConnection>,
+}
+
+impl CouchbaseClient {
+  pub fn from_connection(connection: Arc<CouchbaseConnection>) -> Self {
+    CouchbaseClient { connection }
+  }
+
+  // Client bound to the process-wide connection, backs the top-level napi functions
+  pub fn default_client() -> Self {
+    CouchbaseClient::from_connection(default_connection())
+  }
+
+  pub fn connection(&self) -> Arc<CouchbaseConnection> {
+    Arc::clone(&self.connection)
+  }
+
+  async fn fetch_document(
+    &self,
+    options: GetDocumentOptions,
+  ) -> Result<(CasDocument, Option<Timings>, Option<ReadSource>), napi::Error> {
+    let preference = options
+      .read_preference
+      .as_deref()
+      .map(ReadPreference::parse)
+      .transpose()
+      .map_err(napi::Error::from_reason)?;
+    let mut operation_options = options.operation_options();
+    operation_options.timings = options
+      .timings
+      .unwrap_or(false)
+      .then(OperationTimings::start);
+    operation_options.transcoder = options.transcoder.clone();
+    operation_options.cached = options.cached.unwrap_or(false);
+    let timings = operation_options.timings.clone();
+    let log_prefix = operation_options.log_prefix();
+    let request = async {
+      match preference {
+        Some(preference) => read_document(
+          self.connection(),
+          preference,
+          options.key.clone(),
+          options.keyspace(),
+          operation_options,
+        )
+        .await
+        .map(|(document, source)| (document, Some(source))),
+        None => self
+          .connection
+          .get_document(options.key.clone(), options.keyspace(), operation_options)
+          .await
+          .map(|document| (document, None)),
+      }
+    };
+    let couchbase_data = run_abortable(options.abort_token(), request).await;
+    match couchbase_data {
+      Ok((cb_data, source)) => {
+        log::info!(
+          "{}Couchbase data: {}",
+          log_prefix,
+          log_preview(&cb_data.value)
+        );
+        Ok((cb_data, timings_response(timings.as_ref()), source))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error fetching document from Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error.to_string()))
+      }
+    }
+  }
+  async fn fetch_batch(&self, options: GetBatchOptions) -> Result<BatchDocuments, napi::Error> {
+    let mut operation_options = options.operation_options();
+    operation_options.transcoder = options.transcoder.clone();
+    operation_options.partial_on_timeout = options.partial_on_timeout.unwrap_or(false);
+    let log_prefix = operation_options.log_prefix();
+    let keyspace = options.keyspace();
+    let keys = options.keys.clone();
+    let repair_options = operation_options.clone();
+    let request = async {
+      match options.fallback_bucket.clone() {
+        Some(fallback_bucket) => {
+          let fallback = Keyspace {
+            bucket: fallback_bucket,
+            ..keyspace.clone()
+          };
+          self
+            .connection
+            .get_documents_with_fallback(keys, keyspace, fallback, operation_options)
+            .await
+        }
+        None => {
+          self
+            .connection
+            .get_documents_v2(keys, keyspace, operation_options)
+            .await
+        }
+      }
+    };
+    match run_abortable(options.abort_token(), request).await {
+      Ok(cb_response) => {
+        log::info!(
+          "{}Couchbase batch_v2 response: {} documents, {} errors",
+          log_prefix,
+          cb_response.docs.len(),
+          cb_response.errors.len()
+        );
+        let repair_from = options
+          .fallback_bucket
+          .as_ref()
+          .filter(|fallback_bucket| **fallback_bucket != options.bucket);
+        if let (Some(fallback_bucket), true) = (repair_from, options.read_repair.unwrap_or(false)) {
+          let from_fallback = cb_response
+            .docs
+            .iter()
+            .filter(|(key, _)| cb_response.sources.get(*key) == Some(fallback_bucket))
+            .map(|(key, document)| (key.clone(), document.value.clone()))
+            .collect();
+          repair_in_background(
+            self.connection(),
+            from_fallback,
+            options.keyspace(),
+            &repair_options,
+          );
+        }
+        Ok(cb_response)
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error fetching documents from Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error.to_string()))
+      }
+    }
+  }
+
+  async fn geo_search(
+    &self,
+    index_name: String,
+    request: Value,
+    keyspace: Keyspace,
+    limit: Option<u32>,
+    operation_options: OperationOptions,
+    abort_token: Option<String>,
+  ) -> Result<Vec<SearchHit>, napi::Error> {
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      abort_token,
+      self.connection.search(
+        index_name.clone(),
+        request,
+        keyspace,
+        limit.unwrap_or(100),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(hits) => Ok(
+        hits
+          .into_iter()
+          .map(|(id, document)| SearchHit { id, document })
+          .collect(),
+      ),
+      Err(error) => {
+        log::error!(
+          "{}Error searching index {} in Couchbase: {:?}",
+          log_prefix,
+          index_name,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+}
+
+#[napi]
+impl CouchbaseClient {
+  #[napi(constructor)]
+  pub fn new(options: ConnectionOptions) -> Self {
+    CouchbaseClient::from_connection(Arc::new(CouchbaseConnection::connect(
+      CouchbaseConnParams {
+        connection_url: options.connection_string,
+        username: options.username,
+        password: options.password,
+      },
+    )))
+  }
+
+  #[napi]
+  pub async fn get_document(&self, options: GetDocumentOptions) -> Result<GetResult, napi::Error> {
+    let (cb_data, timings, source) = self.fetch_document(options).await?;
+    Ok(GetResult {
+      value: cb_data.value,
+      timings,
+      served_by: source.as_ref().map(|source| source.served_by.clone()),
+      stale: source.map(|source| source.stale),
+    })
+  }
+
+  #[napi]
+  pub async fn get_document_with_cas(
+    &self,
+    options: GetDocumentOptions,
+  ) -> Result<GetResultWithCas, napi::Error> {
+    let (cb_data, timings, source) = self.fetch_document(options).await?;
+    Ok(GetResultWithCas {
+      value: cb_data.value,
+      cas: cas_to_bigint(cb_data.cas),
+      timings,
+      served_by: source.as_ref().map(|source| source.served_by.clone()),
+      stale: source.map(|source| source.stale),
+    })
+  }
+
+  // Validates and normalizes the document against a registered schema before returning it
+  #[napi]
+  pub async fn get_document_typed(
+    &self,
+    options: GetDocumentOptions,
+    schema: String,
+  ) -> Result<GetResult, napi::Error> {
+    let key = options.key.clone();
+    let (cb_data, timings, source) = self.fetch_document(options).await?;
+    match validate_document(&schema, cb_data.value) {
+      Ok(value) => Ok(GetResult {
+        value,
+        timings,
+        served_by: source.as_ref().map(|source| source.served_by.clone()),
+        stale: source.map(|source| source.stale),
+      }),
+      Err(error) => {
+        log::error!("Document {} failed schema {}: {}", key, schema, error);
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Reads a cache-style document and extends its TTL atomically
+  #[napi]
+  pub async fn get_and_touch(
+    &self,
+    options: GetAndTouchOptions,
+  ) -> Result<Either<Value, GetResultWithCas>, napi::Error> {
+    let mut operation_options = options.operation_options();
+    operation_options.transcoder = options.transcoder.clone();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.get_and_touch(
+        options.key.clone(),
+        Duration::from_secs(options.ttl_secs as u64),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(document) => Ok(document_response(
+        document,
+        options.with_cas.unwrap_or(false),
+      )),
+      Err(error) => {
+        log::error!(
+          "{}Error in get and touch of {} from Couchbase: {:?}",
+          log_prefix,
+          options.key,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Atomic increment, returns the counter's new value
+  #[napi]
+  pub async fn increment_counter(&self, options: CounterOptions) -> Result<BigInt, napi::Error> {
+    let delta = options.counter_delta()?;
+    self
+      .update_counter(CounterChange::Increment(delta), options)
+      .await
+  }
+
+  // Atomic decrement, the counter stops at 0. Returns its new value
+  #[napi]
+  pub async fn decrement_counter(&self, options: CounterOptions) -> Result<BigInt, napi::Error> {
+    let delta = options.counter_delta()?;
+    self
+      .update_counter(CounterChange::Decrement(delta), options)
+      .await
+  }
+
+  async fn update_counter(
+    &self,
+    change: CounterChange,
+    options: CounterOptions,
+  ) -> Result<BigInt, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.counter(
+        options.key.clone(),
+        change,
+        options.counter_initial()?,
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(outcome) => {
+        log::info!(
+          "{}Counter {} is now {}",
+          log_prefix,
+          options.key,
+          outcome.value
+        );
+        Ok(BigInt::from(outcome.value))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error updating counter {} in Couchbase: {:?}",
+          log_prefix,
+          options.key,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Refreshes the TTL of a session-style document without re-sending it, returns the new CAS
+  #[napi]
+  pub async fn touch_document(&self, options: TouchDocumentOptions) -> Result<BigInt, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.touch(
+        options.key.clone(),
+        Duration::from_secs(options.ttl_secs as u64),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(cas) => {
+        log::info!(
+          "{}Touched {} with a TTL of {} seconds",
+          log_prefix,
+          options.key,
+          options.ttl_secs
+        );
+        Ok(cas_to_bigint(cas))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error touching document in Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Existence check that transfers no body, a missing document is not an error
+  #[napi]
+  pub async fn document_exists(
+    &self,
+    options: GetDocumentOptions,
+  ) -> Result<ExistsResult, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self
+        .connection
+        .document_cas(options.key.clone(), options.keyspace(), operation_options),
+    )
+    .await
+    {
+      Ok(cas) => Ok(ExistsResult {
+        exists: cas.is_some(),
+        cas: cas.map(cas_to_bigint),
+      }),
+      Err(error) => {
+        log::error!(
+          "{}Error checking whether {} exists in Couchbase: {:?}",
+          log_prefix,
+          options.key,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Absolute expiry in epoch milliseconds, null for documents without a TTL. Read through META() on
+  // the query service, so it fails while no query node is available
+  #[napi]
+  pub async fn get_document_expiry(
+    &self,
+    options: GetDocumentOptions,
+  ) -> Result<Option<i64>, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.get_document_expiry(
+        options.key.clone(),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(expiry) => Ok(expiry.map(|seconds| seconds as i64 * 1000)),
+      Err(error) => {
+        log::error!(
+          "{}Error fetching document expiry from Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Value, CAS and expiry of the same document version, the expiry read like getDocumentExpiry
+  #[napi]
+  pub async fn get_document_with_meta(
+    &self,
+    options: GetDocumentOptions,
+  ) -> Result<DocumentWithMeta, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.get_document_with_meta(
+        options.key.clone(),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(document) => {
+        let expiry = document.expiry.map(|seconds| seconds as i64 * 1000);
+        let now = chrono::Utc::now().timestamp_millis();
+        Ok(DocumentWithMeta {
+          value: document.value,
+          cas: cas_to_bigint(document.cas),
+          expiry,
+          expires_in_ms: expiry.map(|expiry| (expiry - now).max(0)),
+        })
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error fetching document with metadata from Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn add_document(
+    &self,
+    options: AddDocumentOptions,
+  ) -> Result<MutationResult, napi::Error> {
+    if let Some(geo_fields) = &options.geo_fields {
+      validate_geo_fields(&options.value, geo_fields).map_err(napi::Error::from_reason)?;
+    }
+    let mut operation_options = options.operation_options();
+    operation_options.timings = options
+      .timings
+      .unwrap_or(false)
+      .then(OperationTimings::start);
+    operation_options.transcoder = options.transcoder.clone();
+    operation_options.queue_on_failure = options.queue_on_failure.unwrap_or(false);
+    operation_options.expiry = options
+      .ttl_secs
+      .filter(|ttl_secs| *ttl_secs > 0)
+      .map(|ttl_secs| Duration::from_secs(ttl_secs as u64));
+    let timings = operation_options.timings.clone();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.add_document(
+        options.key.clone(),
+        options.value.clone(),
+        options.keyspace(),
+        operation_options,
+        options.retry,
+      ),
+    )
+    .await
+    {
+      Ok(cb_response) => {
+        log::info!(
+          "{}Data successfully added to Couchbase for key: {}",
+          log_prefix,
+          options.key
+        );
+        Ok(MutationResult {
+          timings: timings_response(timings.as_ref()),
+          ..mutation_response(cb_response)
+        })
+      }
+      Err(cb_error) => {
+        log::error!(
+          "{}Failed to add document to Couchbase: {:?}",
+          log_prefix,
+          cb_error
+        );
+        Err(napi::Error::from_reason(format!(
+          "Couchbase error: {}",
+          cb_error
+        )))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn upsert_document(
+    &self,
+    options: UpsertDocumentOptions,
+  ) -> Result<MutationResult, napi::Error> {
+    if let Some(geo_fields) = &options.geo_fields {
+      validate_geo_fields(&options.value, geo_fields).map_err(napi::Error::from_reason)?;
+    }
+    let mut operation_options = options.operation_options();
+    operation_options.timings = options
+      .timings
+      .unwrap_or(false)
+      .then(OperationTimings::start);
+    operation_options.transcoder = options.transcoder.clone();
+    operation_options.queue_on_failure = options.queue_on_failure.unwrap_or(false);
+    operation_options.expiry = options
+      .ttl_secs
+      .map(|ttl_secs| Duration::from_secs(ttl_secs as u64));
+    operation_options.preserve_expiry = options.preserve_expiry.unwrap_or(false);
+    let timings = operation_options.timings.clone();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.upsert_document(
+        options.key.clone(),
+        options.value.clone(),
+        options.keyspace(),
+        operation_options,
+        options.retry,
+      ),
+    )
+    .await
+    {
+      Ok(cb_response) => {
+        log::info!(
+          "{}Data successfully upserted to Couchbase for key: {}",
+          log_prefix,
+          options.key
+        );
+        Ok(MutationResult {
+          timings: timings_response(timings.as_ref()),
+          ..mutation_response(cb_response)
+        })
+      }
+      Err(cb_error) => {
+        log::error!(
+          "{}Failed to upsert document to Couchbase: {:?}",
+          log_prefix,
+          cb_error
+        );
+        Err(napi::Error::from_reason(format!(
+          "Couchbase error: {}",
+          cb_error
+        )))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn replace_document(
+    &self,
+    options: ReplaceDocumentOptions,
+  ) -> Result<MutationResult, napi::Error> {
+    if let Some(geo_fields) = &options.geo_fields {
+      validate_geo_fields(&options.value, geo_fields).map_err(napi::Error::from_reason)?;
+    }
+    let keyspace = options.keyspace();
+    let mut operation_options = options.operation_options();
+    operation_options.timings = options
+      .timings
+      .unwrap_or(false)
+      .then(OperationTimings::start);
+    operation_options.transcoder = options.transcoder.clone();
+    operation_options.queue_on_failure = options.queue_on_failure.unwrap_or(false);
+    operation_options.expiry = options
+      .ttl_secs
+      .map(|ttl_secs| Duration::from_secs(ttl_secs as u64));
+    operation_options.preserve_expiry = options.preserve_expiry.unwrap_or(false);
+    let timings = operation_options.timings.clone();
+    let log_prefix = operation_options.log_prefix();
+    let abort_token = options.abort_token();
+    let cas = options.cas.map(cas_from_bigint).transpose()?;
+    let cb_replace_response = run_abortable(
+      abort_token,
+      self.connection.replace_document(
+        options.key,
+        options.value,
+        cas,
+        keyspace,
+        operation_options,
+        options.retry,
+      ),
+    )
+    .await;
+
+    match cb_replace_response {
+      Ok(cb_replace_response) => {
+        log::info!(
+          "{}Couchbase replace response: {:?}",
+          log_prefix,
+          cb_replace_response
+        );
+        Ok(MutationResult {
+          timings: timings_response(timings.as_ref()),
+          ..mutation_response(cb_replace_response)
+        })
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error replacing document in Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error.to_string()))
+      }
+    }
+  }
+
+  // Path -> value for the paths the document has, missing paths are left out
+  #[napi]
+  pub async fn lookup_in(
+    &self,
+    options: LookupInOptions,
+  ) -> Result<HashMap<String, Value>, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.lookup_paths(
+        options.key.clone(),
+        options.paths.clone(),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(values) => Ok(values),
+      Err(error) => {
+        log::error!(
+          "{}Error looking up paths in Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn mutate_in(&self, options: MutateInOptions) -> Result<MutationResult, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    let cas = options.cas.clone().map(cas_from_bigint).transpose()?;
+    let cb_response = run_abortable(
+      options.abort_token(),
+      self.connection.mutate_in(
+        options.key.clone(),
+        options.mutations(),
+        options.keyspace(),
+        cas,
+        options.store_semantics(),
+        operation_options,
+      ),
+    )
+    .await;
+    match cb_response {
+      Ok(cb_response) => {
+        log::info!(
+          "{}Couchbase mutate_in response: {:?}",
+          log_prefix,
+          cb_response
+        );
+        Ok(mutation_response(cb_response))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error mutating document in Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn merge_document(
+    &self,
+    options: MergeDocumentOptions,
+  ) -> Result<MutationResult, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    let cb_response = run_abortable(
+      options.abort_token(),
+      self.connection.merge_document(
+        options.key.clone(),
+        options.partial.clone(),
+        options.merge_strategy(),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await;
+    match cb_response {
+      Ok(cb_response) => {
+        log::info!("{}Couchbase merge response: {:?}", log_prefix, cb_response);
+        Ok(mutation_response(cb_response))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error merging document in Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Folds writes to the same key within the coalescing window into one write, see utils::coalescer
+  #[napi]
+  pub async fn coalesce_write(
+    &self,
+    options: CoalesceWriteOptions,
+  ) -> Result<MutationResult, napi::Error> {
+    let change = options.pending_change()?;
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    let cb_response = run_abortable(
+      options.abort_token(),
+      coalesce_write(
+        self.connection(),
+        options.key.clone(),
+        change,
+        options.keyspace(),
+        operation_options,
+        options
+          .window_ms
+          .map(|window_ms| Duration::from_millis(window_ms as u64)),
+      ),
+    )
+    .await;
+    match cb_response {
+      Ok(cb_response) => {
+        log::info!(
+          "{}Couchbase coalesced write response: {:?}",
+          log_prefix,
+          cb_response
+        );
+        Ok(mutation_response(cb_response))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error writing coalesced document to Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn remove_document(
+    &self,
+    options: RemoveDocumentOptions,
+  ) -> Result<MutationResult, napi::Error> {
+    let mut operation_options = options.operation_options();
+    operation_options.timings = options
+      .timings
+      .unwrap_or(false)
+      .then(OperationTimings::start);
+    operation_options.queue_on_failure = options.queue_on_failure.unwrap_or(false);
+    let timings = operation_options.timings.clone();
+    let log_prefix = operation_options.log_prefix();
+    let cas = options.cas.clone().map(cas_from_bigint).transpose()?;
+    let cb_response = run_abortable(
+      options.abort_token(),
+      self.connection.delete_data(
+        options.key.clone(),
+        cas,
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await;
+    match cb_response {
+      Ok(cb_response) => {
+        log::info!("{}Couchbase remove response: {:?}", log_prefix, cb_response);
+        Ok(MutationResult {
+          timings: timings_response(timings.as_ref()),
+          ..mutation_response(cb_response)
+        })
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error deleting document from Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error.to_string()))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn get_batch(&self, options: GetBatchOptions) -> Result<BatchDocumentsV2, napi::Error> {
+    let with_cas = options.with_cas.unwrap_or(false);
+    let cb_response = self.fetch_batch(options).await?;
+    Ok(BatchDocumentsV2 {
+      docs: cb_response
+        .docs
+        .into_iter()
+        .map(|(key, document)| (key, document_response(document, with_cas)))
+        .collect(),
+      errors: cb_response
+        .errors
+        .into_iter()
+        .map(|(key, error)| (key, json!({ "error": error })))
+        .collect(),
+      sources: cb_response.sources,
+    })
+  }
+
+  // Batch get where each document goes through `transform` before being returned.
+  // Transforms are dispatched concurrently, a throwing transform only fails its own key.
+  #[napi(ts_args_type = "options: GetBatchOptions, transform: (item: MultiGetItem) => any")]
+  pub async fn get_multi(
+    &self,
+    options: GetBatchOptions,
+    transform: ThreadsafeFunction<MultiGetItem, ErrorStrategy::Fatal>,
+  ) -> Result<BatchDocumentsV2, napi::Error> {
+    let cb_response = self.fetch_batch(options).await?;
+    let sources = &cb_response.sources;
+    let transform = &transform;
+    let mut transformed = futures::stream::iter(cb_response.docs.into_iter().map(
+      |(key, document)| async move {
+        let item = MultiGetItem {
+          key: key.clone(),
+          value: document.value,
+          source: sources.get(&key).cloned().unwrap_or_default(),
+        };
+        (key, transform.call_async::<Value>(item).await)
+      },
+    ))
+    .buffer_unordered(batch_concurrency());
+
+    let mut docs = HashMap::new();
+    let mut errors: HashMap<String, Value> = cb_response
+      .errors
+      .iter()
+      .map(|(key, error)| (key.to_owned(), json!({ "error": error })))
+      .collect();
+    while let Some((key, result)) = transformed.next().await {
+      match result {
+        Ok(value) => {
+          docs.insert(key, Either::A(value));
+        }
+        Err(error) => {
+          log::error!("Transform failed for key {}: {}", key, error);
+          errors.insert(
+            key,
+            json!({ "error": format!("TRANSFORM_FAILED: {}", error) }),
+          );
+        }
+      }
+    }
+    drop(transformed);
+    Ok(BatchDocumentsV2 {
+      docs,
+      errors,
+      sources: cb_response.sources,
+    })
+  }
+
+  // Read-your-own-writes: the query waits until the index has caught up with every token
+  #[napi]
+  pub async fn query_consistent_with(
+    &self,
+    tokens: Vec<MutationToken>,
+    statement: String,
+    options: Option<QueryOptions>,
+  ) -> Result<Either<Vec<Value>, QueryColumns>, napi::Error> {
+    let consistent_with = tokens
+      .into_iter()
+      .map(mutation_token_from_js)
+      .collect::<Result<Vec<_>, _>>()?;
+    let settings = QuerySettings {
+      cache_ttl: options
+        .as_ref()
+        .and_then(|options| options.cache_ttl_ms)
+        .map(|ttl| Duration::from_millis(ttl as u64)),
+      bypass_cache: options
+        .as_ref()
+        .and_then(|options| options.bypass_cache)
+        .unwrap_or(false),
+      ..Default::default()
+    };
+    let (parameters, operation_options, abort_token, columnar) = match options {
+      Some(options) => (
+        options.parameters.unwrap_or_default(),
+        operation_options(
+          options.timeout_ms,
+          options.deadline_ms,
+          options.context.as_ref(),
+        ),
+        options.abort_token,
+        options.columnar.unwrap_or(false),
+      ),
+      None => (Vec::new(), OperationOptions::default(), None, false),
+    };
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      abort_token,
+      self.connection.query(
+        statement,
+        parameters,
+        consistent_with,
+        settings,
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(rows) => {
+        log::info!("{}Couchbase query returned {} rows", log_prefix, rows.len());
+        Ok(query_response(rows, columnar))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error running query on Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Runs the statement registered under `name`. `params` holds named ($name) arguments as an object
+  // or positional ones as an array; a timeout in `options` overrides the registered default
+  #[napi]
+  pub async fn run_named_query(
+    &self,
+    name: String,
+    params: Option<Value>,
+    options: Option<QueryOptions>,
+  ) -> Result<Either<Vec<Value>, QueryColumns>, napi::Error> {
+    let query = named_query(&name).map_err(napi::Error::from_reason)?;
+    let mut settings = QuerySettings {
+      scan_consistency: Some(query.consistency.scan_consistency().to_string()),
+      cache_ttl: options
+        .as_ref()
+        .and_then(|options| options.cache_ttl_ms)
+        .or(query.cache_ttl_ms)
+        .map(|ttl| Duration::from_millis(ttl as u64)),
+      bypass_cache: options
+        .as_ref()
+        .and_then(|options| options.bypass_cache)
+        .unwrap_or(false),
+      ..Default::default()
+    };
+    let mut parameters = Vec::new();
+    match params {
+      None | Some(Value::Null) => {}
+      Some(Value::Object(named)) => settings.named_parameters = named.into_iter().collect(),
+      Some(Value::Array(positional)) => parameters = positional,
+      Some(other) => {
+        return Err(napi::Error::from_reason(format!(
+          "Params for named query {} must be an object or an array, got {}",
+          name, other
+        )))
+      }
+    }
+    let (operation_options, abort_token, columnar) = match options {
+      Some(options) => (
+        operation_options(
+          options.timeout_ms.or(query.timeout_ms),
+          options.deadline_ms,
+          options.context.as_ref(),
+        ),
+        options.abort_token,
+        options.columnar.unwrap_or(false),
+      ),
+      None => (operation_options(query.timeout_ms, None, None), None, false),
+    };
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      abort_token,
+      self.connection.query(
+        query.statement,
+        parameters,
+        Vec::new(),
+        settings,
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(rows) => {
+        log::info!(
+          "{}Named query {} returned {} rows",
+          log_prefix,
+          name,
+          rows.len()
+        );
+        Ok(query_response(rows, columnar))
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error running named query {} on Couchbase: {:?}",
+          log_prefix,
+          name,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn list_keys(&self, options: ListKeysOptions) -> Result<ListKeysResult, napi::Error> {
+    let limit = options.limit.unwrap_or(100).clamp(1, 1000);
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.list_keys(
+        options.prefix.clone(),
+        options.keyspace(),
+        limit,
+        options.start_after.clone(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(page) => Ok(ListKeysResult {
+        keys: page.keys,
+        next_start_after: page.next_start_after,
+      }),
+      Err(error) => {
+        log::error!(
+          "{}Error listing keys with prefix {} from Couchbase: {:?}",
+          log_prefix,
+          options.prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Removes every document under the prefix, see utils::bulk_delete for the confirmation rules
+  #[napi]
+  pub async fn delete_by_prefix(
+    &self,
+    options: DeleteByPrefixOptions,
+  ) -> Result<DeleteByPrefixResult, napi::Error> {
+    let request = DeleteByPrefixRequest {
+      prefix: options.prefix.clone(),
+      dry_run: options.dry_run.unwrap_or(false),
+      max_docs: options.max_docs.map(|max_docs| max_docs.max(0) as u64),
+      rate_limit_per_sec: options.rate_limit_per_sec,
+      confirmation_token: options.confirmation_token.clone(),
+    };
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      delete_by_prefix(
+        &self.connection,
+        request,
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(summary) => Ok(DeleteByPrefixResult {
+        matched: summary.matched as i64,
+        deleted: summary.deleted as i64,
+        failed: summary.failed as i64,
+        sample_keys: summary.sample_keys,
+        confirmation_token: summary.confirmation_token,
+      }),
+      Err(error) => {
+        log::error!(
+          "{}Error deleting documents with prefix {} from Couchbase: {:?}",
+          log_prefix,
+          options.prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Warms the document cache read by `cached` gets, e.g. with the hot keys of the previous deploy
+  #[napi]
+  pub async fn preload_cache(
+    &self,
+    options: PreloadCacheOptions,
+  ) -> Result<PreloadCacheResult, napi::Error> {
+    let source = match (options.keys.clone(), options.prefix.clone()) {
+      (Some(keys), None) => PreloadSource::Keys(keys),
+      (None, Some(prefix)) => PreloadSource::Prefix(prefix),
+      _ => {
+        return Err(napi::Error::from_reason(
+          "preloadCache needs either keys or a prefix",
+        ))
+      }
+    };
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      preload_cache(
+        &self.connection,
+        source,
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(summary) => Ok(PreloadCacheResult {
+        loaded: summary.loaded as i64,
+        missing: summary.missing as i64,
+        skipped: summary.skipped as i64,
+      }),
+      Err(error) => {
+        log::error!(
+          "{}Error preloading the document cache from Couchbase: {:?}",
+          log_prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn search_near(
+    &self,
+    options: SearchNearOptions,
+  ) -> Result<Vec<SearchHit>, napi::Error> {
+    let request = near_query(
+      options.field.as_deref(),
+      options.lon,
+      options.lat,
+      options.radius_meters,
+    )
+    .map_err(napi::Error::from_reason)?;
+    self
+      .geo_search(
+        options.index_name.clone(),
+        request,
+        options.keyspace(),
+        options.limit,
+        options.operation_options(),
+        options.abort_token(),
+      )
+      .await
+  }
+
+  #[napi]
+  pub async fn search_within_polygon(
+    &self,
+    options: SearchWithinPolygonOptions,
+  ) -> Result<Vec<SearchHit>, napi::Error> {
+    let points = options
+      .points
+      .iter()
+      .map(|point| (point.lon, point.lat))
+      .collect::<Vec<(f64, f64)>>();
+    let request =
+      polygon_query(options.field.as_deref(), &points).map_err(napi::Error::from_reason)?;
+    self
+      .geo_search(
+        options.index_name.clone(),
+        request,
+        options.keyspace(),
+        options.limit,
+        options.operation_options(),
+        options.abort_token(),
+      )
+      .await
+  }
+
+  #[napi]
+  pub async fn analyze_expiry(
+    &self,
+    options: AnalyzeExpiryOptions,
+  ) -> Result<ExpiryReport, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.analyze_expiry(
+        options.prefix.clone(),
+        options.keyspace(),
+        options.interval_secs.unwrap_or(3600) as i64,
+        options.horizon_secs.unwrap_or(24 * 3600) as i64,
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(histogram) => Ok(ExpiryReport {
+        total: histogram.total as i64,
+        without_expiry: histogram.without_expiry as i64,
+        expired: histogram.expired as i64,
+        buckets: histogram
+          .buckets
+          .into_iter()
+          .map(|bucket| ExpiryBucket {
+            from: bucket.from,
+            to: bucket.to,
+            count: bucket.count as i64,
+          })
+          .collect(),
+        later: histogram.later as i64,
+      }),
+      Err(error) => {
+        log::error!(
+          "{}Error analyzing expiry of prefix {} in Couchbase: {:?}",
+          log_prefix,
+          options.prefix,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Deletes or anonymizes a data subject's documents and returns the signed erasure report
+  #[napi]
+  pub async fn erase_subject_data(
+    &self,
+    options: EraseSubjectOptions,
+  ) -> Result<Value, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    let keyspace = options.keyspace();
+    let request = ErasureRequest {
+      subject_id: options.subject_id.clone(),
+      audit_keyspace: options.audit_bucket.clone().map(Keyspace::bucket),
+      keyspace,
+      prefixes: options.prefixes.clone().unwrap_or_default(),
+      named_query: options.named_query.clone(),
+      fields_to_anonymize: options.fields_to_anonymize.clone().unwrap_or_default(),
+    };
+    match run_abortable(
+      options.abort_token(),
+      erase_subject_data(&self.connection, request, operation_options),
+    )
+    .await
+    {
+      Ok(report) => Ok(report),
+      Err(error) => {
+        log::error!(
+          "{}Error erasing data of subject {}: {:?}",
+          log_prefix,
+          options.subject_id,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn append_time_series(
+    &self,
+    options: AppendTimeSeriesOptions,
+  ) -> Result<MutationResult, napi::Error> {
+    let timestamp = options.timestamp.unwrap_or_else(|| {
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or(0)
+    });
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.append_time_series(
+        options.series_key.clone(),
+        timestamp,
+        options.value.clone(),
+        options.bucket_granularity(),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(cb_response) => Ok(mutation_response(cb_response)),
+      Err(error) => {
+        log::error!(
+          "{}Error appending to time series {} in Couchbase: {:?}",
+          log_prefix,
+          options.series_key,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn query_time_series(
+    &self,
+    options: QueryTimeSeriesOptions,
+  ) -> Result<Vec<TimeSeriesPoint>, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self.connection.query_time_series(
+        options.series_key.clone(),
+        options.from,
+        options.to,
+        options.bucket_granularity(),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(points) => Ok(
+        points
+          .into_iter()
+          .map(|(timestamp, value)| TimeSeriesPoint { timestamp, value })
+          .collect(),
+      ),
+      Err(error) => {
+        log::error!(
+          "{}Error querying time series {} from Couchbase: {:?}",
+          log_prefix,
+          options.series_key,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn open_bucket(
+    &self,
+    bucket_name: String,
+    scope: Option<String>,
+    collection: Option<String>,
+  ) -> Result<BucketHandle, napi::Error> {
+    BucketHandle::open(
+      self.connection(),
+      Keyspace {
+        bucket: bucket_name,
+        scope,
+        collection,
+      },
+    )
+    .await
+  }
+
+  #[napi]
+  pub fn list(&self, options: DatastructureOptions) -> CouchbaseList {
+    CouchbaseList::from_connection(self.connection(), options)
+  }
+
+  #[napi]
+  pub fn set(&self, options: DatastructureOptions) -> CouchbaseSet {
+    CouchbaseSet::from_connection(self.connection(), options)
+  }
+
+  #[napi]
+  pub fn map(&self, options: DatastructureOptions) -> CouchbaseMap {
+    CouchbaseMap::from_connection(self.connection(), options)
+  }
+
+  #[napi]
+  pub fn queue(&self, options: DatastructureOptions) -> CouchbaseQueue {
+    CouchbaseQueue::from_connection(self.connection(), options)
+  }
+
+  #[napi]
+  pub fn sorted_set(&self, options: DatastructureOptions) -> CouchbaseSortedSet {
+    CouchbaseSortedSet::from_connection(self.connection(), options)
+  }
+
+  #[napi]
+  pub fn sessions(&self, options: SessionStoreOptions) -> CouchbaseSessionStore {
+    CouchbaseSessionStore::from_connection(self.connection(), options)
+  }
+
+  #[napi]
+  pub async fn wait_until_ready(
+    &self,
+    buckets: Option<Vec<String>>,
+    timeout_ms: Option<u32>,
+  ) -> Result<(), napi::Error> {
+    let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_INIT_TIMEOUT_MS) as u64);
+    self
+      .connection
+      .wait_until_ready(buckets.unwrap_or_default(), timeout)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub fn is_ready(&self) -> bool {
+    self.connection.is_ready()
+  }
+
+  // Pings the cluster and every cached bucket, ready to be returned from a /healthz handler
+  #[napi]
+  pub async fn build_health_report(&self) -> Value {
+    self.connection.health_report().await
+  }
+
+  // Server version, services and which optional features the cluster supports
+  #[napi]
+  pub async fn get_cluster_info(&self) -> Result<ClusterInfo, napi::Error> {
+    let info = self
+      .connection
+      .cluster_info()
+      .await
+      .map_err(napi::Error::from_reason)?;
+    Ok(ClusterInfo {
+      features: ClusterFeatures {
+        collections: info.supports(ClusterFeature::Collections),
+        range_scan: info.supports(ClusterFeature::RangeScan),
+        transactions: info.supports(ClusterFeature::Transactions),
+        search: info.supports(ClusterFeature::Search),
+      },
+      version: info.version,
+      services: info.services,
+      nodes: info.nodes as u32,
+    })
+  }
+
+  // CAS of each key without the bodies, cheap enough to diff tens of thousands of keys against a previous run
+  #[napi]
+  pub async fn get_cas_snapshot(
+    &self,
+    options: CasSnapshotOptions,
+  ) -> Result<CasSnapshotResult, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      self
+        .connection
+        .cas_snapshot(options.keys.clone(), options.keyspace(), operation_options),
+    )
+    .await
+    {
+      Ok(snapshot) => {
+        log::info!(
+          "{}CAS snapshot of {} keys: {} missing, {} errors",
+          log_prefix,
+          options.keys.len(),
+          snapshot.missing.len(),
+          snapshot.errors.len()
+        );
+        Ok(CasSnapshotResult {
+          cas: snapshot
+            .cas
+            .into_iter()
+            .map(|(key, cas)| (key, cas_to_bigint(cas)))
+            .collect(),
+          missing: snapshot.missing,
+          errors: snapshot.errors,
+        })
+      }
+      Err(error) => {
+        log::error!("{}Error taking CAS snapshot: {:?}", log_prefix, error);
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Stores the bytes once per content hash, putting the same bytes again adds a reference
+  #[napi]
+  pub async fn put_blob(
+    &self,
+    bytes: Buffer,
+    options: BlobOptions,
+  ) -> Result<BlobResult, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      put_blob(
+        &self.connection,
+        &bytes,
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(blob) => {
+        log::info!(
+          "{}Stored blob {} of {} bytes, {} references",
+          log_prefix,
+          blob.hash,
+          blob.size,
+          blob.refs
+        );
+        Ok(BlobResult {
+          hash: blob.hash,
+          size: blob.size as u32,
+          refs: blob.refs as i64,
+          deduplicated: blob.deduplicated,
+        })
+      }
+      Err(error) => {
+        log::error!("{}Error storing blob in Couchbase: {:?}", log_prefix, error);
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  #[napi]
+  pub async fn get_blob(&self, hash: String, options: BlobOptions) -> Result<Buffer, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      get_blob(
+        &self.connection,
+        &hash,
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(bytes) => Ok(bytes.into()),
+      Err(error) => {
+        log::error!(
+          "{}Error fetching blob {} from Couchbase: {:?}",
+          log_prefix,
+          hash,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Drops one reference, the blob is removed with its last one. Returns the references left
+  #[napi]
+  pub async fn release_blob(&self, hash: String, options: BlobOptions) -> Result<i64, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      release_blob(
+        &self.connection,
+        &hash,
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(refs) => Ok(refs as i64),
+      Err(error) => {
+        log::error!(
+          "{}Error releasing blob {} in Couchbase: {:?}",
+          log_prefix,
+          hash,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Records `result` for `messageId` unless an earlier delivery already did, see `first`
+  #[napi]
+  pub async fn process_once(
+    &self,
+    options: ProcessOnceOptions,
+  ) -> Result<ProcessOnceResult, napi::Error> {
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      process_once(
+        &self.connection,
+        &options.message_id,
+        Duration::from_secs(options.ttl_secs as u64),
+        options.result.clone(),
+        options.key_prefix.as_deref(),
+        options.keyspace(),
+        operation_options,
+      ),
+    )
+    .await
+    {
+      Ok(outcome) => Ok(ProcessOnceResult {
+        first: outcome.first,
+        result: outcome.result,
+      }),
+      Err(error) => {
+        log::error!(
+          "{}Error recording message {} as processed: {:?}",
+          log_prefix,
+          options.message_id,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Writes the documents to `outputPath` as NDJSON and returns only the counts
+  #[napi(
+    ts_args_type = "options: ExportDocumentsOptions, onProgress?: (progress: BatchProgress) => void"
+  )]
+  pub async fn export_documents(
+    &self,
+    options: ExportDocumentsOptions,
+    on_progress: Option<ThreadsafeFunction<BatchProgress, ErrorStrategy::Fatal>>,
+  ) -> Result<ExportResult, napi::Error> {
+    let source = match (options.keys.clone(), options.prefix.clone()) {
+      (Some(keys), None) => ExportSource::Keys(keys),
+      (None, Some(prefix)) => ExportSource::Prefix(prefix),
+      _ => {
+        return Err(napi::Error::from_reason(
+          "exportDocuments needs either keys or a prefix",
+        ))
+      }
+    };
+    let total = options.keys.as_ref().map(|keys| keys.len() as u64);
+    let progress = progress_reporter(on_progress, options.progress_every, total);
+    let operation_options = options.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      options.abort_token(),
+      export_documents(
+        &self.connection,
+        source,
+        &options.output_path,
+        options.keyspace(),
+        operation_options,
+        progress,
+      ),
+    )
+    .await
+    {
+      Ok(summary) => Ok(ExportResult {
+        output_path: options.output_path,
+        written: summary.written as i64,
+        failed: summary.failed as i64,
+      }),
+      Err(error) => {
+        log::error!(
+          "{}Error exporting documents to {}: {:?}",
+          log_prefix,
+          options.output_path,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Re-applies a mutation journal written with full payloads to `toBucket`, reporting progress every 1000 lines
+  #[napi(
+    ts_args_type = "path: string, toBucket: string, sinceMs?: number | undefined | null, onProgress?: (progress: BatchProgress) => void"
+  )]
+  pub async fn replay_journal(
+    &self,
+    path: String,
+    to_bucket: String,
+    since_ms: Option<i64>,
+    on_progress: Option<ThreadsafeFunction<BatchProgress, ErrorStrategy::Fatal>>,
+  ) -> Result<ReplaySummary, napi::Error> {
+    let summary = replay_journal(
+      &self.connection,
+      &path,
+      to_bucket,
+      since_ms,
+      OperationOptions::default(),
+      progress_reporter(on_progress, None, None),
+    )
+    .await
+    .map_err(napi::Error::from_reason)?;
+    Ok(ReplaySummary {
+      applied: summary.applied as i64,
+      skipped: summary.skipped as i64,
+      failed: summary.failed as i64,
+    })
+  }
+
+  // Applies the `couchbase.retention` rules once and returns a per-rule summary
+  #[napi]
+  pub async fn run_retention(&self, dry_run: Option<bool>) -> Value {
+    run_retention(&self.connection, dry_run.unwrap_or(false)).await
+  }
+
+  // Audits in the background every `intervalSecs`, defaulting to `couchbase.retention.intervalSecs`
+  #[napi]
+  pub async fn start_retention(&self, interval_secs: Option<u32>) {
+    start_retention(
+      self.connection(),
+      interval_secs.map(|secs| Duration::from_secs(secs.max(1) as u64)),
+    );
+  }
+
+  #[napi]
+  pub fn stop_retention(&self) -> bool {
+    stop_retention()
+  }
+
+  // Stores the mutation as a job applied once `at_ms` (epoch milliseconds) has passed, returns the job id.
+  // Jobs only run while some process has started the job executor
+  #[napi]
+  pub async fn schedule_mutation(
+    &self,
+    at_ms: i64,
+    op: ScheduleMutationOptions,
+  ) -> Result<String, napi::Error> {
+    let mutation = op.scheduled_mutation()?;
+    let operation_options = op.operation_options();
+    let log_prefix = operation_options.log_prefix();
+    match run_abortable(
+      op.abort_token(),
+      schedule_mutation(&self.connection, at_ms, mutation, operation_options),
+    )
+    .await
+    {
+      Ok(job_id) => {
+        log::info!(
+          "{}Scheduled {} of {} at {} as {}",
+          log_prefix,
+          op.operation,
+          op.key,
+          at_ms,
+          job_id
+        );
+        Ok(job_id)
+      }
+      Err(error) => {
+        log::error!(
+          "{}Error scheduling {} of {}: {:?}",
+          log_prefix,
+          op.operation,
+          op.key,
+          error
+        );
+        Err(napi::Error::from_reason(error))
+      }
+    }
+  }
+
+  // Applies due jobs every `pollSecs`, defaulting to `couchbase.scheduledJobs.pollSecs`. Only the executor
+  // holding the leader lease applies them, so every process can start one
+  #[napi]
+  pub async fn start_job_executor(&self, poll_secs: Option<u32>) {
+    start_job_executor(
+      self.connection(),
+      poll_secs.map(|secs| Duration::from_secs(secs.max(1) as u64)),
+    );
+  }
+
+  #[napi]
+  pub fn stop_job_executor(&self) -> bool {
+    stop_job_executor()
+  }
+
+  // Writes made with `queueOnFailure` that are still waiting, failed ones included. The queue is process-wide
+  #[napi]
+  pub fn list_queued_writes(&self) -> Vec<QueuedWrite> {
+    queued_writes()
+      .into_iter()
+      .map(|queued| QueuedWrite {
+        operation: queued.operation.name().to_string(),
+        id: queued.id,
+        key: queued.key,
+        bucket: queued.bucket,
+        scope: queued.scope,
+        collection: queued.collection,
+        attempts: queued.attempts,
+        enqueued_at: queued.enqueued_at,
+        next_attempt_at: queued.next_attempt_at,
+        last_error: queued.last_error,
+        failed: queued.failed,
+      })
+      .collect()
+  }
+
+  // Retries every pending queued write now, whatever its backoff
+  #[napi]
+  pub async fn flush_retry_queue(&self) -> Result<RetryQueueSummary, napi::Error> {
+    let summary = retry_queued_writes(&self.connection, false)
+      .await
+      .map_err(napi::Error::from_reason)?;
+    log::info!(
+      "Flushed retry queue: {} applied, {} retrying, {} failed",
+      summary.applied,
+      summary.retrying,
+      summary.failed
+    );
+    Ok(RetryQueueSummary {
+      applied: summary.applied as i64,
+      retrying: summary.retrying as i64,
+      failed: summary.failed as i64,
+    })
+  }
+
+  // Drops a queued write without applying it, false when no entry has that id
+  #[napi]
+  pub fn discard_queued_write(&self, id: String) -> Result<bool, napi::Error> {
+    discard_queued_write(&id).map_err(napi::Error::from_reason)
+  }
+
+  // Retries due writes every `pollSecs`, defaulting to `couchbase.retryQueue.pollSecs`
+  #[napi]
+  pub async fn start_retry_worker(&self, poll_secs: Option<u32>) {
+    start_retry_worker(
+      self.connection(),
+      poll_secs.map(|secs| Duration::from_secs(secs.max(1) as u64)),
+    );
+  }
+
+  #[napi]
+  pub fn stop_retry_worker(&self) -> bool {
+    stop_retry_worker()
+  }
+
+  // Process-wide, every client and the legacy functions share the switch
+  #[napi]
+  pub fn set_read_only_mode(&self, enabled: bool) {
+    set_read_only(enabled)
+  }
+
+  #[napi]
+  pub fn is_read_only_mode(&self) -> bool {
+    is_read_only()
+  }
+
+  // Most accessed keys of the current window across every client, hottest first. `topN` defaults to 10
+  #[napi]
+  pub fn get_hot_keys(&self, top_n: Option<u32>) -> Vec<HotKey> {
+    hot_keys(top_n.unwrap_or(10) as usize)
+      .into_iter()
+      .map(|hot| HotKey {
+        key: hot.key,
+        keyspace: hot.keyspace,
+        count: hot.count as i64,
+      })
+      .collect()
+  }
+
+  #[napi]
+  pub fn get_read_repair_stats(&self) -> ReadRepairStats {
+    let stats = read_repair_stats();
+    ReadRepairStats {
+      repaired: stats.repaired as i64,
+      already_present: stats.already_present as i64,
+      failed: stats.failed as i64,
+    }
+  }
+
+  #[napi]
+  pub fn get_retry_stats(&self) -> RetryStats {
+    RetryStats {
+      window_secs: retry_window().as_secs() as u32,
+      operations: retry_stats()
+        .into_iter()
+        .map(|stat| RetryStat {
+          bucket: stat.bucket,
+          operation: stat.operation,
+          retries: stat.retries as i64,
+          retries_exhausted: stat.retries_exhausted as i64,
+          deadline_exhausted: stat.deadline_exhausted as i64,
+          causes: stat
+            .causes
+            .into_iter()
+            .map(|(cause, count)| (cause, count as i64))
+            .collect(),
+        })
+        .collect(),
+    }
+  }
+
+  // Schedules the `couchbase.maintenance` windows and returns how many there are
+  #[napi]
+  pub fn start_maintenance_scheduler(&self) -> Result<u32, napi::Error> {
+    start_maintenance_scheduler()
+      .map(|count| count as u32)
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub fn stop_maintenance_scheduler(&self) -> bool {
+    stop_maintenance_scheduler()
+  }
+
+  #[napi(ts_args_type = "callback: (event: MaintenanceEvent) => void")]
+  pub fn on_maintenance_event(
+    &self,
+    env: Env,
+    mut callback: ThreadsafeFunction<MaintenanceEvent, ErrorStrategy::Fatal>,
+  ) -> Result<(), napi::Error> {
+    // Listening alone does not keep the process alive
+    callback.unref(&env)?;
+    on_maintenance_event(Box::new(move |event| {
+      callback.call(
+        MaintenanceEvent {
+          window: event.window.clone(),
+          phase: event.phase.as_str().to_string(),
+          at: event.at,
+          in_flight: event.in_flight as u32,
+        },
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }));
+    Ok(())
+  }
+
+  // Calls back whenever the document changes after the watch started, until unwatchDocument. Only the CAS is
+  // polled, the body is fetched when it moved
+  #[napi(
+    ts_args_type = "key: string, bucket: string, callback: (change: DocumentChange) => void, options?: WatchOptions | undefined | null"
+  )]
+  pub fn watch_document(
+    &self,
+    key: String,
+    bucket: String,
+    callback: ThreadsafeFunction<DocumentChange, ErrorStrategy::Fatal>,
+    options: Option<WatchOptions>,
+  ) -> String {
+    let (scope, collection, interval) = match options {
+      Some(options) => (
+        options.scope,
+        options.collection,
+        options
+          .poll_interval_ms
+          .map(|ms| Duration::from_millis(ms.max(1) as u64)),
+      ),
+      None => (None, None, None),
+    };
+    let keyspace = Keyspace {
+      bucket,
+      scope,
+      collection,
+    };
+    let watched_key = key.clone();
+    start_watch(
+      self.connection(),
+      key,
+      keyspace,
+      interval,
+      Box::new(move |change| {
+        let change = match change {
+          Some(document) => DocumentChange {
+            key: watched_key.clone(),
+            value: Some(document.value),
+            cas: Some(cas_to_bigint(document.cas)),
+            deleted: false,
+          },
+          None => DocumentChange {
+            key: watched_key.clone(),
+            value: None,
+            cas: None,
+            deleted: true,
+          },
+        };
+        callback.call(change, ThreadsafeFunctionCallMode::NonBlocking);
+      }),
+    )
+  }
+
+  // False when no watch has that id
+  #[napi]
+  pub fn unwatch_document(&self, watch_id: String) -> bool {
+    stop_watch(&watch_id)
+  }
+
+  #[napi]
+  pub async fn close(&self) -> Result<(), napi::Error> {
+    self.connection.close().await;
+    Ok(())
+  }
+
+  #[napi]
+  pub async fn is_closed(&self) -> bool {
+    self.connection.is_closed().await
+  }
+
+  // Signs a webhook event body with the active couchbase.eventSigning key
+  #[napi]
+  pub fn sign_event_payload(&self, body: String) -> Result<EventSignature, napi::Error> {
+    let signed = sign_event(body.as_bytes()).map_err(napi::Error::from_reason)?;
+    Ok(EventSignature {
+      key_id: signed.key_id,
+      timestamp: signed.timestamp,
+      signature: signed.signature,
+    })
+  }
+
+  // For receivers sharing the signing config, `body` must be the raw body as received
+  #[napi]
+  pub fn verify_event_signature(
+    &self,
+    body: String,
+    signature: EventSignature,
+  ) -> Result<bool, napi::Error> {
+    let signature = SignedEvent {
+      key_id: signature.key_id,
+      timestamp: signature.timestamp,
+      signature: signature.signature,
+    };
+    verify_event(body.as_bytes(), &signature).map_err(napi::Error::from_reason)
+  }
+
+  // CloudEvents envelope of a mutation, source and type from couchbase.cloudEvents
+  #[napi]
+  pub fn to_cloud_event(
+    &self,
+    options: CloudEventOptions,
+  ) -> Result<CloudEventMessage, napi::Error> {
+    let operation: JournalOp = serde_json::from_value(Value::String(options.operation.clone()))
+      .map_err(|_| {
+        napi::Error::from_reason(format!(
+          "Unknown operation {}, expected insert, upsert, replace, remove or mutateIn",
+          options.operation
+        ))
+      })?;
+    let cas = options.cas.clone().map(cas_from_bigint).transpose()?;
+    let message = mutation_event(
+      operation,
+      &options.key,
+      &options.keyspace(),
+      cas,
+      options.value.as_ref(),
+    );
+    Ok(CloudEventMessage {
+      key: message.key,
+      value: message.value,
+    })
+  }
+}
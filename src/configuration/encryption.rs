@@ -0,0 +1,98 @@
+// Config values written as `ENC(<base64 of 12 byte nonce + AES-256-GCM ciphertext>)` are decrypted at load time
+// with the base64 32 byte key in CONFIG_ENCRYPTION_KEY. Keys held in a KMS are expected to be injected into that
+// variable by the deployment (secret mounts, sidecars), the crate itself never talks to a KMS
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::Value;
+
+pub const CONFIG_KEY_VAR: &str = "CONFIG_ENCRYPTION_KEY";
+const NONCE_LEN: usize = 12;
+
+fn cipher() -> Result<Aes256Gcm, String> {
+    let encoded = std::env::var(CONFIG_KEY_VAR).map_err(|_| format!("{} is not set", CONFIG_KEY_VAR))?;
+    let key = BASE64
+        .decode(encoded.trim())
+        .map_err(|error| format!("{} is not valid base64 : {}", CONFIG_KEY_VAR, error))?;
+    if key.len() != 32 {
+        return Err(format!("{} must decode to 32 bytes, got {}", CONFIG_KEY_VAR, key.len()));
+    }
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+fn encrypted_payload(value: &str) -> Option<&str> {
+    value.trim().strip_prefix("ENC(")?.strip_suffix(')')
+}
+
+pub fn contains_encrypted(value: &Value) -> bool {
+    match value {
+        Value::String(text) => encrypted_payload(text).is_some(),
+        Value::Array(items) => items.iter().any(contains_encrypted),
+        Value::Object(fields) => fields.values().any(contains_encrypted),
+        _ => false,
+    }
+}
+
+fn decrypt(cipher: &Aes256Gcm, payload: &str) -> Result<String, String> {
+    let bytes = BASE64.decode(payload).map_err(|error| format!("not valid base64 : {}", error))?;
+    if bytes.len() <= NONCE_LEN {
+        return Err("ciphertext is too short".to_string());
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "wrong key or corrupted ciphertext".to_string())?;
+    String::from_utf8(plaintext).map_err(|_| "plaintext is not UTF-8".to_string())
+}
+
+// Replaces every ENC(...) string in place. `path` names the failing setting in errors
+fn decrypt_tree(cipher: &Aes256Gcm, value: &mut Value, path: &str, errors: &mut Vec<String>) {
+    match value {
+        Value::String(text) => {
+            if let Some(payload) = encrypted_payload(text) {
+                match decrypt(cipher, payload) {
+                    Ok(plaintext) => *text = plaintext,
+                    Err(error) => errors.push(format!("{} : {}", path, error)),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                decrypt_tree(cipher, item, &format!("{}[{}]", path, index), errors);
+            }
+        }
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                let path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                decrypt_tree(cipher, field, &path, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Settings that fail to decrypt keep their ENC(...) value and are reported, so one bad value does not take the
+// rest of the configuration down with it
+pub fn decrypt_values(value: &mut Value) -> Result<(), Vec<String>> {
+    let cipher = cipher().map_err(|error| vec![error])?;
+    let mut errors = Vec::new();
+    decrypt_tree(&cipher, value, "", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// Produces the ENC(...) form of `plaintext` for pasting into a config file
+pub fn encrypt_value(plaintext: &str) -> Result<String, String> {
+    let cipher = cipher()?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| "encryption failed".to_string())?;
+    let mut bytes = nonce.to_vec();
+    bytes.extend_from_slice(&ciphertext);
+    Ok(format!("ENC({})", BASE64.encode(bytes)))
+}
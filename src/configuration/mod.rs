@@ -1,5 +1,8 @@
 use config::Config;
+mod encryption;
+mod redaction;
 mod settings;
+mod sources;
 use lazy_static::lazy_static;
 // use crate::error::{ConfigError, Result};
 use serde::de::Deserialize;
@@ -21,3 +24,11 @@ pub fn get_res<'de, T: Deserialize<'de>>(key: &str) -> Result<T, config::ConfigE
     CONFIG.read().unwrap().get(key)
 }
 
+// Files, RUN_MODE overrides and app_* env vars merged, ENC(...) values decrypted and then masked with every other secret
+pub fn effective_config() -> serde_json::Value {
+    let merged = CONFIG.read().unwrap().clone().try_deserialize::<serde_json::Value>();
+    redaction::redact(merged.unwrap_or_default())
+}
+
+pub use encryption::encrypt_value;
+pub use sources::resolve_setting;
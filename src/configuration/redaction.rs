@@ -0,0 +1,41 @@
+// Effective configuration for support dumps, with every value under a secret-looking key masked
+use serde_json::{Map, Value};
+
+pub const REDACTED: &str = "******";
+
+// Matched against keys lowercased with `_` and `-` removed
+const SECRET_KEY_PARTS: [&str; 8] = [
+    "password",
+    "secret",
+    "token",
+    "credential",
+    "accesskey",
+    "apikey",
+    "privatekey",
+    "signingkey",
+];
+
+fn is_secret_key(key: &str) -> bool {
+    let normalized: String = key.to_lowercase().chars().filter(|c| *c != '_' && *c != '-').collect();
+    SECRET_KEY_PARTS.iter().any(|part| normalized.contains(part))
+}
+
+pub fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = if is_secret_key(&key) && !value.is_null() {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact(value)
+                    };
+                    (key, value)
+                })
+                .collect::<Map<String, Value>>(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        other => other,
+    }
+}
@@ -1,7 +1,9 @@
 #![allow(deprecated)]
-use config::{ Config, Environment, File};
+use config::{ Config, Environment, File, FileFormat};
 use std::env;
 
+use super::encryption::{contains_encrypted, decrypt_values};
+
 
 pub fn get_config() -> Config {
     let run_mode = env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
@@ -18,11 +20,41 @@ pub fn get_config() -> Config {
         // This file shouldn't be checked in to git
         .add_source(env_settings.prefix("app").separator("_"))
         // You may also programmatically change settings
-        .build()
-        .unwrap();
+        .build();
+
+    // Without a readable config file fall back to environment variables only, callers apply their own defaults
+    let s = match s {
+        Ok(s) => s,
+        Err(error) => {
+            eprintln!("Unable to load config/config.json ({}), using environment settings only", error);
+            Config::builder()
+                .add_source(Environment::new().prefix("app").separator("_"))
+                .build()
+                .unwrap_or_default()
+        }
+    };
 
     // Now that we're done, let's access our configuration
 
     // You can deserialize (and thus freeze) the entire configuration as
-    s
+    decrypt_config(s)
+}
+
+// Rebuilds the config with ENC(...) values decrypted, configs without encrypted values are returned as they are
+fn decrypt_config(s: Config) -> Config {
+    let mut tree: serde_json::Value = match s.clone().try_deserialize() {
+        Ok(tree) => tree,
+        Err(_) => return s,
+    };
+    if !contains_encrypted(&tree) {
+        return s;
+    }
+    // Running on with the literal ENC(...) would hand ciphertext to the cluster as a password
+    if let Err(errors) = decrypt_values(&mut tree) {
+        panic!("Unable to decrypt config values : {}", errors.join(", "));
+    }
+    Config::builder()
+        .add_source(File::from_str(&tree.to_string(), FileFormat::Json))
+        .build()
+        .unwrap_or(s)
 }
@@ -0,0 +1,36 @@
+// Where a setting injected by the platform is read from, for deployments that name their env vars differently, e.g.
+//   "couchbase": { "sources": { "password": { "env": ["CB_SECRET_PASSWORD", "DB_PASSWORD"], "configKey": "couchbase.password" } } }
+// The env vars are tried in order, then the config key. A setting without an entry only reads its default env var
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SettingSource {
+    #[serde(default)]
+    env: Vec<String>,
+    config_key: Option<String>,
+}
+
+// `sources_key` is the config path of the setting's entry, e.g. "couchbase.sources.password"
+pub fn resolve_setting(sources_key: &str, default_env: &str) -> Result<String, String> {
+    let source = super::get_res::<SettingSource>(sources_key).unwrap_or_default();
+    let env_names = if source.env.is_empty() {
+        vec![default_env.to_string()]
+    } else {
+        source.env
+    };
+    if let Some(value) = env_names
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+    {
+        return Ok(value);
+    }
+    if let Some(config_key) = &source.config_key {
+        if let Ok(value) = super::get_res::<String>(config_key) {
+            return Ok(value);
+        }
+    }
+    let mut looked_at = env_names;
+    looked_at.extend(source.config_key);
+    Err(format!("{} is not set, looked at {}", sources_key, looked_at.join(", ")))
+}
@@ -0,0 +1,377 @@
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::options::operation_options;
+use crate::utils::couchbase_db::{default_connection, CouchbaseConnection, Keyspace};
+use crate::utils::datastructures::Datastructure;
+use crate::utils::sorted_set::SortedSet;
+
+#[napi(object)]
+pub struct DatastructureOptions {
+  pub key: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+}
+
+fn datastructure(
+  connection: Arc<CouchbaseConnection>,
+  options: DatastructureOptions,
+) -> Datastructure {
+  Datastructure::new(
+    connection,
+    options.key,
+    Keyspace {
+      bucket: options.bucket,
+      scope: options.scope,
+      collection: options.collection,
+    },
+    operation_options(options.timeout_ms, None, None),
+  )
+}
+
+// Ordered values in one array document
+#[napi]
+pub struct CouchbaseList {
+  inner: Datastructure,
+}
+
+impl CouchbaseList {
+  pub fn from_connection(
+    connection: Arc<CouchbaseConnection>,
+    options: DatastructureOptions,
+  ) -> Self {
+    CouchbaseList {
+      inner: datastructure(connection, options),
+    }
+  }
+}
+
+#[napi]
+impl CouchbaseList {
+  #[napi(constructor)]
+  pub fn new(options: DatastructureOptions) -> Self {
+    CouchbaseList::from_connection(default_connection(), options)
+  }
+
+  #[napi]
+  pub async fn push(&self, value: Value) -> Result<(), napi::Error> {
+    self
+      .inner
+      .push_back(value)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn pop(&self) -> Result<Option<Value>, napi::Error> {
+    self
+      .inner
+      .pop_at(-1)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn get(&self, index: i64) -> Result<Option<Value>, napi::Error> {
+    self
+      .inner
+      .get_at(index)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn contains(&self, value: Value) -> Result<bool, napi::Error> {
+    self
+      .inner
+      .array_contains(&value)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn size(&self) -> Result<u32, napi::Error> {
+    self.inner.size().await.map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn clear(&self) -> Result<(), napi::Error> {
+    self.inner.clear().await.map_err(napi::Error::from_reason)
+  }
+}
+
+// Unique primitive values in one array document
+#[napi]
+pub struct CouchbaseSet {
+  inner: Datastructure,
+}
+
+impl CouchbaseSet {
+  pub fn from_connection(
+    connection: Arc<CouchbaseConnection>,
+    options: DatastructureOptions,
+  ) -> Self {
+    CouchbaseSet {
+      inner: datastructure(connection, options),
+    }
+  }
+}
+
+#[napi]
+impl CouchbaseSet {
+  #[napi(constructor)]
+  pub fn new(options: DatastructureOptions) -> Self {
+    CouchbaseSet::from_connection(default_connection(), options)
+  }
+
+  #[napi]
+  pub async fn add(&self, value: Value) -> Result<bool, napi::Error> {
+    self
+      .inner
+      .add_unique(value)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn remove(&self, value: Value) -> Result<bool, napi::Error> {
+    self
+      .inner
+      .remove_element(&value)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn contains(&self, value: Value) -> Result<bool, napi::Error> {
+    self
+      .inner
+      .array_contains(&value)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn size(&self) -> Result<u32, napi::Error> {
+    self.inner.size().await.map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn clear(&self) -> Result<(), napi::Error> {
+    self.inner.clear().await.map_err(napi::Error::from_reason)
+  }
+}
+
+// Field/value pairs in one object document
+#[napi]
+pub struct CouchbaseMap {
+  inner: Datastructure,
+}
+
+impl CouchbaseMap {
+  pub fn from_connection(
+    connection: Arc<CouchbaseConnection>,
+    options: DatastructureOptions,
+  ) -> Self {
+    CouchbaseMap {
+      inner: datastructure(connection, options),
+    }
+  }
+}
+
+#[napi]
+impl CouchbaseMap {
+  #[napi(constructor)]
+  pub fn new(options: DatastructureOptions) -> Self {
+    CouchbaseMap::from_connection(default_connection(), options)
+  }
+
+  #[napi]
+  pub async fn set(&self, field: String, value: Value) -> Result<(), napi::Error> {
+    self
+      .inner
+      .map_set(&field, value)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn get(&self, field: String) -> Result<Option<Value>, napi::Error> {
+    self
+      .inner
+      .map_get(&field)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn remove(&self, field: String) -> Result<bool, napi::Error> {
+    self
+      .inner
+      .map_remove(&field)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn contains(&self, field: String) -> Result<bool, napi::Error> {
+    self
+      .inner
+      .map_contains(&field)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn size(&self) -> Result<u32, napi::Error> {
+    self.inner.size().await.map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn clear(&self) -> Result<(), napi::Error> {
+    self.inner.clear().await.map_err(napi::Error::from_reason)
+  }
+}
+
+// FIFO queue in one array document: push prepends, pop takes from the end
+#[napi]
+pub struct CouchbaseQueue {
+  inner: Datastructure,
+}
+
+impl CouchbaseQueue {
+  pub fn from_connection(
+    connection: Arc<CouchbaseConnection>,
+    options: DatastructureOptions,
+  ) -> Self {
+    CouchbaseQueue {
+      inner: datastructure(connection, options),
+    }
+  }
+}
+
+#[napi]
+impl CouchbaseQueue {
+  #[napi(constructor)]
+  pub fn new(options: DatastructureOptions) -> Self {
+    CouchbaseQueue::from_connection(default_connection(), options)
+  }
+
+  #[napi]
+  pub async fn push(&self, value: Value) -> Result<(), napi::Error> {
+    self
+      .inner
+      .push_front(value)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn pop(&self) -> Result<Option<Value>, napi::Error> {
+    self
+      .inner
+      .pop_at(-1)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn size(&self) -> Result<u32, napi::Error> {
+    self.inner.size().await.map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn clear(&self) -> Result<(), napi::Error> {
+    self.inner.clear().await.map_err(napi::Error::from_reason)
+  }
+}
+
+#[napi(object)]
+pub struct SortedSetEntry {
+  pub member: String,
+  pub score: f64,
+}
+
+// Ranked members, one document per member, `key` names the set. Ranges and ranks run on the query service
+#[napi]
+pub struct CouchbaseSortedSet {
+  inner: SortedSet,
+}
+
+impl CouchbaseSortedSet {
+  pub fn from_connection(
+    connection: Arc<CouchbaseConnection>,
+    options: DatastructureOptions,
+  ) -> Self {
+    CouchbaseSortedSet {
+      inner: SortedSet::new(
+        connection,
+        options.key,
+        Keyspace {
+          bucket: options.bucket,
+          scope: options.scope,
+          collection: options.collection,
+        },
+        operation_options(options.timeout_ms, None, None),
+      ),
+    }
+  }
+}
+
+#[napi]
+impl CouchbaseSortedSet {
+  #[napi(constructor)]
+  pub fn new(options: DatastructureOptions) -> Self {
+    CouchbaseSortedSet::from_connection(default_connection(), options)
+  }
+
+  #[napi(js_name = "zAdd")]
+  pub async fn z_add(&self, member: String, score: f64) -> Result<(), napi::Error> {
+    self
+      .inner
+      .add(&member, score)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  // Inclusive ranks, negative ones count from the end. `reverse` ranks the highest score first
+  #[napi(js_name = "zRange")]
+  pub async fn z_range(
+    &self,
+    start: i64,
+    stop: i64,
+    reverse: Option<bool>,
+  ) -> Result<Vec<SortedSetEntry>, napi::Error> {
+    let entries = self
+      .inner
+      .range(start, stop, reverse.unwrap_or(false))
+      .await
+      .map_err(napi::Error::from_reason)?;
+    Ok(
+      entries
+        .into_iter()
+        .map(|entry| SortedSetEntry {
+          member: entry.member,
+          score: entry.score,
+        })
+        .collect(),
+    )
+  }
+
+  #[napi(js_name = "zRank")]
+  pub async fn z_rank(
+    &self,
+    member: String,
+    reverse: Option<bool>,
+  ) -> Result<Option<i64>, napi::Error> {
+    self
+      .inner
+      .rank(&member, reverse.unwrap_or(false))
+      .await
+      .map(|rank| rank.map(|rank| rank as i64))
+      .map_err(napi::Error::from_reason)
+  }
+}
@@ -3,20 +3,39 @@
 #[macro_use]
 extern crate napi_derive;
 
+pub mod bucket;
+pub mod client;
 pub mod configuration;
+pub mod datastructures;
 // pub mod kafka;
+pub mod options;
+pub mod sessions;
 pub mod utils;
+pub mod v2;
 
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use napi::bindgen_prelude::{BigInt, Either};
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use options::{
+  AddDocumentOptions, GetBatchOptions, GetDocumentOptions, LoggerOptions, LookupInOptions,
+  RemoveDocumentOptions, ReplaceDocumentOptions,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use utils::{
+  abort::{abort, is_aborted, register_abort_token, release_abort_token, run_abortable},
   couchbase_db::{
-    add_document as couchbase_add_document, delete_data as couchbase_delete_document,
-    get_document as get_couchbase_document, get_documents as couchbase_get_documents,
-    init_couchbase_connection, replace_document as couchbase_replace_document,
-    get_documents_v2 as couchbase_get_documents_v2,
+    get_documents as couchbase_get_documents, init_couchbase_connection,
+    init_couchbase_connection_ready, is_couchbase_ready, CasDocument, DocumentMutationToken,
+    Keyspace, MutationOutcome, OperationOptions,
   },
-  logger::LoggerConfig,
+  logger::startLogger as start_logger,
+  progress::{ProgressReporter, DEFAULT_PROGRESS_EVERY},
+  timings::OperationTimings,
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,24 +45,571 @@ pub struct BatchResponse {
   pub values: Option<Vec<Value>>,
 }
 
+// Where the time of one call went, only present when the options asked for `timings`
+#[napi(object)]
+pub struct Timings {
+  // Everything besides network and deserialization: keyspace lookup, validation, retry backoff
+  pub queue_ms: f64,
+  pub network_ms: f64,
+  pub deserialize_ms: f64,
+  pub attempts: u32,
+}
+
+#[napi(object)]
+pub struct GetResult {
+  pub value: Value,
+  pub timings: Option<Timings>,
+  // Set when the read named a readPreference: the cluster that answered, and whether it is an XDCR replica
+  pub served_by: Option<String>,
+  pub stale: Option<bool>,
+}
+
+#[napi(object)]
+pub struct GetResultWithCas {
+  pub value: Value,
+  pub cas: BigInt,
+  pub timings: Option<Timings>,
+  pub served_by: Option<String>,
+  pub stale: Option<bool>,
+}
+
+#[napi(object)]
+pub struct DocumentWithMeta {
+  pub value: Value,
+  pub cas: BigInt,
+  // Absolute expiry in epoch milliseconds, null for documents without a TTL
+  pub expiry: Option<i64>,
+  // Milliseconds left to live when read, null for documents without a TTL
+  pub expires_in_ms: Option<i64>,
+}
+
+#[napi(object)]
+pub struct MutationToken {
+  pub bucket_name: String,
+  pub partition_id: u32,
+  pub partition_uuid: BigInt,
+  pub sequence_number: BigInt,
+}
+
+#[napi(object)]
+pub struct MutationResult {
+  pub cas: BigInt,
+  // Not returned for mutateIn writes
+  pub mutation_token: Option<MutationToken>,
+  pub timings: Option<Timings>,
+  // More than 1 when the write only succeeded after retries
+  pub attempts: u32,
+  pub retry_delay_ms: u32,
+}
+
+// Return-only: the Either values of `docs` cannot be read back from JS
+#[napi(object, object_from_js = false)]
+pub struct BatchDocumentsV2 {
+  pub docs: HashMap<String, Either<Value, GetResultWithCas>>,
+  pub errors: HashMap<String, Value>,
+  // Bucket that served each returned document
+  pub sources: HashMap<String, String>,
+}
+
+// Argument passed to the getMulti transform callback
+#[napi(object)]
+pub struct MultiGetItem {
+  pub key: String,
+  pub value: Value,
+  // Bucket that served the document
+  pub source: String,
+}
+
+#[napi(object)]
+pub struct ListKeysResult {
+  pub keys: Vec<String>,
+  // Pass as `startAfter` to fetch the next page, absent on the last page
+  pub next_start_after: Option<String>,
+}
+
+#[napi(object)]
+pub struct ReplaySummary {
+  pub applied: i64,
+  // Hash-only entries and entries before `sinceMs`
+  pub skipped: i64,
+  pub failed: i64,
+}
+
+// A write waiting in the retry queue, without its document
+#[napi(object)]
+pub struct QueuedWrite {
+  pub id: String,
+  pub key: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // insert, upsert, replace or remove
+  pub operation: String,
+  pub attempts: u32,
+  pub enqueued_at: i64,
+  pub next_attempt_at: i64,
+  pub last_error: String,
+  // Out of attempts or failed permanently, stays queued until discarded
+  pub failed: bool,
+}
+
+#[napi(object)]
+pub struct RetryQueueSummary {
+  pub applied: i64,
+  // Failed again and scheduled for another attempt
+  pub retrying: i64,
+  pub failed: i64,
+}
+
+#[napi(object)]
+pub struct ClusterFeatures {
+  pub collections: bool,
+  pub range_scan: bool,
+  pub transactions: bool,
+  pub search: bool,
+}
+
+#[napi(object)]
+pub struct ClusterInfo {
+  pub version: String,
+  pub services: Vec<String>,
+  pub nodes: u32,
+  pub features: ClusterFeatures,
+}
+
+// Sent alongside a webhook event so the receiver can check it with v2.verifyEventSignature
+#[napi(object)]
+pub struct EventSignature {
+  pub key_id: String,
+  pub timestamp: i64,
+  pub signature: String,
+}
+
+// Produce `value` as the Kafka message value under `key`
+#[napi(object)]
+pub struct CloudEventMessage {
+  pub key: String,
+  pub value: Value,
+}
+
+#[napi(object)]
+pub struct ExistsResult {
+  pub exists: bool,
+  // Absent when the document does not exist
+  pub cas: Option<BigInt>,
+}
+
+#[napi(object)]
+pub struct CasSnapshotResult {
+  // Key -> CAS, for the keys that exist
+  pub cas: HashMap<String, BigInt>,
+  pub missing: Vec<String>,
+  pub errors: HashMap<String, String>,
+}
+
+#[napi(object)]
+pub struct BlobResult {
+  // "sha256:<hex>" of the bytes, pass it to getBlob/releaseBlob
+  pub hash: String,
+  pub size: u32,
+  pub refs: i64,
+  // The same bytes were already stored and only gained a reference
+  pub deduplicated: bool,
+}
+
+#[napi(object)]
+pub struct ProcessOnceResult {
+  // False for a redelivery, `result` is then the one stored by the first delivery
+  pub first: bool,
+  pub result: Value,
+}
+
+#[napi(object)]
+pub struct BatchProgress {
+  pub processed: i64,
+  pub succeeded: i64,
+  pub failed: i64,
+  // Unset when the number of items is not known up front
+  pub total: Option<i64>,
+  pub eta_ms: Option<i64>,
+  // Set on the final report
+  pub done: bool,
+}
+
+#[napi(object)]
+pub struct PreloadCacheResult {
+  pub loaded: i64,
+  pub missing: i64,
+  // Keys left out because the document cache was full
+  pub skipped: i64,
+}
+
+#[napi(object)]
+pub struct DeleteByPrefixResult {
+  pub matched: i64,
+  pub deleted: i64,
+  pub failed: i64,
+  // Dry runs only
+  pub sample_keys: Vec<String>,
+  // Dry runs over the confirmation threshold only
+  pub confirmation_token: Option<String>,
+}
+
+#[napi(object)]
+pub struct ExportResult {
+  pub output_path: String,
+  pub written: i64,
+  pub failed: i64,
+}
+
+#[napi(object)]
+pub struct RetryStat {
+  pub bucket: String,
+  pub operation: String,
+  pub retries: i64,
+  // Writes that failed with no retries left
+  pub retries_exhausted: i64,
+  // Writes that still had retries but not the time for another one before their deadline
+  pub deadline_exhausted: i64,
+  // "busy", "timeout", "ambiguous", "conflict", "network" or "other" -> retries and give-ups it caused
+  pub causes: HashMap<String, i64>,
+}
+
+// Write retries over the last `windowSecs`, see `couchbase.retryStats`
+#[napi(object)]
+pub struct RetryStats {
+  pub window_secs: u32,
+  pub operations: Vec<RetryStat>,
+}
+
+// One line of the crate's JSON log, see queryLocalLogs
+#[napi(object)]
+pub struct LogEntry {
+  // RFC 3339
+  pub time: String,
+  pub level: String,
+  pub message: String,
+  pub module_path: Option<String>,
+  pub file: Option<String>,
+  pub line: Option<u32>,
+}
+
+// Read-repairs since the process started
+#[napi(object)]
+pub struct ReadRepairStats {
+  pub repaired: i64,
+  // The primary had been written in the meantime, nothing was copied
+  pub already_present: i64,
+  pub failed: i64,
+}
+
+#[napi(object)]
+pub struct HotKey {
+  pub key: String,
+  // bucket or bucket.scope.collection
+  pub keyspace: String,
+  // Estimated accesses in the current `couchbase.hotKeys` window
+  pub count: i64,
+}
+
+#[napi(object)]
+pub struct MaintenanceEvent {
+  pub window: String,
+  // started, drained, drainTimedOut or ended
+  pub phase: String,
+  pub at: i64,
+  pub in_flight: u32,
+}
+
+#[napi(object)]
+pub struct DocumentChange {
+  pub key: String,
+  // Both unset when the document was removed or expired
+  pub value: Option<Value>,
+  pub cas: Option<BigInt>,
+  pub deleted: bool,
+}
+
+#[napi(object)]
+pub struct ExpiryBucket {
+  // Epoch milliseconds, `to` is exclusive
+  pub from: i64,
+  pub to: i64,
+  pub count: i64,
+}
+
+#[napi(object)]
+pub struct ExpiryReport {
+  pub total: i64,
+  pub without_expiry: i64,
+  // Past their expiry but not purged yet
+  pub expired: i64,
+  pub buckets: Vec<ExpiryBucket>,
+  // Expiring after the horizon
+  pub later: i64,
+}
+
+#[napi(object)]
+pub struct SearchHit {
+  pub id: String,
+  pub document: Value,
+}
+
+#[napi(object)]
+pub struct TimeSeriesPoint {
+  // Epoch milliseconds
+  pub timestamp: i64,
+  pub value: Value,
+}
+
+// Column-oriented query result, `rows[i][j]` is the value of `columns[j]` in row i
+#[napi(object)]
+pub struct QueryColumns {
+  pub columns: Vec<String>,
+  pub rows: Vec<Vec<Value>>,
+}
+
+pub const DEFAULT_INIT_TIMEOUT_MS: u32 = 30_000;
+
+lazy_static! {
+  static ref DEPRECATION_WARNINGS: Mutex<HashSet<&'static str>> = Mutex::new(HashSet::new());
+}
+
+// Logged once per process for each deprecated export so busy services don't flood the logs.
+// Only for exports that shipped before v2, newer positional exports are not deprecated
+fn warn_deprecated(name: &'static str, replacement: &str) {
+  if DEPRECATION_WARNINGS.lock().unwrap().insert(name) {
+    log::warn!(
+      "{} is deprecated and will be removed in the next major release, use {} instead",
+      name,
+      replacement
+    );
+  }
+}
+
 // pub fn caste
 #[derive(Debug)]
 pub struct ReturnError {
   pub error: String,
 }
 
+// CAS values are full u64s, so they cross the JS boundary as BigInt only.
+pub fn cas_to_bigint(cas: u64) -> BigInt {
+  BigInt::from(cas)
+}
+
+pub fn cas_from_bigint(cas: BigInt) -> Result<u64, napi::Error> {
+  let (signed, value, lossless) = cas.get_u64();
+  if signed || !lossless {
+    return Err(napi::Error::from_reason(
+      "Invalid CAS value: expected an unsigned 64-bit bigint".to_string(),
+    ));
+  }
+  Ok(value)
+}
+
+// Tokens come back from JS as plain objects, so the u64 fields need the same bigint checks as CAS
+pub(crate) fn mutation_token_from_js(
+  token: MutationToken,
+) -> Result<DocumentMutationToken, napi::Error> {
+  let invalid = |field: &str| {
+    napi::Error::from_reason(format!(
+      "Invalid mutation token: {} must be an unsigned 64-bit bigint",
+      field
+    ))
+  };
+  let (signed, partition_uuid, lossless) = token.partition_uuid.get_u64();
+  if signed || !lossless {
+    return Err(invalid("partitionUuid"));
+  }
+  let (signed, sequence_number, lossless) = token.sequence_number.get_u64();
+  if signed || !lossless {
+    return Err(invalid("sequenceNumber"));
+  }
+  Ok(DocumentMutationToken {
+    bucket_name: token.bucket_name,
+    partition_id: u16::try_from(token.partition_id).map_err(|_| {
+      napi::Error::from_reason("Invalid mutation token: partitionId out of range".to_string())
+    })?,
+    partition_uuid,
+    sequence_number,
+  })
+}
+
+pub(crate) fn document_response(document: CasDocument, with_cas: bool) -> Either<Value, GetResultWithCas> {
+  if with_cas {
+    Either::B(GetResultWithCas {
+      value: document.value,
+      cas: cas_to_bigint(document.cas),
+      timings: None,
+      served_by: None,
+      stale: None,
+    })
+  } else {
+    Either::A(document.value)
+  }
+}
+
+// Columns are collected in first-seen order, fields missing from a row are null.
+// Rows that are not objects (SELECT RAW) land in a single "$1" column.
+pub(crate) fn query_response(rows: Vec<Value>, columnar: bool) -> Either<Vec<Value>, QueryColumns> {
+  if !columnar {
+    return Either::A(rows);
+  }
+  let mut columns: Vec<String> = Vec::new();
+  let mut positions: HashMap<String, usize> = HashMap::new();
+  let mut column_of = |name: &str, columns: &mut Vec<String>| -> usize {
+    *positions.entry(name.to_string()).or_insert_with(|| {
+      columns.push(name.to_string());
+      columns.len() - 1
+    })
+  };
+  let mut cells: Vec<Vec<(usize, Value)>> = Vec::with_capacity(rows.len());
+  for row in rows {
+    cells.push(match row {
+      Value::Object(fields) => fields
+        .into_iter()
+        .map(|(name, value)| (column_of(&name, &mut columns), value))
+        .collect(),
+      value => vec![(column_of("$1", &mut columns), value)],
+    });
+  }
+  let rows = cells
+    .into_iter()
+    .map(|row_cells| {
+      let mut row = vec![Value::Null; columns.len()];
+      for (position, value) in row_cells {
+        row[position] = value;
+      }
+      row
+    })
+    .collect();
+  Either::B(QueryColumns { columns, rows })
+}
+
+pub(crate) fn mutation_response(outcome: MutationOutcome) -> MutationResult {
+  MutationResult {
+    cas: cas_to_bigint(outcome.cas),
+    mutation_token: outcome.mutation_token.map(|token| MutationToken {
+      bucket_name: token.bucket_name,
+      partition_id: token.partition_id as u32,
+      partition_uuid: BigInt::from(token.partition_uuid),
+      sequence_number: BigInt::from(token.sequence_number),
+    }),
+    timings: None,
+    attempts: outcome.attempts,
+    retry_delay_ms: outcome.retry_delay.as_millis() as u32,
+  }
+}
+
+pub(crate) fn timings_response(timings: Option<&Arc<OperationTimings>>) -> Option<Timings> {
+  timings.map(|timings| {
+    let breakdown = timings.finish();
+    Timings {
+      queue_ms: breakdown.queue_ms,
+      network_ms: breakdown.network_ms,
+      deserialize_ms: breakdown.deserialize_ms,
+      attempts: breakdown.attempts,
+    }
+  })
+}
+
+// Forwards batch job progress to a JS callback without waiting on it
+pub(crate) fn progress_reporter(
+  callback: Option<ThreadsafeFunction<BatchProgress, ErrorStrategy::Fatal>>,
+  every: Option<u32>,
+  total: Option<u64>,
+) -> Option<ProgressReporter> {
+  let callback = callback?;
+  let every = every.map_or(DEFAULT_PROGRESS_EVERY, u64::from);
+  Some(ProgressReporter::new(
+    Box::new(move |progress| {
+      callback.call(
+        BatchProgress {
+          processed: progress.processed as i64,
+          succeeded: progress.succeeded as i64,
+          failed: progress.failed as i64,
+          total: progress.total.map(|total| total as i64),
+          eta_ms: progress.eta_ms.map(|eta| eta as i64),
+          done: progress.done,
+        },
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }),
+    every,
+    total,
+  ))
+}
+
+// Pass `token.id` as `abortToken` to any operation, then call `token.abort()` to cancel it
+#[napi]
+pub struct AbortToken {
+  id: String,
+}
+
 #[napi]
-pub fn startLogger() {
-  // You can use handle to change logger config at runtime
-  // just call startLogger() in main.rs and you can use log4rs in all your Project-crate.
-  let Global_logs_config = LoggerConfig::create_Global_logs_config();
-  let handle = log4rs::init_config(Global_logs_config).unwrap();
+impl AbortToken {
+  #[napi(constructor)]
+  pub fn new() -> Self {
+    AbortToken {
+      id: register_abort_token(),
+    }
+  }
+
+  #[napi(getter)]
+  pub fn id(&self) -> String {
+    self.id.clone()
+  }
+
+  #[napi(getter)]
+  pub fn aborted(&self) -> bool {
+    is_aborted(&self.id)
+  }
+
+  #[napi]
+  pub fn abort(&self) -> bool {
+    abort(&self.id)
+  }
+}
+
+impl Default for AbortToken {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
+impl Drop for AbortToken {
+  fn drop(&mut self) {
+    release_abort_token(&self.id);
+  }
+}
+
+// Missing logger keys fall back to defaults and are logged as warnings, unusable values are returned as errors
+#[napi]
+pub fn startLogger(options: Option<LoggerOptions>) -> Result<(), napi::Error> {
+  let console = options.as_ref().and_then(LoggerOptions::console_output);
+  start_logger(console).map_err(napi::Error::from_reason)
+}
+
+// Resolves once the cluster (and every bucket in `buckets`) answers a ping
 #[napi(js_name = "initCouchbase")]
+pub async fn init_couchbase(
+  buckets: Option<Vec<String>>,
+  timeout_ms: Option<u32>,
+) -> Result<(), napi::Error> {
+  init_couchbase_connection();
+  let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_INIT_TIMEOUT_MS) as u64);
+  init_couchbase_connection_ready(buckets.unwrap_or_default(), timeout)
+    .await
+    .map_err(|error| {
+      log::error!("Error initializing Couchbase connection: {:?}", error);
+      napi::Error::from_reason(error)
+    })
+}
 
-pub fn init_couchbase() {
-  init_couchbase_connection()
+#[napi(js_name = "isReady")]
+pub fn is_ready() -> bool {
+  is_couchbase_ready()
 }
 
 #[napi(js_name = "getDocuments")]
@@ -51,17 +617,27 @@ pub async fn get_documents(
   key: String,
   with_cas: bool,
   bucket_name: String,
-) -> Result<Value, napi::Error> {
-  let couchbase_data = get_couchbase_document(key.clone(), with_cas, bucket_name.clone()).await;
-  match couchbase_data {
-    Ok(cb_data) => {
-      log::info!("Couchbase data: {:?}", cb_data);
-      Ok(cb_data)
-    }
-    Err(error) => {
-      log::error!("Error fetching document from Couchbase: {:?}", error);
-      Err(napi::Error::from_reason(error.to_string()))
-    }
+  abort_token: Option<String>,
+) -> Result<Either<Value, GetResultWithCas>, napi::Error> {
+  warn_deprecated("getDocuments", "v2.getDocument / v2.getDocumentWithCas");
+  let options = GetDocumentOptions {
+    key,
+    bucket: bucket_name,
+    scope: None,
+    collection: None,
+    timings: None,
+    transcoder: None,
+    read_preference: None,
+    cached: None,
+    timeout_ms: None,
+    deadline_ms: None,
+    abort_token,
+    context: None,
+  };
+  if with_cas {
+    Ok(Either::B(v2::get_document_with_cas(options).await?))
+  } else {
+    Ok(Either::A(v2::get_document(options).await?.value))
   }
 }
 
@@ -70,67 +646,134 @@ pub async fn add_documents(
   key: String,
   value: Value,
   bucket_name: String,
-) -> Result<bool, napi::Error> {
-  match couchbase_add_document(key.clone(), value.clone(), bucket_name.clone(), Some(5)).await {
-    Ok(cb_response) => {
-      log::info!("Data successfully added to Couchbase for key: {}", key);
-      Ok(cb_response)
-    }
-    Err(cb_error) => {
-      log::error!("Failed to add document to Couchbase: {:?}", cb_error);
-      Err(napi::Error::from_reason(format!(
-        "Couchbase error: {}",
-        cb_error
-      )))
-    }
-  }
+  abort_token: Option<String>,
+) -> Result<MutationResult, napi::Error> {
+  warn_deprecated("addDocument", "v2.addDocument");
+  v2::add_document(AddDocumentOptions {
+    key,
+    value,
+    bucket: bucket_name,
+    scope: None,
+    collection: None,
+    retry: Some(5),
+    geo_fields: None,
+    timings: None,
+    queue_on_failure: None,
+    transcoder: None,
+    ttl_secs: None,
+    timeout_ms: None,
+    deadline_ms: None,
+    abort_token,
+    context: None,
+  })
+  .await
+}
+
+// Positional form of v2.addDocument with ttlSecs, for expiring documents such as one-time tokens
+#[napi(js_name = "addDocumentWithTTL")]
+pub async fn add_document_with_ttl(
+  key: String,
+  value: Value,
+  bucket_name: String,
+  ttl_seconds: u32,
+  abort_token: Option<String>,
+) -> Result<MutationResult, napi::Error> {
+  v2::add_document(AddDocumentOptions {
+    key,
+    value,
+    bucket: bucket_name,
+    scope: None,
+    collection: None,
+    retry: Some(5),
+    geo_fields: None,
+    timings: None,
+    queue_on_failure: None,
+    transcoder: None,
+    ttl_secs: Some(ttl_seconds),
+    timeout_ms: None,
+    deadline_ms: None,
+    abort_token,
+    context: None,
+  })
+  .await
+}
+
+// Positional form of v2.lookupIn, path -> value for the paths the document has
+#[napi(js_name = "lookupIn")]
+pub async fn lookup_in(
+  key: String,
+  paths: Vec<String>,
+  bucket_name: String,
+  abort_token: Option<String>,
+) -> Result<HashMap<String, Value>, napi::Error> {
+  v2::lookup_in(LookupInOptions {
+    key,
+    paths,
+    bucket: bucket_name,
+    scope: None,
+    collection: None,
+    timeout_ms: None,
+    deadline_ms: None,
+    abort_token,
+    context: None,
+  })
+  .await
 }
 
 #[napi(js_name = "replaceDocument")]
 pub async fn replace_documents(
   key: String,
   value: Value,
-  #[napi(ts_arg_type = "bigint | null | undefined")] with_cas: Option<i64>,
+  cas: Option<BigInt>,
   bucket_name: String,
-) -> Result<bool, napi::Error> {
-  // Use `with_cas` directly as an `Option<i64>`
-  // convert this with_cas: Option<i64>  as option of u64
-  let with_cas = with_cas.map(|x| x as u64);
-  let cb_replace_response = couchbase_replace_document(
-    key.clone(),
-    value.clone(),
-    with_cas,
-    bucket_name.clone(),
-    Some(5),
-  )
-  .await;
-
-  match cb_replace_response {
-    Ok(cb_replace_response) => {
-      log::info!("Couchbase replace response: {:?}", cb_replace_response);
-      Ok(true)
-    }
-    Err(error) => {
-      log::error!("Error replacing document in Couchbase: {:?}", error);
-      Err(napi::Error::from_reason(error.to_string()))
-    }
-  }
+  abort_token: Option<String>,
+) -> Result<MutationResult, napi::Error> {
+  warn_deprecated("replaceDocument", "v2.replaceDocument");
+  v2::replace_document(ReplaceDocumentOptions {
+    key,
+    value,
+    cas,
+    bucket: bucket_name,
+    scope: None,
+    collection: None,
+    retry: Some(5),
+    geo_fields: None,
+    timings: None,
+    queue_on_failure: None,
+    transcoder: None,
+    ttl_secs: None,
+    preserve_expiry: None,
+    timeout_ms: None,
+    deadline_ms: None,
+    abort_token,
+    context: None,
+  })
+  .await
 }
 
 #[napi(js_name = "removeDocument")]
 
-pub async fn remove_document(key: String, bucket_name: String) -> Result<String, napi::Error> {
-  let cb_response = couchbase_delete_document(key.clone(), bucket_name.clone()).await;
-  match cb_response {
-    Ok(cb_response) => {
-      log::info!("Couchbase response: {}", cb_response);
-      Ok(cb_response)
-    }
-    Err(error) => {
-      log::error!("Error deleting document from Couchbase: {:?}", error);
-      Err(napi::Error::from_reason(error.to_string()))
-    }
-  }
+pub async fn remove_document(
+  key: String,
+  bucket_name: String,
+  cas: Option<BigInt>,
+  abort_token: Option<String>,
+) -> Result<MutationResult, napi::Error> {
+  warn_deprecated("removeDocument", "v2.removeDocument");
+  v2::remove_document(RemoveDocumentOptions {
+    key,
+    cas,
+    bucket: bucket_name,
+    scope: None,
+    collection: None,
+    timings: None,
+    queue_on_failure: None,
+    timeout_ms: None,
+    deadline_ms: None,
+    abort_token,
+    context: None,
+  })
+  .await
 }
 
 #[napi(js_name = "getBatchDocuments")]
@@ -139,12 +782,27 @@ pub async fn couchbase_get_batchdocuments(
   keys: Vec<String>,
   with_cas: bool,
   bucket_name: String,
-) -> Result<Value, napi::Error> {
-  let cb_response = couchbase_get_documents(keys.clone(), with_cas, bucket_name.clone()).await;
+  abort_token: Option<String>,
+) -> Result<HashMap<String, Either<Value, GetResultWithCas>>, napi::Error> {
+  warn_deprecated("getBatchDocuments", "v2.getBatch");
+  let cb_response = run_abortable(
+    abort_token,
+    couchbase_get_documents(
+      keys.clone(),
+      Keyspace::bucket(bucket_name),
+      OperationOptions::default(),
+    ),
+  )
+  .await;
   match cb_response {
     Ok(cb_response) => {
-      log::info!("Couchbase batch response: {}", cb_response);
-      Ok(cb_response)
+      log::info!("Couchbase batch response: {} documents", cb_response.len());
+      Ok(
+        cb_response
+          .into_iter()
+          .map(|(key, document)| (key, document_response(document, with_cas)))
+          .collect(),
+      )
     }
     Err(error) => {
       log::error!("Error deleting document from Couchbase: {:?}", error);
@@ -158,16 +816,23 @@ pub async fn couchbase_get_batchdocuments_v2(
   keys: Vec<String>,
   with_cas: bool,
   bucket_name: String,
-) -> Result<Value, napi::Error> {
-  let cb_response = couchbase_get_documents_v2(keys.clone(), with_cas, bucket_name.clone()).await;
-  match cb_response {
-    Ok(cb_response) => {
-      log::info!("Couchbase batch_v2 response: {}", cb_response);
-      Ok(cb_response)
-    }
-    Err(error) => {
-      log::error!("Error deleting document from Couchbase: {:?}", error);
-      Err(napi::Error::from_reason(error.to_string()))
-    }
-  }
+  abort_token: Option<String>,
+) -> Result<BatchDocumentsV2, napi::Error> {
+  warn_deprecated("getBatchDocumentsV2", "v2.getBatch");
+  v2::get_batch(GetBatchOptions {
+    keys,
+    bucket: bucket_name,
+    scope: None,
+    collection: None,
+    fallback_bucket: None,
+    read_repair: None,
+    with_cas: Some(with_cas),
+    partial_on_timeout: None,
+    transcoder: None,
+    timeout_ms: None,
+    deadline_ms: None,
+    abort_token,
+    context: None,
+  })
+  .await
 }
@@ -0,0 +1,898 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use couchbase::StoreSemantics as CbStoreSemantics;
+use log::Level;
+use napi::bindgen_prelude::BigInt;
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::utils::coalescer::PendingChange;
+use crate::utils::couchbase_db::{
+  CallContext, Keyspace, OperationOptions, SubdocMutation, SubdocOperation,
+};
+use crate::utils::log_query::LogQuery;
+use crate::utils::logger::ConsoleOutput;
+use crate::utils::merge::MergeStrategy as DocumentMergeStrategy;
+use crate::utils::scheduled_jobs::{ScheduledMutation, ScheduledOperation};
+use crate::utils::time_series::TimeSeriesGranularity as BucketGranularity;
+
+#[napi]
+pub enum StoreSemantics {
+  Replace,
+  Upsert,
+  Insert,
+}
+
+// Caller supplied identifiers, echoed into native logs for correlation
+#[napi(object)]
+pub struct OperationContext {
+  pub request_id: Option<String>,
+  pub tenant_id: Option<String>,
+  pub actor: Option<String>,
+}
+
+#[napi(object)]
+pub struct GetDocumentOptions {
+  pub key: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Adds a queue/network/deserialize breakdown to the result
+  pub timings: Option<bool>,
+  // "json" (the default), "string", "binary" or a registered transcoder, else the bucket's
+  pub transcoder: Option<String>,
+  // "primary" (the default), "secondary" or "nearest", see `couchbase.replicaClusters`
+  pub read_preference: Option<String>,
+  // Off by default. Serve from the in-memory document cache (see preloadCache) when the key is
+  // there. Only writes made through this process drop a key, so the value can be as old as
+  // `couchbase.documentCache.ttlSecs` (300 by default). Leave it off for reads that must be current
+  pub cached: Option<bool>,
+  pub timeout_ms: Option<u32>,
+  // Absolute deadline in epoch milliseconds, usually the deadline of the HTTP request being served
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct AddDocumentOptions {
+  pub key: String,
+  pub value: Value,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub retry: Option<u32>,
+  // Dotted paths that must hold valid geo points or GeoJSON when present
+  pub geo_fields: Option<Vec<String>>,
+  pub timings: Option<bool>,
+  // Persist the write to the retry queue if it still fails transiently after `retry`
+  pub queue_on_failure: Option<bool>,
+  pub transcoder: Option<String>,
+  // The document expires this long after the write, never when absent or 0
+  pub ttl_secs: Option<u32>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+// Same shape as AddDocumentOptions, the document is written whether or not it exists
+#[napi(object)]
+pub struct UpsertDocumentOptions {
+  pub key: String,
+  pub value: Value,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub retry: Option<u32>,
+  pub geo_fields: Option<Vec<String>>,
+  pub timings: Option<bool>,
+  pub queue_on_failure: Option<bool>,
+  pub transcoder: Option<String>,
+  // New TTL of the document, 0 removes the expiry
+  pub ttl_secs: Option<u32>,
+  // Keep the current expiry when no ttlSecs is given, otherwise the write clears it. The expiry is
+  // read before the write, so a TTL change made in between is overwritten
+  pub preserve_expiry: Option<bool>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct ReplaceDocumentOptions {
+  pub key: String,
+  pub value: Value,
+  pub cas: Option<BigInt>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub retry: Option<u32>,
+  pub geo_fields: Option<Vec<String>>,
+  pub timings: Option<bool>,
+  pub queue_on_failure: Option<bool>,
+  pub transcoder: Option<String>,
+  // New TTL of the document, 0 removes the expiry
+  pub ttl_secs: Option<u32>,
+  // Keep the current expiry when no ttlSecs is given, otherwise the replace clears it. Read before
+  // the write like UpsertDocumentOptions.preserveExpiry
+  pub preserve_expiry: Option<bool>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct RemoveDocumentOptions {
+  pub key: String,
+  // Only remove this version, fails with a CAS mismatch when the document changed since it was read
+  pub cas: Option<BigInt>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timings: Option<bool>,
+  pub queue_on_failure: Option<bool>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi]
+pub enum MergeStrategy {
+  LastWriteWins,
+  ArrayUnion,
+  NumericSum,
+}
+
+#[napi(object)]
+pub struct MergeDocumentOptions {
+  pub key: String,
+  // Fields to merge into the stored document
+  pub partial: Value,
+  // Defaults to LastWriteWins
+  pub strategy: Option<MergeStrategy>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+impl MergeDocumentOptions {
+  pub fn merge_strategy(&self) -> DocumentMergeStrategy {
+    match self.strategy {
+      None | Some(MergeStrategy::LastWriteWins) => DocumentMergeStrategy::LastWriteWins,
+      Some(MergeStrategy::ArrayUnion) => DocumentMergeStrategy::ArrayUnion,
+      Some(MergeStrategy::NumericSum) => DocumentMergeStrategy::NumericSum,
+    }
+  }
+}
+
+// The counter is read, then written back conditioned on the CAS it was read at. Under contention
+// the call re-reads and tries again, and fails after 64 conflicting writes in a row
+#[napi(object)]
+pub struct CounterOptions {
+  // Document holding the counter, a bare number as getNextCounterKey writes or `{ "value": n }`
+  pub key: String,
+  // Amount added or subtracted, 1 by default and at most 2^32 - 1
+  pub delta: Option<BigInt>,
+  // Value a missing counter starts at, stored as a bare number. Without it a missing counter fails
+  // with not found
+  pub initial: Option<BigInt>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+fn counter_amount(name: &str, amount: &BigInt) -> Result<u64, napi::Error> {
+  let (signed, value, lossless) = amount.get_u64();
+  if signed || !lossless {
+    return Err(napi::Error::from_reason(format!(
+      "Invalid counter {}: expected an unsigned 64-bit bigint",
+      name
+    )));
+  }
+  Ok(value)
+}
+
+impl CounterOptions {
+  pub fn counter_delta(&self) -> Result<u64, napi::Error> {
+    let delta = match &self.delta {
+      Some(delta) => counter_amount("delta", delta)?,
+      None => 1,
+    };
+    if delta > u32::MAX as u64 {
+      return Err(napi::Error::from_reason(format!(
+        "Invalid counter delta {}: at most 2^32 - 1",
+        delta
+      )));
+    }
+    Ok(delta)
+  }
+
+  pub fn counter_initial(&self) -> Result<Option<u64>, napi::Error> {
+    self
+      .initial
+      .as_ref()
+      .map(|initial| counter_amount("initial", initial))
+      .transpose()
+  }
+}
+
+#[napi(object)]
+pub struct ScheduleMutationOptions {
+  pub key: String,
+  // "insert", "replace", "merge" (last-write-wins, creates the document) or "remove"
+  pub operation: String,
+  // The document for insert/replace, the partial document for merge
+  pub value: Option<Value>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+impl ScheduleMutationOptions {
+  pub fn scheduled_mutation(&self) -> Result<ScheduledMutation, napi::Error> {
+    let value = || {
+      self.value.clone().ok_or_else(|| {
+        napi::Error::from_reason(format!(
+          "Scheduled {} of {} needs a value",
+          self.operation, self.key
+        ))
+      })
+    };
+    let operation = match self.operation.as_str() {
+      "insert" => ScheduledOperation::Insert { value: value()? },
+      "replace" => ScheduledOperation::Replace { value: value()? },
+      "merge" => ScheduledOperation::Merge { partial: value()? },
+      "remove" => ScheduledOperation::Remove,
+      other => {
+        return Err(napi::Error::from_reason(format!(
+          "Unknown scheduled operation {}, expected insert, replace, merge or remove",
+          other
+        )))
+      }
+    };
+    Ok(ScheduledMutation {
+      key: self.key.clone(),
+      bucket: self.bucket.clone(),
+      scope: self.scope.clone(),
+      collection: self.collection.clone(),
+      operation,
+    })
+  }
+}
+
+#[napi(object)]
+pub struct CoalesceWriteOptions {
+  pub key: String,
+  // Either the whole document or a JSON merge patch over it
+  pub value: Option<Value>,
+  pub patch: Option<Value>,
+  // Defaults to couchbase.coalescing.windowMs, only the first write of a window sets it
+  pub window_ms: Option<u32>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+impl CoalesceWriteOptions {
+  pub fn pending_change(&self) -> Result<PendingChange, napi::Error> {
+    match (&self.value, &self.patch) {
+      (Some(value), None) => Ok(PendingChange::Value(value.clone())),
+      (None, Some(patch)) => Ok(PendingChange::Patch(patch.clone())),
+      _ => Err(napi::Error::from_reason(
+        "coalesceWrite needs exactly one of value or patch".to_string(),
+      )),
+    }
+  }
+}
+
+#[napi]
+pub enum MutateInOperation {
+  Insert,
+  Upsert,
+  Replace,
+  Remove,
+  ArrayAppend,
+  ArrayPrepend,
+  ArrayAddUnique,
+  Increment,
+  Decrement,
+}
+
+#[napi(object)]
+pub struct MutateInSpec {
+  pub operation: MutateInOperation,
+  pub path: String,
+  // Content to write, the element(s) for array operations or the delta for counters
+  pub value: Option<Value>,
+  // createPath and xattr are not supported, setting either to true fails the call. Missing parent
+  // paths are not created and only document body paths can be written
+  pub create_path: Option<bool>,
+  pub xattr: Option<bool>,
+}
+
+#[napi(object)]
+pub struct LookupInOptions {
+  pub key: String,
+  // Sub-document paths, e.g. "address.city" or "tags[0]", at most 16
+  pub paths: Vec<String>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct MutateInOptions {
+  pub key: String,
+  pub specs: Vec<MutateInSpec>,
+  pub cas: Option<BigInt>,
+  // Upsert/Insert create the document when it does not exist yet, defaults to Replace
+  pub store_semantics: Option<StoreSemantics>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+impl MutateInOptions {
+  pub fn mutations(&self) -> Vec<SubdocMutation> {
+    self
+      .specs
+      .iter()
+      .map(|spec| SubdocMutation {
+        operation: match spec.operation {
+          MutateInOperation::Insert => SubdocOperation::Insert,
+          MutateInOperation::Upsert => SubdocOperation::Upsert,
+          MutateInOperation::Replace => SubdocOperation::Replace,
+          MutateInOperation::Remove => SubdocOperation::Remove,
+          MutateInOperation::ArrayAppend => SubdocOperation::ArrayAppend,
+          MutateInOperation::ArrayPrepend => SubdocOperation::ArrayPrepend,
+          MutateInOperation::ArrayAddUnique => SubdocOperation::ArrayAddUnique,
+          MutateInOperation::Increment => SubdocOperation::Increment,
+          MutateInOperation::Decrement => SubdocOperation::Decrement,
+        },
+        path: spec.path.clone(),
+        value: spec.value.clone(),
+        create_path: spec.create_path.unwrap_or(false),
+        xattr: spec.xattr.unwrap_or(false),
+      })
+      .collect()
+  }
+
+  pub fn store_semantics(&self) -> Option<CbStoreSemantics> {
+    self
+      .store_semantics
+      .as_ref()
+      .map(|store_semantics| match store_semantics {
+        StoreSemantics::Replace => CbStoreSemantics::Replace,
+        StoreSemantics::Upsert => CbStoreSemantics::Upsert,
+        StoreSemantics::Insert => CbStoreSemantics::Insert,
+      })
+  }
+}
+
+#[napi(object)]
+pub struct QueryOptions {
+  pub parameters: Option<Vec<Value>>,
+  // Return { columns, rows } instead of one object per row, much cheaper for wide result sets
+  pub columnar: Option<bool>,
+  // Cache the rows for identical statement and parameters, not applied with consistency tokens
+  pub cache_ttl_ms: Option<u32>,
+  // Run the statement even if cached rows exist, the cache is refreshed with the result
+  pub bypass_cache: Option<bool>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct GetBatchOptions {
+  pub keys: Vec<String>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Keys not found in `bucket` are looked up here, e.g. an archive bucket
+  pub fallback_bucket: Option<String>,
+  // Copy documents served by the fallback bucket back into `bucket`, in the background
+  pub read_repair: Option<bool>,
+  pub with_cas: Option<bool>,
+  // Return the documents fetched so far when the batch times out, the rest get a TIMEOUT error
+  pub partial_on_timeout: Option<bool>,
+  pub transcoder: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+// Keys are found with `META().id LIKE '<prefix>%'` on the query service. That needs a primary index
+// on the keyspace, without one the call fails with an index error. Each page is a query over the
+// prefix's range of that index. A primary index holds every key of the keyspace, which costs index
+// memory and disk and is updated on every write
+#[napi(object)]
+pub struct ListKeysOptions {
+  pub prefix: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Page size, defaults to 100 and is capped at 1000
+  pub limit: Option<u32>,
+  // `nextStartAfter` of the previous page
+  pub start_after: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct ExportDocumentsOptions {
+  // Either the keys to export or a prefix whose keys are all exported. Prefixes are listed like
+  // listKeys and need the keyspace's primary index
+  pub keys: Option<Vec<String>>,
+  pub prefix: Option<String>,
+  // NDJSON file written by the native side, replaced if it exists
+  pub output_path: String,
+  // Documents between progress reports, 1000 by default
+  pub progress_every: Option<u32>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+// Matching keys are listed like listKeys, so the keyspace needs a primary index
+#[napi(object)]
+pub struct DeleteByPrefixOptions {
+  pub prefix: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Only count the matching documents and show a sample of their keys
+  pub dry_run: Option<bool>,
+  pub max_docs: Option<i64>,
+  pub rate_limit_per_sec: Option<u32>,
+  // From a dry run, needed when more documents than couchbase.bulkDelete.confirmThreshold would be removed
+  pub confirmation_token: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+// Loaded entries are only read by gets that set `cached`, see GetDocumentOptions.cached
+#[napi(object)]
+pub struct PreloadCacheOptions {
+  // Either the keys to load or a prefix to list them by, prefixes need a primary index
+  pub keys: Option<Vec<String>>,
+  pub prefix: Option<String>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct CloudEventOptions {
+  pub key: String,
+  // "insert", "upsert", "replace", "remove" or "mutateIn"
+  pub operation: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub cas: Option<BigInt>,
+  // The written document, left out for removals
+  pub value: Option<Value>,
+}
+
+impl CloudEventOptions {
+  pub fn keyspace(&self) -> Keyspace {
+    Keyspace {
+      bucket: self.bucket.clone(),
+      scope: self.scope.clone(),
+      collection: self.collection.clone(),
+    }
+  }
+}
+
+#[napi(object)]
+pub struct GetAndTouchOptions {
+  pub key: String,
+  // New TTL counted from now, 0 removes the expiry
+  pub ttl_secs: u32,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Return `{value, cas}` instead of the bare value
+  pub with_cas: Option<bool>,
+  pub transcoder: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+// The document body is still read and sent back by the server, so a touch costs as much as a get
+#[napi(object)]
+pub struct TouchDocumentOptions {
+  pub key: String,
+  // New TTL counted from now, 0 removes the expiry
+  pub ttl_secs: u32,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct CasSnapshotOptions {
+  pub keys: Vec<String>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct WatchOptions {
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // How often the CAS is checked, 1000 by default
+  pub poll_interval_ms: Option<u32>,
+}
+
+// Keyspace holding the blob documents
+#[napi(object)]
+pub struct BlobOptions {
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct ProcessOnceOptions {
+  pub message_id: String,
+  // How long redeliveries are recognised
+  pub ttl_secs: u32,
+  // Handler result returned to later deliveries of the same message
+  pub result: Value,
+  // Defaults to "processed::"
+  pub key_prefix: Option<String>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+// What startLogger prints to stdout, the log file always receives every line
+#[napi]
+pub enum ConsoleLogging {
+  All,
+  WarnOnly,
+  Off,
+}
+
+#[napi(object)]
+pub struct LoggerOptions {
+  // Defaults to `logger.console` from config, then All
+  pub console: Option<ConsoleLogging>,
+}
+
+impl LoggerOptions {
+  pub fn console_output(&self) -> Option<ConsoleOutput> {
+    self.console.as_ref().map(|console| match console {
+      ConsoleLogging::All => ConsoleOutput::All,
+      ConsoleLogging::WarnOnly => ConsoleOutput::Warn,
+      ConsoleLogging::Off => ConsoleOutput::Off,
+    })
+  }
+}
+
+// Filters of queryLocalLogs, every one that is set must match
+#[napi(object)]
+pub struct LogQueryOptions {
+  // "error", "warn", "info", "debug" or "trace", entries of this level and the more severe ones
+  pub level: Option<String>,
+  // Epoch milliseconds
+  pub since: Option<i64>,
+  // Document key the message mentions
+  pub key: Option<String>,
+  // Operation the message mentions, e.g. "insert", case-insensitive
+  pub operation: Option<String>,
+  // Defaults to 100, newest entries first
+  pub limit: Option<u32>,
+}
+
+impl LogQueryOptions {
+  pub fn log_query(&self) -> Result<LogQuery, String> {
+    let level = match &self.level {
+      Some(level) => Some(level.parse::<Level>().map_err(|_| {
+        format!(
+          "Unknown log level {}, expected error, warn, info, debug or trace",
+          level
+        )
+      })?),
+      None => None,
+    };
+    Ok(LogQuery {
+      level,
+      since_ms: self.since,
+      key: self.key.clone(),
+      operation: self.operation.clone(),
+      limit: self.limit.unwrap_or(0) as usize,
+    })
+  }
+}
+
+// Span of one time series bucket document
+#[napi]
+pub enum TimeSeriesGranularity {
+  Hour,
+  Day,
+}
+
+fn bucket_granularity(granularity: Option<&TimeSeriesGranularity>) -> BucketGranularity {
+  match granularity {
+    None | Some(TimeSeriesGranularity::Hour) => BucketGranularity::Hour,
+    Some(TimeSeriesGranularity::Day) => BucketGranularity::Day,
+  }
+}
+
+#[napi(object)]
+pub struct AppendTimeSeriesOptions {
+  pub series_key: String,
+  // Epoch milliseconds, defaults to now
+  pub timestamp: Option<i64>,
+  pub value: Value,
+  // Defaults to Hour, readers must use the same granularity as writers
+  pub granularity: Option<TimeSeriesGranularity>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+impl AppendTimeSeriesOptions {
+  pub fn bucket_granularity(&self) -> BucketGranularity {
+    bucket_granularity(self.granularity.as_ref())
+  }
+}
+
+#[napi(object)]
+pub struct QueryTimeSeriesOptions {
+  pub series_key: String,
+  // Inclusive range in epoch milliseconds
+  pub from: i64,
+  pub to: i64,
+  pub granularity: Option<TimeSeriesGranularity>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+impl QueryTimeSeriesOptions {
+  pub fn bucket_granularity(&self) -> BucketGranularity {
+    bucket_granularity(self.granularity.as_ref())
+  }
+}
+
+#[napi(object)]
+pub struct AnalyzeExpiryOptions {
+  // Matched on the query service like listKeys, the keyspace needs a primary index
+  pub prefix: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Width of each histogram slot, defaults to one hour
+  pub interval_secs: Option<u32>,
+  // How far ahead slots are reported, defaults to one day
+  pub horizon_secs: Option<u32>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct EraseSubjectOptions {
+  pub subject_id: String,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Documents whose key starts with `<prefix><subjectId>` belong to the subject. They are listed
+  // like listKeys, so prefixes need the keyspace's primary index
+  pub prefixes: Option<Vec<String>>,
+  // Registered named query taking $subjectId and returning keys, or rows with an `id` field
+  pub named_query: Option<String>,
+  // Dotted paths overwritten with "[erased]", matching documents are deleted when omitted
+  pub fields_to_anonymize: Option<Vec<String>>,
+  // Bucket the signed report is stored in
+  pub audit_bucket: Option<String>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct GeoPoint {
+  pub lon: f64,
+  pub lat: f64,
+}
+
+#[napi(object)]
+pub struct SearchNearOptions {
+  // Search index covering the keyspace
+  pub index_name: String,
+  // Geopoint field, defaults to the index's default field
+  pub field: Option<String>,
+  pub lon: f64,
+  pub lat: f64,
+  pub radius_meters: f64,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Defaults to 100
+  pub limit: Option<u32>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+#[napi(object)]
+pub struct SearchWithinPolygonOptions {
+  pub index_name: String,
+  pub field: Option<String>,
+  // Vertices in order, the polygon is closed implicitly
+  pub points: Vec<GeoPoint>,
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  pub limit: Option<u32>,
+  pub timeout_ms: Option<u32>,
+  pub deadline_ms: Option<i64>,
+  pub abort_token: Option<String>,
+  pub context: Option<OperationContext>,
+}
+
+// Converts an epoch milliseconds deadline into a monotonic instant, past deadlines map to now
+fn deadline_instant(deadline_ms: i64) -> Instant {
+  let now_ms = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|now| now.as_millis() as i64)
+    .unwrap_or(0);
+  let remaining_ms = deadline_ms.saturating_sub(now_ms).max(0);
+  Instant::now() + Duration::from_millis(remaining_ms as u64)
+}
+
+pub(crate) fn operation_options(
+  timeout_ms: Option<u32>,
+  deadline_ms: Option<i64>,
+  context: Option<&OperationContext>,
+) -> OperationOptions {
+  OperationOptions {
+    timeout: timeout_ms.map(|ms| Duration::from_millis(ms as u64)),
+    deadline: deadline_ms.map(deadline_instant),
+    context: context.map(|context| CallContext {
+      request_id: context.request_id.clone(),
+      tenant_id: context.tenant_id.clone(),
+      actor: context.actor.clone(),
+    }),
+    ..Default::default()
+  }
+}
+
+// Every options object carries the same keyspace/timeout/abort/context fields, this maps them onto couchbase_db types
+pub trait KeyspaceOptions {
+  fn keyspace(&self) -> Keyspace;
+  fn operation_options(&self) -> OperationOptions;
+  fn abort_token(&self) -> Option<String>;
+}
+
+macro_rules! impl_keyspace_options {
+  ($($options:ty),*) => {
+    $(
+      impl KeyspaceOptions for $options {
+        fn keyspace(&self) -> Keyspace {
+          Keyspace {
+            bucket: self.bucket.clone(),
+            scope: self.scope.clone(),
+            collection: self.collection.clone(),
+          }
+        }
+
+        fn operation_options(&self) -> OperationOptions {
+          operation_options(self.timeout_ms, self.deadline_ms, self.context.as_ref())
+        }
+
+        fn abort_token(&self) -> Option<String> {
+          self.abort_token.clone()
+        }
+      }
+    )*
+  };
+}
+
+impl_keyspace_options!(
+  GetDocumentOptions,
+  AddDocumentOptions,
+  UpsertDocumentOptions,
+  ReplaceDocumentOptions,
+  RemoveDocumentOptions,
+  GetBatchOptions,
+  LookupInOptions,
+  MutateInOptions,
+  MergeDocumentOptions,
+  CounterOptions,
+  CoalesceWriteOptions,
+  ScheduleMutationOptions,
+  ListKeysOptions,
+  DeleteByPrefixOptions,
+  PreloadCacheOptions,
+  ExportDocumentsOptions,
+  GetAndTouchOptions,
+  TouchDocumentOptions,
+  CasSnapshotOptions,
+  BlobOptions,
+  ProcessOnceOptions,
+  AppendTimeSeriesOptions,
+  QueryTimeSeriesOptions,
+  SearchNearOptions,
+  SearchWithinPolygonOptions,
+  EraseSubjectOptions,
+  AnalyzeExpiryOptions
+);
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::options::operation_options;
+use crate::utils::couchbase_db::{default_connection, CouchbaseConnection, Keyspace};
+use crate::utils::sessions::SessionStore;
+
+#[napi(object)]
+pub struct SessionStoreOptions {
+  pub bucket: String,
+  pub scope: Option<String>,
+  pub collection: Option<String>,
+  // Defaults to "session::"
+  pub key_prefix: Option<String>,
+  // Idle timeout, defaults to 30 minutes
+  pub ttl_secs: Option<u32>,
+  pub timeout_ms: Option<u32>,
+}
+
+// Sessions with sliding expiration, each read extends the session by its TTL
+#[napi]
+pub struct CouchbaseSessionStore {
+  inner: SessionStore,
+}
+
+impl CouchbaseSessionStore {
+  pub fn from_connection(
+    connection: Arc<CouchbaseConnection>,
+    options: SessionStoreOptions,
+  ) -> Self {
+    CouchbaseSessionStore {
+      inner: SessionStore::new(
+        connection,
+        Keyspace {
+          bucket: options.bucket,
+          scope: options.scope,
+          collection: options.collection,
+        },
+        options.key_prefix,
+        options
+          .ttl_secs
+          .map(|secs| Duration::from_secs(secs as u64)),
+        operation_options(options.timeout_ms, None, None),
+      ),
+    }
+  }
+}
+
+#[napi]
+impl CouchbaseSessionStore {
+  #[napi(constructor)]
+  pub fn new(options: SessionStoreOptions) -> Self {
+    CouchbaseSessionStore::from_connection(default_connection(), options)
+  }
+
+  #[napi]
+  pub async fn create_session(
+    &self,
+    data: Value,
+    ttl_secs: Option<u32>,
+  ) -> Result<String, napi::Error> {
+    self
+      .inner
+      .create(data, ttl_secs.map(|secs| Duration::from_secs(secs as u64)))
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn get_session(&self, id: String) -> Result<Option<Value>, napi::Error> {
+    self.inner.get(&id).await.map_err(napi::Error::from_reason)
+  }
+
+  #[napi]
+  pub async fn destroy_session(&self, id: String) -> Result<bool, napi::Error> {
+    self
+      .inner
+      .destroy(&id)
+      .await
+      .map_err(napi::Error::from_reason)
+  }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+pub const ABORTED_ERROR: &str = "ABORTED: operation aborted by caller";
+
+lazy_static! {
+    static ref ABORT_SIGNALS: Mutex<HashMap<String, watch::Sender<bool>>> = Mutex::new(HashMap::new());
+}
+
+pub fn register_abort_token() -> String {
+    let id = Uuid::new_v4().to_string();
+    let (sender, _) = watch::channel(false);
+    ABORT_SIGNALS.lock().unwrap().insert(id.clone(), sender);
+    id
+}
+
+pub fn abort(id: &str) -> bool {
+    match ABORT_SIGNALS.lock().unwrap().get(id) {
+        Some(sender) => {
+            sender.send_replace(true);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn is_aborted(id: &str) -> bool {
+    ABORT_SIGNALS.lock().unwrap().get(id).map(|sender| *sender.borrow()).unwrap_or(false)
+}
+
+pub fn release_abort_token(id: &str) {
+    ABORT_SIGNALS.lock().unwrap().remove(id);
+}
+
+async fn wait_aborted(mut receiver: watch::Receiver<bool>) {
+    loop {
+        if *receiver.borrow() {
+            return;
+        }
+        // A released token can never fire, so just let the operation run to completion
+        if receiver.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+// Races the operation (including its retry sleeps) against the caller's abort token.
+// Dropping the losing future cancels any in-flight SDK request from our side.
+pub async fn run_abortable<T, F>(abort_token: Option<String>, future: F) -> Result<T, String>
+where
+    F: Future<Output = Result<T, String>>,
+{
+    let receiver = abort_token
+        .as_deref()
+        .and_then(|id| ABORT_SIGNALS.lock().unwrap().get(id).map(|sender| sender.subscribe()));
+    let receiver = match receiver {
+        Some(receiver) => receiver,
+        None => return future.await,
+    };
+    if *receiver.borrow() {
+        return Err(ABORTED_ERROR.to_string());
+    }
+
+    tokio::select! {
+        result = future => result,
+        _ = wait_aborted(receiver) => {
+            log::warn!("Operation aborted by caller");
+            Err(ABORTED_ERROR.to_string())
+        }
+    }
+}
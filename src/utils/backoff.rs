@@ -0,0 +1,85 @@
+// Retry delays for writes, configured under `couchbase.backoff`.
+// Server busy responses (tmpfail, out of memory, overloaded) back off longer than generic failures.
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::configuration as config;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackoffSettings {
+    // Delay between retries of ordinary failures
+    #[serde(default = "BackoffSettings::default_retry_ms")]
+    retry_ms: u64,
+    // First delay after a busy response, doubled on every further attempt
+    #[serde(default = "BackoffSettings::default_busy_base_ms")]
+    busy_base_ms: u64,
+    // Upper bound for busy delays, including server provided retry-after hints
+    #[serde(default = "BackoffSettings::default_busy_max_ms")]
+    busy_max_ms: u64,
+}
+
+impl BackoffSettings {
+    fn default_retry_ms() -> u64 {
+        1000
+    }
+
+    fn default_busy_base_ms() -> u64 {
+        2000
+    }
+
+    fn default_busy_max_ms() -> u64 {
+        30000
+    }
+}
+
+impl Default for BackoffSettings {
+    fn default() -> Self {
+        BackoffSettings {
+            retry_ms: Self::default_retry_ms(),
+            busy_base_ms: Self::default_busy_base_ms(),
+            busy_max_ms: Self::default_busy_max_ms(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref BACKOFF: BackoffSettings = config::get_res::<BackoffSettings>("couchbase.backoff").unwrap_or_default();
+}
+
+fn normalize(error: &str) -> String {
+    error.to_lowercase().replace([' ', '_', '-'], "")
+}
+
+pub fn is_server_busy(error: &str) -> bool {
+    let error = normalize(error);
+    ["temporaryfailure", "tmpfail", "outofmemory", "busy", "overloaded", "toomanyrequests"]
+        .iter()
+        .any(|marker| error.contains(marker))
+}
+
+// Seconds following a "retry-after" / "retry_after" marker in the error text, as in the HTTP header
+fn retry_after_hint(error: &str) -> Option<Duration> {
+    let error = normalize(error);
+    let start = error.find("retryafter")? + "retryafter".len();
+    let seconds: String = error[start..]
+        .chars()
+        .skip_while(|c| matches!(c, ':' | '=' | '"'))
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    seconds.parse::<u64>().ok().map(Duration::from_secs)
+}
+
+// Delay before retry number `attempt` (0 based) of an operation that failed with `error`
+pub fn retry_delay(error: &str, attempt: u32) -> Duration {
+    if !is_server_busy(error) {
+        return Duration::from_millis(BACKOFF.retry_ms);
+    }
+    let max = Duration::from_millis(BACKOFF.busy_max_ms);
+    let delay = retry_after_hint(error).unwrap_or_else(|| {
+        Duration::from_millis(BACKOFF.busy_base_ms.saturating_mul(1u64 << attempt.min(16)))
+    });
+    delay.min(max)
+}
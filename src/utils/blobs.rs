@@ -0,0 +1,171 @@
+// Content-addressed blobs, e.g. attachments referenced from JSON metadata documents. A blob is stored once under
+// `blob::sha256:<hex>` with its bytes as base64 and a count of the puts that referenced it; putting the same bytes
+// again only bumps the count and releasing the last reference removes the blob
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::json;
+
+use crate::utils::checksum::content_hash;
+use crate::utils::couchbase_db::{
+    is_document_not_found, is_write_conflict, CouchbaseConnection, Keyspace, OperationOptions, SubdocLookup,
+    SubdocMutation, SubdocOperation,
+};
+use crate::utils::transcoder::DEFAULT_TRANSCODER;
+
+const BLOB_KEY_PREFIX: &str = "blob::";
+// Attempts of a put or release racing other puts and releases of the same blob
+const MAX_BLOB_ATTEMPTS: u32 = 16;
+
+pub struct StoredBlob {
+    pub hash: String,
+    pub size: usize,
+    pub refs: u64,
+    // The bytes were already stored, only the reference count changed
+    pub deduplicated: bool,
+}
+
+fn blob_key(hash: &str) -> String {
+    format!("{}{}", BLOB_KEY_PREFIX, hash)
+}
+
+// Blob documents are JSON whatever the bucket's default transcoder is
+fn blob_options(options: &OperationOptions) -> OperationOptions {
+    OperationOptions {
+        transcoder: Some(DEFAULT_TRANSCODER.to_string()),
+        ..options.clone()
+    }
+}
+
+fn refs_change(operation: SubdocOperation) -> SubdocMutation {
+    SubdocMutation {
+        operation,
+        path: "refs".to_string(),
+        value: Some(json!(1)),
+        create_path: false,
+        xattr: false,
+    }
+}
+
+async fn stored_refs(
+    connection: &CouchbaseConnection,
+    key: &str,
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+) -> Result<(u64, u64), String> {
+    let looked_up = connection
+        .lookup_in(key.to_string(), vec![SubdocLookup::get("refs")], keyspace.clone(), options.clone())
+        .await?;
+    let refs = looked_up.values.first().cloned().flatten().and_then(|refs| refs.as_u64()).unwrap_or(0);
+    Ok((refs, looked_up.cas))
+}
+
+pub async fn put_blob(
+    connection: &CouchbaseConnection,
+    bytes: &[u8],
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<StoredBlob, String> {
+    let hash = content_hash(bytes);
+    let key = blob_key(&hash);
+    let options = blob_options(&options);
+    for _ in 0..MAX_BLOB_ATTEMPTS {
+        // Existing blobs only get another reference, the bytes are not sent again
+        let referenced = connection
+            .mutate_in(key.clone(), vec![refs_change(SubdocOperation::Increment)], keyspace.clone(), None, None, options.clone())
+            .await;
+        match referenced {
+            Ok(_) => {
+                let (refs, _) = stored_refs(connection, &key, &keyspace, &options).await?;
+                return Ok(StoredBlob {
+                    hash,
+                    size: bytes.len(),
+                    refs,
+                    deduplicated: true,
+                });
+            }
+            Err(error) if !is_document_not_found(&error) => return Err(error),
+            Err(_) => {}
+        }
+        let blob = json!({
+            "data": BASE64.encode(bytes),
+            "size": bytes.len(),
+            "refs": 1,
+            "createdAt": chrono::Utc::now().timestamp_millis(),
+        });
+        match connection.add_document(key.clone(), blob, keyspace.clone(), options.clone(), Some(0)).await {
+            Ok(_) => {
+                return Ok(StoredBlob {
+                    hash,
+                    size: bytes.len(),
+                    refs: 1,
+                    deduplicated: false,
+                })
+            }
+            // Someone stored the same bytes first, reference theirs
+            Err(error) if is_write_conflict(&error) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Err(format!("Gave up storing blob {} after {} conflicting writes", hash, MAX_BLOB_ATTEMPTS))
+}
+
+// Fails with BLOB_CORRUPT when the stored bytes no longer hash to `hash`
+pub async fn get_blob(
+    connection: &CouchbaseConnection,
+    hash: &str,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<Vec<u8>, String> {
+    let stored = connection
+        .get_document(blob_key(hash), keyspace, blob_options(&options))
+        .await?;
+    let encoded = stored.value["data"]
+        .as_str()
+        .ok_or_else(|| format!("BLOB_CORRUPT: blob {} has no data", hash))?;
+    let bytes = BASE64
+        .decode(encoded)
+        .map_err(|error| format!("BLOB_CORRUPT: blob {} is not valid base64 : {}", hash, error))?;
+    if content_hash(&bytes) != hash {
+        return Err(format!("BLOB_CORRUPT: blob {} does not match its hash", hash));
+    }
+    Ok(bytes)
+}
+
+// Drops one reference and removes the blob with the last one. Returns the references left
+pub async fn release_blob(
+    connection: &CouchbaseConnection,
+    hash: &str,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<u64, String> {
+    let key = blob_key(hash);
+    let options = blob_options(&options);
+    for _ in 0..MAX_BLOB_ATTEMPTS {
+        let (refs, cas) = stored_refs(connection, &key, &keyspace, &options).await?;
+        // Both writes are CAS guarded, so a put landing in between makes this attempt start over
+        let released = if refs > 1 {
+            connection
+                .mutate_in(
+                    key.clone(),
+                    vec![refs_change(SubdocOperation::Decrement)],
+                    keyspace.clone(),
+                    Some(cas),
+                    None,
+                    options.clone(),
+                )
+                .await
+                .map(|_| refs - 1)
+        } else {
+            connection
+                .delete_data(key.clone(), Some(cas), keyspace.clone(), options.clone())
+                .await
+                .map(|_| 0)
+        };
+        match released {
+            Ok(left) => return Ok(left),
+            Err(error) if is_write_conflict(&error) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Err(format!("Gave up releasing blob {} after {} conflicting writes", hash, MAX_BLOB_ATTEMPTS))
+}
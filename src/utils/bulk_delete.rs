@@ -0,0 +1,227 @@
+// Removes every document under a key prefix, in place of hand-written DELETE statements. Keys are paged through
+// list_keys (query service, primary index needed) and removed over KV in batches. Settings under
+// `couchbase.bulkDelete`, e.g.
+//   "bulkDelete": { "confirmThreshold": 1000, "confirmTtlSecs": 300 }
+// Deleting more than `confirmThreshold` documents needs the confirmation token handed out by a dry run of the same
+// prefix, so a mistyped prefix is seen in the dry run's count and sample before anything is removed
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{
+    batch_concurrency, is_document_not_found, like_prefix, uuid, CouchbaseConnection, Keyspace, OperationOptions,
+    QuerySettings,
+};
+use crate::utils::policy::check_policy;
+
+// Keys listed per page, also the most removed per batch
+const DELETE_PAGE_SIZE: u32 = 500;
+// Keys a dry run shows
+const DRY_RUN_SAMPLE: u32 = 20;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkDeleteSettings {
+    #[serde(default = "BulkDeleteSettings::default_confirm_threshold")]
+    confirm_threshold: u64,
+    #[serde(default = "BulkDeleteSettings::default_confirm_ttl_secs")]
+    confirm_ttl_secs: u64,
+}
+
+impl BulkDeleteSettings {
+    fn default_confirm_threshold() -> u64 {
+        1000
+    }
+
+    fn default_confirm_ttl_secs() -> u64 {
+        300
+    }
+}
+
+impl Default for BulkDeleteSettings {
+    fn default() -> Self {
+        BulkDeleteSettings {
+            confirm_threshold: Self::default_confirm_threshold(),
+            confirm_ttl_secs: Self::default_confirm_ttl_secs(),
+        }
+    }
+}
+
+struct Confirmation {
+    // Keyspace and prefix the token was issued for
+    target: String,
+    expires_at: Instant,
+}
+
+lazy_static! {
+    static ref BULK_DELETE: BulkDeleteSettings =
+        config::get_res::<BulkDeleteSettings>("couchbase.bulkDelete").unwrap_or_default();
+    // Token -> what it confirms, each token is used once
+    static ref CONFIRMATIONS: Mutex<HashMap<String, Confirmation>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Default)]
+pub struct DeleteByPrefixRequest {
+    pub prefix: String,
+    pub dry_run: bool,
+    // Stops after this many removals, the rest stays for another run
+    pub max_docs: Option<u64>,
+    pub rate_limit_per_sec: Option<u32>,
+    pub confirmation_token: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct DeleteByPrefixSummary {
+    // Documents under the prefix when the run started
+    pub matched: u64,
+    pub deleted: u64,
+    pub failed: u64,
+    // Dry runs only, the first keys that would be removed
+    pub sample_keys: Vec<String>,
+    // Dry runs over the threshold only, pass it back to delete for real
+    pub confirmation_token: Option<String>,
+}
+
+fn confirmation_target(prefix: &str, keyspace: &Keyspace) -> String {
+    format!("{}|{}", keyspace, prefix)
+}
+
+fn issue_confirmation(target: String) -> String {
+    let token = uuid().to_string();
+    let mut confirmations = CONFIRMATIONS.lock().unwrap();
+    confirmations.retain(|_, confirmation| confirmation.expires_at > Instant::now());
+    confirmations.insert(
+        token.clone(),
+        Confirmation {
+            target,
+            expires_at: Instant::now() + Duration::from_secs(BULK_DELETE.confirm_ttl_secs),
+        },
+    );
+    token
+}
+
+fn take_confirmation(token: Option<&str>, target: &str) -> bool {
+    let token = match token {
+        Some(token) => token,
+        None => return false,
+    };
+    match CONFIRMATIONS.lock().unwrap().remove(token) {
+        Some(confirmation) => confirmation.target == target && confirmation.expires_at > Instant::now(),
+        None => false,
+    }
+}
+
+async fn count_keys(
+    connection: &CouchbaseConnection,
+    prefix: &str,
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+) -> Result<u64, String> {
+    let statement = format!(
+        "SELECT RAW COUNT(*) FROM {} AS d WHERE META(d).id LIKE $1",
+        keyspace.query_path()
+    );
+    let rows = connection
+        .query(statement, vec![json!(like_prefix(prefix))], Vec::new(), QuerySettings::default(), options.clone())
+        .await?;
+    Ok(rows.first().and_then(|count| count.as_u64()).unwrap_or(0))
+}
+
+pub async fn delete_by_prefix(
+    connection: &CouchbaseConnection,
+    request: DeleteByPrefixRequest,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<DeleteByPrefixSummary, String> {
+    check_policy("deleteByPrefix")?;
+    if request.prefix.is_empty() {
+        return Err("deleteByPrefix needs a non-empty prefix".to_string());
+    }
+    let target = confirmation_target(&request.prefix, &keyspace);
+    let mut summary = DeleteByPrefixSummary {
+        matched: count_keys(connection, &request.prefix, &keyspace, &options).await?,
+        ..Default::default()
+    };
+    let to_delete = request.max_docs.map_or(summary.matched, |max_docs| max_docs.min(summary.matched));
+
+    if request.dry_run {
+        summary.sample_keys = connection
+            .list_keys(request.prefix.clone(), keyspace.clone(), DRY_RUN_SAMPLE, None, options.clone())
+            .await?
+            .keys;
+        if to_delete > BULK_DELETE.confirm_threshold {
+            summary.confirmation_token = Some(issue_confirmation(target));
+        }
+        log::info!(
+            "{}Dry run of deleteByPrefix {} in {}: {} documents match",
+            options.log_prefix(),
+            request.prefix,
+            keyspace,
+            summary.matched
+        );
+        return Ok(summary);
+    }
+    if to_delete > BULK_DELETE.confirm_threshold && !take_confirmation(request.confirmation_token.as_deref(), &target) {
+        return Err(format!(
+            "CONFIRMATION_REQUIRED: {} documents under {} in {} is over the threshold of {}, run with dryRun first and pass its confirmationToken",
+            to_delete, request.prefix, keyspace, BULK_DELETE.confirm_threshold
+        ));
+    }
+
+    let batch_size = match request.rate_limit_per_sec {
+        Some(rate) => DELETE_PAGE_SIZE.min(rate.max(1)),
+        None => DELETE_PAGE_SIZE,
+    };
+    let mut start_after = None;
+    while summary.deleted < to_delete {
+        let limit = (batch_size as u64).min(to_delete - summary.deleted) as u32;
+        let page = connection
+            .list_keys(request.prefix.clone(), keyspace.clone(), limit, start_after, options.clone())
+            .await?;
+        if page.keys.is_empty() {
+            break;
+        }
+        start_after = page.keys.last().cloned();
+        let started = Instant::now();
+        let batch_len = page.keys.len();
+        let mut removals = futures::stream::iter(page.keys.into_iter().map(|key| {
+            let keyspace = keyspace.clone();
+            let options = options.clone();
+            async move { connection.delete_data(key, None, keyspace, options).await }
+        }))
+        .buffer_unordered(batch_concurrency());
+        while let Some(removed) = removals.next().await {
+            match removed {
+                Ok(_) => summary.deleted += 1,
+                // Expired or removed by someone else in the meantime
+                Err(error) if is_document_not_found(&error) => {}
+                Err(_) => summary.failed += 1,
+            }
+        }
+        if let Some(rate) = request.rate_limit_per_sec {
+            let paced = Duration::from_secs_f64(batch_len as f64 / rate.max(1) as f64);
+            if let Some(remaining) = paced.checked_sub(started.elapsed()) {
+                tokio::time::sleep(remaining).await;
+            }
+        }
+        if page.next_start_after.is_none() {
+            break;
+        }
+    }
+    log::info!(
+        "{}deleteByPrefix {} in {}: {} matched, {} deleted, {} failed",
+        options.log_prefix(),
+        request.prefix,
+        keyspace,
+        summary.matched,
+        summary.deleted,
+        summary.failed
+    );
+    Ok(summary)
+}
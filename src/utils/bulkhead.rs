@@ -0,0 +1,91 @@
+// Per-bucket limits on concurrent KV operations, so a slow or failing bucket fills its own slots instead of every
+// slot the process has and healthy buckets keep being served. Configured under `couchbase.bulkheads`, e.g.
+//   "bulkheads": { "maxConcurrent": 200, "buckets": { "reports": 20 }, "maxWaitMs": 1000 }
+// `maxConcurrent` applies to every bucket without its own entry, with neither set the bucket is not limited.
+// An operation waits at most `maxWaitMs` (or what is left of its timeout) for a slot, then fails with BULKHEAD_FULL
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{Keyspace, OperationOptions};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkheadSettings {
+    max_concurrent: Option<usize>,
+    #[serde(default)]
+    buckets: HashMap<String, usize>,
+    #[serde(default = "BulkheadSettings::default_max_wait_ms")]
+    max_wait_ms: u64,
+}
+
+impl BulkheadSettings {
+    fn default_max_wait_ms() -> u64 {
+        1000
+    }
+
+    fn limit(&self, bucket: &str) -> Option<usize> {
+        self.buckets.get(bucket).copied().or(self.max_concurrent)
+    }
+}
+
+lazy_static! {
+    static ref BULKHEADS: BulkheadSettings =
+        config::get_res::<BulkheadSettings>("couchbase.bulkheads").unwrap_or_default();
+    // Bucket -> its slots, created on first use
+    static ref BUCKET_SLOTS: Mutex<HashMap<String, Arc<Semaphore>>> = Mutex::new(HashMap::new());
+}
+
+// Holds a slot of the bucket until dropped
+pub struct BulkheadPermit {
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+fn bucket_slots(bucket: &str, limit: usize) -> Arc<Semaphore> {
+    BUCKET_SLOTS
+        .lock()
+        .unwrap()
+        .entry(bucket.to_string())
+        .or_insert_with(|| Arc::new(Semaphore::new(limit.max(1))))
+        .clone()
+}
+
+pub async fn enter_bulkhead(keyspace: &Keyspace, options: &OperationOptions) -> Result<BulkheadPermit, String> {
+    let limit = match BULKHEADS.limit(&keyspace.bucket) {
+        Some(limit) => limit,
+        None => return Ok(BulkheadPermit { _slot: None }),
+    };
+    let slots = bucket_slots(&keyspace.bucket, limit);
+    if let Ok(permit) = slots.clone().try_acquire_owned() {
+        return Ok(BulkheadPermit { _slot: Some(permit) });
+    }
+    let mut wait = Duration::from_millis(BULKHEADS.max_wait_ms);
+    if let Some(timeout) = options.effective_timeout()? {
+        wait = wait.min(timeout);
+    }
+    match tokio::time::timeout(wait, slots.acquire_owned()).await {
+        Ok(Ok(permit)) => Ok(BulkheadPermit { _slot: Some(permit) }),
+        // The semaphore is never closed
+        Ok(Err(error)) => Err(error.to_string()),
+        Err(_) => {
+            log::warn!(
+                "{}Bucket {} has all {} operation slots in use, rejected after {:?}",
+                options.log_prefix(),
+                keyspace.bucket,
+                limit,
+                wait
+            );
+            Err(format!(
+                "{}BULKHEAD_FULL: bucket {} already has {} operations in flight",
+                options.log_prefix(),
+                keyspace.bucket,
+                limit
+            ))
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Content hashes of documents and blobs, and HMAC signatures for reports handed to third parties
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+// serde_json maps are sorted, so equal documents always serialize (and hash) the same way
+pub fn document_checksum(value: &Value) -> String {
+    let bytes = serde_json::to_vec(value).unwrap_or_default();
+    format!("sha256:{}", to_hex(&Sha256::digest(&bytes)))
+}
+
+// "sha256:<hex>" of raw bytes, the key of content-addressed blobs
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("sha256:{}", to_hex(&Sha256::digest(bytes)))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// "hmac-sha256:<hex>" of `payload` under `key`
+pub fn sign(key: &[u8], payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(payload);
+    format!("hmac-sha256:{}", to_hex(&mac.finalize().into_bytes()))
+}
+
+// Compares in constant time so a receiver checking signatures does not leak how much of a forgery matched
+pub fn verify_signature(key: &[u8], payload: &[u8], signature: &str) -> bool {
+    let expected = sign(key, payload);
+    expected.len() == signature.len()
+        && expected.bytes().zip(signature.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn equal_documents_hash_the_same() {
+        let first = json!({ "a": 1, "b": { "c": [1, 2] } });
+        let second = json!({ "b": { "c": [1, 2] }, "a": 1 });
+        assert_eq!(document_checksum(&first), document_checksum(&second));
+        assert_ne!(document_checksum(&first), document_checksum(&json!({ "a": 2, "b": { "c": [1, 2] } })));
+    }
+
+    #[test]
+    fn content_hash_is_sha256() {
+        assert_eq!(
+            content_hash(b""),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn sign_is_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "hmac-sha256:5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn signatures_verify_only_for_their_key_and_payload() {
+        let signature = sign(b"key", b"payload");
+        assert!(verify_signature(b"key", b"payload", &signature));
+        assert!(!verify_signature(b"other", b"payload", &signature));
+        assert!(!verify_signature(b"key", b"payload!", &signature));
+        assert!(!verify_signature(b"key", b"payload", &signature[..signature.len() - 1]));
+    }
+}
@@ -0,0 +1,209 @@
+// Transparent chunking of documents above the write size limit, for the buckets listed under `couchbase.chunking`, e.g.
+//   "chunking": { "buckets": ["reports"], "chunkBytes": 8388608 }
+// The document key then holds a small manifest and the serialized JSON is spread over `{key}::chunk::{gen}::{n}`
+// string documents. Every write puts its chunks under a new generation, switches the manifest to it with a CAS and
+// only then removes the previous generation, so a failed rewrite leaves the old version whole. A read racing the
+// removal finds chunks missing and fails with CHUNKS_INCOMPLETE, the manifest checksum catches anything else
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::configuration as config;
+use crate::utils::checksum::document_checksum;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChunkingSettings {
+    #[serde(default)]
+    buckets: Vec<String>,
+    // Serialized bytes per chunk, well below the document limit since escaping grows each chunk
+    #[serde(default = "ChunkingSettings::default_chunk_bytes")]
+    chunk_bytes: usize,
+}
+
+impl ChunkingSettings {
+    fn default_chunk_bytes() -> usize {
+        8 * 1024 * 1024
+    }
+}
+
+impl Default for ChunkingSettings {
+    fn default() -> Self {
+        ChunkingSettings {
+            buckets: Vec::new(),
+            chunk_bytes: Self::default_chunk_bytes(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref CHUNKING: ChunkingSettings = config::get_res::<ChunkingSettings>("couchbase.chunking").unwrap_or_default();
+}
+
+pub fn chunking_enabled(bucket: &str) -> bool {
+    CHUNKING.buckets.iter().any(|name| name == bucket)
+}
+
+pub fn chunk_key(key: &str, generation: &str, index: usize) -> String {
+    format!("{}::chunk::{}::{}", key, generation, index)
+}
+
+#[derive(Debug)]
+pub struct ChunkManifest {
+    // Set per write, names the chunks this manifest points at
+    pub generation: String,
+    pub count: usize,
+    pub bytes: usize,
+    pub checksum: String,
+}
+
+impl ChunkManifest {
+    pub fn to_value(&self) -> Value {
+        json!({
+            "_chunks": {
+                "generation": self.generation,
+                "count": self.count,
+                "bytes": self.bytes,
+                "checksum": self.checksum,
+            }
+        })
+    }
+
+    // Only a document made of the `_chunks` field alone is a manifest
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let object = value.as_object()?;
+        if object.len() != 1 {
+            return None;
+        }
+        let manifest = object.get("_chunks")?;
+        Some(ChunkManifest {
+            generation: manifest.get("generation")?.as_str()?.to_string(),
+            count: manifest.get("count")?.as_u64()? as usize,
+            bytes: manifest.get("bytes")?.as_u64()? as usize,
+            checksum: manifest.get("checksum")?.as_str()?.to_string(),
+        })
+    }
+}
+
+// Splits the serialized document on character boundaries into the chunks of `generation`
+pub fn split_document(value: &Value, generation: String) -> (ChunkManifest, Vec<String>) {
+    split_into(value, generation, CHUNKING.chunk_bytes)
+}
+
+fn split_into(value: &Value, generation: String, chunk_bytes: usize) -> (ChunkManifest, Vec<String>) {
+    let text = value.to_string();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = (start + chunk_bytes.max(4)).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        chunks.push(text[start..end].to_string());
+        start = end;
+    }
+    let manifest = ChunkManifest {
+        generation,
+        count: chunks.len(),
+        bytes: text.len(),
+        checksum: document_checksum(value),
+    };
+    (manifest, chunks)
+}
+
+// `chunks` in order, None for the ones that could not be read
+pub fn join_chunks(key: &str, manifest: &ChunkManifest, chunks: Vec<Option<String>>) -> Result<Value, String> {
+    let mut text = String::with_capacity(manifest.bytes);
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        match chunk {
+            Some(chunk) => text.push_str(&chunk),
+            None => return Err(format!("CHUNKS_INCOMPLETE: chunk {} of {} is missing", index, key)),
+        }
+    }
+    let value: Value = serde_json::from_str(&text)
+        .map_err(|_| format!("CHUNKS_INCOMPLETE: chunks of {} do not form a document, it may be being rewritten", key))?;
+    if document_checksum(&value) != manifest.checksum {
+        return Err(format!(
+            "CHUNKS_INCOMPLETE: chunks of {} do not match the manifest, it may be being rewritten",
+            key
+        ));
+    }
+    Ok(value)
+}
+
+// Manifest stored under a key of a chunking bucket, None for ordinary documents
+pub fn chunk_manifest(bucket: &str, value: &Value) -> Option<ChunkManifest> {
+    if !chunking_enabled(bucket) {
+        return None;
+    }
+    ChunkManifest::from_value(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> Value {
+        json!({ "title": "größe ✓", "lines": (0..20).map(|line| format!("line {}", line)).collect::<Vec<_>>() })
+    }
+
+    #[test]
+    fn chunks_join_back_into_the_document() {
+        let (manifest, chunks) = split_into(&document(), "g1".to_string(), 7);
+        assert!(chunks.len() > 1);
+        assert_eq!(manifest.count, chunks.len());
+        assert_eq!(manifest.bytes, document().to_string().len());
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 7));
+        let joined = join_chunks("doc", &manifest, chunks.into_iter().map(Some).collect()).unwrap();
+        assert_eq!(joined, document());
+    }
+
+    #[test]
+    fn chunks_split_on_character_boundaries() {
+        // Four bytes is less than some of the characters need with their neighbours
+        let (_, chunks) = split_into(&json!("ééééé✓✓"), "g1".to_string(), 4);
+        assert_eq!(chunks.concat(), json!("ééééé✓✓").to_string());
+    }
+
+    #[test]
+    fn missing_chunk_is_incomplete() {
+        let (manifest, chunks) = split_into(&document(), "g1".to_string(), 16);
+        let mut chunks: Vec<Option<String>> = chunks.into_iter().map(Some).collect();
+        chunks[1] = None;
+        let error = join_chunks("doc", &manifest, chunks).unwrap_err();
+        assert!(error.starts_with("CHUNKS_INCOMPLETE: chunk 1 of doc"));
+    }
+
+    #[test]
+    fn chunks_of_another_version_are_incomplete() {
+        let (manifest, _) = split_into(&json!({ "v": 1 }), "g1".to_string(), 1024);
+        let (_, other) = split_into(&json!({ "v": 2 }), "g2".to_string(), 1024);
+        let error = join_chunks("doc", &manifest, other.into_iter().map(Some).collect()).unwrap_err();
+        assert!(error.starts_with("CHUNKS_INCOMPLETE"));
+    }
+
+    #[test]
+    fn manifest_round_trips() {
+        let (manifest, _) = split_into(&document(), "g1".to_string(), 16);
+        let read = ChunkManifest::from_value(&manifest.to_value()).unwrap();
+        assert_eq!(read.generation, "g1");
+        assert_eq!(read.count, manifest.count);
+        assert_eq!(read.bytes, manifest.bytes);
+        assert_eq!(read.checksum, manifest.checksum);
+    }
+
+    #[test]
+    fn only_a_bare_manifest_is_a_manifest() {
+        let (manifest, _) = split_into(&document(), "g1".to_string(), 16);
+        let mut with_field = manifest.to_value();
+        with_field["other"] = json!(1);
+        assert!(ChunkManifest::from_value(&with_field).is_none());
+        assert!(ChunkManifest::from_value(&json!({ "_chunks": { "count": 1 } })).is_none());
+        assert!(ChunkManifest::from_value(&json!("_chunks")).is_none());
+    }
+
+    #[test]
+    fn chunk_keys_carry_the_generation() {
+        assert_eq!(chunk_key("report::1", "g1", 3), "report::1::chunk::g1::3");
+    }
+}
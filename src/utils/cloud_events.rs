@@ -0,0 +1,101 @@
+// CloudEvents 1.0 envelopes for document mutations, so every team publishing change events to Kafka sends the same
+// shape. Source and type are configured under `couchbase.cloudEvents`, with per bucket overrides, e.g.
+//   "cloudEvents": { "source": "/datalayer", "typePrefix": "com.inventyv.document",
+//                    "buckets": { "orders": { "source": "/orders", "typePrefix": "com.inventyv.order" } } }
+// The type is the prefix followed by the operation, e.g. "com.inventyv.order.replace". Messages are meant for
+// structured mode: the envelope is the Kafka value and the document key the Kafka key, which keeps the events of
+// one document in order on one partition
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{uuid, Keyspace};
+use crate::utils::journal::JournalOp;
+
+const DEFAULT_TYPE_PREFIX: &str = "com.couchbase.document";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EnvelopeSettings {
+    source: Option<String>,
+    type_prefix: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CloudEventSettings {
+    #[serde(flatten)]
+    defaults: EnvelopeSettings,
+    #[serde(default)]
+    buckets: HashMap<String, EnvelopeSettings>,
+}
+
+lazy_static! {
+    static ref CLOUD_EVENTS: CloudEventSettings =
+        config::get_res::<CloudEventSettings>("couchbase.cloudEvents").unwrap_or_default();
+}
+
+pub struct CloudEventMessage {
+    // Kafka message key
+    pub key: String,
+    // The CloudEvents envelope
+    pub value: Value,
+}
+
+fn operation_name(op: JournalOp) -> &'static str {
+    match op {
+        JournalOp::Insert => "insert",
+        JournalOp::Upsert => "upsert",
+        JournalOp::Replace => "replace",
+        JournalOp::Remove => "remove",
+        JournalOp::MutateIn => "mutateIn",
+    }
+}
+
+// Bucket settings first, then the top level ones, then a source naming the keyspace
+fn envelope(keyspace: &Keyspace) -> (String, String) {
+    let bucket = CLOUD_EVENTS.buckets.get(&keyspace.bucket).cloned().unwrap_or_default();
+    let source = bucket
+        .source
+        .or_else(|| CLOUD_EVENTS.defaults.source.clone())
+        .unwrap_or_else(|| format!("/couchbase/{}", keyspace));
+    let type_prefix = bucket
+        .type_prefix
+        .or_else(|| CLOUD_EVENTS.defaults.type_prefix.clone())
+        .unwrap_or_else(|| DEFAULT_TYPE_PREFIX.to_string());
+    (source, type_prefix)
+}
+
+// `document` is the written document, None for removals. The CAS goes out as a string extension attribute since
+// CloudEvents integers stop at 32 bits
+pub fn mutation_event(
+    op: JournalOp,
+    key: &str,
+    keyspace: &Keyspace,
+    cas: Option<u64>,
+    document: Option<&Value>,
+) -> CloudEventMessage {
+    let (source, type_prefix) = envelope(keyspace);
+    let mut event = json!({
+        "specversion": "1.0",
+        "id": uuid().to_string(),
+        "source": source,
+        "type": format!("{}.{}", type_prefix, operation_name(op)),
+        "subject": key,
+        "time": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+    });
+    if let Some(cas) = cas {
+        event["cas"] = json!(cas.to_string());
+    }
+    if let Some(document) = document {
+        event["datacontenttype"] = json!("application/json");
+        event["data"] = document.clone();
+    }
+    CloudEventMessage {
+        key: key.to_string(),
+        value: event,
+    }
+}
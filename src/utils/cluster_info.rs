@@ -0,0 +1,104 @@
+// Server version and services as reported by the query service, used to refuse features an older cluster lacks
+// with a NOT_SUPPORTED error instead of whatever the SDK makes of the unknown request
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClusterFeature {
+    Collections,
+    RangeScan,
+    Transactions,
+    Search,
+}
+
+impl ClusterFeature {
+    fn name(&self) -> &'static str {
+        match self {
+            ClusterFeature::Collections => "collections",
+            ClusterFeature::RangeScan => "range scan",
+            ClusterFeature::Transactions => "transactions",
+            ClusterFeature::Search => "search",
+        }
+    }
+
+    // First server release with the feature
+    fn minimum_version(&self) -> (u32, u32, u32) {
+        match self {
+            ClusterFeature::Collections => (7, 0, 0),
+            ClusterFeature::RangeScan => (7, 5, 0),
+            ClusterFeature::Transactions => (6, 6, 0),
+            ClusterFeature::Search => (5, 0, 0),
+        }
+    }
+
+    // Service that has to run somewhere in the cluster
+    fn service(&self) -> Option<&'static str> {
+        match self {
+            ClusterFeature::Search => Some("fts"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClusterInfo {
+    // As reported, e.g. "7.2.4-7070-enterprise"
+    pub version: String,
+    // Union of the services of every node, e.g. kv, n1ql, index, fts
+    pub services: Vec<String>,
+    pub nodes: usize,
+}
+
+// "7.2.4-7070-enterprise" -> (7, 2, 4)
+pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let release = version.split('-').next()?;
+    let mut parts = release.split('.').map(|part| part.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+impl ClusterInfo {
+    // `node_services` holds one array of service names per node
+    pub fn from_rows(version: String, node_services: &[Value]) -> Self {
+        let mut services: Vec<String> = node_services
+            .iter()
+            .filter_map(Value::as_array)
+            .flatten()
+            .filter_map(|service| service.as_str().map(str::to_string))
+            .collect();
+        services.sort();
+        services.dedup();
+        ClusterInfo {
+            version,
+            services,
+            nodes: node_services.len(),
+        }
+    }
+
+    pub fn supports(&self, feature: ClusterFeature) -> bool {
+        // An unparseable version is assumed recent rather than blocking everything
+        let recent_enough = parse_version(&self.version).is_none_or(|version| version >= feature.minimum_version());
+        let has_service = feature
+            .service()
+            .is_none_or(|service| self.services.iter().any(|name| name == service));
+        recent_enough && has_service
+    }
+
+    pub fn require(&self, feature: ClusterFeature) -> Result<(), String> {
+        if self.supports(feature) {
+            return Ok(());
+        }
+        let (major, minor, patch) = feature.minimum_version();
+        let reason = match feature.service() {
+            Some(service) if !self.services.iter().any(|name| name == service) => {
+                format!("no node runs the {} service", service)
+            }
+            _ => format!(
+                "it needs Couchbase Server {}.{}.{} or later, the cluster runs {}",
+                major, minor, patch, self.version
+            ),
+        };
+        Err(format!("NOT_SUPPORTED: {} is not available, {}", feature.name(), reason))
+    }
+}
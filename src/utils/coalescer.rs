@@ -0,0 +1,203 @@
+// Opt-in write coalescing for chatty writers such as presence updates: coalesced writes to the same key within
+// a window are folded into a single Couchbase write, every caller then gets the outcome of that write.
+// A full value replaces whatever is pending, a JSON merge patch is folded into it. The window defaults to
+// `couchbase.coalescing.windowMs`, e.g.
+//   "coalescing": { "windowMs": 50 }
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+use tokio::sync::oneshot;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{
+    is_document_not_found, is_write_conflict, CouchbaseConnection, Keyspace, MutationOutcome, OperationOptions,
+};
+use crate::utils::merge::{apply_merge_patch, compose_merge_patches};
+
+// Read-modify-write attempts of one flush under contention from non-coalesced writers
+const MAX_FLUSH_ATTEMPTS: u32 = 16;
+
+#[derive(Clone, Debug)]
+pub enum PendingChange {
+    // The whole document
+    Value(Value),
+    // RFC 7386 merge patch over the stored document
+    Patch(Value),
+}
+
+impl PendingChange {
+    fn then(self, next: PendingChange) -> PendingChange {
+        match (self, next) {
+            (_, PendingChange::Value(value)) => PendingChange::Value(value),
+            (PendingChange::Value(value), PendingChange::Patch(patch)) => {
+                PendingChange::Value(apply_merge_patch(value, patch))
+            }
+            (PendingChange::Patch(first), PendingChange::Patch(second)) => {
+                PendingChange::Patch(compose_merge_patches(first, second))
+            }
+        }
+    }
+}
+
+type Waiter = oneshot::Sender<Result<MutationOutcome, String>>;
+
+// While an entry exists its flush task is alive and picks up whatever is pending after each window
+struct PendingWrite {
+    change: Option<PendingChange>,
+    // Options of the earliest caller of the pending change
+    options: OperationOptions,
+    waiters: Vec<Waiter>,
+}
+
+lazy_static! {
+    static ref COALESCING_WINDOW: Duration =
+        Duration::from_millis(config::get_res::<u64>("couchbase.coalescing.windowMs").unwrap_or(50));
+    // (keyspace, key) -> pending write
+    static ref PENDING_WRITES: Mutex<HashMap<(String, String), PendingWrite>> = Mutex::new(HashMap::new());
+}
+
+pub async fn coalesce_write(
+    connection: Arc<CouchbaseConnection>,
+    key: String,
+    change: PendingChange,
+    keyspace: Keyspace,
+    options: OperationOptions,
+    window: Option<Duration>,
+) -> Result<MutationOutcome, String> {
+    let (sender, receiver) = oneshot::channel();
+    let id = (keyspace.to_string(), key.clone());
+    let start_flush = {
+        let mut pending_writes = PENDING_WRITES.lock().unwrap();
+        match pending_writes.get_mut(&id) {
+            Some(pending) => {
+                pending.change = Some(match pending.change.take() {
+                    Some(pending_change) => pending_change.then(change),
+                    None => {
+                        pending.options = options;
+                        change
+                    }
+                });
+                pending.waiters.push(sender);
+                false
+            }
+            None => {
+                pending_writes.insert(
+                    id.clone(),
+                    PendingWrite {
+                        change: Some(change),
+                        options,
+                        waiters: vec![sender],
+                    },
+                );
+                true
+            }
+        }
+    };
+    if start_flush {
+        let window = window.unwrap_or(*COALESCING_WINDOW);
+        tokio::spawn(flush_pending(connection, id, key, keyspace, window));
+    }
+    receiver
+        .await
+        .map_err(|_| "Coalesced write was dropped before it was flushed".to_string())?
+}
+
+async fn flush_pending(
+    connection: Arc<CouchbaseConnection>,
+    id: (String, String),
+    key: String,
+    keyspace: Keyspace,
+    window: Duration,
+) {
+    loop {
+        tokio::time::sleep(window).await;
+        let (change, options, waiters) = {
+            let mut pending_writes = PENDING_WRITES.lock().unwrap();
+            let pending = match pending_writes.get_mut(&id) {
+                Some(pending) => pending,
+                None => return,
+            };
+            match pending.change.take() {
+                Some(change) => (change, pending.options.clone(), std::mem::take(&mut pending.waiters)),
+                None => {
+                    pending_writes.remove(&id);
+                    return;
+                }
+            }
+        };
+        if waiters.len() > 1 {
+            log::debug!(
+                "{}Coalesced {} writes to {} in {} into one",
+                options.log_prefix(),
+                waiters.len(),
+                key,
+                keyspace
+            );
+        }
+        let written = write_change(&connection, &key, change, &keyspace, &options).await;
+        for waiter in waiters {
+            let _ = waiter.send(written.clone());
+        }
+    }
+}
+
+async fn write_change(
+    connection: &CouchbaseConnection,
+    key: &str,
+    change: PendingChange,
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+) -> Result<MutationOutcome, String> {
+    for _ in 0..MAX_FLUSH_ATTEMPTS {
+        let written = match &change {
+            PendingChange::Value(value) => {
+                let replaced = connection
+                    .replace_document(key.to_string(), value.clone(), None, keyspace.clone(), options.clone(), Some(0))
+                    .await;
+                match replaced {
+                    Err(error) if is_document_not_found(&error) => {
+                        connection
+                            .add_document(key.to_string(), value.clone(), keyspace.clone(), options.clone(), Some(0))
+                            .await
+                    }
+                    replaced => replaced,
+                }
+            }
+            PendingChange::Patch(patch) => {
+                match connection.get_document(key.to_string(), keyspace.clone(), options.clone()).await {
+                    Ok(stored) => {
+                        let patched = apply_merge_patch(stored.value, patch.clone());
+                        connection
+                            .replace_document(
+                                key.to_string(),
+                                patched,
+                                Some(stored.cas),
+                                keyspace.clone(),
+                                options.clone(),
+                                Some(0),
+                            )
+                            .await
+                    }
+                    Err(error) if is_document_not_found(&error) => {
+                        let created = apply_merge_patch(Value::Null, patch.clone());
+                        connection
+                            .add_document(key.to_string(), created, keyspace.clone(), options.clone(), Some(0))
+                            .await
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+        };
+        match written {
+            Ok(outcome) => return Ok(outcome),
+            Err(error) if is_write_conflict(&error) => {
+                log::warn!("{}Concurrent write on {} while flushing coalesced writes... retrying", options.log_prefix(), key);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Err(format!("Gave up flushing coalesced writes to {} after {} conflicting writes", key, MAX_FLUSH_ATTEMPTS))
+}
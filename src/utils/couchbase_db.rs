@@ -1,7 +1,20 @@
-use couchbase::{Cluster, Collection, GetOptions, InsertOptions, RemoveOptions, ReplaceOptions, UpsertOptions};
+use couchbase::{
+    Cluster, Collection, ExistsOptions, GetAndTouchOptions, GetOptions, InsertOptions, LookupInOptions, LookupInSpec,
+    MutateInOptions, MutateInResult, MutateInSpec, MutationResult, MutationToken, PingOptions, QueryOptions,
+    RemoveOptions, ReplaceOptions, StoreSemantics, UpsertOptions,
+};
+use futures::StreamExt;
 use lazy_static::lazy_static;
 use serde_json::{json, Value};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::{
     sync::RwLock,
     time::{self},
@@ -9,6 +22,30 @@ use tokio::{
 use uuid::Uuid;
 
 use crate::configuration as config;
+use crate::utils::backoff::retry_delay;
+use crate::utils::bulkhead::enter_bulkhead;
+use crate::utils::chunking::{
+    chunk_key, chunk_manifest, chunking_enabled, join_chunks, split_document, ChunkManifest,
+};
+use crate::utils::cluster_info::{ClusterFeature, ClusterInfo};
+use crate::utils::document_cache::{cached_document, invalidate_on_write, store_document};
+use crate::utils::expiry::{expiry_histogram, ExpiryHistogram};
+use crate::utils::fault_injection::{inject_fault, FaultOperation};
+use crate::utils::hot_keys::record_access;
+use crate::utils::journal::{journal_enabled, mutation_specs, record_mutation, JournalOp};
+use crate::utils::key_rules::check_key;
+use crate::utils::latency_watch::needs_rebuild;
+use crate::utils::merge::{merge_values, MergeStrategy};
+use crate::utils::payload::{check_read_size, check_write_size, exceeds_write_limit};
+use crate::utils::policy::check_policy;
+use crate::utils::query_cache::{cached_rows, query_cache_key, store_rows};
+use crate::utils::read_only::check_writable;
+use crate::utils::retry_queue::{queue_failed_write, QueuedOperation};
+use crate::utils::retry_stats::{record_retry, RetryOutcome};
+use crate::utils::time_series::{bucket_key, points_in_range, TimeSeriesGranularity};
+use crate::utils::timings::OperationTimings;
+use crate::utils::transcoder::{transcoder_for, Transcoder, DEFAULT_TRANSCODER};
+use crate::utils::validation::{validate_subdoc_write, validate_write};
 #[derive(serde::Serialize)]
 pub struct Message<T> {
     status: u32,
@@ -22,352 +59,2626 @@ pub struct CouchbaseConnParams {
     pub password: String,
 }
 
+impl CouchbaseConnParams {
+    // Env var names can be remapped under `couchbase.sources`, see configuration::resolve_setting
+    pub fn from_env() -> Self {
+        // let cluster = Cluster::connect(config::get::<String>("couchbase.connectionurl"), config::get::<String>("couchbase.username"), config::get::<String>("couchbase.password"));
+        let setting = |name: &str, default_env: &str| {
+            config::resolve_setting(&format!("couchbase.sources.{}", name), default_env).unwrap_or_else(|error| panic!("{}", error))
+        };
+        CouchbaseConnParams {
+            connection_url: setting("connectionUrl", "COUCHBASE_CONNECTION_URL"),
+            username: setting("username", "COUCHBASE_USERNAME"),
+            password: setting("password", "COUCHBASE_PASSWORD"),
+        }
+    }
+}
+
 pub fn uuid() -> Uuid {
     Uuid::new_v4()
 }
 
+// mergeDocument re-reads and retries this many times under contention
+const MAX_MERGE_ATTEMPTS: u32 = 16;
+// A chunked upsert re-reads the manifest it replaces this many times under contention
+const MAX_SWITCH_ATTEMPTS: u32 = 16;
+// Counters are hot keys by nature, every caller racing on one needs a turn
+const MAX_COUNTER_ATTEMPTS: u32 = 64;
+// Path of the value inside a counter document
+const COUNTER_PATH: &str = "value";
+// getDocumentWithMeta re-reads this many times when the document changes between its two lookups
+const MAX_META_READS: u32 = 3;
+// A keyspace is re-opened at most once per interval after not-found errors
+const KEYSPACE_REOPEN_INTERVAL: Duration = Duration::from_secs(30);
+// Retries for add/replace when the caller does not pass one
+const DEFAULT_WRITE_RETRIES: u32 = 5;
+
 lazy_static! {
-    static ref CB_CONNECTION: Arc<Cluster> = create_cluster_connection();
-    static ref BUCKET_CONNECTIONS: RwLock<HashMap<String, Arc<Collection>>> = RwLock::new(HashMap::new());
+    static ref CB_CONNECTION: Arc<CouchbaseConnection> = create_cluster_connection();
     static ref OPERATION_TIMEOUT: Duration = Duration::from_secs(120);
+    // Upper bound on in-flight gets per batch call
+    static ref BATCH_CONCURRENCY: usize = config::get_res::<usize>("couchbase.batchConcurrency").unwrap_or(64).max(1);
 }
 
-pub fn create_cluster_connection() -> Arc<Cluster> {
-    // let cluster = Cluster::connect(config::get::<String>("couchbase.connectionurl"), config::get::<String>("couchbase.username"), config::get::<String>("couchbase.password"));
-    let connection_url = std::env::var("COUCHBASE_CONNECTION_URL").expect("CONNECTION_URL must be set");
-    let username = std::env::var("COUCHBASE_USERNAME").expect("USERNAME must be set");
-    let password = std::env::var("COUCHBASE_PASSWORD").expect("PASSWORD must be set");
-    let cluster = Cluster::connect(connection_url, username, password);
-    
-    Arc::new(cluster)
+pub fn create_cluster_connection() -> Arc<CouchbaseConnection> {
+    Arc::new(CouchbaseConnection::connect(CouchbaseConnParams::from_env()))
 }
 
 pub fn init_couchbase_connection() {
     let _ = CB_CONNECTION.clone();
 }
 
-pub async fn get_bucket_connection(bucket_name: String) -> Result<Arc<Collection>, String> {
-    // Ensure we initialize the Couchbase connection first
-    // let init_cb = init_couchbase_connection(None).await;
-    // if let Err(err) = init_cb {
-    //     return Err(format!("Failed to initialize Couchbase connection: {}", err));
-    // }
+pub async fn init_couchbase_connection_ready(buckets: Vec<String>, timeout: Duration) -> Result<(), String> {
+    CB_CONNECTION.wait_until_ready(buckets, timeout).await
+}
+
+pub fn is_couchbase_ready() -> bool {
+    CB_CONNECTION.is_ready()
+}
+
+pub fn batch_concurrency() -> usize {
+    *BATCH_CONCURRENCY
+}
+
+// The process-wide connection used by the free functions below and the top-level napi exports
+pub fn default_connection() -> Arc<CouchbaseConnection> {
+    CB_CONNECTION.clone()
+}
 
-    // Try to get the connection from the map
-    if let Some(collection) = BUCKET_CONNECTIONS.read().await.get(&bucket_name) {
-        // Connection already exists, so reuse it
-        return Ok(Arc::clone(collection));
+#[derive(Clone, Debug, Default)]
+pub struct Keyspace {
+    pub bucket: String,
+    pub scope: Option<String>,
+    pub collection: Option<String>,
+}
+
+impl Keyspace {
+    pub fn bucket(bucket_name: String) -> Self {
+        Keyspace {
+            bucket: bucket_name,
+            scope: None,
+            collection: None,
+        }
+    }
+
+    // Fully qualified N1QL keyspace, the bucket alone for the default collection
+    pub fn query_path(&self) -> String {
+        match (&self.scope, &self.collection) {
+            (None, None) => format!("`{}`", self.bucket),
+            (scope, collection) => format!(
+                "`{}`.`{}`.`{}`",
+                self.bucket,
+                scope.as_deref().unwrap_or("_default"),
+                collection.as_deref().unwrap_or("_default")
+            ),
+        }
+    }
+
+    // N1QL query_context, lets statements name collections without the bucket and scope
+    pub fn query_context(&self) -> String {
+        format!(
+            "default:`{}`.`{}`",
+            self.bucket,
+            self.scope.as_deref().unwrap_or("_default")
+        )
     }
 
-    // If the connection doesn't exist, acquire a write lock to add it
-    log::info!("Creating new connection for bucket: {}", bucket_name);
+    // Cache key for the bucket connections map, the default collection keeps the plain bucket name
+    fn cache_key(&self) -> String {
+        match (&self.scope, &self.collection) {
+            (None, None) => self.bucket.clone(),
+            (scope, collection) => format!(
+                "{}.{}.{}",
+                self.bucket,
+                scope.as_deref().unwrap_or("_default"),
+                collection.as_deref().unwrap_or("_default")
+            ),
+        }
+    }
+}
 
-    // Check if the connection is still available
-    // let cluster = CB_CONNECTION.get().ok_or_else(|| "No connection to cluster available".to_string())?;
+#[derive(Clone, Debug, Default)]
+pub struct OperationOptions {
+    pub timeout: Option<Duration>,
+    pub context: Option<CallContext>,
+    // Absolute point in time after which the caller no longer waits for the result
+    pub deadline: Option<time::Instant>,
+    // TTL given to the written document, replace and upsert clear the expiry without one
+    pub expiry: Option<Duration>,
+    // Replace and upsert keep the document's current expiry when no `expiry` is given
+    pub preserve_expiry: bool,
+    // Set when the caller wants the latency breakdown of this call
+    pub timings: Option<Arc<OperationTimings>>,
+    // Transcoder named by the caller, the bucket's default otherwise
+    pub transcoder: Option<String>,
+    // Persist the write to the retry queue when it fails transiently after its retries
+    pub queue_on_failure: bool,
+    // Batch gets return what was fetched when the batch timeout passes, the rest reported as timed out
+    pub partial_on_timeout: bool,
+    // Single gets are served from the document cache when the key is there, and fill it otherwise
+    pub cached: bool,
+}
 
-    let bucket = CB_CONNECTION.bucket(&bucket_name);
-    let collection = Arc::new(bucket.default_collection());
+impl OperationOptions {
+    // Couchbase timeout bounded by whatever is left before the caller's deadline
+    pub fn effective_timeout(&self) -> Result<Option<Duration>, String> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return Ok(self.timeout),
+        };
+        let remaining = deadline.saturating_duration_since(time::Instant::now());
+        if remaining.is_zero() {
+            return Err(format!("{}DEADLINE_EXCEEDED: operation deadline has already passed", self.log_prefix()));
+        }
+        Ok(Some(match self.timeout {
+            Some(timeout) => timeout.min(remaining),
+            None => remaining,
+        }))
+    }
 
-    // Insert the new connection into the map, ensuring only one write operation is done
-    BUCKET_CONNECTIONS.write().await.insert(bucket_name, Arc::clone(&collection));
+    // Whether a retry after `delay` can still finish before the deadline
+    pub fn has_time_for(&self, delay: Duration) -> bool {
+        match self.deadline {
+            Some(deadline) => time::Instant::now() + delay < deadline,
+            None => true,
+        }
+    }
 
-    Ok(collection)
+    fn record_attempt(&self, started: time::Instant) {
+        if let Some(timings) = &self.timings {
+            timings.record_attempt(started.elapsed());
+        }
+    }
+
+    fn record_deserialize(&self, started: time::Instant) {
+        if let Some(timings) = &self.timings {
+            timings.record_deserialize(started.elapsed());
+        }
+    }
+
+    fn transcoder(&self, keyspace: &Keyspace) -> Result<Arc<dyn Transcoder>, String> {
+        transcoder_for(self.transcoder.as_deref(), &keyspace.bucket)
+    }
+
+    // Prepended to log lines so native logs can be correlated with the caller's request
+    pub fn log_prefix(&self) -> String {
+        match &self.context {
+            Some(context) => format!("[{}] ", context),
+            None => String::new(),
+        }
+    }
 }
 
-// pub async fn get_bucket_connection(bucket_name: String) -> Result<Collection, String> {
-//     // First try to initialzie connection with cluster
-//     let init=init_couchbase_connection(None).await;
+// Caller supplied context for tracing and tenancy, threaded through every operation
+#[derive(Clone, Debug, Default)]
+pub struct CallContext {
+    pub request_id: Option<String>,
+    pub tenant_id: Option<String>,
+    pub actor: Option<String>,
+}
 
-//     let bucket = cluster.bucket(bucket_name.clone());
-//     let collection = bucket.default_collection();
+// bucket or bucket.scope.collection, so log lines and records never conflate equal keys of different collections
+impl fmt::Display for Keyspace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.cache_key())
+    }
+}
 
-//     Ok(collection)
-// }
+impl fmt::Display for CallContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("requestId", &self.request_id),
+            ("tenantId", &self.tenant_id),
+            ("actor", &self.actor),
+        ];
+        let labels: Vec<String> = fields
+            .iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+            .collect();
+        write!(f, "{}", labels.join(" "))
+    }
+}
 
-pub async fn get_document(
-  key: String,
-  with_cas: bool,
-  bucket_name: String,
-) -> Result<Value, String> {
-  let db = get_bucket_connection(bucket_name).await;
-  if let Err(err) = db {
-    return Err(err);
-  }
-  let db = db.unwrap();
+pub struct CasDocument {
+    pub value: Value,
+    pub cas: u64,
+}
 
-  match db.get(key.to_owned(), GetOptions::default()).await {
-    Ok(get_result) => {
-      let mut data = get_result.content::<Value>().unwrap();
-      if with_cas {
-        data = json!({
-            "value":data,
-            "cas":get_result.cas().to_string()
-        });
-      }
-      Ok(data)
+pub struct DocumentWithMeta {
+    pub value: Value,
+    pub cas: u64,
+    // Unix seconds, None when the document never expires
+    pub expiry: Option<u64>,
+}
+
+// Query settings beyond the statement, positional parameters and consistency tokens
+#[derive(Clone, Debug, Default)]
+pub struct QuerySettings {
+    pub query_context: Option<String>,
+    pub named_parameters: HashMap<String, Value>,
+    // "not_bounded" or "request_plus", ignored when consistency tokens are given
+    pub scan_consistency: Option<String>,
+    // Serve identical statements from the query cache for this long, never with consistency requirements
+    pub cache_ttl: Option<Duration>,
+    // Skip the cached rows but still refresh the cache with the new ones
+    pub bypass_cache: bool,
+}
+
+// One page of document keys in ascending order, `next_start_after` is set when more keys follow
+pub struct KeyPage {
+    pub keys: Vec<String>,
+    pub next_start_after: Option<String>,
+}
+
+pub struct BatchDocuments {
+    pub docs: HashMap<String, CasDocument>,
+    pub errors: HashMap<String, String>,
+    // Bucket that served each key in `docs`
+    pub sources: HashMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum CounterChange {
+    Increment(u64),
+    // Stops at 0 rather than going negative
+    Decrement(u64),
+}
+
+
+pub struct CounterOutcome {
+    pub value: u64,
+    pub mutation: MutationOutcome,
+}
+
+#[derive(Debug, Default)]
+pub struct CasSnapshot {
+    pub cas: HashMap<String, u64>,
+    // Keys that do not exist
+    pub missing: Vec<String>,
+    pub errors: HashMap<String, String>,
+}
+
+// The SDK only surfaces errors as text here, so these are recognised by their message
+pub fn is_document_not_found(error: &str) -> bool {
+    let error = error.to_lowercase().replace(' ', "");
+    error.contains("documentnotfound") || error.contains("keynotfound")
+}
+
+// The SDK's StoreSemantics is not Clone, and mutate_in keeps its own for the keyspace retry
+fn copy_store_semantics(store_semantics: &StoreSemantics) -> StoreSemantics {
+    match store_semantics {
+        StoreSemantics::Replace => StoreSemantics::Replace,
+        StoreSemantics::Upsert => StoreSemantics::Upsert,
+        StoreSemantics::Insert => StoreSemantics::Insert,
+    }
+}
+
+// The SDK puts `expiry.as_micros() as u32` on the wire for get_and_touch, where the server reads seconds. So the
+// expiry goes out as the absolute epoch second the TTL ends at, carried in the micros. Absolute expiries also mean
+// the same thing for any TTL, relative ones are only read as such up to 30 days. A zero TTL clears the expiry
+fn touch_expiry(ttl: Duration) -> Result<Duration, String> {
+    if ttl.is_zero() {
+        return Ok(Duration::ZERO);
     }
-    Err(error) => {
-      log::error!(
-        "Error in getting data from couchbase : {:?}",
-        error.to_string()
-      );
-      Err(error.to_string())
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|error| error.to_string())?;
+    match now.as_secs().checked_add(ttl.as_secs().max(1)) {
+        Some(expires_at) if expires_at <= u32::MAX as u64 => Ok(Duration::from_micros(expires_at)),
+        _ => Err(format!("TTL of {}s ends past the latest expiry the server can store", ttl.as_secs())),
     }
-  }
 }
 
-pub async fn add_document(
-  key: String,
-  value: Value,
-  bucket_name: String,
-  retry: Option<u32>,
-) -> Result<bool, String> {
-  let retry = retry.unwrap_or(5);
-  let db = get_bucket_connection(bucket_name.to_owned()).await;
-  if let Err(err) = db {
-    return Err(err);
-  }
-  let db = db.unwrap();
-
-  match db
-    .insert(key.clone(), value.to_owned(), InsertOptions::default())
-    .await
-  {
-    Ok(_) => {
-      // log::info!("Data successfully added to couchbase for key: {}", key);
-      Ok(true)
-    }
-    Err(error) => {
-      if retry <= 0 {
-        return Err(format!(
-          "Error in adding data to couchbase : {:?}... retry limit reached",
-          error.to_string()
-        ));
-      }
-      log::error!(
-        "Error in adding data to couchbase : {:?}... retrying",
-        error.to_string()
-      );
-      time::sleep(Duration::from_secs(1)).await;
-      let res = Box::pin(add_document(key, value, bucket_name, Some(retry - 1))).await;
-      if res.is_ok() {
-        return Ok(true);
-      }
-      Err(error.to_string())
-    }
-  }
+// CAS mismatches and insert races both mean someone else wrote first, callers re-read and try again
+pub fn is_write_conflict(error: &str) -> bool {
+    let error = error.to_lowercase().replace(' ', "");
+    error.contains("casmismatch") || error.contains("documentexists") || error.contains("keyexists")
 }
 
-pub async fn replace_document(
-  key: String,
-  value: Value,
-  cas: Option<u64>,
-  bucket_name: String,
-  retry: Option<u32>,
-) -> Result<String, String> {
-  let retry = retry.unwrap_or(5);
-  let db = get_bucket_connection(bucket_name.to_owned()).await;
-  if let Err(err) = db {
-    return Err(err);
-  }
-  let db = db.unwrap();
-  // let get_document_res = match db.get(key.clone(), GetOptions::default()).await {
-  //     Ok(data) => data,
-  //     Err(err) => {
-  //         log::error!(
-  //             "Error in getting data from couchbase : {:?}",
-  //             err.to_string()
-  //         );
-  //         return Err(err.to_string());
-  //     }
-  // };
-  // let cas = get_document_res.cas();
-  let replace_opt;
-  if cas.is_some() {
-    replace_opt = ReplaceOptions::default().cas(cas.unwrap());
-  } else {
-    replace_opt = ReplaceOptions::default();
-  }
-  let update_data = db.replace(key.to_owned(), value.to_owned(), replace_opt);
-  match update_data.await {
-    Ok(_) => {
-      // log::info!(
-      //     "Data successfully updated to couchbase for key: {} in bucket : {}",
-      //     key,
-      //     bucket_name.to_owned()
-      // );
-      Ok(format!(
-        "Data successfully updated to couchbase for key: {} in bucket : {}",
-        key,
-        bucket_name.to_owned()
-      ))
-    }
-    Err(error) => {
-      if retry <= 0 {
-        return Err(format!(
-          "Error in updating data to couchbase : {:?}... retry limit reached",
-          error.to_string()
-        ));
-      }
-      log::error!(
-        "Error in updating data to couchbase : {:?} in bucket : {}",
-        error.to_string(),
-        bucket_name
-      );
-      time::sleep(Duration::from_secs(1)).await;
-      let res = Box::pin(replace_document(
-        key.to_owned(),
-        value,
-        cas,
-        bucket_name.to_owned(),
-        Some(retry - 1),
-      ))
-      .await;
-      if res.is_ok() {
-        return Ok(format!(
-          "Data successfully updated to couchbase for key: {} in bucket : {}",
-          key,
-          bucket_name.to_owned()
-        ));
-      }
-      Err(error.to_string())
-    }
-  }
-}
-
-pub async fn delete_data(key: String, bucket_name: String) -> Result<String, String> {
-  let db = get_bucket_connection(bucket_name.to_owned()).await;
-  if let Err(err) = db {
-    return Err(err);
-  }
-  let db = db.unwrap();
-
-  let delete_data = db.remove(key.to_owned(), RemoveOptions::default());
-  match delete_data.await {
-    Ok(_) => {
-      // log::info!(
-      //     "Data successfully deleted from couchbase for key: {} in bucket : {}",
-      //     key,
-      //     bucket_name.to_owned()
-      // );
-      Ok(format!(
-        "Data successfully deleted from couchbase for key: {} in bucket : {}",
-        key,
-        bucket_name.to_owned()
-      ))
-    }
-    Err(error) => {
-      log::error!(
-        "Error in deleting data from couchbase : {:?} in bucket : {}",
-        error.to_string(),
-        bucket_name
-      );
-      Err(error.to_string())
-    }
-  }
-}
-
-
-pub async fn get_documents(keys: Vec<String>, with_cas: bool, bucket_name: String) -> Result<Value, String> {
-    let db = get_bucket_connection(bucket_name).await;
-    if let Err(err) = db {
-        return Err(format!("Error in getting bucket connection : {:?}", err));
+// LIKE pattern matching keys that start with `prefix`, wildcards inside the prefix are escaped
+pub fn like_prefix(prefix: &str) -> String {
+    format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"))
+}
+
+// Bucket, scope or collection missing, e.g. dropped and recreated under a cached connection
+pub fn is_keyspace_not_found(error: &str) -> bool {
+    let error = error.to_lowercase().replace(' ', "");
+    error.contains("bucketnotfound") || error.contains("scopenotfound") || error.contains("collectionnotfound")
+}
+
+// The write may or may not have been applied, e.g. a durable write whose replication timed out
+pub fn is_ambiguous(error: &str) -> bool {
+    let error = error.to_lowercase().replace(' ', "");
+    error.contains("ambiguous")
+}
+
+// What a read-back found after an ambiguous write
+enum WriteVerification {
+    Applied(MutationOutcome),
+    NotApplied,
+    Unknown(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubdocLookupOperation {
+    Get,
+    Exists,
+    Count,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubdocLookup {
+    pub operation: SubdocLookupOperation,
+    pub path: String,
+}
+
+impl SubdocLookup {
+    pub fn get(path: &str) -> Self {
+        SubdocLookup {
+            operation: SubdocLookupOperation::Get,
+            path: path.to_string(),
+        }
     }
-    let db = db.unwrap();
 
-    if keys.is_empty() {
-        return Err("Array of Keys need to be on length>0".to_string());
+    pub fn exists(path: &str) -> Self {
+        SubdocLookup {
+            operation: SubdocLookupOperation::Exists,
+            path: path.to_string(),
+        }
     }
 
-    let mut docs: HashMap<String, Value> = HashMap::new();
-    let mut errors: HashMap<String, Value> = HashMap::new();
+    pub fn count(path: &str) -> Self {
+        SubdocLookup {
+            operation: SubdocLookupOperation::Count,
+            path: path.to_string(),
+        }
+    }
 
-    // Loop through each key
-    for key in &keys {
-        match db.get(key, GetOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-            Ok(res) => {
-                let data = res.content::<Value>().unwrap();
+    fn to_spec(&self) -> LookupInSpec {
+        match self.operation {
+            SubdocLookupOperation::Get => LookupInSpec::get(&self.path),
+            SubdocLookupOperation::Exists => LookupInSpec::exists(&self.path),
+            SubdocLookupOperation::Count => LookupInSpec::count(&self.path),
+        }
+    }
+}
 
-                if with_cas {
-                    docs.insert(
+// One entry per lookup spec: None when a Get path is missing
+pub struct SubdocLookupResult {
+    pub values: Vec<Option<Value>>,
+    pub cas: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct DocumentMutationToken {
+    pub bucket_name: String,
+    pub partition_id: u16,
+    pub partition_uuid: u64,
+    pub sequence_number: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MutationOutcome {
+    pub cas: u64,
+    // Always None for sub-document writes
+    pub mutation_token: Option<DocumentMutationToken>,
+    // 1 unless the write only went through after retries
+    pub attempts: u32,
+    // Backoff slept between those attempts
+    pub retry_delay: Duration,
+}
+
+impl MutationOutcome {
+    // Accounts for the failed attempt and the backoff that preceded a successful retry
+    fn after_retry(mut self, delay: Duration) -> Self {
+        self.attempts += 1;
+        self.retry_delay += delay;
+        self
+    }
+
+    // Keys that keep needing retries are worth a look before they start failing outright
+    fn warn_if_retried(&self, operation: &str, key: &str, keyspace: &Keyspace, options: &OperationOptions) {
+        if self.attempts > 1 {
+            log::warn!(
+                "{}{} of {} in {} succeeded after {} attempts, {} ms of retry delay",
+                options.log_prefix(),
+                operation,
+                key,
+                keyspace,
+                self.attempts,
+                self.retry_delay.as_millis()
+            );
+        }
+    }
+
+    fn from_result(result: &MutationResult) -> Self {
+        Self::from_parts(result.cas(), result.mutation_token())
+    }
+
+    fn from_mutate_in(result: &MutateInResult) -> Self {
+        Self::from_parts(result.cas(), None)
+    }
+
+    fn from_parts(cas: u64, mutation_token: Option<&MutationToken>) -> Self {
+        MutationOutcome {
+            cas,
+            mutation_token: mutation_token.map(|token| DocumentMutationToken {
+                bucket_name: token.bucket_name().to_string(),
+                partition_id: token.partition_id(),
+                partition_uuid: token.partition_uuid(),
+                sequence_number: token.sequence_number(),
+            }),
+            attempts: 1,
+            retry_delay: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubdocOperation {
+    Insert,
+    Upsert,
+    Replace,
+    Remove,
+    ArrayAppend,
+    ArrayPrepend,
+    ArrayAddUnique,
+    Increment,
+    Decrement,
+}
+
+// One path level change inside a mutateIn, `value` is the delta for counters
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubdocMutation {
+    pub operation: SubdocOperation,
+    pub path: String,
+    pub value: Option<Value>,
+    pub create_path: bool,
+    pub xattr: bool,
+}
+
+impl SubdocMutation {
+    // xattr and create_path are rejected, see MutateInSpec
+    fn to_spec(&self) -> Result<MutateInSpec, String> {
+        let path = self.path.as_str();
+        if self.xattr {
+            return Err(format!("mutateIn {:?} on {} : xattr paths are not supported", self.operation, path));
+        }
+        if self.create_path {
+            return Err(format!("mutateIn {:?} on {} : createPath is not supported", self.operation, path));
+        }
+        let value = || {
+            self.value
+                .clone()
+                .ok_or_else(|| format!("mutateIn {:?} on {} requires a value", self.operation, path))
+        };
+        let delta = || {
+            value()?
+                .as_u64()
+                .and_then(|delta| u32::try_from(delta).ok())
+                .ok_or_else(|| format!("mutateIn {:?} on {} requires a positive 32-bit delta", self.operation, path))
+        };
+        // Array operations accept either a single element or a non-empty array of elements
+        let elements = || {
+            let elements = match value()? {
+                Value::Array(elements) => elements,
+                element => vec![element],
+            };
+            if elements.is_empty() {
+                return Err(format!("mutateIn {:?} on {} requires at least one element", self.operation, path));
+            }
+            Ok(elements)
+        };
+        Ok(match self.operation {
+            SubdocOperation::Insert => MutateInSpec::insert(path, value()?),
+            SubdocOperation::Upsert => MutateInSpec::upsert(path, value()?),
+            SubdocOperation::Replace => MutateInSpec::replace(path, value()?),
+            SubdocOperation::Remove => MutateInSpec::remove(path),
+            SubdocOperation::ArrayAppend => MutateInSpec::array_append(path, elements()?),
+            SubdocOperation::ArrayPrepend => MutateInSpec::array_prepend(path, elements()?),
+            SubdocOperation::ArrayAddUnique => MutateInSpec::array_add_unique(path, value()?),
+            SubdocOperation::Increment => MutateInSpec::increment(path, delta()?),
+            SubdocOperation::Decrement => MutateInSpec::decrement(path, delta()?),
+        })
+    }
+}
+
+// One cluster connection plus its cached collections. Dropped clusters are closed by the SDK.
+pub struct CouchbaseConnection {
+    cluster: RwLock<Option<Arc<Cluster>>>,
+    bucket_connections: RwLock<HashMap<String, Arc<Collection>>>,
+    // When each keyspace was last re-opened after a not-found error
+    reopened_at: std::sync::Mutex<HashMap<String, time::Instant>>,
+    // Fetched on first use, see cluster_info
+    cluster_info: RwLock<Option<ClusterInfo>>,
+    ready: AtomicBool,
+}
+
+impl CouchbaseConnection {
+    pub fn connect(params: CouchbaseConnParams) -> Self {
+        let cluster = Cluster::connect(params.connection_url, params.username, params.password);
+        CouchbaseConnection {
+            cluster: RwLock::new(Some(Arc::new(cluster))),
+            bucket_connections: RwLock::new(HashMap::new()),
+            reopened_at: std::sync::Mutex::new(HashMap::new()),
+            cluster_info: RwLock::new(None),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    pub async fn close(&self) {
+        self.ready.store(false, Ordering::SeqCst);
+        self.bucket_connections.write().await.clear();
+        *self.cluster_info.write().await = None;
+        if self.cluster.write().await.take().is_some() {
+            log::info!("Couchbase cluster connection closed");
+        }
+    }
+
+    pub async fn is_closed(&self) -> bool {
+        self.cluster.read().await.is_none()
+    }
+
+    // True once wait_until_ready has succeeded and the connection has not been closed since
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    async fn cluster(&self) -> Result<Arc<Cluster>, String> {
+        self.cluster
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| "No connection to cluster available, client is closed".to_string())
+    }
+
+    // Pings every requested bucket (or runs a trivial query when none are given) until it answers
+    pub async fn wait_until_ready(&self, buckets: Vec<String>, timeout: Duration) -> Result<(), String> {
+        let deadline = time::Instant::now() + timeout;
+        loop {
+            let cluster = self.cluster().await?;
+            match Self::ping(&cluster, &buckets).await {
+                Ok(()) => {
+                    for bucket_name in &buckets {
+                        self.collection(Keyspace::bucket(bucket_name.to_owned())).await?;
+                    }
+                    self.ready.store(true, Ordering::SeqCst);
+                    log::info!("Couchbase connection is ready");
+                    return Ok(());
+                }
+                Err(error) => {
+                    if time::Instant::now() >= deadline {
+                        return Err(format!("Couchbase not ready after {:?} : {}", timeout, error));
+                    }
+                    log::warn!("Couchbase not ready yet : {}... retrying", error);
+                    time::sleep(Duration::from_millis(500)).await;
+                }
+            }
+        }
+    }
+
+    async fn ping(cluster: &Cluster, buckets: &[String]) -> Result<(), String> {
+        if buckets.is_empty() {
+            return cluster
+                .query("SELECT RAW 1", QueryOptions::default())
+                .await
+                .map(|_| ())
+                .map_err(|error| error.to_string());
+        }
+        for bucket_name in buckets {
+            cluster
+                .bucket(bucket_name)
+                .ping(PingOptions::default())
+                .await
+                .map_err(|error| format!("bucket {} : {}", bucket_name, error))?;
+        }
+        Ok(())
+    }
+
+    // Single JSON document shaped for /healthz responses, status is "ok", "degraded" or "down"
+    pub async fn health_report(&self) -> Value {
+        let cached_keyspaces: Vec<String> = self.bucket_connections.read().await.keys().cloned().collect();
+        let mut bucket_names: Vec<String> = cached_keyspaces
+            .iter()
+            .map(|cache_key| cache_key.split('.').next().unwrap_or_default().to_string())
+            .collect();
+        bucket_names.sort();
+        bucket_names.dedup();
+
+        let cluster = match self.cluster().await {
+            Ok(cluster) => cluster,
+            Err(error) => {
+                return json!({
+                    "status": "down",
+                    "ready": false,
+                    "cluster": { "ok": false, "error": error },
+                    "buckets": {},
+                    "kafka": { "enabled": false },
+                    "cache": { "cachedKeyspaces": cached_keyspaces.len() },
+                    "version": env!("CARGO_PKG_VERSION"),
+                });
+            }
+        };
+
+        let started = time::Instant::now();
+        let cluster_health = match Self::ping(&cluster, &[]).await {
+            Ok(()) => json!({ "ok": true, "latencyMs": started.elapsed().as_millis() as u64 }),
+            Err(error) => json!({ "ok": false, "error": error }),
+        };
+
+        let mut buckets = serde_json::Map::new();
+        for bucket_name in &bucket_names {
+            let started = time::Instant::now();
+            let bucket_health = match Self::ping(&cluster, std::slice::from_ref(bucket_name)).await {
+                Ok(()) => json!({ "ok": true, "latencyMs": started.elapsed().as_millis() as u64 }),
+                Err(error) => json!({ "ok": false, "error": error }),
+            };
+            buckets.insert(bucket_name.clone(), bucket_health);
+        }
+
+        let cluster_ok = cluster_health["ok"].as_bool().unwrap_or(false);
+        let buckets_ok = buckets.values().all(|bucket| bucket["ok"].as_bool().unwrap_or(false));
+        let status = match (cluster_ok, buckets_ok) {
+            (true, true) => "ok",
+            (false, false) => "down",
+            _ => "degraded",
+        };
+
+        json!({
+            "status": status,
+            "ready": self.is_ready(),
+            "cluster": cluster_health,
+            "buckets": buckets,
+            // The Kafka producer is not compiled into this build
+            "kafka": { "enabled": false },
+            "cache": { "cachedKeyspaces": cached_keyspaces.len() },
+            "version": env!("CARGO_PKG_VERSION"),
+        })
+    }
+
+    // Server version and services, asked once per connection from the query service
+    pub async fn cluster_info(&self) -> Result<ClusterInfo, String> {
+        if let Some(info) = self.cluster_info.read().await.clone() {
+            return Ok(info);
+        }
+        let version = self
+            .query(
+                "SELECT RAW ds_version()".to_string(),
+                Vec::new(),
+                Vec::new(),
+                QuerySettings::default(),
+                OperationOptions::default(),
+            )
+            .await?
+            .first()
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| "The query service did not report a server version".to_string())?;
+        let node_services = self
+            .query(
+                "SELECT RAW n.services FROM system:nodes AS n".to_string(),
+                Vec::new(),
+                Vec::new(),
+                QuerySettings::default(),
+                OperationOptions::default(),
+            )
+            .await?;
+        let info = ClusterInfo::from_rows(version, &node_services);
+        log::info!("Connected to Couchbase Server {} with services {:?}", info.version, info.services);
+        *self.cluster_info.write().await = Some(info.clone());
+        Ok(info)
+    }
+
+    // NOT_SUPPORTED when the cluster lacks `feature`. If the cluster cannot be asked the operation goes ahead and
+    // the SDK reports whatever goes wrong
+    async fn require_feature(&self, feature: ClusterFeature) -> Result<(), String> {
+        match self.cluster_info().await {
+            Ok(info) => info.require(feature),
+            Err(error) => {
+                log::warn!("Unable to check cluster support for {:?} : {}", feature, error);
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn collection(&self, keyspace: Keyspace) -> Result<Arc<Collection>, String> {
+        let cache_key = keyspace.cache_key();
+
+        // Try to get the connection from the map
+        if let Some(collection) = self.bucket_connections.read().await.get(&cache_key) {
+            // Connection already exists, so reuse it
+            return Ok(Arc::clone(collection));
+        }
+
+        // If the connection doesn't exist, acquire a write lock to add it
+        log::info!("Creating new connection for keyspace: {}", cache_key);
+        if keyspace.scope.is_some() || keyspace.collection.is_some() {
+            self.require_feature(ClusterFeature::Collections).await?;
+        }
+
+        // Check if the connection is still available
+        let cluster = self.cluster().await?;
+
+        let bucket = cluster.bucket(&keyspace.bucket);
+        let collection = match (keyspace.scope, keyspace.collection) {
+            (None, None) => Arc::new(bucket.default_collection()),
+            (scope, collection) => Arc::new(
+                bucket
+                    .scope(scope.unwrap_or_else(|| "_default".to_string()))
+                    .collection(collection.unwrap_or_else(|| "_default".to_string())),
+            ),
+        };
+
+        // Insert the new connection into the map, ensuring only one write operation is done
+        self.bucket_connections.write().await.insert(cache_key, Arc::clone(&collection));
+
+        Ok(collection)
+    }
+
+    // Drops the cached collection of a keyspace whose operations have stayed slow, see latency_watch
+    async fn observe_latency(&self, keyspace: &Keyspace, elapsed: Duration) {
+        let cache_key = keyspace.cache_key();
+        if needs_rebuild(&cache_key, elapsed) {
+            self.bucket_connections.write().await.remove(&cache_key);
+        }
+    }
+
+    // Drops a cached collection whose bucket, scope or collection was dropped (and maybe recreated) so the
+    // next call opens it again. Returns false if it was already re-opened recently, callers then give up
+    // instead of looping on a keyspace that is really gone
+    async fn reopen_missing_keyspace(&self, keyspace: &Keyspace, error: &str) -> bool {
+        if !is_keyspace_not_found(error) {
+            return false;
+        }
+        let cache_key = keyspace.cache_key();
+        {
+            let mut reopened_at = self.reopened_at.lock().unwrap();
+            if let Some(at) = reopened_at.get(&cache_key) {
+                if at.elapsed() < KEYSPACE_REOPEN_INTERVAL {
+                    return false;
+                }
+            }
+            reopened_at.insert(cache_key.clone(), time::Instant::now());
+        }
+        self.bucket_connections.write().await.remove(&cache_key);
+        log::warn!("Keyspace {} not found, re-opening its connection : {}", cache_key, error);
+        true
+    }
+
+    pub async fn get_document(
+        &self,
+        key: String,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<CasDocument, String> {
+        check_policy("get")?;
+        record_access(&key, &keyspace);
+        if options.cached && options.transcoder.is_none() {
+            if let Some(document) = cached_document(&key, &keyspace) {
+                return Ok(document);
+            }
+            let uncached = OperationOptions {
+                cached: false,
+                ..options
+            };
+            let document = Box::pin(self.get_document(key.clone(), keyspace.clone(), uncached)).await?;
+            store_document(&key, &keyspace, &document);
+            return Ok(document);
+        }
+        let transcoder = options.transcoder(&keyspace)?;
+        let db = self.collection(keyspace.clone()).await?;
+
+        let mut get_opt = GetOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            get_opt = get_opt.timeout(timeout);
+        }
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let started = time::Instant::now();
+        let fetched = match inject_fault(FaultOperation::Get, &key).await {
+            Some(fault) => Err(fault),
+            None => db.get(key.to_owned(), get_opt).await.map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        options.record_attempt(started);
+        self.observe_latency(&keyspace, started.elapsed()).await;
+        match fetched {
+            Ok(get_result) => {
+                let started = time::Instant::now();
+                let content = get_result.content::<Value>().map_err(|error| error.to_string())?;
+                if let Some(manifest) = chunk_manifest(&keyspace.bucket, &content) {
+                    let value = self.read_chunks(&key, &manifest, keyspace, &options).await?;
+                    options.record_deserialize(started);
+                    return Ok(CasDocument {
+                        value,
+                        cas: get_result.cas(),
+                    });
+                }
+                let data = transcoder.decode(content)?;
+                options.record_deserialize(started);
+                check_read_size(&key, &data);
+                Ok(CasDocument {
+                    value: data,
+                    cas: get_result.cas(),
+                })
+            }
+            Err(error) => {
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.get_document(key, keyspace, options)).await;
+                }
+                log::error!(
+                    "{}Error in getting data from couchbase : {:?}",
+                    options.log_prefix(),
+                    error.to_string()
+                );
+                Err(error.to_string())
+            }
+        }
+    }
+
+    pub async fn lookup_in(
+        &self,
+        key: String,
+        lookups: Vec<SubdocLookup>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<SubdocLookupResult, String> {
+        if lookups.is_empty() {
+            return Err("lookupIn needs at least one spec".to_string());
+        }
+        check_policy("lookupIn")?;
+        let db = self.collection(keyspace.clone()).await?;
+
+        let mut lookup_opt = LookupInOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            lookup_opt = lookup_opt.timeout(timeout);
+        }
+        let specs = lookups.iter().map(SubdocLookup::to_spec).collect::<Vec<LookupInSpec>>();
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let looked_up = match inject_fault(FaultOperation::Get, &key).await {
+            Some(fault) => Err(fault),
+            None => db.lookup_in(key.to_owned(), specs, lookup_opt).await.map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        match looked_up {
+            Ok(result) => {
+                let values = lookups
+                    .iter()
+                    .enumerate()
+                    .map(|(index, lookup)| match lookup.operation {
+                        SubdocLookupOperation::Get => result.content::<Value>(index).ok(),
+                        SubdocLookupOperation::Exists => Some(Value::Bool(result.exists(index))),
+                        SubdocLookupOperation::Count => result.content::<Value>(index).ok(),
+                    })
+                    .collect();
+                Ok(SubdocLookupResult {
+                    values,
+                    cas: result.cas(),
+                })
+            }
+            Err(error) => {
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.lookup_in(key, lookups, keyspace, options)).await;
+                }
+                log::error!("{}Error in looking up data in couchbase : {:?}", options.log_prefix(), error);
+                Err(error)
+            }
+        }
+    }
+
+    // Values of `paths` in one round trip, paths the document does not have are left out
+    pub async fn lookup_paths(
+        &self,
+        key: String,
+        paths: Vec<String>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<HashMap<String, Value>, String> {
+        let lookups = paths.iter().map(|path| SubdocLookup::get(path)).collect();
+        let result = self.lookup_in(key, lookups, keyspace, options).await?;
+        Ok(paths
+            .into_iter()
+            .zip(result.values)
+            .filter_map(|(path, value)| value.map(|value| (path, value)))
+            .collect())
+    }
+
+    // Reads the document back after an ambiguous write of `expected`. `previous_cas` is the CAS the write was
+    // conditioned on, a document still at that CAS was not touched
+    async fn verify_ambiguous_write(
+        &self,
+        key: &str,
+        expected: &Value,
+        previous_cas: Option<u64>,
+        keyspace: Keyspace,
+        options: &OperationOptions,
+    ) -> WriteVerification {
+        match self.get_document(key.to_string(), keyspace, options.clone()).await {
+            Ok(stored) if Some(stored.cas) == previous_cas => WriteVerification::NotApplied,
+            Ok(stored) if stored.value == *expected => WriteVerification::Applied(MutationOutcome {
+                cas: stored.cas,
+                mutation_token: None,
+                attempts: 1,
+                retry_delay: Duration::ZERO,
+            }),
+            Ok(_) => WriteVerification::NotApplied,
+            Err(error) if is_document_not_found(&error) => WriteVerification::NotApplied,
+            Err(error) => WriteVerification::Unknown(format!(
+                "DURABILITY_AMBIGUOUS: write of {} could not be verified : {}",
+                key, error
+            )),
+        }
+    }
+
+    // Writes an oversized document as a manifest under `key` plus its chunks, see utils::chunking.
+    // Chunks hold the JSON text, the transcoder does not apply to chunked documents
+    #[allow(clippy::too_many_arguments)]
+    async fn write_chunked(
+        &self,
+        key: String,
+        value: &Value,
+        cas: Option<u64>,
+        semantics: StoreSemantics,
+        keyspace: Keyspace,
+        options: OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<MutationOutcome, String> {
+        let (manifest, chunks) = split_document(value, uuid().simple().to_string());
+        let raw_options = OperationOptions {
+            transcoder: Some(DEFAULT_TRANSCODER.to_string()),
+            ..options.clone()
+        };
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let chunk_key = chunk_key(&key, &manifest.generation, index);
+            if let Err(error) = self
+                .upsert_with_retries(chunk_key, Value::String(chunk), keyspace.clone(), raw_options.clone(), retry)
+                .await
+            {
+                self.remove_chunks(&key, &manifest.generation, index, &keyspace, &options).await;
+                return Err(format!("Unable to write chunk {} of {} : {}", index, key, error));
+            }
+        }
+        match self
+            .switch_manifest(&key, &manifest, cas, semantics, &keyspace, &raw_options, retry)
+            .await
+        {
+            Ok((outcome, previous)) => {
+                if let Some(previous) = previous {
+                    self.remove_chunks(&key, &previous.generation, previous.count, &keyspace, &options).await;
+                }
+                log::info!(
+                    "{}Stored {} as {} chunks ({} bytes) in {}",
+                    options.log_prefix(),
+                    key,
+                    manifest.count,
+                    manifest.bytes,
+                    keyspace
+                );
+                Ok(outcome)
+            }
+            Err(error) => {
+                // The stored manifest still points at its own chunks, only the ones written here are dropped
+                self.remove_chunks(&key, &manifest.generation, manifest.count, &keyspace, &options).await;
+                Err(error)
+            }
+        }
+    }
+
+    // Points `key` at the chunks of `manifest`, conditioned on the CAS of the version it replaces. Without a caller
+    // CAS a concurrent write is re-read and the switch tried again. Returns the manifest that was replaced, if any
+    #[allow(clippy::too_many_arguments)]
+    async fn switch_manifest(
+        &self,
+        key: &str,
+        manifest: &ChunkManifest,
+        cas: Option<u64>,
+        semantics: StoreSemantics,
+        keyspace: &Keyspace,
+        options: &OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<(MutationOutcome, Option<ChunkManifest>), String> {
+        let inserts = matches!(semantics, StoreSemantics::Insert);
+        // A caller CAS or an insert conflicting means the caller's view is stale, that is theirs to handle
+        let retries_conflicts = cas.is_none() && !inserts;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let current = if inserts {
+                None
+            } else {
+                self.stored_version(key, keyspace, options).await?
+            };
+            let switched = match (&semantics, &current) {
+                (StoreSemantics::Insert, _) | (StoreSemantics::Upsert, None) => {
+                    let value = manifest.to_value();
+                    self.insert_with_retries(key.to_string(), value, keyspace.clone(), options.clone(), retry).await
+                }
+                (_, current) => {
+                    let expected = cas.or(current.as_ref().map(|(current_cas, _)| *current_cas));
+                    self.replace_with_retries(
                         key.to_string(),
-                        json!({
-                            "value": data,
-                            "cas": res.cas()
-                        }),
+                        manifest.to_value(),
+                        expected,
+                        keyspace.clone(),
+                        options.clone(),
+                        retry,
+                    )
+                    .await
+                }
+            };
+            match switched {
+                Ok(outcome) => return Ok((outcome, current.and_then(|(_, previous)| previous))),
+                Err(error) if retries_conflicts && is_write_conflict(&error) && attempts < MAX_SWITCH_ATTEMPTS => {
+                    log::warn!(
+                        "{}Concurrent write on {} while switching its chunks... retrying",
+                        options.log_prefix(),
+                        key
                     );
-                } else {
-                    docs.insert(key.to_string(), json!(data));
                 }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    // CAS of what is stored under `key`, with its manifest when it is chunked. None when the key does not exist
+    async fn stored_version(
+        &self,
+        key: &str,
+        keyspace: &Keyspace,
+        options: &OperationOptions,
+    ) -> Result<Option<(u64, Option<ChunkManifest>)>, String> {
+        let db = self.collection(keyspace.clone()).await?;
+        let mut get_opt = GetOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            get_opt = get_opt.timeout(timeout);
+        }
+        let permit = enter_bulkhead(keyspace, options).await?;
+        let fetched = db.get(key.to_owned(), get_opt).await.map_err(|error| error.to_string());
+        drop(permit);
+        match fetched {
+            Ok(result) => {
+                let content = result.content::<Value>().map_err(|error| error.to_string())?;
+                Ok(Some((result.cas(), ChunkManifest::from_value(&content))))
+            }
+            Err(error) if is_document_not_found(&error) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn read_chunks(
+        &self,
+        key: &str,
+        manifest: &ChunkManifest,
+        keyspace: Keyspace,
+        options: &OperationOptions,
+    ) -> Result<Value, String> {
+        let keys: Vec<String> = (0..manifest.count)
+            .map(|index| chunk_key(key, &manifest.generation, index))
+            .collect();
+        let chunk_options = OperationOptions {
+            transcoder: Some(DEFAULT_TRANSCODER.to_string()),
+            ..options.clone()
+        };
+        let mut batch = Box::pin(self.get_documents_v2(keys.clone(), keyspace, chunk_options)).await?;
+        let chunks = keys
+            .iter()
+            .map(|chunk| batch.docs.remove(chunk).and_then(|document| document.value.as_str().map(str::to_string)))
+            .collect();
+        join_chunks(key, manifest, chunks)
+    }
+
+    // Removes the first `count` chunks of `generation`. Failures are only logged, the chunks are unreachable either way
+    async fn remove_chunks(
+        &self,
+        key: &str,
+        generation: &str,
+        count: usize,
+        keyspace: &Keyspace,
+        options: &OperationOptions,
+    ) {
+        let db = match self.collection(keyspace.clone()).await {
+            Ok(db) => db,
+            Err(error) => {
+                log::warn!("{}Chunks of {} not cleaned up : {}", options.log_prefix(), key, error);
+                return;
             }
-            Err(err) => {
-                errors.insert(
-                    key.to_string(),
-                    json!({
-                        "error": err.to_string()
-                    }),
+        };
+        for index in 0..count {
+            let removed = match enter_bulkhead(keyspace, options).await {
+                Ok(_permit) => db
+                    .remove(chunk_key(key, generation, index), RemoveOptions::default())
+                    .await
+                    .map_err(|error| error.to_string()),
+                Err(error) => Err(error),
+            };
+            if let Err(error) = removed {
+                if !is_document_not_found(&error) {
+                    log::warn!("{}Chunk {} of {} not cleaned up : {}", options.log_prefix(), index, key, error);
+                }
+            }
+        }
+    }
+
+    // CAS of the document without fetching its body, None when it does not exist
+    pub async fn document_cas(
+        &self,
+        key: String,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<Option<u64>, String> {
+        check_policy("exists")?;
+        let db = self.collection(keyspace.clone()).await?;
+
+        let mut exists_opt = ExistsOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            exists_opt = exists_opt.timeout(timeout);
+        }
+        match db.exists(key.to_owned(), exists_opt).await {
+            Ok(result) if result.exists() => Ok(*result.cas()),
+            Ok(_) => Ok(None),
+            Err(error) => {
+                let error = error.to_string();
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.document_cas(key, keyspace, options)).await;
+                }
+                log::error!("{}Error checking {} in {} : {:?}", options.log_prefix(), key, keyspace, error);
+                Err(error)
+            }
+        }
+    }
+
+    // CAS of every key without fetching bodies, so sync jobs can find what changed since their last run
+    pub async fn cas_snapshot(
+        &self,
+        keys: Vec<String>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<CasSnapshot, String> {
+        if keys.is_empty() {
+            return Err("Array of Keys need to be on length>0".to_string());
+        }
+        let mut checks = futures::stream::iter(keys.into_iter().map(|key| {
+            let keyspace = keyspace.clone();
+            let options = options.clone();
+            async move {
+                let cas = self.document_cas(key.clone(), keyspace, options).await;
+                (key, cas)
+            }
+        }))
+        .buffer_unordered(*BATCH_CONCURRENCY);
+
+        let mut snapshot = CasSnapshot::default();
+        while let Some((key, cas)) = checks.next().await {
+            match cas {
+                Ok(Some(cas)) => {
+                    snapshot.cas.insert(key, cas);
+                }
+                Ok(None) => snapshot.missing.push(key),
+                Err(error) => {
+                    snapshot.errors.insert(key, error);
+                }
+            }
+        }
+        Ok(snapshot)
+    }
+
+    // Unix seconds the document expires at, or None when it never expires
+    pub async fn get_document_expiry(
+        &self,
+        key: String,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<Option<u64>, String> {
+        self.expiry_with_cas(key, keyspace, options).await.map(|(expiry, _)| expiry)
+    }
+
+    // The body, CAS and expiry of one version of the document. The expiry is a second read, so a write landing
+    // between the two reads makes the CAS differ and the pair is read again
+    pub async fn get_document_with_meta(
+        &self,
+        key: String,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<DocumentWithMeta, String> {
+        for _ in 0..MAX_META_READS {
+            let document = self.get_document(key.clone(), keyspace.clone(), options.clone()).await?;
+            let (expiry, cas) = self.expiry_with_cas(key.clone(), keyspace.clone(), options.clone()).await?;
+            if cas == document.cas {
+                return Ok(DocumentWithMeta {
+                    value: document.value,
+                    cas,
+                    expiry,
+                });
+            }
+        }
+        Err(format!(
+            "Document {} kept changing while reading its expiry, gave up after {} reads",
+            key, MAX_META_READS
+        ))
+    }
+
+    // Expiry and CAS from META() on the query service. USE KEYS fetches by key, no index is needed
+    async fn expiry_with_cas(
+        &self,
+        key: String,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<(Option<u64>, u64), String> {
+        let statement = format!(
+            "SELECT META(d).expiration AS expiration, META(d).cas AS cas FROM {} AS d USE KEYS $1",
+            keyspace.query_path()
+        );
+        let rows = self
+            .query(statement, vec![json!(key)], Vec::new(), QuerySettings::default(), options)
+            .await?;
+        let row = rows
+            .first()
+            .ok_or_else(|| format!("Document not found: {} in {}", key, keyspace))?;
+        let cas = row
+            .get("cas")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("Unexpected meta row {}", row))?;
+        let expiration = row.get("expiration").and_then(Value::as_u64).unwrap_or(0);
+        Ok((if expiration == 0 { None } else { Some(expiration) }, cas))
+    }
+
+    // Reads the document and resets its TTL to `expiry` in one round trip
+    pub async fn get_and_touch(
+        &self,
+        key: String,
+        expiry: Duration,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<CasDocument, String> {
+        let transcoder = options.transcoder(&keyspace)?;
+        let db = self.collection(keyspace.clone()).await?;
+
+        let mut touch_opt = GetAndTouchOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            touch_opt = touch_opt.timeout(timeout);
+        }
+        let wire_expiry = touch_expiry(expiry)?;
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let fetched = match inject_fault(FaultOperation::Get, &key).await {
+            Some(fault) => Err(fault),
+            None => db
+                .get_and_touch(key.to_owned(), wire_expiry, touch_opt)
+                .await
+                .map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        match fetched {
+            Ok(get_result) => {
+                let data = get_result.content::<Value>().map_err(|error| error.to_string())?;
+                let data = transcoder.decode(data)?;
+                check_read_size(&key, &data);
+                Ok(CasDocument {
+                    value: data,
+                    cas: get_result.cas(),
+                })
+            }
+            Err(error) => {
+                log::error!("{}Error in get and touch from couchbase : {:?}", options.log_prefix(), error);
+                Err(error)
+            }
+        }
+    }
+
+    // Resets the TTL without rewriting the document, through get_and_touch with the body dropped
+    pub async fn touch(
+        &self,
+        key: String,
+        expiry: Duration,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<u64, String> {
+        check_policy("touch")?;
+        let _in_flight = check_writable("touch", &key)?;
+        record_access(&key, &keyspace);
+        let _cached = invalidate_on_write(&key, &keyspace);
+        let db = self.collection(keyspace.clone()).await?;
+
+        let mut touch_opt = GetAndTouchOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            touch_opt = touch_opt.timeout(timeout);
+        }
+        let wire_expiry = touch_expiry(expiry)?;
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let touched = db.get_and_touch(key.to_owned(), wire_expiry, touch_opt).await;
+        drop(permit);
+        match touched {
+            Ok(result) => Ok(result.cas()),
+            Err(error) => {
+                let error = error.to_string();
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.touch(key, expiry, keyspace, options)).await;
+                }
+                log::error!("{}Error in touching document in couchbase : {:?}", options.log_prefix(), error);
+                Err(error)
+            }
+        }
+    }
+
+    // Counter stored either as a bare number, the shape get_next_counter_key writes, or at COUNTER_PATH of a JSON
+    // document. Every write is conditioned on the CAS the counter was read at, so concurrent callers never hand out
+    // the same value and the read value plus the delta is the exact new value. A missing counter is created at
+    // `initial` as a bare number, without one the call fails with not found
+    pub async fn counter(
+        &self,
+        key: String,
+        change: CounterChange,
+        initial: Option<u64>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<CounterOutcome, String> {
+        check_policy("counter")?;
+        let _in_flight = check_writable("counter", &key)?;
+        record_access(&key, &keyspace);
+        let raw_options = OperationOptions {
+            transcoder: Some(DEFAULT_TRANSCODER.to_string()),
+            cached: false,
+            ..options.clone()
+        };
+        for _ in 0..MAX_COUNTER_ATTEMPTS {
+            let stored = match self.get_document(key.clone(), keyspace.clone(), raw_options.clone()).await {
+                Ok(stored) => stored,
+                Err(error) if is_document_not_found(&error) => {
+                    let initial = match initial {
+                        Some(initial) => initial,
+                        None => return Err(error),
+                    };
+                    match self
+                        .add_document(key.clone(), json!(initial), keyspace.clone(), raw_options.clone(), Some(0))
+                        .await
+                    {
+                        Ok(mutation) => return Ok(CounterOutcome { value: initial, mutation }),
+                        Err(error) if is_write_conflict(&error) => continue,
+                        Err(error) => return Err(error),
+                    }
+                }
+                Err(error) => return Err(error),
+            };
+            let current = match &stored.value {
+                Value::Object(fields) => fields.get(COUNTER_PATH).and_then(Value::as_u64),
+                bare => bare.as_u64(),
+            }
+            .ok_or_else(|| format!("Document {} is not a counter", key))?;
+            let (operation, delta, value) = match change {
+                CounterChange::Increment(delta) => (SubdocOperation::Increment, delta, current.saturating_add(delta)),
+                // Clamped here, subdoc counters would go below 0
+                CounterChange::Decrement(delta) => {
+                    let delta = delta.min(current);
+                    (SubdocOperation::Decrement, delta, current - delta)
+                }
+            };
+            if delta == 0 {
+                // The server rejects a zero delta, and there is nothing to write
+                return Ok(CounterOutcome {
+                    value,
+                    mutation: MutationOutcome::from_parts(stored.cas, None),
+                });
+            }
+            let written = if stored.value.is_object() {
+                let mutation = SubdocMutation {
+                    operation,
+                    path: COUNTER_PATH.to_string(),
+                    value: Some(json!(delta)),
+                    create_path: false,
+                    xattr: false,
+                };
+                self.mutate_in(key.clone(), vec![mutation], keyspace.clone(), Some(stored.cas), None, options.clone())
+                    .await
+            } else {
+                self.replace_document(
+                    key.clone(),
+                    json!(value),
+                    Some(stored.cas),
+                    keyspace.clone(),
+                    raw_options.clone(),
+                    Some(0),
+                )
+                .await
+            };
+            match written {
+                Ok(mutation) => return Ok(CounterOutcome { value, mutation }),
+                Err(error) if is_write_conflict(&error) => continue,
+                Err(error) => {
+                    log::error!(
+                        "{}Error in updating counter {} in couchbase : {:?}",
+                        options.log_prefix(),
+                        key,
+                        error
+                    );
+                    return Err(error);
+                }
+            }
+        }
+        Err(format!("Gave up updating counter {} after {} conflicting writes", key, MAX_COUNTER_ATTEMPTS))
+    }
+
+    pub async fn add_document(
+        &self,
+        key: String,
+        value: Value,
+        keyspace: Keyspace,
+        options: OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<MutationOutcome, String> {
+        check_policy("insert")?;
+        let _in_flight = check_writable("insert", &key)?;
+        record_access(&key, &keyspace);
+        let _cached = invalidate_on_write(&key, &keyspace);
+        check_key(&key, &keyspace.bucket)?;
+        validate_write(&key, &value, &keyspace, &options)?;
+        if chunking_enabled(&keyspace.bucket) && exceeds_write_limit(&value) {
+            return self.write_chunked(key, &value, None, StoreSemantics::Insert, keyspace, options, retry).await;
+        }
+        check_write_size(&key, &value)?;
+        let queued = options.queue_on_failure.then(|| QueuedOperation::Insert { value: value.clone() });
+        let outcome = match self
+            .insert_with_retries(key.clone(), value, keyspace.clone(), options.clone(), retry)
+            .await
+        {
+            Ok(outcome) => outcome,
+            Err(error) => return Err(queue_failed_write(queued, &key, &keyspace, &options, error)),
+        };
+        outcome.warn_if_retried("Insert", &key, &keyspace, &options);
+        Ok(outcome)
+    }
+
+    async fn insert_with_retries(
+        &self,
+        key: String,
+        value: Value,
+        keyspace: Keyspace,
+        options: OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<MutationOutcome, String> {
+        let stored = options.transcoder(&keyspace)?.encode(&value)?;
+        let retry = retry.unwrap_or(DEFAULT_WRITE_RETRIES);
+        let db = self.collection(keyspace.to_owned()).await?;
+
+        let mut insert_opt = InsertOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            insert_opt = insert_opt.timeout(timeout);
+        }
+        if let Some(expiry) = options.expiry {
+            insert_opt = insert_opt.expiry(expiry);
+        }
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let started = time::Instant::now();
+        let insert_result = match inject_fault(FaultOperation::Insert, &key).await {
+            Some(fault) => Err(fault),
+            None => db
+                .insert(key.clone(), stored, insert_opt)
+                .await
+                .map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        options.record_attempt(started);
+        self.observe_latency(&keyspace, started.elapsed()).await;
+        match insert_result {
+            Ok(result) => {
+                // log::info!("Data successfully added to couchbase for key: {}", key);
+                let outcome = MutationOutcome::from_result(&result);
+                record_mutation(JournalOp::Insert, &key, &keyspace, outcome.cas, Some(&value));
+                Ok(outcome)
+            }
+            Err(error) => {
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.insert_with_retries(key, value, keyspace, options, Some(retry))).await;
+                }
+                if is_ambiguous(&error) {
+                    match self.verify_ambiguous_write(&key, &value, None, keyspace.clone(), &options).await {
+                        WriteVerification::Applied(outcome) => {
+                            log::warn!(
+                                "{}Ambiguous insert of {} was applied, not retrying",
+                                options.log_prefix(),
+                                key
+                            );
+                            return Ok(outcome);
+                        }
+                        WriteVerification::NotApplied => {}
+                        WriteVerification::Unknown(reason) => return Err(reason),
+                    }
+                }
+                if retry <= 0 {
+                    record_retry(&keyspace.bucket, "insert", &error, RetryOutcome::RetriesExhausted);
+                    return Err(format!(
+                        "Error in adding data to couchbase : {:?}... retry limit reached",
+                        error.to_string()
+                    ));
+                }
+                let delay = retry_delay(&error, DEFAULT_WRITE_RETRIES.saturating_sub(retry));
+                if !options.has_time_for(delay) {
+                    record_retry(&keyspace.bucket, "insert", &error, RetryOutcome::DeadlineExhausted);
+                    return Err(format!(
+                        "Error in adding data to couchbase : {:?}... deadline reached",
+                        error.to_string()
+                    ));
+                }
+                log::error!(
+                    "{}Error in adding data to couchbase : {:?}... retrying",
+                    options.log_prefix(),
+                    error.to_string()
                 );
+                record_retry(&keyspace.bucket, "insert", &error, RetryOutcome::Retried);
+                time::sleep(delay).await;
+                let res = Box::pin(self.insert_with_retries(key, value, keyspace, options, Some(retry - 1))).await;
+                if let Ok(outcome) = res {
+                    return Ok(outcome.after_retry(delay));
+                }
+                Err(error.to_string())
             }
         }
     }
-    if errors.is_empty() {
-        log::info!("All documents fetched successfully");
-        Ok(json!(docs))
-    } else {
-        log::error!("Some documents failed to fetch");
-        return Err(format!("Error occured while fetching documents {:?} :  {:?}", keys, errors));
+
+    // Turns preserve_expiry into the document's remaining TTL, read before the write
+    async fn preserved_expiry(
+        &self,
+        key: &str,
+        keyspace: &Keyspace,
+        options: OperationOptions,
+    ) -> Result<OperationOptions, String> {
+        if !options.preserve_expiry || options.expiry.is_some() {
+            return Ok(options);
+        }
+        let expires_at = match self.get_document_expiry(key.to_string(), keyspace.clone(), options.clone()).await {
+            Ok(expires_at) => expires_at,
+            // Nothing to preserve, an upsert creates the document and a replace fails on its own
+            Err(error) if is_document_not_found(&error) => None,
+            Err(error) => return Err(error),
+        };
+        let expiry = expires_at.map(|expires_at| {
+            let now_secs = chrono::Utc::now().timestamp().max(0) as u64;
+            // Already due, still expire it rather than make it permanent
+            Duration::from_secs(expires_at.saturating_sub(now_secs).max(1))
+        });
+        Ok(OperationOptions { expiry, ..options })
     }
-}
 
-pub async fn get_documents_v2(keys: Vec<String>, with_cas: bool, bucket_name: String) -> Result<Value, String> {
-    let db = get_bucket_connection(bucket_name).await;
-    if let Err(err) = db {
-        return Err(format!("Error in getting bucket connection : {:?}", err));
+    // Writes the document whether or not it exists, retrying like add_document
+    pub async fn upsert_document(
+        &self,
+        key: String,
+        value: Value,
+        keyspace: Keyspace,
+        options: OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<MutationOutcome, String> {
+        check_policy("upsert")?;
+        let _in_flight = check_writable("upsert", &key)?;
+        record_access(&key, &keyspace);
+        let _cached = invalidate_on_write(&key, &keyspace);
+        check_key(&key, &keyspace.bucket)?;
+        validate_write(&key, &value, &keyspace, &options)?;
+        let options = self.preserved_expiry(&key, &keyspace, options).await?;
+        if chunking_enabled(&keyspace.bucket) && exceeds_write_limit(&value) {
+            return self.write_chunked(key, &value, None, StoreSemantics::Upsert, keyspace, options, retry).await;
+        }
+        check_write_size(&key, &value)?;
+        let queued = options.queue_on_failure.then(|| QueuedOperation::Upsert { value: value.clone() });
+        let outcome = match self
+            .upsert_with_retries(key.clone(), value, keyspace.clone(), options.clone(), retry)
+            .await
+        {
+            Ok(outcome) => outcome,
+            Err(error) => return Err(queue_failed_write(queued, &key, &keyspace, &options, error)),
+        };
+        outcome.warn_if_retried("Upsert", &key, &keyspace, &options);
+        Ok(outcome)
+    }
+
+    async fn upsert_with_retries(
+        &self,
+        key: String,
+        value: Value,
+        keyspace: Keyspace,
+        options: OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<MutationOutcome, String> {
+        let stored = options.transcoder(&keyspace)?.encode(&value)?;
+        let retry = retry.unwrap_or(DEFAULT_WRITE_RETRIES);
+        let db = self.collection(keyspace.to_owned()).await?;
+
+        let mut upsert_opt = UpsertOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            upsert_opt = upsert_opt.timeout(timeout);
+        }
+        if let Some(expiry) = options.expiry {
+            upsert_opt = upsert_opt.expiry(expiry);
+        }
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let started = time::Instant::now();
+        let upsert_result = match inject_fault(FaultOperation::Upsert, &key).await {
+            Some(fault) => Err(fault),
+            None => db
+                .upsert(key.clone(), stored, upsert_opt)
+                .await
+                .map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        options.record_attempt(started);
+        self.observe_latency(&keyspace, started.elapsed()).await;
+        match upsert_result {
+            Ok(result) => {
+                log::info!(
+                    "{}Data successfully upserted to couchbase for key: {} in {}",
+                    options.log_prefix(),
+                    key,
+                    keyspace
+                );
+                let outcome = MutationOutcome::from_result(&result);
+                record_mutation(JournalOp::Upsert, &key, &keyspace, outcome.cas, Some(&value));
+                Ok(outcome)
+            }
+            Err(error) => {
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.upsert_with_retries(key, value, keyspace, options, Some(retry))).await;
+                }
+                if is_ambiguous(&error) {
+                    match self.verify_ambiguous_write(&key, &value, None, keyspace.clone(), &options).await {
+                        WriteVerification::Applied(outcome) => {
+                            log::warn!(
+                                "{}Ambiguous upsert of {} was applied, not retrying",
+                                options.log_prefix(),
+                                key
+                            );
+                            return Ok(outcome);
+                        }
+                        WriteVerification::NotApplied => {}
+                        WriteVerification::Unknown(reason) => return Err(reason),
+                    }
+                }
+                if retry == 0 {
+                    record_retry(&keyspace.bucket, "upsert", &error, RetryOutcome::RetriesExhausted);
+                    return Err(format!(
+                        "Error in upserting data to couchbase : {:?}... retry limit reached",
+                        error.to_string()
+                    ));
+                }
+                let delay = retry_delay(&error, DEFAULT_WRITE_RETRIES.saturating_sub(retry));
+                if !options.has_time_for(delay) {
+                    record_retry(&keyspace.bucket, "upsert", &error, RetryOutcome::DeadlineExhausted);
+                    return Err(format!(
+                        "Error in upserting data to couchbase : {:?}... deadline reached",
+                        error.to_string()
+                    ));
+                }
+                log::error!(
+                    "{}Error in upserting data to couchbase : {:?}... retrying",
+                    options.log_prefix(),
+                    error.to_string()
+                );
+                record_retry(&keyspace.bucket, "upsert", &error, RetryOutcome::Retried);
+                time::sleep(delay).await;
+                let res = Box::pin(self.upsert_with_retries(key, value, keyspace, options, Some(retry - 1))).await;
+                if let Ok(outcome) = res {
+                    return Ok(outcome.after_retry(delay));
+                }
+                Err(error.to_string())
+            }
+        }
     }
-    let db = db.unwrap();
 
-    if keys.is_empty() {
-        return Err("Array of Keys need to be on length>0".to_string());
+    pub async fn replace_document(
+        &self,
+        key: String,
+        value: Value,
+        cas: Option<u64>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<MutationOutcome, String> {
+        check_policy("replace")?;
+        let _in_flight = check_writable("replace", &key)?;
+        record_access(&key, &keyspace);
+        let _cached = invalidate_on_write(&key, &keyspace);
+        validate_write(&key, &value, &keyspace, &options)?;
+        let options = self.preserved_expiry(&key, &keyspace, options).await?;
+        if chunking_enabled(&keyspace.bucket) && exceeds_write_limit(&value) {
+            return self.write_chunked(key, &value, cas, StoreSemantics::Replace, keyspace, options, retry).await;
+        }
+        check_write_size(&key, &value)?;
+        let queued = options
+            .queue_on_failure
+            .then(|| QueuedOperation::Replace { value: value.clone(), cas });
+        let outcome = match self
+            .replace_with_retries(key.clone(), value, cas, keyspace.clone(), options.clone(), retry)
+            .await
+        {
+            Ok(outcome) => outcome,
+            Err(error) => return Err(queue_failed_write(queued, &key, &keyspace, &options, error)),
+        };
+        outcome.warn_if_retried("Replace", &key, &keyspace, &options);
+        Ok(outcome)
     }
 
-    let mut docs: HashMap<String, Value> = HashMap::new();
-    let mut errors: HashMap<String, Value> = HashMap::new();
+    async fn replace_with_retries(
+        &self,
+        key: String,
+        value: Value,
+        cas: Option<u64>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+        retry: Option<u32>,
+    ) -> Result<MutationOutcome, String> {
+        let stored = options.transcoder(&keyspace)?.encode(&value)?;
+        let retry = retry.unwrap_or(DEFAULT_WRITE_RETRIES);
+        let db = self.collection(keyspace.to_owned()).await?;
+
+        let mut replace_opt;
+        if cas.is_some() {
+            replace_opt = ReplaceOptions::default().cas(cas.unwrap());
+        } else {
+            replace_opt = ReplaceOptions::default();
+        }
+        if let Some(timeout) = options.effective_timeout()? {
+            replace_opt = replace_opt.timeout(timeout);
+        }
+        if let Some(expiry) = options.expiry {
+            replace_opt = replace_opt.expiry(expiry);
+        }
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let started = time::Instant::now();
+        let update_data = match inject_fault(FaultOperation::Replace, &key).await {
+            Some(fault) => Err(fault),
+            None => db
+                .replace(key.to_owned(), stored, replace_opt)
+                .await
+                .map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        options.record_attempt(started);
+        self.observe_latency(&keyspace, started.elapsed()).await;
+        match update_data {
+            Ok(result) => {
+                log::info!(
+                    "{}Data successfully updated to couchbase for key: {} in {}",
+                    options.log_prefix(),
+                    key,
+                    keyspace
+                );
+                let outcome = MutationOutcome::from_result(&result);
+                record_mutation(JournalOp::Replace, &key, &keyspace, outcome.cas, Some(&value));
+                Ok(outcome)
+            }
+            Err(error) => {
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.replace_with_retries(key, value, cas, keyspace, options, Some(retry))).await;
+                }
+                if is_ambiguous(&error) {
+                    match self.verify_ambiguous_write(&key, &value, cas, keyspace.clone(), &options).await {
+                        WriteVerification::Applied(outcome) => {
+                            log::warn!(
+                                "{}Ambiguous replace of {} was applied, not retrying",
+                                options.log_prefix(),
+                                key
+                            );
+                            return Ok(outcome);
+                        }
+                        WriteVerification::NotApplied => {}
+                        WriteVerification::Unknown(reason) => return Err(reason),
+                    }
+                }
+                if retry <= 0 {
+                    record_retry(&keyspace.bucket, "replace", &error, RetryOutcome::RetriesExhausted);
+                    return Err(format!(
+                        "Error in updating data to couchbase : {:?}... retry limit reached",
+                        error.to_string()
+                    ));
+                }
+                let delay = retry_delay(&error, DEFAULT_WRITE_RETRIES.saturating_sub(retry));
+                if !options.has_time_for(delay) {
+                    record_retry(&keyspace.bucket, "replace", &error, RetryOutcome::DeadlineExhausted);
+                    return Err(format!(
+                        "Error in updating data to couchbase : {:?}... deadline reached",
+                        error.to_string()
+                    ));
+                }
+                log::error!(
+                    "{}Error in updating data to couchbase : {:?} in {}",
+                    options.log_prefix(),
+                    error.to_string(),
+                    keyspace
+                );
+                record_retry(&keyspace.bucket, "replace", &error, RetryOutcome::Retried);
+                time::sleep(delay).await;
+                let res = Box::pin(self.replace_with_retries(
+                    key.to_owned(),
+                    value,
+                    cas,
+                    keyspace.to_owned(),
+                    options,
+                    Some(retry - 1),
+                ))
+                .await;
+                if let Ok(outcome) = res {
+                    return Ok(outcome.after_retry(delay));
+                }
+                Err(error.to_string())
+            }
+        }
+    }
 
-    // Loop through each key
-    for key in &keys {
-        match db.get(key, GetOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-            Ok(res) => {
-                let data = res.content::<Value>().unwrap();
+    // Applies path level changes to one document. `store_semantics` decides whether a missing document is created
+    pub async fn mutate_in(
+        &self,
+        key: String,
+        mutations: Vec<SubdocMutation>,
+        keyspace: Keyspace,
+        cas: Option<u64>,
+        store_semantics: Option<StoreSemantics>,
+        options: OperationOptions,
+    ) -> Result<MutationOutcome, String> {
+        check_policy("mutateIn")?;
+        let _in_flight = check_writable("mutateIn", &key)?;
+        record_access(&key, &keyspace);
+        let _cached = invalidate_on_write(&key, &keyspace);
+        if mutations.is_empty() {
+            return Err("mutateIn needs at least one spec".to_string());
+        }
+        for value in mutations.iter().filter_map(|mutation| mutation.value.as_ref()) {
+            check_write_size(&key, value)?;
+        }
+        // Only these semantics can create the document
+        let creates = matches!(store_semantics, Some(StoreSemantics::Insert) | Some(StoreSemantics::Upsert));
+        if creates {
+            check_key(&key, &keyspace.bucket)?;
+        }
+        validate_subdoc_write(&key, &mutations, creates, &keyspace, &options)?;
+        let specs = mutations
+            .iter()
+            .map(SubdocMutation::to_spec)
+            .collect::<Result<Vec<MutateInSpec>, String>>()?;
+        let db = self.collection(keyspace.to_owned()).await?;
+
+        let mut mutate_opt = MutateInOptions::default();
+        if let Some(cas) = cas {
+            mutate_opt = mutate_opt.cas(cas);
+        }
+        if let Some(store_semantics) = &store_semantics {
+            mutate_opt = mutate_opt.store_semantics(copy_store_semantics(store_semantics));
+        }
+        if let Some(timeout) = options.effective_timeout()? {
+            mutate_opt = mutate_opt.timeout(timeout);
+        }
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let mutated = match inject_fault(FaultOperation::MutateIn, &key).await {
+            Some(fault) => Err(fault),
+            None => db
+                .mutate_in(key.to_owned(), specs, mutate_opt)
+                .await
+                .map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        match mutated {
+            Ok(result) => {
+                log::info!(
+                    "{}Subdoc mutation applied in couchbase for key: {} in {}",
+                    options.log_prefix(),
+                    key,
+                    keyspace
+                );
+                let outcome = MutationOutcome::from_mutate_in(&result);
+                if journal_enabled() {
+                    let specs = mutation_specs(&mutations);
+                    record_mutation(JournalOp::MutateIn, &key, &keyspace, outcome.cas, Some(&specs));
+                }
+                Ok(outcome)
+            }
+            Err(error) => {
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.mutate_in(key, mutations, keyspace, cas, store_semantics, options)).await;
+                }
+                log::error!(
+                    "{}Error in mutating data in couchbase : {:?} in {}",
+                    options.log_prefix(),
+                    error,
+                    keyspace
+                );
+                if !is_ambiguous(&error) {
+                    return Err(error);
+                }
+                // Counters and array appends are not idempotent, so only an unchanged CAS proves it is safe to retry
+                if let Some(cas) = cas {
+                    if let Ok(Some(current)) = self.document_cas(key.clone(), keyspace, options.clone()).await {
+                        if current == cas {
+                            return Err(format!("Mutation of {} was not applied, safe to retry : {}", key, error));
+                        }
+                    }
+                }
+                Err(format!(
+                    "DURABILITY_AMBIGUOUS: mutation of {} may have been applied, read the document before retrying : {}",
+                    key, error
+                ))
+            }
+        }
+    }
 
-                if with_cas {
+    // get/merge/replace-with-CAS until no other writer got in between. A missing document is created from `partial`
+    pub async fn merge_document(
+        &self,
+        key: String,
+        partial: Value,
+        strategy: MergeStrategy,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<MutationOutcome, String> {
+        for _ in 0..MAX_MERGE_ATTEMPTS {
+            let written = match self.get_document(key.clone(), keyspace.clone(), options.clone()).await {
+                Ok(stored) => {
+                    let merged = merge_values(stored.value, partial.clone(), strategy);
+                    self.replace_document(
+                        key.clone(),
+                        merged,
+                        Some(stored.cas),
+                        keyspace.clone(),
+                        options.clone(),
+                        Some(0),
+                    )
+                    .await
+                }
+                Err(error) if is_document_not_found(&error) => {
+                    self.add_document(key.clone(), partial.clone(), keyspace.clone(), options.clone(), Some(0))
+                        .await
+                }
+                Err(error) => return Err(error),
+            };
+            match written {
+                Ok(outcome) => return Ok(outcome),
+                Err(error) if is_write_conflict(&error) => {
+                    log::warn!("{}Concurrent write on {} while merging... retrying", options.log_prefix(), key);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(format!("Gave up merging {} after {} conflicting writes", key, MAX_MERGE_ATTEMPTS))
+    }
+
+    // Appends one point to the bucket document covering `timestamp`, creating the bucket on first use
+    pub async fn append_time_series(
+        &self,
+        series_key: String,
+        timestamp: i64,
+        value: Value,
+        granularity: TimeSeriesGranularity,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<MutationOutcome, String> {
+        let start = granularity.bucket_start(timestamp);
+        let key = bucket_key(&series_key, start);
+        let point = json!([timestamp, value]);
+        // Without create_path an append needs the bucket to exist, a missing one is inserted whole and an
+        // insert losing the race to another writer appends instead
+        for _ in 0..MAX_MERGE_ATTEMPTS {
+            let append = SubdocMutation {
+                operation: SubdocOperation::ArrayAppend,
+                path: "points".to_string(),
+                // Wrapped once more, array appends spread a top level array into separate elements
+                value: Some(json!([point])),
+                create_path: false,
+                xattr: false,
+            };
+            match self
+                .mutate_in(key.clone(), vec![append], keyspace.clone(), None, None, options.clone())
+                .await
+            {
+                Err(error) if is_document_not_found(&error) => {}
+                written => return written,
+            }
+            let bucket = json!({ "series": series_key, "start": start, "points": [point] });
+            match self
+                .add_document(key.clone(), bucket, keyspace.clone(), options.clone(), Some(0))
+                .await
+            {
+                Err(error) if is_write_conflict(&error) => continue,
+                written => return written,
+            }
+        }
+        Err(format!("Gave up appending to {} after {} conflicting writes", key, MAX_MERGE_ATTEMPTS))
+    }
+
+    // Points with `from <= timestamp <= to` in timestamp order, buckets that were never written are skipped
+    pub async fn query_time_series(
+        &self,
+        series_key: String,
+        from: i64,
+        to: i64,
+        granularity: TimeSeriesGranularity,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<Vec<(i64, Value)>, String> {
+        let keys = granularity
+            .bucket_starts(from, to)?
+            .into_iter()
+            .map(|start| bucket_key(&series_key, start))
+            .collect::<Vec<String>>();
+        let mut fetches = futures::stream::iter(keys.into_iter().map(|key| {
+            let keyspace = keyspace.clone();
+            let options = options.clone();
+            async move { self.get_document(key, keyspace, options).await }
+        }))
+        .buffer_unordered(*BATCH_CONCURRENCY);
+
+        let mut points = Vec::new();
+        while let Some(fetched) = fetches.next().await {
+            match fetched {
+                Ok(bucket) => points.extend(points_in_range(&bucket.value, from, to)),
+                Err(error) if is_document_not_found(&error) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        points.sort_by_key(|(timestamp, _)| *timestamp);
+        Ok(points)
+    }
+
+    // With `cas` the remove fails with a CAS mismatch if the document changed since it was read
+    pub async fn delete_data(
+        &self,
+        key: String,
+        cas: Option<u64>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<MutationOutcome, String> {
+        check_policy("remove")?;
+        let _in_flight = check_writable("remove", &key)?;
+        record_access(&key, &keyspace);
+        let _cached = invalidate_on_write(&key, &keyspace);
+        // The manifest names the chunks to remove once the key itself is gone
+        let manifest = if chunking_enabled(&keyspace.bucket) {
+            match self.stored_version(&key, &keyspace, &options).await {
+                Ok(stored) => stored.and_then(|(_, manifest)| manifest),
+                Err(_) => None,
+            }
+        } else {
+            None
+        };
+        let db = self.collection(keyspace.to_owned()).await?;
+
+        let mut remove_opt = RemoveOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            remove_opt = remove_opt.timeout(timeout);
+        }
+        if let Some(cas) = cas {
+            remove_opt = remove_opt.cas(cas);
+        }
+        let permit = enter_bulkhead(&keyspace, &options).await?;
+        let started = time::Instant::now();
+        let delete_data = match inject_fault(FaultOperation::Remove, &key).await {
+            Some(fault) => Err(fault),
+            None => db.remove(key.to_owned(), remove_opt).await.map_err(|error| error.to_string()),
+        };
+        drop(permit);
+        options.record_attempt(started);
+        self.observe_latency(&keyspace, started.elapsed()).await;
+        match delete_data {
+            Ok(result) => {
+                log::info!(
+                    "{}Data successfully deleted from couchbase for key: {} in {}",
+                    options.log_prefix(),
+                    key,
+                    keyspace
+                );
+                let outcome = MutationOutcome::from_result(&result);
+                record_mutation(JournalOp::Remove, &key, &keyspace, outcome.cas, None);
+                if let Some(manifest) = manifest {
+                    self.remove_chunks(&key, &manifest.generation, manifest.count, &keyspace, &options).await;
+                }
+                Ok(outcome)
+            }
+            Err(error) => {
+                if self.reopen_missing_keyspace(&keyspace, &error).await {
+                    return Box::pin(self.delete_data(key, cas, keyspace, options)).await;
+                }
+                log::error!(
+                    "{}Error in deleting data from couchbase : {:?} in {}",
+                    options.log_prefix(),
+                    error.to_string(),
+                    keyspace
+                );
+                let queued = Some(QueuedOperation::Remove { cas });
+                Err(queue_failed_write(queued, &key, &keyspace, &options, error))
+            }
+        }
+    }
+
+    pub async fn get_documents(
+        &self,
+        keys: Vec<String>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<HashMap<String, CasDocument>, String> {
+        let batch = self.get_documents_v2(keys.clone(), keyspace, options).await?;
+        if batch.errors.is_empty() {
+            log::info!("All documents fetched successfully");
+            Ok(batch.docs)
+        } else {
+            log::error!("Some documents failed to fetch");
+            return Err(format!("Error occured while fetching documents {:?} :  {:?}", keys, batch.errors));
+        }
+    }
+
+    pub async fn get_documents_v2(
+        &self,
+        keys: Vec<String>,
+        keyspace: Keyspace,
+        options: OperationOptions,
+    ) -> Result<BatchDocuments, String> {
+        check_policy("getBatch")?;
+        let bucket_name = keyspace.bucket.clone();
+        for key in &keys {
+            record_access(key, &keyspace);
+        }
+        let chunk_keyspace = keyspace.clone();
+        let transcoder = options.transcoder(&keyspace)?;
+        let db = self
+            .collection(keyspace)
+            .await
+            .map_err(|err| format!("Error in getting bucket connection : {:?}", err))?;
+
+        if keys.is_empty() {
+            return Err("Array of Keys need to be on length>0".to_string());
+        }
+
+        let mut docs: HashMap<String, CasDocument> = HashMap::new();
+        let mut errors: HashMap<String, String> = HashMap::new();
+        let mut sources: HashMap<String, String> = HashMap::new();
+        let timeout = options.effective_timeout()?.unwrap_or(*OPERATION_TIMEOUT);
+        // In partial mode the timeout bounds the whole batch rather than each get
+        let batch_deadline = options.partial_on_timeout.then(|| time::Instant::now() + timeout);
+        let requested = options.partial_on_timeout.then(|| keys.clone());
+
+        // The SDK has no bulk get, so singles are issued concurrently and pipelined over the shared connection
+        let db = &db;
+        let (bulkhead_keyspace, bulkhead_options) = (&chunk_keyspace, &options);
+        let mut fetches = futures::stream::iter(keys.into_iter().map(|key| async move {
+            let permit = match enter_bulkhead(bulkhead_keyspace, bulkhead_options).await {
+                Ok(permit) => permit,
+                Err(error) => return (key, Err(error)),
+            };
+            let fetched = match inject_fault(FaultOperation::Get, &key).await {
+                Some(fault) => Err(fault),
+                None => db
+                    .get(&key, GetOptions::default().timeout(timeout))
+                    .await
+                    .map_err(|error| error.to_string()),
+            };
+            drop(permit);
+            (key, fetched)
+        }))
+        .buffer_unordered(*BATCH_CONCURRENCY);
+
+        let mut timed_out = false;
+        loop {
+            let next = match batch_deadline {
+                Some(deadline) => match time::timeout_at(deadline, fetches.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        timed_out = true;
+                        break;
+                    }
+                },
+                None => fetches.next().await,
+            };
+            let (key, fetched) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            let fetched = fetched.and_then(|res| {
+                let content = res.content::<Value>().map_err(|error| error.to_string())?;
+                // Manifests are stored as plain JSON whatever the transcoder, they are reassembled below
+                let data = match chunk_manifest(&bucket_name, &content) {
+                    Some(_) => content,
+                    None => transcoder.decode(content)?,
+                };
+                Ok((data, res.cas()))
+            });
+            match fetched {
+                Ok((data, cas)) => {
+                    check_read_size(&key, &data);
                     docs.insert(
                         key.to_string(),
-                        json!({
-                            "value": data,
-                            "cas": res.cas()
-                        }),
+                        CasDocument { value: data, cas },
                     );
-                } else {
-                    docs.insert(key.to_string(), json!(data));
+                    sources.insert(key.to_string(), bucket_name.to_owned());
+                }
+                Err(err) => {
+                    errors.insert(key.to_string(), err.to_string());
                 }
             }
-            Err(err) => {
-                errors.insert(
-                    key.to_string(),
-                    json!({
-                        "error": err.to_string()
-                    }),
+        }
+        // Cancels the gets still in flight after a timeout
+        drop(fetches);
+        if timed_out {
+            let mut unfinished = 0;
+            for key in requested.unwrap_or_default() {
+                if !docs.contains_key(&key) && !errors.contains_key(&key) {
+                    errors.insert(key, "TIMEOUT: batch deadline reached before the document was fetched".to_string());
+                    unfinished += 1;
+                }
+            }
+            log::warn!(
+                "{}Batch deadline reached, returning {} documents with {} keys unfetched",
+                options.log_prefix(),
+                docs.len(),
+                unfinished
+            );
+        }
+        if chunking_enabled(&bucket_name) {
+            let chunked: Vec<(String, ChunkManifest)> = docs
+                .iter()
+                .filter_map(|(key, document)| ChunkManifest::from_value(&document.value).map(|manifest| (key.clone(), manifest)))
+                .collect();
+            for (key, manifest) in chunked {
+                match self.read_chunks(&key, &manifest, chunk_keyspace.clone(), &options).await {
+                    Ok(value) => {
+                        if let Some(document) = docs.get_mut(&key) {
+                            document.value = value;
+                        }
+                    }
+                    Err(error) => {
+                        docs.remove(&key);
+                        sources.remove(&key);
+                        errors.insert(key, error);
+                    }
+                }
+            }
+        }
+        Ok(BatchDocuments { docs, errors, sources })
+    }
+
+    // Keys missing from the primary keyspace are looked up again in `fallback` (e.g. an archive bucket)
+    pub async fn get_documents_with_fallback(
+        &self,
+        keys: Vec<String>,
+        keyspace: Keyspace,
+        fallback: Keyspace,
+        options: OperationOptions,
+    ) -> Result<BatchDocuments, String> {
+        let mut batch = self.get_documents_v2(keys, keyspace, options.clone()).await?;
+        let missing: Vec<String> = batch
+            .errors
+            .iter()
+            .filter(|(_, error)| is_document_not_found(error))
+            .map(|(key, _)| key.to_owned())
+            .collect();
+        if missing.is_empty() {
+            return Ok(batch);
+        }
+
+        log::info!("Looking up {} missing keys in fallback bucket : {}", missing.len(), fallback.bucket);
+        // Keys the fallback can't serve either keep the primary's error
+        let fallback_batch = match self.get_documents_v2(missing, fallback, options.clone()).await {
+            Ok(fallback_batch) => fallback_batch,
+            // The primary used up the deadline, what it fetched is still returned
+            Err(error) if options.partial_on_timeout => {
+                log::warn!("{}Fallback lookup skipped : {}", options.log_prefix(), error);
+                return Ok(batch);
+            }
+            Err(error) => return Err(error),
+        };
+        for (key, document) in fallback_batch.docs {
+            batch.errors.remove(&key);
+            batch.docs.insert(key, document);
+        }
+        batch.sources.extend(fallback_batch.sources);
+        Ok(batch)
+    }
+
+    // Runs a N1QL statement, waiting for the index to catch up with `consistent_with` when tokens are given
+    pub async fn query(
+        &self,
+        statement: String,
+        parameters: Vec<Value>,
+        consistent_with: Vec<DocumentMutationToken>,
+        settings: QuerySettings,
+        options: OperationOptions,
+    ) -> Result<Vec<Value>, String> {
+        check_policy("query")?;
+        let request_plus = settings.scan_consistency.as_deref() == Some("request_plus");
+        let cache = match settings.cache_ttl {
+            Some(ttl) if consistent_with.is_empty() && !request_plus => {
+                let cache_key = query_cache_key(&statement, &parameters, &settings);
+                if !settings.bypass_cache {
+                    if let Some(rows) = cached_rows(&cache_key) {
+                        log::debug!("{}Query served from cache : {}", options.log_prefix(), statement);
+                        return Ok(rows);
+                    }
+                }
+                Some((cache_key, ttl))
+            }
+            _ => None,
+        };
+        let cluster = self.cluster().await?;
+
+        let mut query_opt = QueryOptions::default();
+        if let Some(timeout) = options.effective_timeout()? {
+            query_opt = query_opt.timeout(timeout);
+        }
+        if !parameters.is_empty() {
+            query_opt = query_opt.positional_parameters(parameters);
+        }
+        // Extra request fields go in one map, `raw` replaces whatever an earlier call set. MutationState can't be
+        // built outside the SDK, so consistency tokens are sent as scan vectors here instead of `consistent_with`
+        let mut raw = serde_json::Map::new();
+        if !consistent_with.is_empty() {
+            // Scan vectors use the query service wire format: { bucket: { vbucket: [seqno, "vbuuid"] } }
+            let mut scan_vectors: HashMap<String, HashMap<String, Value>> = HashMap::new();
+            for token in consistent_with {
+                scan_vectors.entry(token.bucket_name).or_default().insert(
+                    token.partition_id.to_string(),
+                    json!([token.sequence_number, token.partition_uuid.to_string()]),
                 );
             }
+            raw.insert("scan_consistency".to_string(), json!("at_plus"));
+            raw.insert("scan_vectors".to_string(), json!(scan_vectors));
+        } else if let Some(scan_consistency) = settings.scan_consistency {
+            raw.insert("scan_consistency".to_string(), Value::String(scan_consistency));
+        }
+        if let Some(query_context) = settings.query_context {
+            raw.insert("query_context".to_string(), Value::String(query_context));
+        }
+        // Named arguments travel as top-level "$name" fields of the query request
+        for (name, value) in settings.named_parameters {
+            raw.insert(format!("${}", name.trim_start_matches('$')), value);
+        }
+        if !raw.is_empty() {
+            query_opt = query_opt.raw(raw);
+        }
+
+        let mut result = cluster.query(statement.to_owned(), query_opt).await.map_err(|error| {
+            log::error!("{}Error in running query {} : {:?}", options.log_prefix(), statement, error.to_string());
+            error.to_string()
+        })?;
+        let mut rows = Vec::new();
+        let mut stream = result.rows::<Value>();
+        while let Some(row) = stream.next().await {
+            rows.push(row.map_err(|error| error.to_string())?);
         }
+        if let Some((cache_key, ttl)) = cache {
+            store_rows(cache_key, &rows, ttl);
+        }
+        Ok(rows)
+    }
+
+    // Keys are paged through META().id on the query service, see ListKeysOptions for the index this needs
+    pub async fn list_keys(
+        &self,
+        prefix: String,
+        keyspace: Keyspace,
+        limit: u32,
+        start_after: Option<String>,
+        options: OperationOptions,
+    ) -> Result<KeyPage, String> {
+        let pattern = like_prefix(&prefix);
+        let statement = format!(
+            "SELECT RAW META(d).id FROM {} AS d WHERE META(d).id LIKE $1 AND META(d).id > $2 ORDER BY META(d).id LIMIT $3",
+            keyspace.query_path()
+        );
+        // One extra row tells whether another page exists
+        let parameters = vec![json!(pattern), json!(start_after.unwrap_or_default()), json!(limit + 1)];
+        let rows = self
+            .query(statement, parameters, Vec::new(), QuerySettings::default(), options)
+            .await?;
+        let mut keys = rows
+            .into_iter()
+            .map(|row| row.as_str().map(str::to_string).ok_or_else(|| format!("Unexpected key row {}", row)))
+            .collect::<Result<Vec<String>, String>>()?;
+        let next_start_after = if keys.len() > limit as usize {
+            keys.truncate(limit as usize);
+            keys.last().cloned()
+        } else {
+            None
+        };
+        Ok(KeyPage { keys, next_start_after })
     }
-    Ok(json!({
-        "docs":docs,
-        "errors":errors
-    }))
+
+    // Groups the prefix's documents by time to expiry on the query service, `interval_secs` wide slots up to
+    // `horizon_secs` ahead. Needs the keyspace's primary index like list_keys
+    pub async fn analyze_expiry(
+        &self,
+        prefix: String,
+        keyspace: Keyspace,
+        interval_secs: i64,
+        horizon_secs: i64,
+        options: OperationOptions,
+    ) -> Result<ExpiryHistogram, String> {
+        if interval_secs <= 0 || horizon_secs <= 0 {
+            return Err("analyzeExpiry interval and horizon must be positive".to_string());
+        }
+        let now_secs = chrono::Utc::now().timestamp();
+        let slot = "CASE WHEN META(d).expiration = 0 THEN NULL ELSE FLOOR((META(d).expiration - $2) / $3) END";
+        let statement = format!(
+            "SELECT {} AS slot, COUNT(*) AS count FROM {} AS d WHERE META(d).id LIKE $1 GROUP BY {}",
+            slot,
+            keyspace.query_path(),
+            slot
+        );
+        let parameters = vec![json!(like_prefix(&prefix)), json!(now_secs), json!(interval_secs)];
+        let rows = self
+            .query(statement, parameters, Vec::new(), QuerySettings::default(), options)
+            .await?;
+        Ok(expiry_histogram(&rows, now_secs, interval_secs, horizon_secs))
+    }
+
+    // Runs a search service request through SEARCH() on the query service, so no separate search connection
+    // is needed. Returns (id, document) pairs
+    pub async fn search(
+        &self,
+        index_name: String,
+        request: Value,
+        keyspace: Keyspace,
+        limit: u32,
+        options: OperationOptions,
+    ) -> Result<Vec<(String, Value)>, String> {
+        check_policy("search")?;
+        self.require_feature(ClusterFeature::Search).await?;
+        let statement = format!(
+            "SELECT META(d).id AS id, d AS document FROM {} AS d WHERE SEARCH(d, $1, $2) LIMIT $3",
+            keyspace.query_path()
+        );
+        let parameters = vec![request, json!({ "index": index_name }), json!(limit)];
+        let rows = self
+            .query(statement, parameters, Vec::new(), QuerySettings::default(), options)
+            .await?;
+        rows.into_iter()
+            .map(|mut row| match row["id"].as_str().map(str::to_string) {
+                Some(id) => Ok((id, row["document"].take())),
+                None => Err(format!("Unexpected search row {}", row)),
+            })
+            .collect()
+    }
+}
+
+pub async fn get_bucket_connection(bucket_name: String) -> Result<Arc<Collection>, String> {
+    CB_CONNECTION.collection(Keyspace::bucket(bucket_name)).await
+}
+
+pub async fn get_collection_connection(keyspace: Keyspace) -> Result<Arc<Collection>, String> {
+    CB_CONNECTION.collection(keyspace).await
+}
+
+pub async fn get_document(key: String, keyspace: Keyspace, options: OperationOptions) -> Result<CasDocument, String> {
+    CB_CONNECTION.get_document(key, keyspace, options).await
+}
+
+pub async fn get_document_expiry(key: String, keyspace: Keyspace, options: OperationOptions) -> Result<Option<u64>, String> {
+    CB_CONNECTION.get_document_expiry(key, keyspace, options).await
+}
+
+pub async fn get_document_with_meta(key: String, keyspace: Keyspace, options: OperationOptions) -> Result<DocumentWithMeta, String> {
+    CB_CONNECTION.get_document_with_meta(key, keyspace, options).await
+}
+
+pub async fn lookup_in(
+    key: String,
+    paths: Vec<String>,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<HashMap<String, Value>, String> {
+    CB_CONNECTION.lookup_paths(key, paths, keyspace, options).await
+}
+
+// Reads the document and resets its TTL to `ttl_secs` in one round trip
+pub async fn get_and_touch(key: String, ttl_secs: u64, keyspace: Keyspace, options: OperationOptions) -> Result<CasDocument, String> {
+    CB_CONNECTION.get_and_touch(key, Duration::from_secs(ttl_secs), keyspace, options).await
+}
+
+// Sets the document's TTL to `ttl_secs` without re-sending it, 0 removes the expiry. Returns the new CAS.
+pub async fn touch_document(key: String, ttl_secs: u64, keyspace: Keyspace, options: OperationOptions) -> Result<u64, String> {
+    CB_CONNECTION.touch(key, Duration::from_secs(ttl_secs), keyspace, options).await
+}
+
+pub async fn increment_counter(
+    key: String,
+    delta: u64,
+    initial: Option<u64>,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<CounterOutcome, String> {
+    CB_CONNECTION.counter(key, CounterChange::Increment(delta), initial, keyspace, options).await
+}
+
+pub async fn decrement_counter(
+    key: String,
+    delta: u64,
+    initial: Option<u64>,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<CounterOutcome, String> {
+    CB_CONNECTION.counter(key, CounterChange::Decrement(delta), initial, keyspace, options).await
+}
+
+// CAS of the document when it exists, without fetching it
+pub async fn exists(key: String, keyspace: Keyspace, options: OperationOptions) -> Result<Option<u64>, String> {
+    CB_CONNECTION.document_cas(key, keyspace, options).await
+}
+
+pub async fn add_document(
+    key: String,
+    value: Value,
+    keyspace: Keyspace,
+    options: OperationOptions,
+    retry: Option<u32>,
+) -> Result<MutationOutcome, String> {
+    CB_CONNECTION.add_document(key, value, keyspace, options, retry).await
+}
+
+// Inserts a document that expires `ttl_secs` after the write
+pub async fn add_document_with_ttl(
+    key: String,
+    value: Value,
+    ttl_secs: u64,
+    keyspace: Keyspace,
+    options: OperationOptions,
+    retry: Option<u32>,
+) -> Result<MutationOutcome, String> {
+    let options = OperationOptions {
+        expiry: Some(Duration::from_secs(ttl_secs)),
+        ..options
+    };
+    CB_CONNECTION.add_document(key, value, keyspace, options, retry).await
+}
+
+pub async fn upsert_document(
+    key: String,
+    value: Value,
+    keyspace: Keyspace,
+    options: OperationOptions,
+    retry: Option<u32>,
+) -> Result<MutationOutcome, String> {
+    CB_CONNECTION.upsert_document(key, value, keyspace, options, retry).await
 }
 
+pub async fn replace_document(
+    key: String,
+    value: Value,
+    cas: Option<u64>,
+    keyspace: Keyspace,
+    options: OperationOptions,
+    retry: Option<u32>,
+) -> Result<MutationOutcome, String> {
+    CB_CONNECTION.replace_document(key, value, cas, keyspace, options, retry).await
+}
+
+// With `cas` only that version is removed, a document changed since fails with a CAS mismatch
+pub async fn delete_data(
+    key: String,
+    cas: Option<u64>,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<MutationOutcome, String> {
+    CB_CONNECTION.delete_data(key, cas, keyspace, options).await
+}
+
+pub async fn get_documents(
+    keys: Vec<String>,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<HashMap<String, CasDocument>, String> {
+    CB_CONNECTION.get_documents(keys, keyspace, options).await
+}
+
+pub async fn get_documents_v2(keys: Vec<String>, keyspace: Keyspace, options: OperationOptions) -> Result<BatchDocuments, String> {
+    CB_CONNECTION.get_documents_v2(keys, keyspace, options).await
+}
+
+pub async fn query(
+    statement: String,
+    parameters: Vec<Value>,
+    consistent_with: Vec<DocumentMutationToken>,
+    settings: QuerySettings,
+    options: OperationOptions,
+) -> Result<Vec<Value>, String> {
+    CB_CONNECTION.query(statement, parameters, consistent_with, settings, options).await
+}
+
+// Read then write, so concurrent callers can get the same value. increment_counter is the atomic alternative and
+// continues the counters written here
 pub async fn get_next_counter_key(bucket_name: String, key: String, initial_counter: Option<u32>) -> Result<String, String> {
+    check_policy("counter")?;
+    let _in_flight = check_writable("counter", &key)?;
+    let _cached = invalidate_on_write(&key, &Keyspace::bucket(bucket_name.clone()));
     // Try to get existing document
     let db = get_bucket_connection(bucket_name).await;
     if let Err(err) = db {
@@ -442,495 +2753,3 @@ pub async fn get_next_counter_key(bucket_name: String, key: String, initial_coun
 pub fn get_next_key() -> String {
   Uuid::new_v4().to_string()
 }
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-//------------------------------------------------------
-
-
-// use couchbase::{Cluster, Collection, GetOptions, InsertOptions, RemoveOptions, ReplaceOptions, UpsertOptions};
-// use lazy_static::lazy_static;
-// use serde_json::{json, Value};
-// use std::{collections::HashMap, sync::Arc, time::Duration};
-// use tokio::{
-//     sync::RwLock,
-//     time::{self},
-// };
-// use uuid::Uuid;
-
-// use crate::configuration as config;
-// #[derive(serde::Serialize)]
-// pub struct Message<T> {
-//     status: u32,
-//     message_key: String,
-//     data: T,
-// }
-
-// pub struct CouchbaseConnParams {
-//     pub connection_url: String,
-//     pub username: String,
-//     pub password: String,
-// }
-
-// pub fn uuid() -> Uuid {
-//     Uuid::new_v4()
-// }
-
-// lazy_static! {
-//     static ref CB_CONNECTION: Arc<Cluster> = create_cluster_connection();
-//     static ref BUCKET_CONNECTIONS: RwLock<HashMap<String, Arc<Collection>>> = RwLock::new(HashMap::new());
-//     static ref OPERATION_TIMEOUT: Duration = Duration::from_secs(120);
-// }
-
-// pub fn create_cluster_connection() -> Arc<Cluster> {
-//     // let cluster = Cluster::connect(config::get::<String>("couchbase.connectionurl"), config::get::<String>("couchbase.username"), config::get::<String>("couchbase.password"));
-//     let connection_url = std::env::var("CONNECTION_URL").expect("CONNECTION_URL must be set");
-//     let username = std::env::var("USERNAME").expect("USERNAME must be set");
-//     let password = std::env::var("PASSWORD").expect("PASSWORD must be set");
-//     let cluster = Cluster::connect(connection_url, username, password);
-    
-//     Arc::new(cluster)
-// }
-
-// pub fn init_couchbase_connection() {
-//     let _ = CB_CONNECTION.clone();
-// }
-
-// pub async fn get_bucket_connection(bucket_name: String) -> Result<Arc<Collection>, String> {
-//     // Ensure we initialize the Couchbase connection first
-//     // let init_cb = init_couchbase_connection(None).await;
-//     // if let Err(err) = init_cb {
-//     //     return Err(format!("Failed to initialize Couchbase connection: {}", err));
-//     // }
-
-//     // Try to get the connection from the map
-//     if let Some(collection) = BUCKET_CONNECTIONS.read().await.get(&bucket_name) {
-//         // Connection already exists, so reuse it
-//         return Ok(Arc::clone(collection));
-//     }
-
-//     // If the connection doesn't exist, acquire a write lock to add it
-//     log::info!("Creating new connection for bucket: {}", bucket_name);
-
-//     // Check if the connection is still available
-//     // let cluster = CB_CONNECTION.get().ok_or_else(|| "No connection to cluster available".to_string())?;
-
-//     let bucket = CB_CONNECTION.bucket(&bucket_name);
-//     let collection = Arc::new(bucket.default_collection());
-
-//     // Insert the new connection into the map, ensuring only one write operation is done
-//     BUCKET_CONNECTIONS.write().await.insert(bucket_name, Arc::clone(&collection));
-
-//     Ok(collection)
-// }
-
-// // pub async fn get_bucket_connection(bucket_name: String) -> Result<Collection, String> {
-// //     // First try to initialzie connection with cluster
-// //     let init=init_couchbase_connection(None).await;
-
-// //     let bucket = cluster.bucket(bucket_name.clone());
-// //     let collection = bucket.default_collection();
-
-// //     Ok(collection)
-// // }
-
-// pub async fn get_document(key: String, with_cas: bool, bucket_name: String,retry: Option<u32>) -> Result<Value, String> {
-//     let db = get_bucket_connection(bucket_name.to_owned()).await;
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-//     let retry = retry.unwrap_or(5);
-
-//     match db.get(key.to_owned(), GetOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-//         Ok(get_result) => {
-//             let mut data = get_result.content::<Value>().unwrap();
-//             if with_cas {
-//                 data = json!({
-//                     "value":data,
-//                     "cas":get_result.cas().to_string()
-//                 });
-//             }
-//             Ok(data)
-//         }
-//         Err(error) => {
-//             log::error!("Error in getting data from couchbase : {:?}", error.to_string());
-//             if retry <= 0 {
-//                 return Err(format!("Error in adding data to couchbase : {:?}... retry limit reached", error.to_string()));
-//             }
-//             time::sleep(Duration::from_secs(1)).await;
-//             let res = Box::pin(get_document(key, with_cas, bucket_name, Some(retry - 1))).await;
-//             if let Ok(data) = res {
-//                 return Ok(data);
-//             }
-//             Err(error.to_string())
-//         }
-//     }
-// }
-
-// pub async fn add_document(key: String, value: Value, bucket_name: String, retry: Option<u32>) -> Result<bool, String> {
-//     let db = get_bucket_connection(bucket_name.to_owned()).await;
-//     let retry = retry.unwrap_or(5);
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-
-//     match db.insert(key.clone(), value.to_owned(), InsertOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-//         Ok(_) => {
-//             // log::info!("Data successfully added to couchbase for key: {}", key);
-//             Ok(true)
-//         }
-//         Err(error) => {
-//             log::error!("Error in adding data to couchbase : {:?}", error.to_string());
-//             if retry <= 0 {
-//                 return Err(format!("Error in adding data to couchbase : {:?}... retry limit reached", error.to_string()));
-//             }
-//             time::sleep(Duration::from_secs(1)).await;
-//             let res = Box::pin(add_document(key, value, bucket_name, Some(retry - 1))).await;
-//             if res.is_ok() {
-//                 return Ok(true);
-//             }
-//             Err(error.to_string())
-//         }
-//     }
-// }
-
-// pub async fn add_document_with_ttl(key: String, value: Value, bucket_name: String, ttl:u64,  retry: Option<u32>) -> Result<bool, String> {
-//     let retry = retry.unwrap_or(5);
-//     let db = get_bucket_connection(bucket_name.to_owned()).await;
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-//     let ttl=Duration::from_secs(ttl);
-//     match db.insert(key.clone(), value.to_owned(), InsertOptions::default().timeout(OPERATION_TIMEOUT.clone()).expiry(ttl)).await {
-//         Ok(_) => {
-//             // log::info!("Data successfully added to couchbase for key: {}", key);
-//             Ok(true)
-//         }
-//         Err(error) => {
-//             log::error!("Error in adding data to couchbase : {:?}", error.to_string());
-//             if retry <= 0 {
-//                 return Err(format!("Error in adding data to couchbase : {:?}... retry limit reached", error.to_string()));
-//             }
-//             time::sleep(Duration::from_secs(1)).await;
-//             let res = Box::pin(add_document(key, value, bucket_name, Some(retry - 1))).await;
-//             if res.is_ok() {
-//                 return Ok(true);
-//             }
-//             Err(error.to_string())
-//         }
-//     }
-// }
-
-
-
-// pub async fn replace_document(key: String, value: Value, cas: Option<u64>, bucket_name: String, retry: Option<u32>) -> Result<String, String> {
-//     let retry = retry.unwrap_or(5);
-//     let db = get_bucket_connection(bucket_name.to_owned()).await;
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-//     // let get_document_res = match db.get(key.clone(), GetOptions::default()).await {
-//     //     Ok(data) => data,
-//     //     Err(err) => {
-//     //         log::error!(
-//     //             "Error in getting data from couchbase : {:?}",
-//     //             err.to_string()
-//     //         );
-//     //         return Err(err.to_string());
-//     //     }
-//     // };
-//     // let cas = get_document_res.cas();
-//     let replace_opt;
-//     if cas.is_some() {
-//         replace_opt = ReplaceOptions::default().cas(cas.unwrap());
-//     } else {
-//         replace_opt = ReplaceOptions::default();
-//     }
-//     let update_data = db.replace(key.to_owned(), value.to_owned(), replace_opt.timeout(OPERATION_TIMEOUT.clone()));
-//     match update_data.await {
-//         Ok(_) => {
-//             // log::info!(
-//             //     "Data successfully updated to couchbase for key: {} in bucket : {}",
-//             //     key,
-//             //     bucket_name.to_owned()
-//             // );
-//             Ok(format!("Data successfully updated to couchbase for key: {} in bucket : {}", key, bucket_name.to_owned()))
-//         }
-//         Err(error) => {
-//             log::error!("Error in updating data to couchbase : {:?} in bucket : {}", error.to_string(), bucket_name);
-//             if retry <= 0 {
-//                 return Err(format!("Error in updating data to couchbase : {:?}... retry limit reached", error.to_string()));
-//             }
-//             time::sleep(Duration::from_secs(1)).await;
-//             let res = Box::pin(replace_document(key.to_owned(), value, cas, bucket_name.to_owned(), Some(retry - 1))).await;
-//             if res.is_ok() {
-//                 return Ok(format!("Data successfully updated to couchbase for key: {} in bucket : {}", key, bucket_name.to_owned()));
-//             }
-//             Err(error.to_string())
-//         }
-//     }
-// }
-
-// pub async fn delete_document(key: String, bucket_name: String) -> Result<String, String> {
-//     let db = get_bucket_connection(bucket_name.to_owned()).await;
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-
-//     let delete_document = db.remove(key.to_owned(), RemoveOptions::default().timeout(OPERATION_TIMEOUT.clone()));
-//     match delete_document.await {
-//         Ok(_) => {
-//             // log::info!(
-//             //     "Data successfully deleted from couchbase for key: {} in bucket : {}",
-//             //     key,
-//             //     bucket_name.to_owned()
-//             // );
-//             Ok(format!("Data successfully deleted from couchbase for key: {} in bucket : {}", key, bucket_name.to_owned()))
-//         }
-//         Err(error) => {
-//             log::error!("Error in deleting data from couchbase : {:?} in bucket : {}", error.to_string(), bucket_name);
-//             Err(error.to_string())
-//         }
-//     }
-// }
-
-// // pub async fn get_batch_using_scan(
-// //   start: String,
-// //   end: String,
-// //   batch_size: i32,
-// //   keysOnly: bool,
-// //   bucket_name: String,
-// // ){
-
-// // }
-
-// pub async fn get_documents(keys: Vec<String>, with_cas: bool, bucket_name: String) -> Result<Value, String> {
-//     let db = get_bucket_connection(bucket_name).await;
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-
-//     if keys.is_empty() {
-//         return Err("Array of Keys need to be on length>0".to_string());
-//     }
-
-//     let mut docs: HashMap<String, Value> = HashMap::new();
-//     let mut errors: HashMap<String, Value> = HashMap::new();
-
-//     // Loop through each key
-//     for key in &keys {
-//         match db.get(key, GetOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-//             Ok(res) => {
-//                 let data = res.content::<Value>().unwrap();
-
-//                 if with_cas {
-//                     docs.insert(
-//                         key.to_string(),
-//                         json!({
-//                             "value": data,
-//                             "cas": res.cas()
-//                         }),
-//                     );
-//                 } else {
-//                     docs.insert(key.to_string(), json!(data));
-//                 }
-//             }
-//             Err(err) => {
-//                 errors.insert(
-//                     key.to_string(),
-//                     json!({
-//                         "error": err.to_string()
-//                     }),
-//                 );
-//             }
-//         }
-//     }
-//     if errors.is_empty() {
-//         log::info!("All documents fetched successfully");
-//         Ok(json!(docs))
-//     } else {
-//         log::error!("Some documents failed to fetch");
-//         return Err(format!("Error occured while fetching documents {:?} :  {:?}", keys, errors));
-//     }
-// }
-
-// pub async fn get_documents_v2(keys: Vec<String>, with_cas: bool, bucket_name: String) -> Result<Value, String> {
-//     let db = get_bucket_connection(bucket_name).await;
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-
-//     if keys.is_empty() {
-//         return Err("Array of Keys need to be on length>0".to_string());
-//     }
-
-//     let mut docs: HashMap<String, Value> = HashMap::new();
-//     let mut errors: HashMap<String, Value> = HashMap::new();
-
-//     // Loop through each key
-//     for key in &keys {
-//         match db.get(key, GetOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-//             Ok(res) => {
-//                 let data = res.content::<Value>().unwrap();
-
-//                 if with_cas {
-//                     docs.insert(
-//                         key.to_string(),
-//                         json!({
-//                             "value": data,
-//                             "cas": res.cas()
-//                         }),
-//                     );
-//                 } else {
-//                     docs.insert(key.to_string(), json!(data));
-//                 }
-//             }
-//             Err(err) => {
-//                 errors.insert(
-//                     key.to_string(),
-//                     json!({
-//                         "error": err.to_string()
-//                     }),
-//                 );
-//             }
-//         }
-//     }
-//     Ok(json!({
-//         "docs":docs,
-//         "errors":errors
-//     }))
-// }
-
-// pub async fn get_next_counter_key(bucket_name: String, key: String, initial_counter: Option<u32>) -> Result<String, String> {
-//     // Try to get existing document
-//     let db = get_bucket_connection(bucket_name).await;
-//     if let Err(err) = db {
-//         return Err(format!("Error in getting bucket connection : {:?}", err));
-//     }
-//     let db = db.unwrap();
-
-//     let get_result = db.get(&key, GetOptions::default().timeout(OPERATION_TIMEOUT.clone())).await;
-
-//     match get_result {
-//         Ok(doc) => {
-//             // Document exists
-//             if let Some(initial) = initial_counter {
-//                 // If initial counter provided, set it
-//                 let value = Value::Number(initial.into());
-//                 match db.upsert(&key, &value, UpsertOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-//                     Ok(_) => {
-//                         log::info!("Initial counter set to {}", initial);
-//                     }
-//                     Err(err) => {
-//                         log::error!("Error in setting initial counter : {:?}", err);
-//                         return Err(err.to_string());
-//                     }
-//                 };
-//                 Ok(initial.to_string())
-//             } else {
-//                 // Extract current counter value
-//                 let counter = if let Ok(num) = doc.content::<i64>() {
-//                     num
-//                 } else if let Ok(str_val) = doc.content::<String>() {
-//                     str_val.parse::<i64>().unwrap_or(0)
-//                 } else {
-//                     return Err("Invalid counter format".to_string());
-//                 };
-
-//                 // Increment counter
-//                 let new_counter = counter + 1;
-//                 let value = Value::Number(new_counter.into());
-
-//                 // Update document
-//                 match db.upsert(&key, &value, UpsertOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-//                     Ok(_) => {
-//                         log::info!("Counter incremented to {}", new_counter);
-//                     }
-//                     Err(err) => {
-//                         log::error!("Error in incrementing counter : {:?}", err);
-//                         return Err(err.to_string());
-//                     }
-//                 };
-//                 Ok(new_counter.to_string())
-//             }
-//         }
-//         Err(_) => {
-//             // Document doesn't exist, create with initial value
-//             let counter = initial_counter.unwrap_or(1) as i64;
-//             let value = Value::Number(counter.into());
-
-//             match db.upsert(&key, &value, UpsertOptions::default().timeout(OPERATION_TIMEOUT.clone())).await {
-//                 Ok(_) => {
-//                     log::info!("Initial counter set to {}", counter);
-//                 }
-//                 Err(err) => {
-//                     log::error!("Error in setting initial counter : {:?}", err);
-//                     return Err(err.to_string());
-//                 }
-//             };
-//             Ok(counter.to_string())
-//         }
-//     }
-// }
-
-// // pub async fn get_documents_from_view(
-// //     cluster: &Cluster,
-// //     design_doc: &str,
-// //     view_name: &str,
-// //     query_key: Option<Value>,
-// //     bucket_name: &str,
-// // ) -> Result<Vec<Value>, String> {
-// //     // Get bucket reference
-// //     let bucket = cluster.bucket(bucket_name);
-    
-// //     // Create view query
-// //     let mut view_query = ViewQuery::from(design_doc, view_name);
-    
-// //     // Add key if provided
-// //     if let Some(key) = query_key {
-// //         view_query = view_query.key(key);
-// //     }
-    
-// //     // Execute query
-// //     let result = bucket
-// //         .view_query(view_query)
-// //         .await
-// //         .map_err(|e| {
-// //             eprintln!("Error occurred during view query: {:?}", e);
-// //             e
-// //         })?;
-    
-// //     // Collect results into a vector
-// //     let mut docs = Vec::new();
-// //     let mut rows = result.rows();
-    
-// //     while let Some(row) = rows.next().await {
-// //         match row {
-// //             Ok(doc) => docs.push(doc),
-// //             Err(e) => {
-// //                 eprintln!("Error processing row: {:?}", e);
-// //                 return Err(Box::new(e));
-// //             }
-// //         }
-// //     }
-    
-// //     Ok(docs)
-// // }
\ No newline at end of file
@@ -0,0 +1,257 @@
+// List/set/map/queue helpers kept as a single JSON document per structure and changed through subdoc calls,
+// so concurrent Node callers don't have to read-modify-write with CAS themselves
+use std::sync::Arc;
+
+use couchbase::StoreSemantics;
+use serde_json::Value;
+
+use crate::utils::couchbase_db::{
+    is_document_not_found, is_write_conflict, CouchbaseConnection, Keyspace, OperationOptions, SubdocLookup,
+    SubdocMutation, SubdocOperation,
+};
+
+// Contended structures re-read and retry this many times before giving up
+const MAX_CONFLICT_RETRIES: u32 = 16;
+
+fn is_path_error(error: &str, kind: &str) -> bool {
+    error.to_lowercase().replace(' ', "").contains(kind)
+}
+
+fn conflict_error(key: &str) -> String {
+    format!("Gave up on {} after {} conflicting writes", key, MAX_CONFLICT_RETRIES)
+}
+
+// Subdoc paths need backticks around map fields containing dots, brackets or backticks
+fn field_path(field: &str) -> String {
+    format!("`{}`", field.replace('`', "``"))
+}
+
+pub struct Datastructure {
+    connection: Arc<CouchbaseConnection>,
+    key: String,
+    keyspace: Keyspace,
+    options: OperationOptions,
+}
+
+impl Datastructure {
+    pub fn new(connection: Arc<CouchbaseConnection>, key: String, keyspace: Keyspace, options: OperationOptions) -> Self {
+        Datastructure {
+            connection,
+            key,
+            keyspace,
+            options,
+        }
+    }
+
+    fn mutation(operation: SubdocOperation, path: String, value: Option<Value>) -> SubdocMutation {
+        SubdocMutation {
+            operation,
+            path,
+            value,
+            create_path: false,
+            xattr: false,
+        }
+    }
+
+    async fn mutate(
+        &self,
+        mutation: SubdocMutation,
+        cas: Option<u64>,
+        store_semantics: Option<StoreSemantics>,
+    ) -> Result<(), String> {
+        self.connection
+            .mutate_in(
+                self.key.clone(),
+                vec![mutation],
+                self.keyspace.clone(),
+                cas,
+                store_semantics,
+                self.options.clone(),
+            )
+            .await
+            .map(|_| ())
+    }
+
+    async fn lookup(&self, lookup: SubdocLookup) -> Result<Option<(Option<Value>, u64)>, String> {
+        match self
+            .connection
+            .lookup_in(self.key.clone(), vec![lookup], self.keyspace.clone(), self.options.clone())
+            .await
+        {
+            Ok(mut result) => Ok(Some((result.values.remove(0), result.cas))),
+            Err(error) if is_document_not_found(&error) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Array root changes: subdoc can't create an array document, so the first element inserts it
+    async fn array_insert(&self, operation: SubdocOperation, element: Value) -> Result<(), String> {
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let mutation = Self::mutation(operation, String::new(), Some(Value::Array(vec![element.clone()])));
+            match self.mutate(mutation, None, None).await {
+                Ok(()) => return Ok(()),
+                Err(error) if is_document_not_found(&error) => {
+                    match self
+                        .connection
+                        .add_document(
+                            self.key.clone(),
+                            Value::Array(vec![element.clone()]),
+                            self.keyspace.clone(),
+                            self.options.clone(),
+                            Some(0),
+                        )
+                        .await
+                    {
+                        Ok(_) => return Ok(()),
+                        Err(error) if is_write_conflict(&error) => continue,
+                        Err(error) => return Err(error),
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(conflict_error(&self.key))
+    }
+
+    pub async fn push_back(&self, element: Value) -> Result<(), String> {
+        self.array_insert(SubdocOperation::ArrayAppend, element).await
+    }
+
+    pub async fn push_front(&self, element: Value) -> Result<(), String> {
+        self.array_insert(SubdocOperation::ArrayPrepend, element).await
+    }
+
+    // Only primitives can be set members. Returns false when the element was already present
+    pub async fn add_unique(&self, element: Value) -> Result<bool, String> {
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let mutation = Self::mutation(SubdocOperation::ArrayAddUnique, String::new(), Some(element.clone()));
+            match self.mutate(mutation, None, None).await {
+                Ok(()) => return Ok(true),
+                Err(error) if is_path_error(&error, "pathexists") => return Ok(false),
+                Err(error) if is_document_not_found(&error) => {
+                    match self
+                        .connection
+                        .add_document(
+                            self.key.clone(),
+                            Value::Array(vec![element.clone()]),
+                            self.keyspace.clone(),
+                            self.options.clone(),
+                            Some(0),
+                        )
+                        .await
+                    {
+                        Ok(_) => return Ok(true),
+                        Err(error) if is_write_conflict(&error) => continue,
+                        Err(error) => return Err(error),
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(conflict_error(&self.key))
+    }
+
+    pub async fn get_at(&self, index: i64) -> Result<Option<Value>, String> {
+        Ok(self
+            .lookup(SubdocLookup::get(&format!("[{}]", index)))
+            .await?
+            .and_then(|(value, _)| value))
+    }
+
+    // Removes and returns the element at `index` (-1 is the last one), None when empty
+    pub async fn pop_at(&self, index: i64) -> Result<Option<Value>, String> {
+        let path = format!("[{}]", index);
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let (element, cas) = match self.lookup(SubdocLookup::get(&path)).await? {
+                Some((Some(element), cas)) => (element, cas),
+                _ => return Ok(None),
+            };
+            match self
+                .mutate(Self::mutation(SubdocOperation::Remove, path.clone(), None), Some(cas), None)
+                .await
+            {
+                Ok(()) => return Ok(Some(element)),
+                Err(error) if is_write_conflict(&error) || is_path_error(&error, "pathnotfound") => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        Err(conflict_error(&self.key))
+    }
+
+    pub async fn array_contains(&self, element: &Value) -> Result<bool, String> {
+        Ok(match self.lookup(SubdocLookup::get("")).await? {
+            Some((Some(Value::Array(elements)), _)) => elements.contains(element),
+            _ => false,
+        })
+    }
+
+    // Removes the first occurrence of `element`, false when it was not present
+    pub async fn remove_element(&self, element: &Value) -> Result<bool, String> {
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let (position, cas) = match self.lookup(SubdocLookup::get("")).await? {
+                Some((Some(Value::Array(elements)), cas)) => match elements.iter().position(|e| e == element) {
+                    Some(position) => (position, cas),
+                    None => return Ok(false),
+                },
+                _ => return Ok(false),
+            };
+            let mutation = Self::mutation(SubdocOperation::Remove, format!("[{}]", position), None);
+            match self.mutate(mutation, Some(cas), None).await {
+                Ok(()) => return Ok(true),
+                Err(error) if is_write_conflict(&error) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        Err(conflict_error(&self.key))
+    }
+
+    pub async fn map_set(&self, field: &str, value: Value) -> Result<(), String> {
+        let mutation = Self::mutation(SubdocOperation::Upsert, field_path(field), Some(value));
+        self.mutate(mutation, None, Some(StoreSemantics::Upsert)).await
+    }
+
+    pub async fn map_get(&self, field: &str) -> Result<Option<Value>, String> {
+        Ok(self
+            .lookup(SubdocLookup::get(&field_path(field)))
+            .await?
+            .and_then(|(value, _)| value))
+    }
+
+    pub async fn map_contains(&self, field: &str) -> Result<bool, String> {
+        Ok(matches!(
+            self.lookup(SubdocLookup::exists(&field_path(field))).await?,
+            Some((Some(Value::Bool(true)), _))
+        ))
+    }
+
+    pub async fn map_remove(&self, field: &str) -> Result<bool, String> {
+        match self
+            .mutate(Self::mutation(SubdocOperation::Remove, field_path(field), None), None, None)
+            .await
+        {
+            Ok(()) => Ok(true),
+            Err(error) if is_document_not_found(&error) || is_path_error(&error, "pathnotfound") => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Number of elements (or fields for maps), 0 when the document doesn't exist
+    pub async fn size(&self) -> Result<u32, String> {
+        Ok(match self.lookup(SubdocLookup::count("")).await? {
+            Some((Some(count), _)) => count.as_u64().unwrap_or(0) as u32,
+            _ => 0,
+        })
+    }
+
+    pub async fn clear(&self) -> Result<(), String> {
+        match self
+            .connection
+            .delete_data(self.key.clone(), None, self.keyspace.clone(), self.options.clone())
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) if is_document_not_found(&error) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// In-memory cache of documents for reads that ask for it, warmed at startup with preloadCache so hot keys are not
+// all fetched cold right after a deploy. Settings under `couchbase.documentCache`, e.g.
+//   "documentCache": { "maxEntries": 10000, "ttlSecs": 300 }
+// Writes made through this process drop the key from the cache, writes from elsewhere (other processes, N1QL
+// updates) are only picked up once the entry expires, so cached reads are at most `ttlSecs` stale
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{CasDocument, CouchbaseConnection, Keyspace, OperationOptions};
+
+// Keys listed per page when preloading a prefix
+const PRELOAD_PAGE_SIZE: u32 = 500;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentCacheSettings {
+    #[serde(default = "DocumentCacheSettings::default_max_entries")]
+    max_entries: usize,
+    #[serde(default = "DocumentCacheSettings::default_ttl_secs")]
+    ttl_secs: u64,
+}
+
+impl DocumentCacheSettings {
+    fn default_max_entries() -> usize {
+        10000
+    }
+
+    fn default_ttl_secs() -> u64 {
+        300
+    }
+}
+
+impl Default for DocumentCacheSettings {
+    fn default() -> Self {
+        DocumentCacheSettings {
+            max_entries: Self::default_max_entries(),
+            ttl_secs: Self::default_ttl_secs(),
+        }
+    }
+}
+
+struct CachedDocument {
+    value: Value,
+    cas: u64,
+    stored_at: Instant,
+    expires_at: Instant,
+}
+
+lazy_static! {
+    static ref DOCUMENT_CACHE_SETTINGS: DocumentCacheSettings =
+        config::get_res::<DocumentCacheSettings>("couchbase.documentCache").unwrap_or_default();
+    // Keyspace -> key -> document
+    static ref DOCUMENT_CACHE: Mutex<HashMap<String, HashMap<String, CachedDocument>>> = Mutex::new(HashMap::new());
+}
+
+pub enum PreloadSource {
+    Keys(Vec<String>),
+    Prefix(String),
+}
+
+#[derive(Debug, Default)]
+pub struct PreloadSummary {
+    pub loaded: u64,
+    // Keys that did not exist or could not be read
+    pub missing: u64,
+    // Keys left out because the cache was full
+    pub skipped: u64,
+}
+
+fn cached_entries(cache: &HashMap<String, HashMap<String, CachedDocument>>) -> usize {
+    cache.values().map(HashMap::len).sum()
+}
+
+pub fn cached_document(key: &str, keyspace: &Keyspace) -> Option<CasDocument> {
+    let mut cache = DOCUMENT_CACHE.lock().unwrap();
+    let documents = cache.get_mut(&keyspace.to_string())?;
+    match documents.get(key) {
+        Some(cached) if cached.expires_at > Instant::now() => Some(CasDocument {
+            value: cached.value.clone(),
+            cas: cached.cas,
+        }),
+        Some(_) => {
+            documents.remove(key);
+            None
+        }
+        None => None,
+    }
+}
+
+pub fn store_document(key: &str, keyspace: &Keyspace, document: &CasDocument) {
+    let settings = &*DOCUMENT_CACHE_SETTINGS;
+    if settings.max_entries == 0 || settings.ttl_secs == 0 {
+        return;
+    }
+    let keyspace = keyspace.to_string();
+    let mut cache = DOCUMENT_CACHE.lock().unwrap();
+    let cached = cache.get(&keyspace).is_some_and(|documents| documents.contains_key(key));
+    if !cached && cached_entries(&cache) >= settings.max_entries {
+        let now = Instant::now();
+        for documents in cache.values_mut() {
+            documents.retain(|_, cached| cached.expires_at > now);
+        }
+        if cached_entries(&cache) >= settings.max_entries {
+            let oldest = cache
+                .iter()
+                .flat_map(|(keyspace, documents)| documents.iter().map(move |(key, cached)| (keyspace, key, cached)))
+                .min_by_key(|(_, _, cached)| cached.stored_at)
+                .map(|(keyspace, key, _)| (keyspace.clone(), key.clone()));
+            if let Some((oldest_keyspace, oldest_key)) = oldest {
+                if let Some(documents) = cache.get_mut(&oldest_keyspace) {
+                    documents.remove(&oldest_key);
+                }
+            }
+        }
+    }
+    let now = Instant::now();
+    cache.entry(keyspace).or_default().insert(
+        key.to_string(),
+        CachedDocument {
+            value: document.value.clone(),
+            cas: document.cas,
+            stored_at: now,
+            expires_at: now + Duration::from_secs(settings.ttl_secs),
+        },
+    );
+}
+
+pub fn forget_document(key: &str, keyspace: &Keyspace) {
+    if let Some(documents) = DOCUMENT_CACHE.lock().unwrap().get_mut(&keyspace.to_string()) {
+        documents.remove(key);
+    }
+}
+
+// Held for the duration of a write. The key is dropped when the write starts and again when it ends, so a cached
+// read racing the write cannot leave the old body behind
+pub struct CacheInvalidation {
+    key: String,
+    keyspace: Keyspace,
+}
+
+impl Drop for CacheInvalidation {
+    fn drop(&mut self) {
+        forget_document(&self.key, &self.keyspace);
+    }
+}
+
+pub fn invalidate_on_write(key: &str, keyspace: &Keyspace) -> CacheInvalidation {
+    forget_document(key, keyspace);
+    CacheInvalidation {
+        key: key.to_string(),
+        keyspace: keyspace.clone(),
+    }
+}
+
+// Returns how many entries were dropped
+pub fn clear_document_cache() -> usize {
+    let mut cache = DOCUMENT_CACHE.lock().unwrap();
+    let cleared = cached_entries(&cache);
+    cache.clear();
+    cleared
+}
+
+// Room left in the cache, preloading never evicts what is already cached
+fn free_entries() -> usize {
+    let cache = DOCUMENT_CACHE.lock().unwrap();
+    DOCUMENT_CACHE_SETTINGS.max_entries.saturating_sub(cached_entries(&cache))
+}
+
+async fn prefix_keys(
+    connection: &CouchbaseConnection,
+    prefix: String,
+    limit: usize,
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+) -> Result<(Vec<String>, u64), String> {
+    let mut keys = Vec::new();
+    let mut skipped = 0;
+    let mut start_after = None;
+    loop {
+        let page = connection
+            .list_keys(prefix.clone(), keyspace.clone(), PRELOAD_PAGE_SIZE, start_after, options.clone())
+            .await?;
+        for key in page.keys {
+            if keys.len() < limit {
+                keys.push(key);
+            } else {
+                skipped += 1;
+            }
+        }
+        match page.next_start_after {
+            Some(next) => start_after = Some(next),
+            None => return Ok((keys, skipped)),
+        }
+    }
+}
+
+// Reads the documents and caches them, up to the room left in the cache
+pub async fn preload_cache(
+    connection: &CouchbaseConnection,
+    source: PreloadSource,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<PreloadSummary, String> {
+    if options.transcoder.is_some() {
+        return Err("preloadCache reads with the bucket's transcoder, the cache only holds those reads".to_string());
+    }
+    let limit = free_entries();
+    let mut summary = PreloadSummary::default();
+    let keys = match source {
+        PreloadSource::Keys(mut keys) => {
+            if keys.len() > limit {
+                summary.skipped = (keys.len() - limit) as u64;
+                keys.truncate(limit);
+            }
+            keys
+        }
+        PreloadSource::Prefix(prefix) => {
+            let (keys, skipped) = prefix_keys(connection, prefix, limit, &keyspace, &options).await?;
+            summary.skipped = skipped;
+            keys
+        }
+    };
+    if keys.is_empty() {
+        return Ok(summary);
+    }
+    let batch = connection.get_documents_v2(keys, keyspace.clone(), options).await?;
+    for (key, document) in &batch.docs {
+        store_document(key, &keyspace, document);
+    }
+    summary.loaded = batch.docs.len() as u64;
+    summary.missing = batch.errors.len() as u64;
+    log::info!(
+        "Preloaded {} documents of {} into the document cache, {} missing, {} skipped",
+        summary.loaded,
+        keyspace,
+        summary.missing,
+        summary.skipped
+    );
+    Ok(summary)
+}
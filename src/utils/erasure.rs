@@ -0,0 +1,227 @@
+// Right-to-erasure helper: finds a data subject's documents by key prefix and/or a named query, deletes them or
+// overwrites the configured fields, and returns a report signed with `ERASURE_SIGNING_KEY` (or
+// `couchbase.erasure.signingKey`) so it can be handed to auditors as proof
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::{json, Map, Value};
+
+use crate::configuration as config;
+use crate::utils::checksum::sign;
+use crate::utils::couchbase_db::{
+    is_document_not_found, is_write_conflict, uuid, CouchbaseConnection, Keyspace, OperationOptions, QuerySettings,
+};
+use crate::utils::named_queries::named_query;
+use crate::utils::policy::check_policy;
+
+// Written in place of anonymized fields
+pub const ERASED_VALUE: &str = "[erased]";
+// Anonymizing re-reads and retries this many times when the document changes underneath
+const MAX_ERASE_ATTEMPTS: u32 = 5;
+
+pub struct ErasureRequest {
+    pub subject_id: String,
+    pub keyspace: Keyspace,
+    // Documents whose key starts with `<prefix><subjectId>`
+    pub prefixes: Vec<String>,
+    // Named query taking `$subjectId`, returning keys or rows with an `id` field
+    pub named_query: Option<String>,
+    // Dotted paths to overwrite, whole documents are deleted when empty
+    pub fields_to_anonymize: Vec<String>,
+    // Where the signed report is stored, not stored when None
+    pub audit_keyspace: Option<Keyspace>,
+}
+
+fn signing_key() -> Result<String, String> {
+    std::env::var("ERASURE_SIGNING_KEY")
+        .ok()
+        .or_else(|| config::get_res::<String>("couchbase.erasure.signingKey").ok())
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| "ERASURE_NOT_CONFIGURED: set ERASURE_SIGNING_KEY to sign erasure reports".to_string())
+}
+
+// Overwrites the dotted paths present in `document`, returns the ones that were changed
+fn anonymize(document: &mut Value, fields: &[String]) -> Vec<String> {
+    let mut erased = Vec::new();
+    for field in fields {
+        let mut segments = field.split('.').peekable();
+        let mut current = &mut *document;
+        while let Some(segment) = segments.next() {
+            let object = match current.as_object_mut() {
+                Some(object) => object,
+                None => break,
+            };
+            if segments.peek().is_none() {
+                if let Some(value) = object.get_mut(segment) {
+                    *value = json!(ERASED_VALUE);
+                    erased.push(field.clone());
+                }
+                break;
+            }
+            current = match object.get_mut(segment) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+    erased
+}
+
+async fn subject_keys(
+    connection: &CouchbaseConnection,
+    request: &ErasureRequest,
+    options: &OperationOptions,
+) -> Result<BTreeSet<String>, String> {
+    let mut keys = BTreeSet::new();
+    for prefix in &request.prefixes {
+        let mut start_after = None;
+        loop {
+            let page = connection
+                .list_keys(
+                    format!("{}{}", prefix, request.subject_id),
+                    request.keyspace.clone(),
+                    1000,
+                    start_after,
+                    options.clone(),
+                )
+                .await?;
+            keys.extend(page.keys);
+            match page.next_start_after {
+                Some(next) => start_after = Some(next),
+                None => break,
+            }
+        }
+    }
+    if let Some(name) = &request.named_query {
+        let query = named_query(name)?;
+        let settings = QuerySettings {
+            query_context: Some(request.keyspace.query_context()),
+            named_parameters: HashMap::from([("subjectId".to_string(), json!(request.subject_id))]),
+            scan_consistency: Some(query.consistency.scan_consistency().to_string()),
+            ..Default::default()
+        };
+        let rows = connection
+            .query(query.statement, Vec::new(), Vec::new(), settings, options.clone())
+            .await?;
+        for row in rows {
+            match row.as_str().or_else(|| row["id"].as_str()) {
+                Some(key) => {
+                    keys.insert(key.to_string());
+                }
+                None => return Err(format!("Erasure query {} returned a row without a key: {}", name, row)),
+            }
+        }
+    }
+    Ok(keys)
+}
+
+// Returns the fields that were overwritten, None when the document no longer exists
+async fn anonymize_document(
+    connection: &CouchbaseConnection,
+    key: &str,
+    fields: &[String],
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+) -> Result<Option<Vec<String>>, String> {
+    for _ in 0..MAX_ERASE_ATTEMPTS {
+        let mut stored = match connection.get_document(key.to_string(), keyspace.clone(), options.clone()).await {
+            Ok(stored) => stored,
+            Err(error) if is_document_not_found(&error) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let erased = anonymize(&mut stored.value, fields);
+        if erased.is_empty() {
+            return Ok(Some(erased));
+        }
+        match connection
+            .replace_document(
+                key.to_string(),
+                stored.value,
+                Some(stored.cas),
+                keyspace.clone(),
+                options.clone(),
+                Some(0),
+            )
+            .await
+        {
+            Ok(_) => return Ok(Some(erased)),
+            Err(error) if is_write_conflict(&error) => {
+                log::warn!("{}Concurrent write on {} while erasing... retrying", options.log_prefix(), key);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Err(format!("Gave up erasing {} after {} conflicting writes", key, MAX_ERASE_ATTEMPTS))
+}
+
+pub async fn erase_subject_data(
+    connection: &CouchbaseConnection,
+    request: ErasureRequest,
+    options: OperationOptions,
+) -> Result<Value, String> {
+    check_policy("eraseSubject")?;
+    if request.subject_id.is_empty() {
+        return Err("eraseSubjectData needs a subjectId".to_string());
+    }
+    if request.prefixes.is_empty() && request.named_query.is_none() {
+        return Err("eraseSubjectData needs prefixes or a namedQuery to find the subject's documents".to_string());
+    }
+    // Checked up front so nothing is erased without a report to show for it
+    let signing_key = signing_key()?;
+    let keys = subject_keys(connection, &request, &options).await?;
+
+    let mut documents = Vec::new();
+    let mut failures = Vec::new();
+    for key in keys {
+        let outcome = if request.fields_to_anonymize.is_empty() {
+            match connection.delete_data(key.clone(), None, request.keyspace.clone(), options.clone()).await {
+                Ok(_) => Ok(Some(json!({ "key": key, "action": "deleted" }))),
+                Err(error) if is_document_not_found(&error) => Ok(None),
+                Err(error) => Err(error),
+            }
+        } else {
+            anonymize_document(connection, &key, &request.fields_to_anonymize, &request.keyspace, &options)
+                .await
+                .map(|erased| erased.map(|fields| json!({ "key": key, "action": "anonymized", "fields": fields })))
+        };
+        match outcome {
+            Ok(Some(entry)) => {
+                log::info!(
+                    "{}Erasure for subject {}: {} in {}",
+                    options.log_prefix(),
+                    request.subject_id,
+                    entry,
+                    request.keyspace
+                );
+                documents.push(entry);
+            }
+            Ok(None) => {}
+            Err(error) => {
+                log::error!("{}Erasure of {} failed : {}", options.log_prefix(), key, error);
+                failures.push(json!({ "key": key, "error": error }));
+            }
+        }
+    }
+
+    let mut report = Map::new();
+    report.insert("reportId".to_string(), json!(uuid().to_string()));
+    report.insert("subjectId".to_string(), json!(request.subject_id));
+    report.insert("bucket".to_string(), json!(request.keyspace.bucket));
+    report.insert("scope".to_string(), json!(request.keyspace.scope.as_deref().unwrap_or("_default")));
+    report.insert("collection".to_string(), json!(request.keyspace.collection.as_deref().unwrap_or("_default")));
+    report.insert("erasedAt".to_string(), json!(chrono::Utc::now().to_rfc3339()));
+    report.insert("documents".to_string(), Value::Array(documents));
+    report.insert("failures".to_string(), Value::Array(failures));
+    // Signed over the report without the signature field, serde_json keeps keys sorted so verifiers can re-serialize
+    let payload = serde_json::to_vec(&report).map_err(|error| error.to_string())?;
+    report.insert("signature".to_string(), json!(sign(signing_key.as_bytes(), &payload)));
+    let report = Value::Object(report);
+
+    if let Some(audit_keyspace) = request.audit_keyspace {
+        let audit_key = format!("erasure::{}::{}", request.subject_id, report["reportId"].as_str().unwrap_or_default());
+        connection
+            .add_document(audit_key, report.clone(), audit_keyspace, options, None)
+            .await
+            .map_err(|error| format!("Subject data was erased but the report could not be stored : {}", error))?;
+    }
+    Ok(report)
+}
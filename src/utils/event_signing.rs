@@ -0,0 +1,157 @@
+// HMAC signatures on change events sent to webhooks, so receivers can tell they came from this layer. Keys are
+// configured under `couchbase.eventSigning`, secrets may be ENC(...) values, e.g.
+//   "eventSigning": { "activeKey": "2024-10", "keys": { "2024-10": "<secret>", "2024-04": "<old secret>" }, "toleranceSecs": 300 }
+// Events are signed with the active key only, every configured key still verifies. Rotating is adding the new key,
+// switching activeKey to it and removing the old one once receivers have caught up
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::configuration as config;
+use crate::utils::checksum::{sign, verify_signature};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventSigningSettings {
+    active_key: String,
+    keys: HashMap<String, String>,
+    // How old a signature may be, bounds how long a captured event can be replayed
+    #[serde(default = "EventSigningSettings::default_tolerance_secs")]
+    tolerance_secs: i64,
+}
+
+impl EventSigningSettings {
+    fn default_tolerance_secs() -> i64 {
+        300
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EventSignature {
+    pub key_id: String,
+    // Seconds since the epoch, part of what is signed
+    pub timestamp: i64,
+    // "hmac-sha256:<hex>" of "<timestamp>.<body>"
+    pub signature: String,
+}
+
+fn signing_settings() -> Result<EventSigningSettings, String> {
+    config::get_res::<EventSigningSettings>("couchbase.eventSigning").map_err(|error| {
+        format!("EVENT_SIGNING_NOT_CONFIGURED: couchbase.eventSigning is missing or invalid : {}", error)
+    })
+}
+
+fn signed_payload(timestamp: i64, body: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{}.", timestamp).into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+// Signs the event body exactly as it will be sent
+pub fn sign_event(body: &[u8]) -> Result<EventSignature, String> {
+    sign_with(&signing_settings()?, body, chrono::Utc::now().timestamp())
+}
+
+fn sign_with(settings: &EventSigningSettings, body: &[u8], timestamp: i64) -> Result<EventSignature, String> {
+    let secret = settings.keys.get(&settings.active_key).ok_or_else(|| {
+        format!(
+            "EVENT_SIGNING_NOT_CONFIGURED: active key {} is not among couchbase.eventSigning.keys",
+            settings.active_key
+        )
+    })?;
+    Ok(EventSignature {
+        key_id: settings.active_key.clone(),
+        timestamp,
+        signature: sign(secret.as_bytes(), &signed_payload(timestamp, body)),
+    })
+}
+
+// False for a wrong signature, an unknown key or a stale timestamp. Errors only when signing is not configured
+pub fn verify_event(body: &[u8], signature: &EventSignature) -> Result<bool, String> {
+    Ok(verify_with(&signing_settings()?, body, signature, chrono::Utc::now().timestamp()))
+}
+
+fn verify_with(settings: &EventSigningSettings, body: &[u8], signature: &EventSignature, now: i64) -> bool {
+    let secret = match settings.keys.get(&signature.key_id) {
+        Some(secret) => secret,
+        None => {
+            log::warn!("Event signed with unknown key {}", signature.key_id);
+            return false;
+        }
+    };
+    let age = now - signature.timestamp;
+    if age.abs() > settings.tolerance_secs {
+        log::warn!(
+            "Event signature with key {} is {} seconds off, more than the {} allowed",
+            signature.key_id,
+            age,
+            settings.tolerance_secs
+        );
+        return false;
+    }
+    let valid = verify_signature(
+        secret.as_bytes(),
+        &signed_payload(signature.timestamp, body),
+        &signature.signature,
+    );
+    if !valid {
+        log::warn!("Event signature with key {} does not match the body", signature.key_id);
+    }
+    valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(active_key: &str) -> EventSigningSettings {
+        EventSigningSettings {
+            active_key: active_key.to_string(),
+            keys: HashMap::from([
+                ("2024-10".to_string(), "new secret".to_string()),
+                ("2024-04".to_string(), "old secret".to_string()),
+            ]),
+            tolerance_secs: 300,
+        }
+    }
+
+    #[test]
+    fn events_verify_with_the_key_they_were_signed_with() {
+        let signature = sign_with(&settings("2024-10"), b"{}", 1000).unwrap();
+        assert_eq!(signature.key_id, "2024-10");
+        assert!(verify_with(&settings("2024-10"), b"{}", &signature, 1000));
+        // Rotated away from, the old key still verifies
+        let old = sign_with(&settings("2024-04"), b"{}", 1000).unwrap();
+        assert!(verify_with(&settings("2024-10"), b"{}", &old, 1000));
+    }
+
+    #[test]
+    fn tampered_events_do_not_verify() {
+        let signature = sign_with(&settings("2024-10"), b"{\"a\":1}", 1000).unwrap();
+        assert!(!verify_with(&settings("2024-10"), b"{\"a\":2}", &signature, 1000));
+        let restamped = EventSignature {
+            timestamp: 1001,
+            ..signature.clone()
+        };
+        assert!(!verify_with(&settings("2024-10"), b"{\"a\":1}", &restamped, 1001));
+        let unknown = EventSignature {
+            key_id: "2023-01".to_string(),
+            ..signature
+        };
+        assert!(!verify_with(&settings("2024-10"), b"{\"a\":1}", &unknown, 1000));
+    }
+
+    #[test]
+    fn stale_events_do_not_verify() {
+        let signature = sign_with(&settings("2024-10"), b"{}", 1000).unwrap();
+        assert!(verify_with(&settings("2024-10"), b"{}", &signature, 1300));
+        assert!(!verify_with(&settings("2024-10"), b"{}", &signature, 1301));
+        assert!(!verify_with(&settings("2024-10"), b"{}", &signature, 699));
+    }
+
+    #[test]
+    fn a_missing_active_key_is_not_configured() {
+        let error = sign_with(&settings("2025-01"), b"{}", 1000).unwrap_err();
+        assert!(error.starts_with("EVENT_SIGNING_NOT_CONFIGURED"));
+    }
+}
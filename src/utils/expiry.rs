@@ -0,0 +1,46 @@
+// Histogram of upcoming expirations for a key prefix, to spot cohorts of documents that expire together
+use serde_json::Value;
+
+pub struct ExpiryBucket {
+    // Epoch milliseconds, `to` is exclusive
+    pub from: i64,
+    pub to: i64,
+    pub count: u64,
+}
+
+#[derive(Default)]
+pub struct ExpiryHistogram {
+    pub total: u64,
+    pub without_expiry: u64,
+    // Past their expiry but not purged yet
+    pub expired: u64,
+    pub buckets: Vec<ExpiryBucket>,
+    // Expiring after the horizon
+    pub later: u64,
+}
+
+// `rows` are `{ slot, count }` with `slot = floor((expiration - now) / interval)`, null for documents without expiry
+pub fn expiry_histogram(rows: &[Value], now_secs: i64, interval_secs: i64, horizon_secs: i64) -> ExpiryHistogram {
+    let slots = ((horizon_secs + interval_secs - 1) / interval_secs).max(1);
+    let mut histogram = ExpiryHistogram {
+        buckets: (0..slots)
+            .map(|slot| ExpiryBucket {
+                from: (now_secs + slot * interval_secs) * 1000,
+                to: (now_secs + (slot + 1) * interval_secs) * 1000,
+                count: 0,
+            })
+            .collect(),
+        ..Default::default()
+    };
+    for row in rows {
+        let count = row["count"].as_u64().unwrap_or(0);
+        histogram.total += count;
+        match row["slot"].as_f64().map(|slot| slot as i64) {
+            None => histogram.without_expiry += count,
+            Some(slot) if slot < 0 => histogram.expired += count,
+            Some(slot) if slot >= slots => histogram.later += count,
+            Some(slot) => histogram.buckets[slot as usize].count += count,
+        }
+    }
+    histogram
+}
@@ -0,0 +1,133 @@
+// Streams documents to an NDJSON file, one `{"id": ..., "value": ...}` line per document, so extraction jobs
+// never hold the whole result in the JS heap. Keys are fetched a page at a time, either the given list or every
+// key under a prefix
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::utils::couchbase_db::{CouchbaseConnection, Keyspace, OperationOptions};
+use crate::utils::policy::check_policy;
+use crate::utils::progress::{record_progress, ProgressReporter};
+
+const EXPORT_PAGE_SIZE: usize = 1000;
+
+pub enum ExportSource {
+    Keys(Vec<String>),
+    Prefix(String),
+}
+
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub written: u64,
+    // Keys that could not be read, logged one by one and left out of the file
+    pub failed: u64,
+}
+
+async fn export_page(
+    connection: &CouchbaseConnection,
+    keys: Vec<String>,
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+    writer: &mut BufWriter<File>,
+    summary: &mut ExportSummary,
+    progress: &mut Option<ProgressReporter>,
+) -> Result<(), String> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let batch = connection.get_documents_v2(keys.clone(), keyspace.clone(), options.clone()).await?;
+    // Lines follow the order of `keys` so exports are reproducible
+    for key in keys {
+        match batch.docs.get(&key) {
+            Some(document) => {
+                let line = json!({ "id": key, "value": document.value });
+                writeln!(writer, "{}", line).map_err(|error| format!("Unable to write export : {}", error))?;
+                summary.written += 1;
+                record_progress(progress, true);
+            }
+            None => {
+                let error = batch.errors.get(&key).map(String::as_str).unwrap_or("not returned");
+                log::error!("{}Export skipped {} : {}", options.log_prefix(), key, error);
+                summary.failed += 1;
+                record_progress(progress, false);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn export_documents(
+    connection: &CouchbaseConnection,
+    source: ExportSource,
+    output_path: &str,
+    keyspace: Keyspace,
+    options: OperationOptions,
+    mut progress: Option<ProgressReporter>,
+) -> Result<ExportSummary, String> {
+    check_policy("exportDocuments")?;
+    if let Some(parent) = Path::new(output_path).parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let file = File::create(output_path).map_err(|error| format!("Unable to create {} : {}", output_path, error))?;
+    let mut writer = BufWriter::new(file);
+    let mut summary = ExportSummary::default();
+    match source {
+        ExportSource::Keys(keys) => {
+            for page in keys.chunks(EXPORT_PAGE_SIZE) {
+                export_page(
+                    connection,
+                    page.to_vec(),
+                    &keyspace,
+                    &options,
+                    &mut writer,
+                    &mut summary,
+                    &mut progress,
+                )
+                .await?;
+            }
+        }
+        ExportSource::Prefix(prefix) => {
+            let mut start_after = None;
+            loop {
+                let page = connection
+                    .list_keys(
+                        prefix.clone(),
+                        keyspace.clone(),
+                        EXPORT_PAGE_SIZE as u32,
+                        start_after,
+                        options.clone(),
+                    )
+                    .await?;
+                export_page(
+                    connection,
+                    page.keys,
+                    &keyspace,
+                    &options,
+                    &mut writer,
+                    &mut summary,
+                    &mut progress,
+                )
+                .await?;
+                match page.next_start_after {
+                    Some(next) => start_after = Some(next),
+                    None => break,
+                }
+            }
+        }
+    }
+    writer.flush().map_err(|error| format!("Unable to write export : {}", error))?;
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+    log::info!(
+        "{}Exported {} documents from {} to {}, {} failed",
+        options.log_prefix(),
+        summary.written,
+        keyspace,
+        output_path,
+        summary.failed
+    );
+    Ok(summary)
+}
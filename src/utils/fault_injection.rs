@@ -0,0 +1,115 @@
+// Forces errors into KV operations so chaos tests can exercise retry paths deterministically.
+// Compiled in only with the `fault-injection` feature, and rules come from `couchbase.faultInjection`:
+//   { "enabled": true, "rules": [{ "fault": "timeout", "operations": ["get"], "keys": ["user::1"], "percentage": 100 }] }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FaultOperation {
+    Get,
+    Insert,
+    Upsert,
+    Replace,
+    Remove,
+    MutateIn,
+}
+
+#[cfg(feature = "fault-injection")]
+mod injector {
+    use std::sync::RwLock;
+    use std::time::Duration;
+
+    use lazy_static::lazy_static;
+    use rand::Rng;
+    use serde::Deserialize;
+
+    use super::FaultOperation;
+    use crate::configuration as config;
+
+    #[derive(Clone, Copy, Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub enum Fault {
+        Timeout,
+        CasMismatch,
+        NotFound,
+    }
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct FaultRule {
+        pub fault: Fault,
+        // Empty means every operation
+        #[serde(default)]
+        pub operations: Vec<FaultOperation>,
+        // Empty means every key
+        #[serde(default)]
+        pub keys: Vec<String>,
+        // Share of matching operations that fail, 0-100
+        #[serde(default = "FaultRule::always")]
+        pub percentage: f64,
+        // Simulated latency before a timeout fault is reported
+        #[serde(default)]
+        pub delay_ms: u64,
+    }
+
+    impl FaultRule {
+        fn always() -> f64 {
+            100.0
+        }
+
+        fn matches(&self, operation: FaultOperation, key: &str) -> bool {
+            (self.operations.is_empty() || self.operations.contains(&operation))
+                && (self.keys.is_empty() || self.keys.iter().any(|k| k == key))
+        }
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    struct FaultInjectionConfig {
+        #[serde(default)]
+        enabled: bool,
+        #[serde(default)]
+        rules: Vec<FaultRule>,
+    }
+
+    lazy_static! {
+        static ref FAULT_RULES: RwLock<Vec<FaultRule>> = RwLock::new(load_rules());
+    }
+
+    fn load_rules() -> Vec<FaultRule> {
+        let settings = config::get_res::<FaultInjectionConfig>("couchbase.faultInjection").unwrap_or_default();
+        if !settings.enabled {
+            return Vec::new();
+        }
+        log::warn!("Fault injection enabled with {} rules", settings.rules.len());
+        settings.rules
+    }
+
+    // Replaces the configured rules at runtime, an empty list disables injection
+    pub fn set_fault_rules(rules: Vec<FaultRule>) {
+        *FAULT_RULES.write().unwrap() = rules;
+    }
+
+    pub async fn inject_fault(operation: FaultOperation, key: &str) -> Option<String> {
+        let rule = FAULT_RULES
+            .read()
+            .unwrap()
+            .iter()
+            .find(|rule| rule.matches(operation, key) && rand::thread_rng().gen_range(0.0..100.0) < rule.percentage)
+            .cloned()?;
+        log::warn!("Injecting {:?} fault into {:?} for key: {}", rule.fault, operation, key);
+        Some(match rule.fault {
+            Fault::Timeout => {
+                tokio::time::sleep(Duration::from_millis(rule.delay_ms)).await;
+                "fault injected: operation timed out".to_string()
+            }
+            Fault::CasMismatch => "fault injected: cas mismatch".to_string(),
+            Fault::NotFound => "fault injected: document not found".to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "fault-injection")]
+pub use injector::{inject_fault, set_fault_rules, Fault, FaultRule};
+
+#[cfg(not(feature = "fault-injection"))]
+pub async fn inject_fault(_operation: FaultOperation, _key: &str) -> Option<String> {
+    None
+}
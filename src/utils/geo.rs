@@ -0,0 +1,108 @@
+// Geo helpers for the search service: point validation for writes and the geo query bodies used by
+// searchNear/searchWithinPolygon. Accepted point shapes are the ones a search geopoint field indexes:
+// `{ lon, lat }`, `[lon, lat]` and GeoJSON `{ type: "Point", coordinates: [lon, lat] }`
+use serde_json::{json, Map, Value};
+
+fn check_coordinates(path: &str, lon: f64, lat: f64) -> Result<(), String> {
+    if !(-180.0..=180.0).contains(&lon) || !(-90.0..=90.0).contains(&lat) {
+        return Err(format!(
+            "INVALID_GEO: {} has lon {} / lat {} outside [-180, 180] / [-90, 90]",
+            path, lon, lat
+        ));
+    }
+    Ok(())
+}
+
+fn lon_lat_pair(path: &str, value: &Value) -> Result<(f64, f64), String> {
+    match value.as_array().map(Vec::as_slice) {
+        Some([lon, lat]) => match (lon.as_f64(), lat.as_f64()) {
+            (Some(lon), Some(lat)) => Ok((lon, lat)),
+            _ => Err(format!("INVALID_GEO: {} coordinates must be numbers", path)),
+        },
+        _ => Err(format!("INVALID_GEO: {} must be a [lon, lat] pair", path)),
+    }
+}
+
+pub fn validate_geo_value(path: &str, value: &Value) -> Result<(), String> {
+    let (lon, lat) = match value {
+        Value::Array(_) => lon_lat_pair(path, value)?,
+        Value::Object(object) => match object.get("type").and_then(Value::as_str) {
+            Some("Point") => lon_lat_pair(path, object.get("coordinates").unwrap_or(&Value::Null))?,
+            Some("Polygon") => {
+                let rings = object
+                    .get("coordinates")
+                    .and_then(Value::as_array)
+                    .filter(|rings| !rings.is_empty())
+                    .ok_or_else(|| format!("INVALID_GEO: {} polygon needs at least one ring", path))?;
+                for ring in rings {
+                    let positions = ring
+                        .as_array()
+                        .filter(|positions| positions.len() >= 4)
+                        .ok_or_else(|| format!("INVALID_GEO: {} polygon rings need at least 4 positions", path))?;
+                    for position in positions {
+                        let (lon, lat) = lon_lat_pair(path, position)?;
+                        check_coordinates(path, lon, lat)?;
+                    }
+                    if positions.first() != positions.last() {
+                        return Err(format!("INVALID_GEO: {} polygon rings must be closed", path));
+                    }
+                }
+                return Ok(());
+            }
+            Some(other) => return Err(format!("INVALID_GEO: {} has unsupported GeoJSON type {}", path, other)),
+            None => match (object.get("lon").and_then(Value::as_f64), object.get("lat").and_then(Value::as_f64)) {
+                (Some(lon), Some(lat)) => (lon, lat),
+                _ => return Err(format!("INVALID_GEO: {} must have numeric lon and lat", path)),
+            },
+        },
+        _ => return Err(format!("INVALID_GEO: {} is not a geo point", path)),
+    };
+    check_coordinates(path, lon, lat)
+}
+
+// Checks every dotted `fields` path present in the document, absent fields are left to schema validation
+pub fn validate_geo_fields(document: &Value, fields: &[String]) -> Result<(), String> {
+    for field in fields {
+        let value = field
+            .split('.')
+            .try_fold(document, |value, segment| value.as_object().and_then(|object| object.get(segment)));
+        if let Some(value) = value.filter(|value| !value.is_null()) {
+            validate_geo_value(field, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn with_field(mut query: Map<String, Value>, field: Option<&str>) -> Value {
+    if let Some(field) = field {
+        query.insert("field".to_string(), json!(field));
+    }
+    Value::Object(query)
+}
+
+// Search request matching points within `radius_meters` of (lon, lat)
+pub fn near_query(field: Option<&str>, lon: f64, lat: f64, radius_meters: f64) -> Result<Value, String> {
+    check_coordinates("location", lon, lat)?;
+    if !(radius_meters > 0.0 && radius_meters.is_finite()) {
+        return Err(format!("INVALID_GEO: radius must be a positive number of meters, got {}", radius_meters));
+    }
+    let mut query = Map::new();
+    query.insert("location".to_string(), json!({ "lon": lon, "lat": lat }));
+    query.insert("distance".to_string(), json!(format!("{}m", radius_meters)));
+    Ok(json!({ "query": with_field(query, field) }))
+}
+
+// Search request matching points inside the polygon, given as (lon, lat) vertices
+pub fn polygon_query(field: Option<&str>, points: &[(f64, f64)]) -> Result<Value, String> {
+    if points.len() < 3 {
+        return Err("INVALID_GEO: a polygon needs at least 3 points".to_string());
+    }
+    let mut vertices = Vec::new();
+    for (lon, lat) in points {
+        check_coordinates("polygon", *lon, *lat)?;
+        vertices.push(json!({ "lon": lon, "lat": lat }));
+    }
+    let mut query = Map::new();
+    query.insert("polygon_points".to_string(), Value::Array(vertices));
+    Ok(json!({ "query": with_field(query, field) }))
+}
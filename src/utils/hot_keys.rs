@@ -0,0 +1,191 @@
+// Per-key access frequency for finding the documents behind vbucket hotspots, configured under `couchbase.hotKeys`, e.g.
+//   "hotKeys": { "enabled": true, "windowSecs": 60, "warnThreshold": 1000, "tracked": 256 }
+// Counts go into a count-min sketch, so memory stays fixed however many keys are touched, and the `tracked` keys
+// with the highest estimates are kept as candidates. Counts restart every window, keys at or above
+// `warnThreshold` in the window that just ended are logged as warnings
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::Keyspace;
+
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH: usize = 2048;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HotKeySettings {
+    #[serde(default = "HotKeySettings::default_enabled")]
+    enabled: bool,
+    #[serde(default = "HotKeySettings::default_window_secs")]
+    window_secs: u64,
+    #[serde(default = "HotKeySettings::default_warn_threshold")]
+    warn_threshold: u64,
+    #[serde(default = "HotKeySettings::default_tracked")]
+    tracked: usize,
+}
+
+impl HotKeySettings {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_window_secs() -> u64 {
+        60
+    }
+
+    fn default_warn_threshold() -> u64 {
+        1000
+    }
+
+    fn default_tracked() -> usize {
+        256
+    }
+}
+
+impl Default for HotKeySettings {
+    fn default() -> Self {
+        HotKeySettings {
+            enabled: Self::default_enabled(),
+            window_secs: Self::default_window_secs(),
+            warn_threshold: Self::default_warn_threshold(),
+            tracked: Self::default_tracked(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HotKey {
+    pub key: String,
+    // bucket or bucket.scope.collection
+    pub keyspace: String,
+    // Estimated accesses in the current window, never below the real count
+    pub count: u64,
+}
+
+struct HotKeyTracker {
+    sketch: Vec<Vec<u32>>,
+    // (keyspace, key) -> estimate
+    candidates: HashMap<(String, String), u64>,
+    window_started: Instant,
+}
+
+impl HotKeyTracker {
+    fn new() -> Self {
+        HotKeyTracker {
+            sketch: vec![vec![0; SKETCH_WIDTH]; SKETCH_DEPTH],
+            candidates: HashMap::new(),
+            window_started: Instant::now(),
+        }
+    }
+
+    fn slot(row: usize, id: &(String, String)) -> usize {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        id.hash(&mut hasher);
+        (hasher.finish() as usize) % SKETCH_WIDTH
+    }
+
+    // Adds one access and returns the new estimate
+    fn add(&mut self, id: &(String, String)) -> u64 {
+        let mut estimate = u32::MAX;
+        for (row, counters) in self.sketch.iter_mut().enumerate() {
+            let counter = &mut counters[Self::slot(row, id)];
+            *counter = counter.saturating_add(1);
+            estimate = estimate.min(*counter);
+        }
+        estimate as u64
+    }
+
+    fn record(&mut self, id: (String, String), capacity: usize) {
+        let estimate = self.add(&id);
+        if let Some(count) = self.candidates.get_mut(&id) {
+            *count = estimate;
+            return;
+        }
+        if self.candidates.len() < capacity {
+            self.candidates.insert(id, estimate);
+            return;
+        }
+        let coldest = self
+            .candidates
+            .iter()
+            .min_by_key(|(_, count)| **count)
+            .map(|(candidate, count)| (candidate.clone(), *count));
+        if let Some((coldest, count)) = coldest {
+            if estimate > count {
+                self.candidates.remove(&coldest);
+                self.candidates.insert(id, estimate);
+            }
+        }
+    }
+
+    fn top(&self, limit: usize) -> Vec<HotKey> {
+        let mut hot: Vec<HotKey> = self
+            .candidates
+            .iter()
+            .map(|((keyspace, key), count)| HotKey {
+                key: key.clone(),
+                keyspace: keyspace.clone(),
+                count: *count,
+            })
+            .collect();
+        hot.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        hot.truncate(limit);
+        hot
+    }
+
+    fn reset(&mut self) {
+        for counters in self.sketch.iter_mut() {
+            counters.iter_mut().for_each(|counter| *counter = 0);
+        }
+        self.candidates.clear();
+        self.window_started = Instant::now();
+    }
+}
+
+lazy_static! {
+    static ref HOT_KEY_SETTINGS: HotKeySettings =
+        config::get_res::<HotKeySettings>("couchbase.hotKeys").unwrap_or_default();
+    static ref HOT_KEYS: Mutex<HotKeyTracker> = Mutex::new(HotKeyTracker::new());
+}
+
+fn close_window(tracker: &mut HotKeyTracker) {
+    let settings = &*HOT_KEY_SETTINGS;
+    for hot in tracker.top(usize::MAX) {
+        if hot.count < settings.warn_threshold {
+            break;
+        }
+        log::warn!(
+            "Hot key {} in {}: about {} accesses in the last {}s",
+            hot.key,
+            hot.keyspace,
+            hot.count,
+            settings.window_secs
+        );
+    }
+    tracker.reset();
+}
+
+pub fn record_access(key: &str, keyspace: &Keyspace) {
+    let settings = &*HOT_KEY_SETTINGS;
+    if !settings.enabled {
+        return;
+    }
+    let mut tracker = HOT_KEYS.lock().unwrap();
+    if tracker.window_started.elapsed() >= Duration::from_secs(settings.window_secs.max(1)) {
+        close_window(&mut tracker);
+    }
+    tracker.record((keyspace.to_string(), key.to_string()), settings.tracked);
+}
+
+// Most accessed keys of the current window, hottest first
+pub fn hot_keys(limit: usize) -> Vec<HotKey> {
+    HOT_KEYS.lock().unwrap().top(limit)
+}
@@ -0,0 +1,68 @@
+// Deduplication for at-least-once consumers: the first processOnce for a message id inserts a marker holding the
+// handler's result, redeliveries lose the insert race and get the stored result back. Markers expire after `ttl`,
+// which should outlast the broker's redelivery window
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::utils::couchbase_db::{
+    is_document_not_found, is_write_conflict, CouchbaseConnection, Keyspace, OperationOptions,
+};
+
+pub const DEFAULT_PROCESSED_PREFIX: &str = "processed::";
+
+pub struct ProcessOnceOutcome {
+    // False for a redelivery, `result` is then what the first delivery stored
+    pub first: bool,
+    pub result: Value,
+}
+
+pub async fn process_once(
+    connection: &CouchbaseConnection,
+    message_id: &str,
+    ttl: Duration,
+    result: Value,
+    key_prefix: Option<&str>,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<ProcessOnceOutcome, String> {
+    if message_id.is_empty() {
+        return Err("processOnce needs a messageId".to_string());
+    }
+    if ttl.is_zero() {
+        return Err("processOnce TTL must be greater than zero".to_string());
+    }
+    let key = format!("{}{}", key_prefix.unwrap_or(DEFAULT_PROCESSED_PREFIX), message_id);
+    let marker = json!({
+        "messageId": message_id,
+        "processedAt": chrono::Utc::now().timestamp_millis(),
+        "result": result,
+    });
+    let insert_options = OperationOptions {
+        expiry: Some(ttl),
+        ..options.clone()
+    };
+    // No retries, an existing marker is the answer rather than a transient failure
+    match connection
+        .add_document(key.clone(), marker, keyspace.clone(), insert_options, Some(0))
+        .await
+    {
+        Ok(_) => Ok(ProcessOnceOutcome { first: true, result }),
+        Err(error) if is_write_conflict(&error) => {
+            log::info!("{}Message {} was already processed", options.log_prefix(), message_id);
+            match connection.get_document(key, keyspace, options).await {
+                Ok(stored) => Ok(ProcessOnceOutcome {
+                    first: false,
+                    result: stored.value["result"].clone(),
+                }),
+                // Expired between the insert and the read, the delivery still counts as a duplicate
+                Err(error) if is_document_not_found(&error) => Ok(ProcessOnceOutcome {
+                    first: false,
+                    result: Value::Null,
+                }),
+                Err(error) => Err(error),
+            }
+        }
+        Err(error) => Err(error),
+    }
+}
@@ -0,0 +1,262 @@
+// Optional append-only journal of successful mutations, one JSON line per write, configured under
+// `couchbase.journal`, e.g. { "enabled": true, "path": "./journal/mutations.ndjson", "payload": "full" }.
+// With full payloads replayJournal can re-apply recent writes to another bucket after an incident; with
+// "hash" only the document hash is kept, enough to audit but not to replay. Kafka output is not available
+// while the Kafka producer is compiled out
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use couchbase::StoreSemantics;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::configuration as config;
+use crate::utils::checksum::document_checksum;
+use crate::utils::couchbase_db::{is_document_not_found, CouchbaseConnection, Keyspace, OperationOptions, SubdocMutation};
+use crate::utils::policy::check_policy;
+use crate::utils::progress::{record_progress, ProgressReporter};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum JournalPayload {
+    #[default]
+    Hash,
+    Full,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalSettings {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "JournalSettings::default_path")]
+    path: String,
+    #[serde(default)]
+    payload: JournalPayload,
+}
+
+impl JournalSettings {
+    fn default_path() -> String {
+        "./journal/mutations.ndjson".to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JournalOp {
+    Insert,
+    Upsert,
+    Replace,
+    Remove,
+    MutateIn,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalEntry {
+    // Epoch milliseconds
+    ts: i64,
+    op: JournalOp,
+    bucket: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collection: Option<String>,
+    key: String,
+    cas: u64,
+    // The document for insert/replace, the specs for mutateIn
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_hash: Option<String>,
+}
+
+struct Journal {
+    file: Mutex<File>,
+    payload: JournalPayload,
+}
+
+fn open_journal() -> Option<Journal> {
+    let settings = config::get_res::<JournalSettings>("couchbase.journal").ok()?;
+    if !settings.enabled {
+        return None;
+    }
+    if let Some(parent) = Path::new(&settings.path).parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    match OpenOptions::new().create(true).append(true).open(&settings.path) {
+        Ok(file) => {
+            log::info!("Journaling mutations to {} ({:?} payloads)", settings.path, settings.payload);
+            Some(Journal {
+                file: Mutex::new(file),
+                payload: settings.payload,
+            })
+        }
+        Err(error) => {
+            log::error!("Unable to open mutation journal {} : {}", settings.path, error);
+            None
+        }
+    }
+}
+
+lazy_static! {
+    static ref JOURNAL: Option<Journal> = open_journal();
+}
+
+pub fn journal_enabled() -> bool {
+    JOURNAL.is_some()
+}
+
+// Never fails the mutation, a journal that cannot be written is logged
+pub fn record_mutation(op: JournalOp, key: &str, keyspace: &Keyspace, cas: u64, payload: Option<&Value>) {
+    let journal = match JOURNAL.as_ref() {
+        Some(journal) => journal,
+        None => return,
+    };
+    let (payload, payload_hash) = match (journal.payload, payload) {
+        (_, None) => (None, None),
+        (JournalPayload::Full, Some(payload)) => (Some(payload.clone()), None),
+        (JournalPayload::Hash, Some(payload)) => (None, Some(document_checksum(payload))),
+    };
+    let entry = JournalEntry {
+        ts: chrono::Utc::now().timestamp_millis(),
+        op,
+        bucket: keyspace.bucket.clone(),
+        scope: keyspace.scope.clone(),
+        collection: keyspace.collection.clone(),
+        key: key.to_string(),
+        cas,
+        payload,
+        payload_hash,
+    };
+    let line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(error) => {
+            log::error!("Unable to serialize journal entry for {} : {}", key, error);
+            return;
+        }
+    };
+    let mut file = journal.file.lock().unwrap();
+    if let Err(error) = writeln!(file, "{}", line) {
+        log::error!("Unable to write journal entry for {} : {}", key, error);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    pub applied: u64,
+    // Entries journaled with a hash only, or older than `since`
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+async fn replay_entry(
+    connection: &CouchbaseConnection,
+    entry: JournalEntry,
+    keyspace: Keyspace,
+    options: &OperationOptions,
+) -> Result<bool, String> {
+    let payload = match (entry.op, entry.payload) {
+        (JournalOp::Remove, _) => Value::Null,
+        (_, Some(payload)) => payload,
+        (_, None) => return Ok(false),
+    };
+    match entry.op {
+        JournalOp::Insert | JournalOp::Upsert | JournalOp::Replace => {
+            // Last write wins, the document may or may not exist in the target
+            match connection
+                .replace_document(entry.key.clone(), payload.clone(), None, keyspace.clone(), options.clone(), Some(0))
+                .await
+            {
+                Err(error) if is_document_not_found(&error) => connection
+                    .add_document(entry.key, payload, keyspace, options.clone(), Some(0))
+                    .await
+                    .map(|_| true),
+                written => written.map(|_| true),
+            }
+        }
+        JournalOp::Remove => match connection.delete_data(entry.key, None, keyspace, options.clone()).await {
+            Err(error) if !is_document_not_found(&error) => Err(error),
+            _ => Ok(true),
+        },
+        JournalOp::MutateIn => {
+            let mutations: Vec<SubdocMutation> =
+                serde_json::from_value(payload).map_err(|error| format!("Invalid journaled specs : {}", error))?;
+            connection
+                .mutate_in(entry.key, mutations, keyspace, None, Some(StoreSemantics::Upsert), options.clone())
+                .await
+                .map(|_| true)
+        }
+    }
+}
+
+// Re-applies the journal at `path` in order to `to_bucket`, keeping each entry's scope and collection.
+// Entries before `since` (epoch ms) are skipped
+pub async fn replay_journal(
+    connection: &CouchbaseConnection,
+    path: &str,
+    to_bucket: String,
+    since: Option<i64>,
+    options: OperationOptions,
+    mut progress: Option<ProgressReporter>,
+) -> Result<ReplaySummary, String> {
+    check_policy("replayJournal")?;
+    let file = File::open(path).map_err(|error| format!("Unable to open journal {} : {}", path, error))?;
+    let mut summary = ReplaySummary::default();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|error| format!("Unable to read journal {} : {}", path, error))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: JournalEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(error) => {
+                log::error!("Skipping unreadable journal line {} of {} : {}", index + 1, path, error);
+                summary.failed += 1;
+                record_progress(&mut progress, false);
+                continue;
+            }
+        };
+        if since.is_some_and(|since| entry.ts < since) {
+            summary.skipped += 1;
+            record_progress(&mut progress, true);
+            continue;
+        }
+        let keyspace = Keyspace {
+            bucket: to_bucket.clone(),
+            scope: entry.scope.clone(),
+            collection: entry.collection.clone(),
+        };
+        let key = entry.key.clone();
+        let replayed = replay_entry(connection, entry, keyspace, &options).await;
+        record_progress(&mut progress, replayed.is_ok());
+        match replayed {
+            Ok(true) => summary.applied += 1,
+            Ok(false) => summary.skipped += 1,
+            Err(error) => {
+                log::error!("Replay of journal line {} ({}) failed : {}", index + 1, key, error);
+                summary.failed += 1;
+            }
+        }
+    }
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+    log::info!(
+        "Replayed journal {} into {}: {} applied, {} skipped, {} failed",
+        path,
+        to_bucket,
+        summary.applied,
+        summary.skipped,
+        summary.failed
+    );
+    Ok(summary)
+}
+
+// Specs as journaled for mutateIn entries
+pub fn mutation_specs(mutations: &[SubdocMutation]) -> Value {
+    serde_json::to_value(mutations).unwrap_or_else(|_| json!([]))
+}
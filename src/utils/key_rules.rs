@@ -0,0 +1,108 @@
+// Key naming rules per bucket, configured under `couchbase.keyRules`, e.g.
+//   "keyRules": { "users": { "template": "user::{id}" }, "orders": { "pattern": "^order::[0-9]+$", "mode": "warn" } }
+// A template matches its literal text with every `{name}` standing for one or more characters other than ':'.
+// Only writes that can create a key are checked, existing keys stay readable and replaceable
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::configuration as config;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum KeyRuleMode {
+    #[default]
+    Reject,
+    Warn,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyRuleSettings {
+    pattern: Option<String>,
+    template: Option<String>,
+    #[serde(default)]
+    mode: KeyRuleMode,
+}
+
+struct KeyRule {
+    regex: Regex,
+    // Shown in errors, the template or pattern as configured
+    description: String,
+    mode: KeyRuleMode,
+}
+
+fn template_pattern(template: &str) -> String {
+    let placeholder = Regex::new(r"\{[^{}]*\}").unwrap();
+    let mut pattern = String::from("^");
+    let mut last = 0;
+    for found in placeholder.find_iter(template) {
+        pattern.push_str(&regex::escape(&template[last..found.start()]));
+        pattern.push_str("[^:]+");
+        last = found.end();
+    }
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+    pattern
+}
+
+// Compiles a naming rule given as a regex or a template, returns it with the text to show in errors
+pub fn key_pattern(pattern: Option<&str>, template: Option<&str>) -> Result<(Regex, String), String> {
+    let (pattern, description) = match (pattern, template) {
+        (Some(pattern), _) => (pattern.to_string(), pattern.to_string()),
+        (None, Some(template)) => (template_pattern(template), template.to_string()),
+        (None, None) => return Err("a key rule needs a pattern or a template".to_string()),
+    };
+    let regex = Regex::new(&pattern).map_err(|error| error.to_string())?;
+    Ok((regex, description))
+}
+
+fn load_key_rules() -> HashMap<String, KeyRule> {
+    let settings = match config::get_res::<HashMap<String, KeyRuleSettings>>("couchbase.keyRules") {
+        Ok(settings) => settings,
+        Err(_) => return HashMap::new(),
+    };
+    let mut rules = HashMap::new();
+    for (bucket, setting) in settings {
+        match key_pattern(setting.pattern.as_deref(), setting.template.as_deref()) {
+            Ok((regex, description)) => {
+                rules.insert(
+                    bucket,
+                    KeyRule {
+                        regex,
+                        description,
+                        mode: setting.mode,
+                    },
+                );
+            }
+            Err(error) => log::error!("Invalid key rule for bucket {} : {}, ignoring it", bucket, error),
+        }
+    }
+    rules
+}
+
+lazy_static! {
+    static ref KEY_RULES: HashMap<String, KeyRule> = load_key_rules();
+}
+
+pub fn check_key(key: &str, bucket: &str) -> Result<(), String> {
+    let rule = match KEY_RULES.get(bucket) {
+        Some(rule) => rule,
+        None => return Ok(()),
+    };
+    if rule.regex.is_match(key) {
+        return Ok(());
+    }
+    match rule.mode {
+        KeyRuleMode::Reject => Err(format!(
+            "INVALID_KEY: key {} does not follow the naming rule {} of bucket {}",
+            key, rule.description, bucket
+        )),
+        KeyRuleMode::Warn => {
+            log::warn!("Key {} does not follow the naming rule {} of bucket {}", key, rule.description, bucket);
+            Ok(())
+        }
+    }
+}
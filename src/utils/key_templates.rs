@@ -0,0 +1,217 @@
+// Named key templates, loaded from `couchbase.keyTemplates` or registered at runtime, e.g.
+//   "keyTemplates": { "user": "usr::{tenantId}::{userId}", "order": "order::{orderId}" }
+// so keys are built and taken apart in one place instead of formatted by hand. Like key rules, a parameter stands
+// for one or more characters other than ':', and two parameters need literal text between them
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::configuration as config;
+
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+struct KeyTemplate {
+    template: String,
+    segments: Vec<Segment>,
+    regex: Regex,
+}
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    static ref KEY_TEMPLATES: RwLock<HashMap<String, KeyTemplate>> = RwLock::new(load_key_templates());
+}
+
+fn compile_template(template: &str) -> Result<KeyTemplate, String> {
+    let mut segments = Vec::new();
+    let mut pattern = String::from("^");
+    let mut last = 0;
+    for found in PLACEHOLDER.captures_iter(template) {
+        let whole = found.get(0).unwrap();
+        let literal = &template[last..whole.start()];
+        if literal.contains('{') || literal.contains('}') {
+            return Err(format!("{} has a placeholder that is not a plain name", template));
+        }
+        if literal.is_empty() && matches!(segments.last(), Some(Segment::Param(_))) {
+            return Err(format!("{} has two parameters with nothing between them", template));
+        }
+        let name = found[1].to_string();
+        if segments.iter().any(|segment| matches!(segment, Segment::Param(existing) if *existing == name)) {
+            return Err(format!("{} uses the parameter {} twice", template, name));
+        }
+        if !literal.is_empty() {
+            pattern.push_str(&regex::escape(literal));
+            segments.push(Segment::Literal(literal.to_string()));
+        }
+        pattern.push_str(&format!("(?P<{}>[^:]+)", name));
+        segments.push(Segment::Param(name));
+        last = whole.end();
+    }
+    let rest = &template[last..];
+    if rest.contains('{') || rest.contains('}') {
+        return Err(format!("{} has a placeholder that is not a plain name", template));
+    }
+    if !rest.is_empty() {
+        pattern.push_str(&regex::escape(rest));
+        segments.push(Segment::Literal(rest.to_string()));
+    }
+    pattern.push('$');
+    let regex = Regex::new(&pattern).map_err(|error| error.to_string())?;
+    Ok(KeyTemplate {
+        template: template.to_string(),
+        segments,
+        regex,
+    })
+}
+
+fn load_key_templates() -> HashMap<String, KeyTemplate> {
+    let settings = match config::get_res::<HashMap<String, String>>("couchbase.keyTemplates") {
+        Ok(settings) => settings,
+        Err(_) => return HashMap::new(),
+    };
+    let mut templates = HashMap::new();
+    for (name, template) in settings {
+        match compile_template(&template) {
+            Ok(compiled) => {
+                templates.insert(name, compiled);
+            }
+            Err(error) => log::error!("Invalid key template {} : {}, ignoring it", name, error),
+        }
+    }
+    templates
+}
+
+pub fn register_key_template(name: String, template: String) -> Result<(), String> {
+    let compiled = compile_template(&template).map_err(|error| format!("Invalid key template {} : {}", name, error))?;
+    KEY_TEMPLATES.write().unwrap().insert(name, compiled);
+    Ok(())
+}
+
+fn unknown_template(name: &str) -> String {
+    format!("UNKNOWN_KEY_TEMPLATE: no key template registered as {}", name)
+}
+
+// Parameters may be strings or numbers, every parameter of the template is required and no others are accepted
+pub fn build_key(name: &str, params: &Map<String, Value>) -> Result<String, String> {
+    let templates = KEY_TEMPLATES.read().unwrap();
+    let template = templates.get(name).ok_or_else(|| unknown_template(name))?;
+    let mut key = String::new();
+    let mut used = 0;
+    for segment in &template.segments {
+        let param = match segment {
+            Segment::Literal(literal) => {
+                key.push_str(literal);
+                continue;
+            }
+            Segment::Param(param) => param,
+        };
+        let value = match params.get(param) {
+            Some(Value::String(value)) => value.clone(),
+            Some(Value::Number(value)) => value.to_string(),
+            Some(other) => {
+                return Err(format!(
+                    "INVALID_KEY_PARAM: {} of key template {} must be a string or a number, got {}",
+                    param, name, other
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "INVALID_KEY_PARAM: key template {} ({}) needs {}",
+                    name, template.template, param
+                ))
+            }
+        };
+        if value.is_empty() || value.contains(':') {
+            return Err(format!(
+                "INVALID_KEY_PARAM: {} of key template {} must be non-empty and free of ':', got {:?}",
+                param, name, value
+            ));
+        }
+        key.push_str(&value);
+        used += 1;
+    }
+    if used != params.len() {
+        let unknown: Vec<&String> = params
+            .keys()
+            .filter(|param| {
+                !template.segments.iter().any(|segment| matches!(segment, Segment::Param(known) if known == *param))
+            })
+            .collect();
+        return Err(format!(
+            "INVALID_KEY_PARAM: key template {} ({}) has no parameter {:?}",
+            name, template.template, unknown
+        ));
+    }
+    Ok(key)
+}
+
+pub fn parse_key(name: &str, key: &str) -> Result<HashMap<String, String>, String> {
+    let templates = KEY_TEMPLATES.read().unwrap();
+    let template = templates.get(name).ok_or_else(|| unknown_template(name))?;
+    let captures = template.regex.captures(key).ok_or_else(|| {
+        format!(
+            "INVALID_KEY: key {} does not match key template {} ({})",
+            key, name, template.template
+        )
+    })?;
+    Ok(template
+        .segments
+        .iter()
+        .filter_map(|segment| match segment {
+            Segment::Param(param) => Some((param.clone(), captures[param.as_str()].to_string())),
+            Segment::Literal(_) => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn keys_build_and_parse_back() {
+        register_key_template("test-user".to_string(), "usr::{tenantId}::{userId}".to_string()).unwrap();
+        let key = build_key("test-user", &params(json!({ "tenantId": "t1", "userId": 42 }))).unwrap();
+        assert_eq!(key, "usr::t1::42");
+        let parsed = parse_key("test-user", &key).unwrap();
+        assert_eq!(parsed.get("tenantId").map(String::as_str), Some("t1"));
+        assert_eq!(parsed.get("userId").map(String::as_str), Some("42"));
+    }
+
+    #[test]
+    fn params_are_checked() {
+        register_key_template("test-order".to_string(), "order::{orderId}".to_string()).unwrap();
+        for bad in [json!({}), json!({ "orderId": "a:b" }), json!({ "orderId": "" }), json!({ "orderId": true })] {
+            let error = build_key("test-order", &params(bad)).unwrap_err();
+            assert!(error.starts_with("INVALID_KEY_PARAM"), "{}", error);
+        }
+        let error = build_key("test-order", &params(json!({ "orderId": "1", "extra": "2" }))).unwrap_err();
+        assert!(error.starts_with("INVALID_KEY_PARAM"), "{}", error);
+    }
+
+    #[test]
+    fn keys_of_another_shape_do_not_parse() {
+        register_key_template("test-session".to_string(), "session::{id}".to_string()).unwrap();
+        assert!(parse_key("test-session", "session::a::b").unwrap_err().starts_with("INVALID_KEY"));
+        assert!(parse_key("test-session", "other::a").unwrap_err().starts_with("INVALID_KEY"));
+        assert!(parse_key("test-unknown", "session::a").unwrap_err().starts_with("UNKNOWN_KEY_TEMPLATE"));
+    }
+
+    #[test]
+    fn ambiguous_templates_are_rejected() {
+        assert!(compile_template("{a}{b}").is_err());
+        assert!(compile_template("x::{a}::{a}").is_err());
+        assert!(compile_template("x::{a-b}").is_err());
+        assert!(compile_template("x::{a}::y").is_ok());
+    }
+}
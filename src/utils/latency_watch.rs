@@ -0,0 +1,95 @@
+// Rebuilds cached keyspace connections whose KV operations stay slow, which is how a connection left stale by a
+// rebalance shows up. Configured under `couchbase.latencyRebuild`, e.g.
+//   "latencyRebuild": { "enabled": true, "thresholdMs": 500, "consecutive": 50, "cooldownSecs": 300 }
+// A keyspace is rebuilt once `consecutive` operations in a row took longer than `thresholdMs`, and at most once
+// per cooldown so a cluster that is slow everywhere does not get reconnected in a loop
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::configuration as config;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LatencyRebuildSettings {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "LatencyRebuildSettings::default_threshold_ms")]
+    threshold_ms: u64,
+    #[serde(default = "LatencyRebuildSettings::default_consecutive")]
+    consecutive: u32,
+    #[serde(default = "LatencyRebuildSettings::default_cooldown_secs")]
+    cooldown_secs: u64,
+}
+
+impl LatencyRebuildSettings {
+    fn default_threshold_ms() -> u64 {
+        500
+    }
+
+    fn default_consecutive() -> u32 {
+        50
+    }
+
+    fn default_cooldown_secs() -> u64 {
+        300
+    }
+}
+
+impl Default for LatencyRebuildSettings {
+    fn default() -> Self {
+        LatencyRebuildSettings {
+            enabled: false,
+            threshold_ms: Self::default_threshold_ms(),
+            consecutive: Self::default_consecutive(),
+            cooldown_secs: Self::default_cooldown_secs(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct KeyspaceLatency {
+    slow_in_a_row: u32,
+    rebuilt_at: Option<Instant>,
+}
+
+lazy_static! {
+    static ref LATENCY_REBUILD: LatencyRebuildSettings =
+        config::get_res::<LatencyRebuildSettings>("couchbase.latencyRebuild").unwrap_or_default();
+    // Keyspace cache key -> recent latency
+    static ref KEYSPACE_LATENCIES: Mutex<HashMap<String, KeyspaceLatency>> = Mutex::new(HashMap::new());
+}
+
+// Records one operation on `cache_key`, true when its connection should be rebuilt now
+pub fn needs_rebuild(cache_key: &str, elapsed: Duration) -> bool {
+    let settings = &*LATENCY_REBUILD;
+    if !settings.enabled {
+        return false;
+    }
+    let mut latencies = KEYSPACE_LATENCIES.lock().unwrap();
+    let latency = latencies.entry(cache_key.to_string()).or_default();
+    if elapsed <= Duration::from_millis(settings.threshold_ms) {
+        latency.slow_in_a_row = 0;
+        return false;
+    }
+    latency.slow_in_a_row += 1;
+    if latency.slow_in_a_row < settings.consecutive.max(1) {
+        return false;
+    }
+    let cooldown = Duration::from_secs(settings.cooldown_secs);
+    if latency.rebuilt_at.is_some_and(|at| at.elapsed() < cooldown) {
+        return false;
+    }
+    log::warn!(
+        "Keyspace {} had {} operations in a row slower than {} ms, rebuilding its connection",
+        cache_key,
+        latency.slow_in_a_row,
+        settings.threshold_ms
+    );
+    latency.slow_in_a_row = 0;
+    latency.rebuilt_at = Some(Instant::now());
+    true
+}
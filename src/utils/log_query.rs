@@ -0,0 +1,107 @@
+// Reads back the JSON log lines this crate writes (see logger.rs), newest first, for support tooling that needs
+// recent native-layer events without shipping the logs anywhere. The active file is scanned first, then the rolled
+// ones from the most recent roll on, until `limit` entries matched. Lines that are not JSON are skipped
+use std::path::Path;
+
+use log::Level;
+use serde::Deserialize;
+
+use crate::utils::logger::LoggerSettings;
+
+pub const DEFAULT_LOG_QUERY_LIMIT: usize = 100;
+
+#[derive(Debug, Default)]
+pub struct LogQuery {
+    // This level and the more severe ones
+    pub level: Option<Level>,
+    // Epoch milliseconds
+    pub since_ms: Option<i64>,
+    // Document key the message must mention
+    pub key: Option<String>,
+    // Operation the message must mention, e.g. "insert" or "replace", matched case-insensitively
+    pub operation: Option<String>,
+    pub limit: usize,
+}
+
+// One line as log4rs' JsonEncoder writes it
+#[derive(Debug, Deserialize)]
+pub struct LogEntry {
+    pub time: String,
+    pub level: String,
+    pub message: String,
+    pub module_path: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl LogQuery {
+    fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(level) = self.level {
+            match entry.level.parse::<Level>() {
+                Ok(entry_level) if entry_level <= level => {}
+                _ => return false,
+            }
+        }
+        if let Some(since_ms) = self.since_ms {
+            match chrono::DateTime::parse_from_rfc3339(&entry.time) {
+                Ok(time) if time.timestamp_millis() >= since_ms => {}
+                _ => return false,
+            }
+        }
+        if let Some(key) = &self.key {
+            if !entry.message.contains(key.as_str()) {
+                return false;
+            }
+        }
+        if let Some(operation) = &self.operation {
+            if !entry.message.to_lowercase().contains(&operation.to_lowercase()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Active file first, then the rolled ones newest first. The fixed window roller keeps the latest roll at the base
+// index, so the scan stops at the first index without a file
+fn log_files(settings: &LoggerSettings) -> Vec<String> {
+    let mut files = vec![settings.all_log_file_path.clone()];
+    let first = settings.roller_base_start;
+    for index in first..first.saturating_add(settings.roller_max_count) {
+        let path = settings.roller_filepath_pattern.replace("{}", &index.to_string());
+        if !Path::new(&path).exists() {
+            break;
+        }
+        files.push(path);
+    }
+    files
+}
+
+pub fn query_logs(query: &LogQuery) -> Result<Vec<LogEntry>, String> {
+    let (settings, _) = LoggerSettings::load();
+    let limit = if query.limit == 0 { DEFAULT_LOG_QUERY_LIMIT } else { query.limit };
+    let mut entries = Vec::new();
+    for path in log_files(&settings) {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            // Rolled away between listing and reading, or compressed
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) if error.kind() == std::io::ErrorKind::InvalidData => continue,
+            Err(error) => return Err(format!("Unable to read log file {} : {}", path, error)),
+        };
+        for line in contents.lines().rev() {
+            let entry = match serde_json::from_str::<LogEntry>(line) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            if !query.matches(&entry) {
+                continue;
+            }
+            entries.push(entry);
+            if entries.len() >= limit {
+                return Ok(entries);
+            }
+        }
+    }
+    Ok(entries)
+}
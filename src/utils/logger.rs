@@ -9,6 +9,7 @@
 #![allow(unused_unsafe)]
 
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
 use lazy_static::lazy_static;
@@ -27,16 +28,54 @@ use log4rs::Config;
 use crate::configuration;
 use log::{Level, LevelFilter, Record};
 
-lazy_static! {
-    //Most of these will define in config File.
-    // static ref LOG_LINE_PATTERN_CONSOLE: &'static str = "{d(%Y-%m-%d %H:%M:%S)} | {({l}):5.5} | {f}:{L} — {m}{n}";
-    // static ref LOG_LINE_PATTERN_FILE: &'static str = "{d(%Y-%m-%d %H:%M:%S)} | {({l}):5.5} | {f}:{L} — {m}{n}";
+//Most of these will define in config File.
+// static ref LOG_LINE_PATTERN_CONSOLE: &'static str = "{d(%Y-%m-%d %H:%M:%S)} | {({l}):5.5} | {f}:{L} — {m}{n}";
+// static ref LOG_LINE_PATTERN_FILE: &'static str = "{d(%Y-%m-%d %H:%M:%S)} | {({l}):5.5} | {f}:{L} — {m}{n}";
 
-    static ref TRIGGER_FILE_SIZE : u64 = configuration::get::<u64>("logger.logFileSize");
-    static ref ROLLER_FILEPATH_PATTERN: String  = configuration::get::<String>("logger.roller_filepath_pattern");
-    static ref ROLLER_MAX_COUNT: u32 = configuration::get::<u32>("logger.roller_max_count") as u32;
-    static ref ROLLER_BASE_START: u32 = configuration::get::<u32>("logger.roller_base_start") as u32;
-    static ref ALL_LOG_FILE_PATH:String = configuration::get::<String>("logger.all_logs_common_file_path");
+// What is printed to stdout, the log file always receives every line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConsoleOutput {
+    All,
+    // WARN and ERROR only
+    Warn,
+    Off,
+}
+
+// Logger settings read from config at startLogger time, built-in defaults cover any missing key
+pub struct LoggerSettings {
+    pub trigger_file_size: u64,
+    pub roller_filepath_pattern: String,
+    pub roller_max_count: u32,
+    pub roller_base_start: u32,
+    pub all_log_file_path: String,
+    pub console: ConsoleOutput,
+}
+
+impl LoggerSettings {
+    // Returns the settings plus one warning per key that fell back to its default
+    pub fn load() -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let settings = LoggerSettings {
+            trigger_file_size: setting("logger.logFileSize", 17408, &mut warnings),
+            roller_filepath_pattern: setting("logger.roller_filepath_pattern", "logs/LogsFiles/All-Logs_{}.log".to_string(), &mut warnings),
+            roller_max_count: setting("logger.roller_max_count", 999, &mut warnings),
+            roller_base_start: setting("logger.roller_base_start", 1, &mut warnings),
+            all_log_file_path: setting("logger.all_logs_common_file_path", "logs/step/All-Logs.log".to_string(), &mut warnings),
+            console: setting("logger.console", ConsoleOutput::All, &mut warnings),
+        };
+        (settings, warnings)
+    }
+}
+
+fn setting<T: serde::de::DeserializeOwned + std::fmt::Debug>(key: &str, default: T, warnings: &mut Vec<String>) -> T {
+    match configuration::get_res::<T>(key) {
+        Ok(value) => value,
+        Err(error) => {
+            warnings.push(format!("Logger config {} not usable ({}), defaulting to {:?}", key, error, default));
+            default
+        }
+    }
 }
 
 pub struct RollingFileAppenderComponent {
@@ -45,23 +84,70 @@ pub struct RollingFileAppenderComponent {
     pub compound_policy: Box<CompoundPolicy>,
 }
 impl RollingFileAppenderComponent {
-    pub fn new_policy() -> Box<CompoundPolicy> {
+    pub fn new_policy(settings: &LoggerSettings) -> Result<Box<CompoundPolicy>, String> {
         //Set trigger, roller and compoundPolicy for "RollingFileAppender"
-        let trigger: Box<SizeTrigger> = Box::new(SizeTrigger::new(*TRIGGER_FILE_SIZE));
-        let roller: Box<FixedWindowRoller> = Box::new(FixedWindowRoller::builder().base(*ROLLER_BASE_START).build(&ROLLER_FILEPATH_PATTERN, *ROLLER_MAX_COUNT).unwrap());
+        let trigger: Box<SizeTrigger> = Box::new(SizeTrigger::new(settings.trigger_file_size));
+        let roller: Box<FixedWindowRoller> = Box::new(
+            FixedWindowRoller::builder()
+                .base(settings.roller_base_start)
+                .build(&settings.roller_filepath_pattern, settings.roller_max_count)
+                .map_err(|error| format!("Invalid logger roller config : {}", error))?,
+        );
         let compound_policy: Box<CompoundPolicy> = Box::new(CompoundPolicy::new(trigger.clone(), roller.clone()));
-        compound_policy
+        Ok(compound_policy)
+    }
+}
+
+// File appender that moves to the console for good after its first failed write (disk full, permissions), reporting
+// the failure once on stderr instead of log4rs printing an error for every line
+#[derive(Debug)]
+struct FallbackFileAppender {
+    file: RollingFileAppender,
+    console: ConsoleAppender,
+    path: String,
+    // Levels the console appender already prints, None when it is off
+    console_level: Option<LevelFilter>,
+    failed: AtomicBool,
+}
+
+impl Append for FallbackFileAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        if !self.failed.load(Ordering::Relaxed) {
+            match self.file.append(record) {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    // Logging through `log` here would come straight back to this appender
+                    if !self.failed.swap(true, Ordering::Relaxed) {
+                        eprintln!("ERROR - Log file {} is not writable ({}), logging to console only", self.path, error);
+                    }
+                }
+            }
+        }
+        if self.console_level.is_some_and(|level| record.level() <= level) {
+            return Ok(());
+        }
+        self.console.append(record)
+    }
+
+    fn flush(&self) {
+        self.file.flush();
+        self.console.flush();
     }
 }
 
 pub struct LoggerConfig {}
 impl LoggerConfig {
-    pub fn create_Global_logs_config() -> Config {
+    pub fn create_Global_logs_config(settings: &LoggerSettings) -> Result<Config, String> {
         //===== get compound Policy =====
-        let compound_policy: Box<CompoundPolicy> = RollingFileAppenderComponent::new_policy();
+        let compound_policy: Box<CompoundPolicy> = RollingFileAppenderComponent::new_policy(settings)?;
 
         //===== set appanders for console and file =====
         let console_appender = ConsoleAppender::builder().encoder(Box::new(PatternEncoder::new("{l} - {m}{n}"))).build();
+        let console_level = match settings.console {
+            ConsoleOutput::All => Some(LevelFilter::Info),
+            ConsoleOutput::Warn => Some(LevelFilter::Warn),
+            ConsoleOutput::Off => None,
+        };
 
         // Pattern vise logs
         // let all_log_appender = RollingFileAppender::builder()
@@ -70,7 +156,17 @@ impl LoggerConfig {
         // .unwrap();
 
         //json format logs
-        let all_log_appender = RollingFileAppender::builder().encoder(Box::new(JsonEncoder::new())).build(ALL_LOG_FILE_PATH.as_str(), compound_policy).unwrap();
+        let all_log_appender = RollingFileAppender::builder()
+            .encoder(Box::new(JsonEncoder::new()))
+            .build(settings.all_log_file_path.as_str(), compound_policy)
+            .map_err(|error| format!("Unable to open log file {} : {}", settings.all_log_file_path, error))?;
+        let all_log_appender = FallbackFileAppender {
+            file: all_log_appender,
+            console: ConsoleAppender::builder().encoder(Box::new(PatternEncoder::new("{l} - {m}{n}"))).build(),
+            path: settings.all_log_file_path.clone(),
+            console_level,
+            failed: AtomicBool::new(false),
+        };
 
         //===== create config =====
         //ThresholdFilter is mendatory for set LogLevel on specific appenders
@@ -79,20 +175,53 @@ impl LoggerConfig {
 
         // TO-DO : replace level og log
 
-        let Global_logs_config: Config = Config::builder()
-            .appender(Appender::builder().filter(Box::new(ThresholdFilter::new(LevelFilter::Info))).build("console_appender", Box::new(console_appender)))
+        let mut builder = Config::builder()
             .appender(Appender::builder().filter(Box::new(ThresholdFilter::new(LevelFilter::Info))).build("all_log_appender", Box::new(all_log_appender)))
-            .logger(Logger::builder().appender("all_log_appender").additive(true).build("All-Logs", LevelFilter::Info))
-            .logger(Logger::builder().appender("console_appender").additive(true).build("console", LevelFilter::Info))
-            .build(Root::builder().appenders(["console_appender", "all_log_appender"]).build(LevelFilter::Trace))
-            .unwrap();
-        Global_logs_config
+            .logger(Logger::builder().appender("all_log_appender").additive(true).build("All-Logs", LevelFilter::Info));
+        let mut root_appenders = vec!["all_log_appender"];
+        if let Some(console_level) = console_level {
+            builder = builder
+                .appender(Appender::builder().filter(Box::new(ThresholdFilter::new(console_level))).build("console_appender", Box::new(console_appender)))
+                .logger(Logger::builder().appender("console_appender").additive(true).build("console", console_level));
+            root_appenders.push("console_appender");
+        }
+        let Global_logs_config: Config = builder
+            .build(Root::builder().appenders(root_appenders).build(LevelFilter::Trace))
+            .map_err(|error| format!("Invalid logger config : {}", error))?;
+        Ok(Global_logs_config)
+    }
+
+    // Used when the log file cannot be opened at all
+    pub fn create_console_config() -> Result<Config, String> {
+        let console_appender = ConsoleAppender::builder().encoder(Box::new(PatternEncoder::new("{l} - {m}{n}"))).build();
+        Config::builder()
+            .appender(Appender::builder().filter(Box::new(ThresholdFilter::new(LevelFilter::Info))).build("console_appender", Box::new(console_appender)))
+            .build(Root::builder().appender("console_appender").build(LevelFilter::Trace))
+            .map_err(|error| format!("Invalid logger config : {}", error))
     }
 }
 
-pub fn startLogger() {
+// `console` overrides `logger.console` from config
+pub fn startLogger(console: Option<ConsoleOutput>) -> Result<(), String> {
     // You can use handle to change logger config at runtime
     // just call startLogger() in main.rs and you can use log4rs in all your Project-crate.
-    let Global_logs_config = LoggerConfig::create_Global_logs_config();
-    let handle = log4rs::init_config(Global_logs_config).unwrap();
+    let (mut settings, warnings) = LoggerSettings::load();
+    if let Some(console) = console {
+        settings.console = console;
+    }
+    let (Global_logs_config, file_error) = match LoggerConfig::create_Global_logs_config(&settings) {
+        Ok(config) => (config, None),
+        Err(error) => (LoggerConfig::create_console_config()?, Some(error)),
+    };
+    let handle = log4rs::init_config(Global_logs_config).map_err(|error| format!("Unable to start logger : {}", error))?;
+    if let Some(error) = file_error {
+        log::error!("{}, logging to console only", error);
+    }
+    for warning in warnings {
+        log::warn!("{}", warning);
+    }
+    if configuration::get_res::<bool>("logger.logEffectiveConfig").unwrap_or(false) {
+        log::info!("Effective configuration: {}", configuration::effective_config());
+    }
+    Ok(())
 }
@@ -0,0 +1,165 @@
+// Maintenance windows configured under `couchbase.maintenance`, e.g.
+//   "maintenance": { "windows": [{ "name": "weekly-rebalance", "cron": "0 0 2 * * Sun", "durationSecs": 1800 }] }
+// Cron expressions include seconds (sec min hour day-of-month month day-of-week [year]) and are evaluated in UTC.
+// At each start the scheduler enables read-only mode, waits up to `drainTimeoutSecs` for in-flight mutations,
+// and lifts read-only mode again after `durationSecs` unless it was already on when the window opened
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use cron::Schedule;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+
+use crate::configuration as config;
+use crate::utils::read_only::{drain_mutations, in_flight_mutations, is_read_only, set_read_only};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MaintenanceWindow {
+    name: String,
+    cron: String,
+    duration_secs: u64,
+    #[serde(default = "MaintenanceWindow::default_drain_timeout_secs")]
+    drain_timeout_secs: u64,
+}
+
+impl MaintenanceWindow {
+    fn default_drain_timeout_secs() -> u64 {
+        30
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MaintenanceSettings {
+    #[serde(default)]
+    windows: Vec<MaintenanceWindow>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenancePhase {
+    Started,
+    Drained,
+    // Mutations were still running when `drainTimeoutSecs` ran out, the window goes ahead regardless
+    DrainTimedOut,
+    Ended,
+}
+
+impl MaintenancePhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenancePhase::Started => "started",
+            MaintenancePhase::Drained => "drained",
+            MaintenancePhase::DrainTimedOut => "drainTimedOut",
+            MaintenancePhase::Ended => "ended",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MaintenanceEvent {
+    pub window: String,
+    pub phase: MaintenancePhase,
+    // Epoch milliseconds
+    pub at: i64,
+    pub in_flight: usize,
+}
+
+type MaintenanceListener = Box<dyn Fn(&MaintenanceEvent) + Send + Sync>;
+
+lazy_static! {
+    static ref MAINTENANCE_LISTENERS: Mutex<Vec<MaintenanceListener>> = Mutex::new(Vec::new());
+    static ref MAINTENANCE_TASKS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+}
+
+pub fn on_maintenance_event(listener: MaintenanceListener) {
+    MAINTENANCE_LISTENERS.lock().unwrap().push(listener);
+}
+
+fn emit(window: &MaintenanceWindow, phase: MaintenancePhase) {
+    let event = MaintenanceEvent {
+        window: window.name.clone(),
+        phase,
+        at: Utc::now().timestamp_millis(),
+        in_flight: in_flight_mutations(),
+    };
+    log::warn!(
+        "Maintenance window {} {} ({} mutations in flight)",
+        event.window,
+        phase.as_str(),
+        event.in_flight
+    );
+    for listener in MAINTENANCE_LISTENERS.lock().unwrap().iter() {
+        listener(&event);
+    }
+}
+
+async fn run_window(window: &MaintenanceWindow) {
+    let was_read_only = is_read_only();
+    set_read_only(true);
+    emit(window, MaintenancePhase::Started);
+    if drain_mutations(Duration::from_secs(window.drain_timeout_secs)).await {
+        emit(window, MaintenancePhase::Drained);
+    } else {
+        emit(window, MaintenancePhase::DrainTimedOut);
+    }
+    tokio::time::sleep(Duration::from_secs(window.duration_secs)).await;
+    if !was_read_only {
+        set_read_only(false);
+    }
+    emit(window, MaintenancePhase::Ended);
+}
+
+fn schedule_window(window: MaintenanceWindow, schedule: Schedule) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(next) = schedule.upcoming(Utc).next() {
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            log::info!("Next maintenance window {} at {}", window.name, next.to_rfc3339());
+            tokio::time::sleep(wait).await;
+            run_window(&window).await;
+        }
+    })
+}
+
+// Schedules the configured windows, replacing any previous schedule. Returns the number of windows scheduled
+pub fn start_maintenance_scheduler() -> Result<usize, String> {
+    let windows = config::get_res::<MaintenanceSettings>("couchbase.maintenance")
+        .unwrap_or_default()
+        .windows;
+    // Every expression is checked before anything is scheduled
+    let schedules = windows
+        .iter()
+        .map(|window| {
+            Schedule::from_str(&window.cron)
+                .map_err(|error| format!("Invalid cron for maintenance window {} : {}", window.name, error))
+        })
+        .collect::<Result<Vec<Schedule>, String>>()?;
+    let count = windows.len();
+    let tasks: Vec<JoinHandle<()>> = windows
+        .into_iter()
+        .zip(schedules)
+        .map(|(window, schedule)| schedule_window(window, schedule))
+        .collect();
+    let previous = std::mem::replace(&mut *MAINTENANCE_TASKS.lock().unwrap(), tasks);
+    for task in previous {
+        task.abort();
+    }
+    log::info!("Maintenance scheduler started with {} windows", count);
+    Ok(count)
+}
+
+// False when nothing was scheduled. A window that is open when stopped leaves read-only mode as it is
+pub fn stop_maintenance_scheduler() -> bool {
+    let tasks = std::mem::take(&mut *MAINTENANCE_TASKS.lock().unwrap());
+    let stopped = !tasks.is_empty();
+    for task in tasks {
+        task.abort();
+    }
+    if stopped {
+        log::info!("Maintenance scheduler stopped");
+    }
+    stopped
+}
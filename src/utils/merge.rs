@@ -0,0 +1,140 @@
+// Field level merge used by mergeDocument, so writers of disjoint fields don't overwrite each other, and the
+// JSON merge patches folded together by the write coalescer
+use serde_json::{Map, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    // Fields in the partial replace the stored ones
+    LastWriteWins,
+    // Arrays are unioned (stored order first), everything else is last-write-wins
+    ArrayUnion,
+    // Numbers are added to the stored value, everything else is last-write-wins
+    NumericSum,
+}
+
+// Nested objects are merged recursively, so `{a: {b: 1}}` only touches `a.b`
+pub fn merge_values(stored: Value, partial: Value, strategy: MergeStrategy) -> Value {
+    match (stored, partial) {
+        (Value::Object(stored), Value::Object(partial)) => Value::Object(merge_objects(stored, partial, strategy)),
+        (Value::Array(mut stored), Value::Array(partial)) if strategy == MergeStrategy::ArrayUnion => {
+            for element in partial {
+                if !stored.contains(&element) {
+                    stored.push(element);
+                }
+            }
+            Value::Array(stored)
+        }
+        (Value::Number(stored), Value::Number(partial)) if strategy == MergeStrategy::NumericSum => {
+            match (stored.as_i64(), partial.as_i64()) {
+                (Some(stored), Some(partial)) => Value::from(stored.saturating_add(partial)),
+                _ => Value::from(stored.as_f64().unwrap_or(0.0) + partial.as_f64().unwrap_or(0.0)),
+            }
+        }
+        (_, partial) => partial,
+    }
+}
+
+fn merge_objects(mut stored: Map<String, Value>, partial: Map<String, Value>, strategy: MergeStrategy) -> Map<String, Value> {
+    for (field, value) in partial {
+        let merged = match stored.remove(&field) {
+            Some(existing) => merge_values(existing, value, strategy),
+            None => value,
+        };
+        stored.insert(field, merged);
+    }
+    stored
+}
+
+// RFC 7386 JSON merge patch: null removes a field, objects merge recursively, anything else replaces
+pub fn apply_merge_patch(target: Value, patch: Value) -> Value {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        patch => return patch,
+    };
+    let mut target = match target {
+        Value::Object(target) => target,
+        _ => Map::new(),
+    };
+    for (field, value) in patch {
+        if value.is_null() {
+            target.remove(&field);
+            continue;
+        }
+        let existing = target.remove(&field).unwrap_or(Value::Null);
+        target.insert(field, apply_merge_patch(existing, value));
+    }
+    Value::Object(target)
+}
+
+// One patch with the effect of applying `first` then `second`, nulls are kept so removals still apply
+pub fn compose_merge_patches(first: Value, second: Value) -> Value {
+    match (first, second) {
+        (Value::Object(mut first), Value::Object(second)) => {
+            for (field, value) in second {
+                let composed = match first.remove(&field) {
+                    Some(existing) => compose_merge_patches(existing, value),
+                    None => value,
+                };
+                first.insert(field, composed);
+            }
+            Value::Object(first)
+        }
+        // An object patch over a value (or a removal) sets the field to the patched result
+        (first, second @ Value::Object(_)) => apply_merge_patch(first, second),
+        (_, second) => second,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn last_write_wins_merges_nested_objects() {
+        let stored = json!({ "a": { "b": 1, "c": 2 }, "d": [1] });
+        let merged = merge_values(stored, json!({ "a": { "b": 3 }, "d": [2] }), MergeStrategy::LastWriteWins);
+        assert_eq!(merged, json!({ "a": { "b": 3, "c": 2 }, "d": [2] }));
+    }
+
+    #[test]
+    fn array_union_keeps_stored_order() {
+        let stored = json!({ "tags": ["a", "b"] });
+        let merged = merge_values(stored, json!({ "tags": ["c", "a"] }), MergeStrategy::ArrayUnion);
+        assert_eq!(merged, json!({ "tags": ["a", "b", "c"] }));
+    }
+
+    #[test]
+    fn numeric_sum_adds_numbers() {
+        let merged = merge_values(
+            json!({ "count": 2, "ratio": 0.5, "name": "x" }),
+            json!({ "count": 3, "ratio": 0.25, "name": "y" }),
+            MergeStrategy::NumericSum,
+        );
+        assert_eq!(merged, json!({ "count": 5, "ratio": 0.75, "name": "y" }));
+        assert_eq!(
+            merge_values(json!(i64::MAX), json!(1), MergeStrategy::NumericSum),
+            json!(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn merge_patch_removes_nulls() {
+        let target = json!({ "a": 1, "b": { "c": 2, "d": 3 } });
+        let patched = apply_merge_patch(target, json!({ "a": null, "b": { "c": null, "e": 4 } }));
+        assert_eq!(patched, json!({ "b": { "d": 3, "e": 4 } }));
+        assert_eq!(apply_merge_patch(json!({ "a": 1 }), json!([1])), json!([1]));
+    }
+
+    #[test]
+    fn composed_patches_apply_like_both_in_turn() {
+        let target = json!({ "a": 1, "b": { "c": 2 }, "d": 4 });
+        let first = json!({ "a": null, "b": { "x": 1 } });
+        let second = json!({ "a": { "y": 2 }, "b": { "c": null }, "d": null });
+        let composed = compose_merge_patches(first.clone(), second.clone());
+        assert_eq!(
+            apply_merge_patch(target.clone(), composed),
+            apply_merge_patch(apply_merge_patch(target, first), second)
+        );
+    }
+}
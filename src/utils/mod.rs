@@ -1,3 +1,50 @@
+pub mod abort;
+pub mod backoff;
+pub mod blobs;
+pub mod bulk_delete;
+pub mod bulkhead;
+pub mod checksum;
+pub mod chunking;
+pub mod cloud_events;
+pub mod cluster_info;
+pub mod coalescer;
+pub mod document_cache;
+pub mod erasure;
+pub mod event_signing;
+pub mod expiry;
+pub mod export;
+pub mod fault_injection;
+pub mod geo;
+pub mod hot_keys;
+pub mod idempotency;
+pub mod journal;
+pub mod key_templates;
+pub mod key_rules;
+pub mod latency_watch;
+pub mod log_query;
 pub mod logger;
+pub mod maintenance;
+pub mod merge;
+pub mod named_queries;
+pub mod payload;
+pub mod policy;
+pub mod progress;
+pub mod query_cache;
+pub mod read_only;
+pub mod read_preference;
+pub mod read_repair;
+pub mod retention;
+pub mod retry_queue;
+pub mod retry_stats;
+pub mod scheduled_jobs;
+pub mod schema;
+pub mod sessions;
+pub mod sorted_set;
+pub mod time_series;
+pub mod timings;
+pub mod transcoder;
+pub mod validation;
+pub mod watch;
 
-pub mod couchbase_db;
\ No newline at end of file
+pub mod couchbase_db;
+pub mod datastructures;
\ No newline at end of file
@@ -0,0 +1,70 @@
+// Named SQL++ statements, loaded from `couchbase.namedQueries` or registered at runtime, e.g.
+//   "ordersByCustomer": { "statement": "SELECT o.* FROM orders o WHERE o.customerId = $customerId", "timeoutMs": 5000, "consistency": "requestPlus" }
+//   "dailyTotals": { "statement": "SELECT d.day, SUM(d.total) AS total FROM orders d GROUP BY d.day", "cacheTtlMs": 60000 }
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::configuration as config;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryConsistency {
+    #[default]
+    NotBounded,
+    RequestPlus,
+}
+
+impl QueryConsistency {
+    pub fn scan_consistency(&self) -> &'static str {
+        match self {
+            QueryConsistency::NotBounded => "not_bounded",
+            QueryConsistency::RequestPlus => "request_plus",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamedQuery {
+    pub statement: String,
+    // Used when the caller does not pass a timeout
+    pub timeout_ms: Option<u32>,
+    #[serde(default)]
+    pub consistency: QueryConsistency,
+    // Results are cached this long when the caller does not pass cacheTtlMs
+    pub cache_ttl_ms: Option<u32>,
+}
+
+lazy_static! {
+    static ref NAMED_QUERIES: RwLock<HashMap<String, NamedQuery>> = RwLock::new(load_named_queries());
+}
+
+fn load_named_queries() -> HashMap<String, NamedQuery> {
+    match config::get_res::<HashMap<String, NamedQuery>>("couchbase.namedQueries") {
+        Ok(queries) => {
+            log::info!("Loaded {} named queries from config", queries.len());
+            queries
+        }
+        Err(_) => HashMap::new(),
+    }
+}
+
+pub fn register_named_query(name: String, definition: Value) -> Result<(), String> {
+    let query: NamedQuery =
+        serde_json::from_value(definition).map_err(|error| format!("Invalid named query {} : {}", name, error))?;
+    NAMED_QUERIES.write().unwrap().insert(name, query);
+    Ok(())
+}
+
+pub fn named_query(name: &str) -> Result<NamedQuery, String> {
+    NAMED_QUERIES
+        .read()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("UNKNOWN_QUERY: no named query registered as {}", name))
+}
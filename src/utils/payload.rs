@@ -0,0 +1,84 @@
+// Size guards for documents crossing the NAPI boundary, configured under `couchbase.payloadLimits`
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::configuration as config;
+
+// Log lines show at most this many characters of a document
+const LOG_PREVIEW_CHARS: usize = 1024;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayloadLimits {
+    // Writes above this are rejected before reaching Couchbase
+    #[serde(default = "PayloadLimits::default_max_write_bytes")]
+    max_write_bytes: usize,
+    // Reads above this are logged as a warning
+    #[serde(default = "PayloadLimits::default_warn_read_bytes")]
+    warn_read_bytes: usize,
+}
+
+impl PayloadLimits {
+    // Couchbase's own document size limit
+    fn default_max_write_bytes() -> usize {
+        20 * 1024 * 1024
+    }
+
+    fn default_warn_read_bytes() -> usize {
+        5 * 1024 * 1024
+    }
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        PayloadLimits {
+            max_write_bytes: Self::default_max_write_bytes(),
+            warn_read_bytes: Self::default_warn_read_bytes(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref PAYLOAD_LIMITS: PayloadLimits = config::get_res::<PayloadLimits>("couchbase.payloadLimits").unwrap_or_default();
+}
+
+pub fn payload_size(value: &Value) -> usize {
+    serde_json::to_vec(value).map(|bytes| bytes.len()).unwrap_or(0)
+}
+
+pub fn exceeds_write_limit(value: &Value) -> bool {
+    payload_size(value) > PAYLOAD_LIMITS.max_write_bytes
+}
+
+pub fn check_write_size(key: &str, value: &Value) -> Result<(), String> {
+    let size = payload_size(value);
+    if size > PAYLOAD_LIMITS.max_write_bytes {
+        return Err(format!(
+            "PAYLOAD_TOO_LARGE: document {} is {} bytes, the limit is {} bytes",
+            key, size, PAYLOAD_LIMITS.max_write_bytes
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_read_size(key: &str, value: &Value) {
+    let size = payload_size(value);
+    if size > PAYLOAD_LIMITS.warn_read_bytes {
+        log::warn!(
+            "Document {} is {} bytes, above the {} bytes read warning threshold",
+            key,
+            size,
+            PAYLOAD_LIMITS.warn_read_bytes
+        );
+    }
+}
+
+// Truncated rendering for log lines so big documents don't flood the logs
+pub fn log_preview(value: &Value) -> String {
+    let rendered = value.to_string();
+    match rendered.char_indices().nth(LOG_PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}... ({} bytes)", &rendered[..end], rendered.len()),
+        None => rendered,
+    }
+}
@@ -0,0 +1,68 @@
+// Per-environment safety net over which operations may run, configured under `couchbase.policy` in the
+// config-<RUN_MODE> files, e.g.
+//   "policy": { "deny": ["delete", "eraseSubject"] }       no removals or erasures in this environment
+//   "policy": { "allow": ["read", "query"] }               a read-only reporting deployment
+// Entries name an operation or one of the categories below. Deny wins over allow, and when `allow` is given
+// anything it does not list is denied
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::configuration as config;
+
+// Operation -> category, operations not listed here can still be named individually
+const OPERATION_CATEGORIES: [(&str, &str); 17] = [
+    ("get", "read"),
+    ("exists", "read"),
+    ("getBatch", "read"),
+    ("lookupIn", "read"),
+    ("insert", "write"),
+    ("upsert", "write"),
+    ("replace", "write"),
+    ("mutateIn", "write"),
+    ("touch", "write"),
+    ("counter", "write"),
+    ("remove", "delete"),
+    ("deleteByPrefix", "delete"),
+    ("query", "query"),
+    ("search", "query"),
+    ("eraseSubject", "admin"),
+    ("replayJournal", "admin"),
+    ("exportDocuments", "admin"),
+];
+
+#[derive(Debug, Default, Deserialize)]
+struct OperationPolicy {
+    #[serde(default)]
+    deny: Vec<String>,
+    allow: Option<Vec<String>>,
+}
+
+lazy_static! {
+    static ref POLICY: OperationPolicy = config::get_res::<OperationPolicy>("couchbase.policy").unwrap_or_default();
+}
+
+fn category(operation: &str) -> Option<&'static str> {
+    OPERATION_CATEGORIES
+        .iter()
+        .find(|(name, _)| *name == operation)
+        .map(|(_, category)| *category)
+}
+
+fn matches(entries: &[String], operation: &str) -> bool {
+    let category = category(operation);
+    entries
+        .iter()
+        .any(|entry| entry == operation || Some(entry.as_str()) == category)
+}
+
+pub fn check_policy(operation: &str) -> Result<(), String> {
+    let denied = matches(&POLICY.deny, operation)
+        || POLICY.allow.as_ref().is_some_and(|allow| !matches(allow, operation));
+    if denied {
+        return Err(format!(
+            "POLICY_DENIED: {} is not allowed in this environment",
+            operation
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+// Progress reports for long-running batch jobs (exports, journal replays), emitted every `every` items and once
+// more when the job finishes so CLIs and admin UIs can show how far along a multi-minute run is
+use std::time::Instant;
+
+pub const DEFAULT_PROGRESS_EVERY: u64 = 1000;
+
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub processed: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    // Unknown when items are discovered as the job runs, e.g. exports by prefix
+    pub total: Option<u64>,
+    // Extrapolated from the rate so far, only with a known total
+    pub eta_ms: Option<u64>,
+    pub done: bool,
+}
+
+pub type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+pub struct ProgressReporter {
+    callback: ProgressCallback,
+    every: u64,
+    total: Option<u64>,
+    started: Instant,
+    processed: u64,
+    succeeded: u64,
+    failed: u64,
+}
+
+impl ProgressReporter {
+    pub fn new(callback: ProgressCallback, every: u64, total: Option<u64>) -> Self {
+        ProgressReporter {
+            callback,
+            every: every.max(1),
+            total,
+            started: Instant::now(),
+            processed: 0,
+            succeeded: 0,
+            failed: 0,
+        }
+    }
+
+    fn eta_ms(&self) -> Option<u64> {
+        let total = self.total?;
+        if self.processed == 0 {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        let remaining = total.saturating_sub(self.processed);
+        Some(elapsed.saturating_mul(remaining) / self.processed)
+    }
+
+    fn emit(&self, done: bool) {
+        (self.callback)(&Progress {
+            processed: self.processed,
+            succeeded: self.succeeded,
+            failed: self.failed,
+            total: self.total,
+            eta_ms: if done { Some(0) } else { self.eta_ms() },
+            done,
+        });
+    }
+
+    pub fn record(&mut self, succeeded: bool) {
+        self.processed += 1;
+        if succeeded {
+            self.succeeded += 1;
+        } else {
+            self.failed += 1;
+        }
+        if self.processed.is_multiple_of(self.every) {
+            self.emit(false);
+        }
+    }
+
+    // Final report, sent even when the last item already triggered one so listeners see `done`
+    pub fn finish(&self) {
+        self.emit(true);
+    }
+}
+
+// Records against an optional reporter, for jobs where progress was not asked for
+pub fn record_progress(reporter: &mut Option<ProgressReporter>, succeeded: bool) {
+    if let Some(reporter) = reporter.as_mut() {
+        reporter.record(succeeded);
+    }
+}
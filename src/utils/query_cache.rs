@@ -0,0 +1,109 @@
+// TTL cache of query results for statements whose answer is the same for every caller, e.g. dashboard
+// aggregates. Only used when the call asks for it with a TTL, keyed by the statement and all its arguments.
+// Bounded by `couchbase.queryCache.maxEntries`, expired entries are dropped first, then the oldest ones
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::QuerySettings;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryCacheSettings {
+    #[serde(default = "QueryCacheSettings::default_max_entries")]
+    max_entries: usize,
+}
+
+impl QueryCacheSettings {
+    fn default_max_entries() -> usize {
+        1000
+    }
+}
+
+impl Default for QueryCacheSettings {
+    fn default() -> Self {
+        QueryCacheSettings {
+            max_entries: Self::default_max_entries(),
+        }
+    }
+}
+
+struct CachedRows {
+    rows: Vec<Value>,
+    stored_at: Instant,
+    expires_at: Instant,
+}
+
+lazy_static! {
+    static ref QUERY_CACHE_SETTINGS: QueryCacheSettings =
+        config::get_res::<QueryCacheSettings>("couchbase.queryCache").unwrap_or_default();
+    static ref QUERY_CACHE: Mutex<HashMap<String, CachedRows>> = Mutex::new(HashMap::new());
+}
+
+// Named parameters go through a BTreeMap so their order does not change the key
+pub fn query_cache_key(statement: &str, parameters: &[Value], settings: &QuerySettings) -> String {
+    let named: BTreeMap<&String, &Value> = settings.named_parameters.iter().collect();
+    json!({
+        "statement": statement,
+        "parameters": parameters,
+        "named": named,
+        "queryContext": settings.query_context,
+        "scanConsistency": settings.scan_consistency,
+    })
+    .to_string()
+}
+
+pub fn cached_rows(key: &str) -> Option<Vec<Value>> {
+    let mut cache = QUERY_CACHE.lock().unwrap();
+    match cache.get(key) {
+        Some(cached) if cached.expires_at > Instant::now() => Some(cached.rows.clone()),
+        Some(_) => {
+            cache.remove(key);
+            None
+        }
+        None => None,
+    }
+}
+
+pub fn store_rows(key: String, rows: &[Value], ttl: Duration) {
+    let max_entries = QUERY_CACHE_SETTINGS.max_entries;
+    if max_entries == 0 || ttl.is_zero() {
+        return;
+    }
+    let mut cache = QUERY_CACHE.lock().unwrap();
+    if cache.len() >= max_entries && !cache.contains_key(&key) {
+        let now = Instant::now();
+        cache.retain(|_, cached| cached.expires_at > now);
+        if cache.len() >= max_entries {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.stored_at)
+                .map(|(oldest, _)| oldest.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+    }
+    let now = Instant::now();
+    cache.insert(
+        key,
+        CachedRows {
+            rows: rows.to_vec(),
+            stored_at: now,
+            expires_at: now + ttl,
+        },
+    );
+}
+
+// Returns how many entries were dropped
+pub fn clear_query_cache() -> usize {
+    let mut cache = QUERY_CACHE.lock().unwrap();
+    let cleared = cache.len();
+    cache.clear();
+    cleared
+}
@@ -0,0 +1,62 @@
+// Process-wide switch that makes every mutating call fail fast with READ_ONLY_MODE, for maintenance windows and
+// failovers. Starts from `couchbase.readOnly` and can be flipped at runtime with setReadOnlyMode
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use crate::configuration as config;
+
+lazy_static! {
+    static ref READ_ONLY: AtomicBool = AtomicBool::new(config::get_res::<bool>("couchbase.readOnly").unwrap_or(false));
+}
+
+// Mutations that passed the read-only check and have not returned yet
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub fn set_read_only(enabled: bool) {
+    if READ_ONLY.swap(enabled, Ordering::SeqCst) != enabled {
+        log::warn!("Read-only mode {}", if enabled { "enabled" } else { "disabled" });
+    }
+}
+
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::SeqCst)
+}
+
+// Held for the duration of a mutation so drain_mutations can wait for it
+pub struct WriteGuard(());
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn check_writable(operation: &str, key: &str) -> Result<WriteGuard, String> {
+    // Counted before the flag is read, a mutation is either rejected or visible to drain_mutations
+    IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+    let guard = WriteGuard(());
+    if is_read_only() {
+        return Err(format!("READ_ONLY_MODE: {} of {} rejected while read-only mode is on", operation, key));
+    }
+    Ok(guard)
+}
+
+pub fn in_flight_mutations() -> usize {
+    IN_FLIGHT.load(Ordering::SeqCst)
+}
+
+// Waits for in-flight mutations to finish, false when some are still running after `timeout`
+pub async fn drain_mutations(timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while in_flight_mutations() > 0 {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+    true
+}
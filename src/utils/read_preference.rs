@@ -0,0 +1,176 @@
+// Reads served from XDCR replica clusters, listed under `couchbase.replicaClusters`, e.g.
+//   "replicaClusters": { "eu-west": {}, "us-east": { "password": { "env": ["CB_US_EAST_PASSWORD"] } } }
+// Each entry resolves its connectionUrl/username/password like `couchbase.sources`, defaulting to the
+// COUCHBASE_<NAME>_CONNECTION_URL, COUCHBASE_<NAME>_USERNAME and COUCHBASE_<NAME>_PASSWORD env vars.
+// Only reads take a preference, every write goes to the primary cluster. Replicas trail the primary by the
+// XDCR lag, so documents served from one are marked stale
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{CasDocument, CouchbaseConnParams, CouchbaseConnection, Keyspace, OperationOptions};
+
+pub const PRIMARY_CLUSTER: &str = "primary";
+
+// Weight of the newest sample in the per-cluster latency average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadPreference {
+    Primary,
+    // A replica cluster, the primary only when none of them can serve the read
+    Secondary,
+    // Whichever cluster has answered fastest lately, the primary included
+    Nearest,
+}
+
+impl ReadPreference {
+    pub fn parse(preference: &str) -> Result<Self, String> {
+        match preference {
+            "primary" => Ok(ReadPreference::Primary),
+            "secondary" => Ok(ReadPreference::Secondary),
+            "nearest" => Ok(ReadPreference::Nearest),
+            other => Err(format!(
+                "Unknown read preference {}, expected primary, secondary or nearest",
+                other
+            )),
+        }
+    }
+}
+
+// Which cluster answered a read
+#[derive(Clone, Debug)]
+pub struct ReadSource {
+    pub served_by: String,
+    pub stale: bool,
+}
+
+struct ReplicaCluster {
+    name: String,
+    connection: Arc<CouchbaseConnection>,
+}
+
+lazy_static! {
+    static ref REPLICA_CLUSTERS: Vec<ReplicaCluster> = connect_replica_clusters();
+    // Cluster name -> smoothed read latency in ms
+    static ref READ_LATENCIES: Mutex<HashMap<String, f64>> = Mutex::new(HashMap::new());
+}
+
+fn replica_params(name: &str) -> Result<CouchbaseConnParams, String> {
+    let env_prefix = format!("COUCHBASE_{}", name.to_uppercase().replace('-', "_"));
+    let setting = |setting: &str, env_suffix: &str| {
+        config::resolve_setting(
+            &format!("couchbase.replicaClusters.{}.{}", name, setting),
+            &format!("{}_{}", env_prefix, env_suffix),
+        )
+    };
+    Ok(CouchbaseConnParams {
+        connection_url: setting("connectionUrl", "CONNECTION_URL")?,
+        username: setting("username", "USERNAME")?,
+        password: setting("password", "PASSWORD")?,
+    })
+}
+
+// A replica whose settings are missing is left out rather than failing the primary connection
+fn connect_replica_clusters() -> Vec<ReplicaCluster> {
+    let configured = config::get_res::<HashMap<String, Value>>("couchbase.replicaClusters").unwrap_or_default();
+    let mut names: Vec<String> = configured.into_keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter_map(|name| match replica_params(&name) {
+            Ok(params) => {
+                log::info!("Connecting to replica cluster {}", name);
+                Some(ReplicaCluster {
+                    connection: Arc::new(CouchbaseConnection::connect(params)),
+                    name,
+                })
+            }
+            Err(error) => {
+                log::error!("Replica cluster {} is not usable : {}", name, error);
+                None
+            }
+        })
+        .collect()
+}
+
+fn record_latency(cluster: &str, elapsed: Duration) {
+    let sample = elapsed.as_secs_f64() * 1000.0;
+    let mut latencies = READ_LATENCIES.lock().unwrap();
+    let average = latencies.entry(cluster.to_string()).or_insert(sample);
+    *average = *average * (1.0 - LATENCY_SMOOTHING) + sample * LATENCY_SMOOTHING;
+}
+
+// Clusters that have not been read from yet sort first so each gets measured
+fn by_latency(mut clusters: Vec<(String, Arc<CouchbaseConnection>)>) -> Vec<(String, Arc<CouchbaseConnection>)> {
+    let latencies = READ_LATENCIES.lock().unwrap();
+    clusters.sort_by(|(a, _), (b, _)| {
+        let a = latencies.get(a).copied().unwrap_or(0.0);
+        let b = latencies.get(b).copied().unwrap_or(0.0);
+        a.total_cmp(&b)
+    });
+    clusters
+}
+
+fn candidates(
+    preference: ReadPreference,
+    primary: Arc<CouchbaseConnection>,
+) -> Vec<(String, Arc<CouchbaseConnection>)> {
+    let replicas: Vec<(String, Arc<CouchbaseConnection>)> = REPLICA_CLUSTERS
+        .iter()
+        .map(|replica| (replica.name.clone(), Arc::clone(&replica.connection)))
+        .collect();
+    match preference {
+        ReadPreference::Primary => vec![(PRIMARY_CLUSTER.to_string(), primary)],
+        ReadPreference::Secondary => {
+            let mut clusters = by_latency(replicas);
+            clusters.push((PRIMARY_CLUSTER.to_string(), primary));
+            clusters
+        }
+        ReadPreference::Nearest => {
+            let mut clusters = replicas;
+            clusters.push((PRIMARY_CLUSTER.to_string(), primary));
+            by_latency(clusters)
+        }
+    }
+}
+
+// Tries the clusters in preference order. A replica that fails, or has not received the document yet, hands
+// the read to the next one, the error of the last cluster tried is returned
+pub async fn read_document(
+    primary: Arc<CouchbaseConnection>,
+    preference: ReadPreference,
+    key: String,
+    keyspace: Keyspace,
+    options: OperationOptions,
+) -> Result<(CasDocument, ReadSource), String> {
+    let mut last_error = String::new();
+    for (name, connection) in candidates(preference, primary) {
+        let started = Instant::now();
+        match connection.get_document(key.clone(), keyspace.clone(), options.clone()).await {
+            Ok(document) => {
+                record_latency(&name, started.elapsed());
+                let stale = name != PRIMARY_CLUSTER;
+                return Ok((document, ReadSource { served_by: name, stale }));
+            }
+            Err(error) => {
+                if name != PRIMARY_CLUSTER {
+                    log::warn!(
+                        "{}Replica cluster {} could not serve {} in {} : {}",
+                        options.log_prefix(),
+                        name,
+                        key,
+                        keyspace,
+                        error
+                    );
+                }
+                last_error = error;
+            }
+        }
+    }
+    Err(last_error)
+}
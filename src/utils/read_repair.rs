@@ -0,0 +1,80 @@
+// Read-repair for fallback reads: documents only found in the fallback keyspace (e.g. an archive bucket) are
+// written back to the primary one in the background, so diverged datasets heal as they are read. Repairs are
+// inserts, a document written to the primary in the meantime wins and is counted as already present. The copy
+// gets no expiry, whatever the fallback document had
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+use crate::utils::couchbase_db::{batch_concurrency, is_write_conflict, CouchbaseConnection, Keyspace, OperationOptions};
+
+lazy_static! {
+    static ref REPAIRED: AtomicU64 = AtomicU64::new(0);
+    static ref ALREADY_PRESENT: AtomicU64 = AtomicU64::new(0);
+    static ref REPAIR_FAILED: AtomicU64 = AtomicU64::new(0);
+}
+
+// Counts since the process started
+#[derive(Debug, Default)]
+pub struct ReadRepairStats {
+    pub repaired: u64,
+    pub already_present: u64,
+    pub failed: u64,
+}
+
+pub fn read_repair_stats() -> ReadRepairStats {
+    ReadRepairStats {
+        repaired: REPAIRED.load(Ordering::Relaxed),
+        already_present: ALREADY_PRESENT.load(Ordering::Relaxed),
+        failed: REPAIR_FAILED.load(Ordering::Relaxed),
+    }
+}
+
+// Returns right away, `documents` are written to `keyspace` by a background task
+pub fn repair_in_background(
+    connection: Arc<CouchbaseConnection>,
+    documents: Vec<(String, Value)>,
+    keyspace: Keyspace,
+    options: &OperationOptions,
+) {
+    if documents.is_empty() {
+        return;
+    }
+    // The read's deadline does not apply to the repair, its transcoder and context do
+    let options = OperationOptions {
+        transcoder: options.transcoder.clone(),
+        context: options.context.clone(),
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        let count = documents.len();
+        let mut repairs = futures::stream::iter(documents.into_iter().map(|(key, value)| {
+            let connection = &connection;
+            let keyspace = keyspace.clone();
+            let options = options.clone();
+            async move {
+                let repaired = connection.add_document(key.clone(), value, keyspace, options, Some(0)).await;
+                (key, repaired)
+            }
+        }))
+        .buffer_unordered(batch_concurrency());
+        while let Some((key, repaired)) = repairs.next().await {
+            match repaired {
+                Ok(_) => {
+                    REPAIRED.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(error) if is_write_conflict(&error) => {
+                    ALREADY_PRESENT.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(error) => {
+                    REPAIR_FAILED.fetch_add(1, Ordering::Relaxed);
+                    log::warn!("{}Read-repair of {} into {} failed : {}", options.log_prefix(), key, keyspace, error);
+                }
+            }
+        }
+        log::info!("{}Read-repair of {} documents into {} done", options.log_prefix(), count, keyspace);
+    });
+}
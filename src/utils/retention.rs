@@ -0,0 +1,238 @@
+// Retention rules per key prefix, configured under `couchbase.retention`, e.g.
+//   "retention": { "intervalSecs": 3600, "rules": [
+//     { "prefix": "audit::", "bucket": "logs", "maxAgeSecs": 7776000, "timestampField": "createdAt" },
+//     { "prefix": "tmp::", "bucket": "app", "ttlSecs": 86400 } ] }
+// `maxAgeSecs` removes documents whose timestamp field (epoch ms) is older than the limit, `ttlSecs` gives
+// documents that never expire that TTL. Matching runs on the query service and needs an index on the keyspace
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{
+    batch_concurrency, is_document_not_found, like_prefix, CouchbaseConnection, Keyspace, OperationOptions,
+    QuerySettings,
+};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionRule {
+    pub prefix: String,
+    pub bucket: String,
+    pub scope: Option<String>,
+    pub collection: Option<String>,
+    pub max_age_secs: Option<u64>,
+    // Dotted path of the epoch millisecond timestamp `maxAgeSecs` is measured against
+    #[serde(default = "RetentionRule::default_timestamp_field")]
+    pub timestamp_field: String,
+    pub ttl_secs: Option<u64>,
+}
+
+impl RetentionRule {
+    fn default_timestamp_field() -> String {
+        "createdAt".to_string()
+    }
+
+    fn keyspace(&self) -> Keyspace {
+        Keyspace {
+            bucket: self.bucket.clone(),
+            scope: self.scope.clone(),
+            collection: self.collection.clone(),
+        }
+    }
+
+    fn timestamp_path(&self) -> String {
+        self.timestamp_field
+            .split('.')
+            .map(|segment| format!("`{}`", segment.replace('`', "``")))
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RetentionSettings {
+    #[serde(default)]
+    rules: Vec<RetentionRule>,
+    #[serde(default = "RetentionSettings::default_interval_secs")]
+    interval_secs: u64,
+    // Upper bound on documents handled per rule and run, the rest are picked up by the next run
+    #[serde(default = "RetentionSettings::default_batch_size")]
+    batch_size: u32,
+}
+
+impl RetentionSettings {
+    fn default_interval_secs() -> u64 {
+        3600
+    }
+
+    fn default_batch_size() -> u32 {
+        1000
+    }
+
+    fn load() -> Self {
+        config::get_res::<RetentionSettings>("couchbase.retention").unwrap_or(RetentionSettings {
+            rules: Vec::new(),
+            interval_secs: Self::default_interval_secs(),
+            batch_size: Self::default_batch_size(),
+        })
+    }
+}
+
+lazy_static! {
+    static ref RETENTION: RetentionSettings = RetentionSettings::load();
+    static ref RETENTION_TASK: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSummary {
+    pub prefix: String,
+    pub bucket: String,
+    pub matched: usize,
+    pub removed: usize,
+    pub touched: usize,
+    pub failed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+async fn matching_keys(
+    connection: &CouchbaseConnection,
+    rule: &RetentionRule,
+    condition: String,
+    mut parameters: Vec<Value>,
+) -> Result<Vec<String>, String> {
+    let statement = format!(
+        "SELECT RAW META(d).id FROM {} AS d WHERE META(d).id LIKE $1 AND {} LIMIT {}",
+        rule.keyspace().query_path(),
+        condition,
+        RETENTION.batch_size
+    );
+    parameters.insert(0, json!(like_prefix(&rule.prefix)));
+    let rows = connection
+        .query(statement, parameters, Vec::new(), QuerySettings::default(), OperationOptions::default())
+        .await?;
+    Ok(rows.into_iter().filter_map(|row| row.as_str().map(str::to_string)).collect())
+}
+
+async fn apply_rule(connection: &CouchbaseConnection, rule: &RetentionRule, dry_run: bool) -> RuleSummary {
+    let mut summary = RuleSummary {
+        prefix: rule.prefix.clone(),
+        bucket: rule.bucket.clone(),
+        ..Default::default()
+    };
+    let keyspace = rule.keyspace();
+
+    if let Some(max_age_secs) = rule.max_age_secs {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as u64)
+            .unwrap_or(0);
+        let cutoff_ms = now_ms.saturating_sub(max_age_secs.saturating_mul(1000));
+        let condition = format!("d.{} < $2", rule.timestamp_path());
+        match matching_keys(connection, rule, condition, vec![json!(cutoff_ms)]).await {
+            Ok(keys) => {
+                summary.matched += keys.len();
+                if !dry_run {
+                    let mut removals = futures::stream::iter(keys.into_iter().map(|key| {
+                        let keyspace = keyspace.clone();
+                        async move { connection.delete_data(key, None, keyspace, OperationOptions::default()).await }
+                    }))
+                    .buffer_unordered(batch_concurrency());
+                    while let Some(removed) = removals.next().await {
+                        match removed {
+                            Ok(_) => summary.removed += 1,
+                            // Expired or removed by someone else in the meantime
+                            Err(error) if is_document_not_found(&error) => {}
+                            Err(_) => summary.failed += 1,
+                        }
+                    }
+                }
+            }
+            Err(error) => summary.error = Some(error),
+        }
+    }
+
+    if let Some(ttl_secs) = rule.ttl_secs {
+        let condition = "META(d).expiration = 0".to_string();
+        match matching_keys(connection, rule, condition, Vec::new()).await {
+            Ok(keys) => {
+                summary.matched += keys.len();
+                if !dry_run {
+                    let expiry = Duration::from_secs(ttl_secs);
+                    let mut touches = futures::stream::iter(keys.into_iter().map(|key| {
+                        let keyspace = keyspace.clone();
+                        async move { connection.touch(key, expiry, keyspace, OperationOptions::default()).await }
+                    }))
+                    .buffer_unordered(batch_concurrency());
+                    while let Some(touched) = touches.next().await {
+                        match touched {
+                            Ok(_) => summary.touched += 1,
+                            Err(error) if is_document_not_found(&error) => {}
+                            Err(_) => summary.failed += 1,
+                        }
+                    }
+                }
+            }
+            Err(error) => summary.error = Some(error),
+        }
+    }
+    summary
+}
+
+// One audit over every configured rule. `dry_run` only counts the documents that would be changed
+pub async fn run_retention(connection: &CouchbaseConnection, dry_run: bool) -> Value {
+    let mut rules = Vec::new();
+    for rule in &RETENTION.rules {
+        let summary = apply_rule(connection, rule, dry_run).await;
+        match &summary.error {
+            Some(error) => log::error!("Retention rule {} on {} failed : {}", rule.prefix, rule.bucket, error),
+            None => log::info!(
+                "Retention rule {} on {}: {} matched, {} removed, {} touched, {} failed",
+                rule.prefix,
+                rule.bucket,
+                summary.matched,
+                summary.removed,
+                summary.touched,
+                summary.failed
+            ),
+        }
+        rules.push(summary);
+    }
+    json!({ "dryRun": dry_run, "rules": rules })
+}
+
+// Runs the audit every `interval` (config `intervalSecs` by default) until stopped, replacing a running task
+pub fn start_retention(connection: Arc<CouchbaseConnection>, interval: Option<Duration>) {
+    let interval = interval.unwrap_or(Duration::from_secs(RETENTION.interval_secs.max(1)));
+    let task = tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            run_retention(&connection, false).await;
+        }
+    });
+    if let Some(previous) = RETENTION_TASK.lock().unwrap().replace(task) {
+        previous.abort();
+    }
+    log::info!("Retention audits started, every {:?} for {} rules", interval, RETENTION.rules.len());
+}
+
+// False when no audit task was running
+pub fn stop_retention() -> bool {
+    match RETENTION_TASK.lock().unwrap().take() {
+        Some(task) => {
+            task.abort();
+            log::info!("Retention audits stopped");
+            true
+        }
+        None => false,
+    }
+}
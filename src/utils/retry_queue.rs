@@ -0,0 +1,374 @@
+// Durable queue for writes that exhausted their retries, for ingestion paths where losing a write is worse than
+// applying it late. Only writes made with `queueOnFailure` that failed transiently (timeouts, busy or unreachable
+// nodes) are queued; conflicts and rejected documents still fail the call. The queue is a JSON file, so it outlives
+// both the process and a cluster outage, configured under `couchbase.retryQueue`, e.g.
+//   "retryQueue": { "path": "./retry-queue/writes.json", "pollSecs": 5, "maxAttempts": 20 }
+// The worker retries each entry with exponential backoff, an entry out of attempts stays in the queue as failed
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use crate::configuration as config;
+use crate::utils::backoff::is_server_busy;
+use crate::utils::couchbase_db::{
+    is_ambiguous, is_document_not_found, is_write_conflict, uuid, CouchbaseConnection, Keyspace, MutationOutcome,
+    OperationOptions,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RetryQueueSettings {
+    #[serde(default = "RetryQueueSettings::default_path")]
+    path: String,
+    #[serde(default = "RetryQueueSettings::default_poll_secs")]
+    poll_secs: u64,
+    // First delay after a failed retry, doubled on every further attempt
+    #[serde(default = "RetryQueueSettings::default_base_delay_ms")]
+    base_delay_ms: u64,
+    #[serde(default = "RetryQueueSettings::default_max_delay_ms")]
+    max_delay_ms: u64,
+    #[serde(default = "RetryQueueSettings::default_max_attempts")]
+    max_attempts: u32,
+}
+
+impl RetryQueueSettings {
+    fn default_path() -> String {
+        "./retry-queue/writes.json".to_string()
+    }
+
+    fn default_poll_secs() -> u64 {
+        5
+    }
+
+    fn default_base_delay_ms() -> u64 {
+        1000
+    }
+
+    fn default_max_delay_ms() -> u64 {
+        300000
+    }
+
+    fn default_max_attempts() -> u32 {
+        20
+    }
+}
+
+impl Default for RetryQueueSettings {
+    fn default() -> Self {
+        RetryQueueSettings {
+            path: Self::default_path(),
+            poll_secs: Self::default_poll_secs(),
+            base_delay_ms: Self::default_base_delay_ms(),
+            max_delay_ms: Self::default_max_delay_ms(),
+            max_attempts: Self::default_max_attempts(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum QueuedOperation {
+    Insert { value: Value },
+    Upsert { value: Value },
+    // The CAS still guards the retry, a document changed in the meantime fails the entry
+    Replace { value: Value, cas: Option<u64> },
+    Remove { cas: Option<u64> },
+}
+
+impl QueuedOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QueuedOperation::Insert { .. } => "insert",
+            QueuedOperation::Upsert { .. } => "upsert",
+            QueuedOperation::Replace { .. } => "replace",
+            QueuedOperation::Remove { .. } => "remove",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedWrite {
+    pub id: String,
+    pub key: String,
+    pub bucket: String,
+    pub scope: Option<String>,
+    pub collection: Option<String>,
+    #[serde(flatten)]
+    pub operation: QueuedOperation,
+    pub expiry_ms: Option<u64>,
+    pub transcoder: Option<String>,
+    pub attempts: u32,
+    // Epoch milliseconds
+    pub enqueued_at: i64,
+    pub next_attempt_at: i64,
+    pub last_error: String,
+    // Out of attempts or failed permanently, kept until discarded
+    pub failed: bool,
+}
+
+impl QueuedWrite {
+    fn keyspace(&self) -> Keyspace {
+        Keyspace {
+            bucket: self.bucket.clone(),
+            scope: self.scope.clone(),
+            collection: self.collection.clone(),
+        }
+    }
+
+    // Without queue_on_failure, so a failing retry is not queued a second time
+    async fn apply(&self, connection: &CouchbaseConnection) -> Result<MutationOutcome, String> {
+        let keyspace = self.keyspace();
+        let options = OperationOptions {
+            expiry: self.expiry_ms.map(Duration::from_millis),
+            transcoder: self.transcoder.clone(),
+            ..Default::default()
+        };
+        match &self.operation {
+            QueuedOperation::Insert { value } => {
+                connection.add_document(self.key.clone(), value.clone(), keyspace, options, Some(0)).await
+            }
+            QueuedOperation::Upsert { value } => {
+                connection.upsert_document(self.key.clone(), value.clone(), keyspace, options, Some(0)).await
+            }
+            QueuedOperation::Replace { value, cas } => {
+                connection
+                    .replace_document(self.key.clone(), value.clone(), *cas, keyspace, options, Some(0))
+                    .await
+            }
+            QueuedOperation::Remove { cas } => connection.delete_data(self.key.clone(), *cas, keyspace, options).await,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RetryQueueSummary {
+    pub applied: u64,
+    // Failed again and scheduled for another attempt
+    pub retrying: u64,
+    pub failed: u64,
+}
+
+lazy_static! {
+    static ref RETRY_QUEUE_SETTINGS: RetryQueueSettings =
+        config::get_res::<RetryQueueSettings>("couchbase.retryQueue").unwrap_or_default();
+    static ref RETRY_QUEUE: Mutex<Vec<QueuedWrite>> = Mutex::new(load_queue());
+    static ref RETRY_WORKER: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn load_queue() -> Vec<QueuedWrite> {
+    let path = &RETRY_QUEUE_SETTINGS.path;
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(error) => {
+            log::error!("Unable to read retry queue {} : {}", path, error);
+            return Vec::new();
+        }
+    };
+    match serde_json::from_str::<Vec<QueuedWrite>>(&contents) {
+        Ok(queue) => {
+            if !queue.is_empty() {
+                log::info!("Loaded {} queued writes from {}", queue.len(), path);
+            }
+            queue
+        }
+        Err(error) => {
+            log::error!("Retry queue {} is not valid, starting empty : {}", path, error);
+            Vec::new()
+        }
+    }
+}
+
+// Written to a temporary file first so a crash mid-write leaves the previous queue intact
+fn save_queue(queue: &[QueuedWrite]) -> Result<(), String> {
+    let path = Path::new(&RETRY_QUEUE_SETTINGS.path);
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let contents =
+        serde_json::to_string(queue).map_err(|error| format!("Unable to serialize the retry queue : {}", error))?;
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, contents)
+        .and_then(|_| std::fs::rename(&temporary, path))
+        .map_err(|error| format!("Unable to write retry queue {} : {}", path.display(), error))
+}
+
+// Worth trying again later: timeouts and busy or unreachable nodes. Conflicts, missing documents and
+// rejected writes fail the same way however often they are retried
+fn is_transient(error: &str) -> bool {
+    if is_write_conflict(error) || is_document_not_found(error) {
+        return false;
+    }
+    let normalized = error.to_lowercase().replace([' ', '_', '-'], "");
+    is_server_busy(error)
+        || is_ambiguous(error)
+        || ["timeout", "timedout", "deadlineexceeded", "unavailable", "connection", "network", "unreachable"]
+            .iter()
+            .any(|marker| normalized.contains(marker))
+}
+
+fn backoff(attempts: u32) -> Duration {
+    let settings = &*RETRY_QUEUE_SETTINGS;
+    let delay = settings.base_delay_ms.saturating_mul(1u64 << attempts.min(32));
+    Duration::from_millis(delay.min(settings.max_delay_ms))
+}
+
+// Called by the write APIs once their own retries are exhausted. Queues `operation` when the caller opted in and
+// the failure is transient, returns the error to report either way
+pub fn queue_failed_write(
+    operation: Option<QueuedOperation>,
+    key: &str,
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+    error: String,
+) -> String {
+    let operation = match operation {
+        Some(operation) if options.queue_on_failure && is_transient(&error) => operation,
+        _ => return error,
+    };
+    let now = now_ms();
+    let entry = QueuedWrite {
+        id: uuid().to_string(),
+        key: key.to_string(),
+        bucket: keyspace.bucket.clone(),
+        scope: keyspace.scope.clone(),
+        collection: keyspace.collection.clone(),
+        operation,
+        expiry_ms: options.expiry.map(|expiry| expiry.as_millis() as u64),
+        transcoder: options.transcoder.clone(),
+        attempts: 0,
+        enqueued_at: now,
+        next_attempt_at: now + backoff(0).as_millis() as i64,
+        last_error: error.clone(),
+        failed: false,
+    };
+    let id = entry.id.clone();
+    let mut queue = RETRY_QUEUE.lock().unwrap();
+    queue.push(entry);
+    match save_queue(&queue) {
+        Ok(()) => {
+            log::warn!("{}Queued failed write of {} in {} as {}", options.log_prefix(), key, keyspace, id);
+            format!("QUEUED_FOR_RETRY: write of {} was queued as {} : {}", key, id, error)
+        }
+        Err(save_error) => {
+            queue.pop();
+            log::error!("{}Unable to queue failed write of {} : {}", options.log_prefix(), key, save_error);
+            error
+        }
+    }
+}
+
+pub fn queued_writes() -> Vec<QueuedWrite> {
+    RETRY_QUEUE.lock().unwrap().clone()
+}
+
+// False when no entry has that id
+pub fn discard_queued_write(id: &str) -> Result<bool, String> {
+    let mut queue = RETRY_QUEUE.lock().unwrap();
+    let before = queue.len();
+    queue.retain(|entry| entry.id != id);
+    if queue.len() == before {
+        return Ok(false);
+    }
+    save_queue(&queue)?;
+    log::info!("Discarded queued write {}", id);
+    Ok(true)
+}
+
+// Retries the pending entries that are due, or all of them with `due_only` false
+pub async fn retry_queued_writes(
+    connection: &CouchbaseConnection,
+    due_only: bool,
+) -> Result<RetryQueueSummary, String> {
+    let now = now_ms();
+    let pending: Vec<QueuedWrite> = RETRY_QUEUE
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|entry| !entry.failed && (!due_only || entry.next_attempt_at <= now))
+        .cloned()
+        .collect();
+    let mut summary = RetryQueueSummary::default();
+    for entry in pending {
+        let result = entry.apply(connection).await;
+        let mut queue = RETRY_QUEUE.lock().unwrap();
+        let position = match queue.iter().position(|queued| queued.id == entry.id) {
+            Some(position) => position,
+            // Discarded while it was being retried
+            None => continue,
+        };
+        match result {
+            Ok(_) => {
+                log::info!(
+                    "Applied queued {} of {} after {} attempts",
+                    entry.operation.name(),
+                    entry.key,
+                    entry.attempts + 1
+                );
+                queue.remove(position);
+                summary.applied += 1;
+            }
+            Err(error) => {
+                let queued = &mut queue[position];
+                queued.attempts += 1;
+                queued.last_error = error.clone();
+                if !is_transient(&error) || queued.attempts >= RETRY_QUEUE_SETTINGS.max_attempts {
+                    log::error!(
+                        "Queued {} of {} failed after {} attempts : {}",
+                        queued.operation.name(),
+                        queued.key,
+                        queued.attempts,
+                        error
+                    );
+                    queued.failed = true;
+                    summary.failed += 1;
+                } else {
+                    queued.next_attempt_at = now_ms() + backoff(queued.attempts).as_millis() as i64;
+                    summary.retrying += 1;
+                }
+            }
+        }
+        save_queue(&queue)?;
+    }
+    Ok(summary)
+}
+
+// Polls every `interval` (config `pollSecs` by default) until stopped, replacing a running worker
+pub fn start_retry_worker(connection: Arc<CouchbaseConnection>, interval: Option<Duration>) {
+    let interval = interval.unwrap_or(Duration::from_secs(RETRY_QUEUE_SETTINGS.poll_secs.max(1)));
+    let task = tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            if let Err(error) = retry_queued_writes(&connection, true).await {
+                log::error!("Retry queue poll failed : {}", error);
+            }
+        }
+    });
+    if let Some(previous) = RETRY_WORKER.lock().unwrap().replace(task) {
+        previous.abort();
+    }
+    log::info!("Retry queue worker started, polling every {:?}", interval);
+}
+
+// False when no worker was running
+pub fn stop_retry_worker() -> bool {
+    match RETRY_WORKER.lock().unwrap().take() {
+        Some(task) => {
+            task.abort();
+            log::info!("Retry queue worker stopped");
+            true
+        }
+        None => false,
+    }
+}
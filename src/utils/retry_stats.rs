@@ -0,0 +1,145 @@
+// Retries of KV writes over a rolling window, per bucket and operation, for tuning `couchbase.backoff` and retry
+// counts from data. Configured under `couchbase.retryStats`, e.g.
+//   "retryStats": { "windowSecs": 300 }
+// Every retry is recorded with the class of the error that caused it, and so is every write that gave up: either
+// its retries ran out or the next retry would not have finished before the caller's deadline
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::configuration as config;
+use crate::utils::backoff::is_server_busy;
+
+// Oldest events are dropped beyond this, so a retry storm cannot grow the window without bound
+const MAX_RETRY_EVENTS: usize = 100_000;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RetryStatsSettings {
+    #[serde(default = "RetryStatsSettings::default_window_secs")]
+    window_secs: u64,
+}
+
+impl RetryStatsSettings {
+    fn default_window_secs() -> u64 {
+        300
+    }
+}
+
+impl Default for RetryStatsSettings {
+    fn default() -> Self {
+        RetryStatsSettings {
+            window_secs: Self::default_window_secs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetryOutcome {
+    Retried,
+    // No retries left
+    RetriesExhausted,
+    // Retries left but not enough time before the deadline
+    DeadlineExhausted,
+}
+
+struct RetryEvent {
+    at: Instant,
+    bucket: String,
+    operation: &'static str,
+    cause: &'static str,
+    outcome: RetryOutcome,
+}
+
+#[derive(Debug, Default)]
+pub struct RetryStat {
+    pub bucket: String,
+    pub operation: String,
+    pub retries: u64,
+    pub retries_exhausted: u64,
+    pub deadline_exhausted: u64,
+    // Error class -> retries and give-ups it caused
+    pub causes: BTreeMap<String, u64>,
+}
+
+lazy_static! {
+    static ref RETRY_STATS: RetryStatsSettings =
+        config::get_res::<RetryStatsSettings>("couchbase.retryStats").unwrap_or_default();
+    static ref RETRY_EVENTS: Mutex<VecDeque<RetryEvent>> = Mutex::new(VecDeque::new());
+}
+
+pub fn retry_window() -> Duration {
+    Duration::from_secs(RETRY_STATS.window_secs.max(1))
+}
+
+// "busy", "timeout", "ambiguous", "conflict", "network" or "other"
+pub fn retry_cause(error: &str) -> &'static str {
+    let error = error.to_lowercase().replace([' ', '_', '-'], "");
+    if is_server_busy(&error) {
+        "busy"
+    } else if error.contains("timeout") || error.contains("timedout") {
+        "timeout"
+    } else if error.contains("ambiguous") {
+        "ambiguous"
+    } else if error.contains("casmismatch") || error.contains("documentexists") || error.contains("keyexists") {
+        "conflict"
+    } else if error.contains("connection") || error.contains("network") || error.contains("socket") {
+        "network"
+    } else {
+        "other"
+    }
+}
+
+fn prune(events: &mut VecDeque<RetryEvent>, now: Instant) {
+    let window = retry_window();
+    while events.front().is_some_and(|event| now.duration_since(event.at) > window) {
+        events.pop_front();
+    }
+}
+
+pub fn record_retry(bucket: &str, operation: &'static str, error: &str, outcome: RetryOutcome) {
+    let now = Instant::now();
+    let mut events = RETRY_EVENTS.lock().unwrap();
+    prune(&mut events, now);
+    if events.len() >= MAX_RETRY_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(RetryEvent {
+        at: now,
+        bucket: bucket.to_string(),
+        operation,
+        cause: retry_cause(error),
+        outcome,
+    });
+}
+
+// One entry per bucket and operation with retries in the window, busiest first
+pub fn retry_stats() -> Vec<RetryStat> {
+    let mut events = RETRY_EVENTS.lock().unwrap();
+    prune(&mut events, Instant::now());
+    let mut stats: HashMap<(String, &'static str), RetryStat> = HashMap::new();
+    for event in events.iter() {
+        let stat = stats
+            .entry((event.bucket.clone(), event.operation))
+            .or_insert_with(|| RetryStat {
+                bucket: event.bucket.clone(),
+                operation: event.operation.to_string(),
+                ..Default::default()
+            });
+        match event.outcome {
+            RetryOutcome::Retried => stat.retries += 1,
+            RetryOutcome::RetriesExhausted => stat.retries_exhausted += 1,
+            RetryOutcome::DeadlineExhausted => stat.deadline_exhausted += 1,
+        }
+        *stat.causes.entry(event.cause.to_string()).or_insert(0) += 1;
+    }
+    let mut stats: Vec<RetryStat> = stats.into_values().collect();
+    stats.sort_by(|a, b| {
+        let total = |stat: &RetryStat| stat.retries + stat.retries_exhausted + stat.deadline_exhausted;
+        total(b).cmp(&total(a)).then_with(|| (&a.bucket, &a.operation).cmp(&(&b.bucket, &b.operation)))
+    });
+    stats
+}
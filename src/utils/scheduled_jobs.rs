@@ -0,0 +1,316 @@
+// Mutations applied at a later time, e.g. publishing a document at 9am, kept as job documents in the keyspace
+// configured under `couchbase.scheduledJobs`, e.g.
+//   "scheduledJobs": { "bucket": "jobs", "pollSecs": 5, "leaseSecs": 30 }
+// Any process can schedule a job, due jobs are only applied by the executor holding the leader lease: an expiring
+// document that the other executors fail to insert until its holder stops renewing it. Finding due jobs runs on the
+// query service and needs an index on (type, status, dueAt) of the jobs keyspace. Jobs stay in the keyspace as
+// done or failed records, a job whose executor died while applying it stays running for an operator to look at
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{
+    is_document_not_found, is_write_conflict, uuid, CouchbaseConnection, Keyspace, MutationOutcome,
+    OperationOptions, QuerySettings,
+};
+use crate::utils::merge::MergeStrategy;
+
+const JOB_TYPE: &str = "scheduledMutation";
+const JOB_KEY_PREFIX: &str = "job::";
+const LEASE_KEY: &str = "job::leader";
+// Due jobs picked up per poll
+const JOBS_PER_POLL: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScheduledJobSettings {
+    bucket: Option<String>,
+    scope: Option<String>,
+    collection: Option<String>,
+    #[serde(default = "ScheduledJobSettings::default_poll_secs")]
+    poll_secs: u64,
+    // Should be a few polls long, so one slow poll does not hand the lease to another process
+    #[serde(default = "ScheduledJobSettings::default_lease_secs")]
+    lease_secs: u64,
+}
+
+impl ScheduledJobSettings {
+    fn default_poll_secs() -> u64 {
+        5
+    }
+
+    fn default_lease_secs() -> u64 {
+        30
+    }
+
+    fn keyspace(&self) -> Result<Keyspace, String> {
+        let bucket = self
+            .bucket
+            .clone()
+            .ok_or_else(|| "couchbase.scheduledJobs.bucket is not configured".to_string())?;
+        Ok(Keyspace {
+            bucket,
+            scope: self.scope.clone(),
+            collection: self.collection.clone(),
+        })
+    }
+}
+
+impl Default for ScheduledJobSettings {
+    fn default() -> Self {
+        ScheduledJobSettings {
+            bucket: None,
+            scope: None,
+            collection: None,
+            poll_secs: Self::default_poll_secs(),
+            lease_secs: Self::default_lease_secs(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ScheduledOperation {
+    Insert { value: Value },
+    Replace { value: Value },
+    // Last-write-wins merge of the partial document, creating it when missing
+    Merge { partial: Value },
+    Remove,
+}
+
+// The mutation a job applies, stored in the job document
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMutation {
+    pub key: String,
+    pub bucket: String,
+    pub scope: Option<String>,
+    pub collection: Option<String>,
+    #[serde(flatten)]
+    pub operation: ScheduledOperation,
+}
+
+impl ScheduledMutation {
+    fn keyspace(&self) -> Keyspace {
+        Keyspace {
+            bucket: self.bucket.clone(),
+            scope: self.scope.clone(),
+            collection: self.collection.clone(),
+        }
+    }
+
+    async fn apply(&self, connection: &CouchbaseConnection, options: OperationOptions) -> Result<MutationOutcome, String> {
+        let keyspace = self.keyspace();
+        match &self.operation {
+            ScheduledOperation::Insert { value } => {
+                connection.add_document(self.key.clone(), value.clone(), keyspace, options, None).await
+            }
+            ScheduledOperation::Replace { value } => {
+                connection
+                    .replace_document(self.key.clone(), value.clone(), None, keyspace, options, None)
+                    .await
+            }
+            ScheduledOperation::Merge { partial } => {
+                connection
+                    .merge_document(self.key.clone(), partial.clone(), MergeStrategy::LastWriteWins, keyspace, options)
+                    .await
+            }
+            ScheduledOperation::Remove => connection.delete_data(self.key.clone(), None, keyspace, options).await,
+        }
+    }
+}
+
+lazy_static! {
+    static ref SCHEDULED_JOBS: ScheduledJobSettings =
+        config::get_res::<ScheduledJobSettings>("couchbase.scheduledJobs").unwrap_or_default();
+    // Holder id written into the lease document
+    static ref EXECUTOR_ID: String = uuid().to_string();
+    static ref EXECUTOR_TASK: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+// Returns the job id
+pub async fn schedule_mutation(
+    connection: &CouchbaseConnection,
+    at_ms: i64,
+    mutation: ScheduledMutation,
+    options: OperationOptions,
+) -> Result<String, String> {
+    let keyspace = SCHEDULED_JOBS.keyspace()?;
+    let job_id = format!("{}{}", JOB_KEY_PREFIX, uuid());
+    let job = json!({
+        "type": JOB_TYPE,
+        "status": "pending",
+        "dueAt": at_ms,
+        "createdAt": now_ms(),
+        "op": mutation,
+    });
+    connection.add_document(job_id.clone(), job, keyspace, options, None).await?;
+    Ok(job_id)
+}
+
+// Takes or renews the leader lease, false while another executor holds it
+async fn hold_lease(connection: &CouchbaseConnection, keyspace: &Keyspace) -> Result<bool, String> {
+    let lease = Duration::from_secs(SCHEDULED_JOBS.lease_secs.max(1));
+    let lease_options = OperationOptions {
+        expiry: Some(lease),
+        ..Default::default()
+    };
+    let holder = json!({ "holder": *EXECUTOR_ID, "acquiredAt": now_ms() });
+    match connection
+        .add_document(LEASE_KEY.to_string(), holder, keyspace.clone(), lease_options, Some(0))
+        .await
+    {
+        Ok(_) => {
+            log::info!("Took the scheduled job leader lease");
+            Ok(true)
+        }
+        Err(error) if is_write_conflict(&error) => {
+            let current = match connection
+                .get_document(LEASE_KEY.to_string(), keyspace.clone(), OperationOptions::default())
+                .await
+            {
+                Ok(current) => current,
+                // Expired in between, the next poll can take it
+                Err(error) if is_document_not_found(&error) => return Ok(false),
+                Err(error) => return Err(error),
+            };
+            if current.value["holder"].as_str() != Some(EXECUTOR_ID.as_str()) {
+                return Ok(false);
+            }
+            connection
+                .touch(LEASE_KEY.to_string(), lease, keyspace.clone(), OperationOptions::default())
+                .await?;
+            Ok(true)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn with_fields(mut job: Value, fields: Value) -> Value {
+    if let (Some(job), Value::Object(fields)) = (job.as_object_mut(), fields) {
+        job.extend(fields);
+    }
+    job
+}
+
+// Writes the job back if nobody changed it since `cas`, None when another executor got there first
+async fn update_job(
+    connection: &CouchbaseConnection,
+    job_id: &str,
+    job: Value,
+    cas: u64,
+    keyspace: &Keyspace,
+) -> Result<Option<u64>, String> {
+    match connection
+        .replace_document(job_id.to_string(), job, Some(cas), keyspace.clone(), OperationOptions::default(), Some(0))
+        .await
+    {
+        Ok(outcome) => Ok(Some(outcome.cas)),
+        Err(error) if is_write_conflict(&error) || is_document_not_found(&error) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+async fn run_job(connection: &CouchbaseConnection, job_id: String, keyspace: &Keyspace) -> Result<(), String> {
+    let stored = match connection
+        .get_document(job_id.clone(), keyspace.clone(), OperationOptions::default())
+        .await
+    {
+        Ok(stored) => stored,
+        Err(error) if is_document_not_found(&error) => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    // The index may lag behind a job that was already picked up
+    if stored.value["status"].as_str() != Some("pending") {
+        return Ok(());
+    }
+    let mutation: ScheduledMutation = serde_json::from_value(stored.value["op"].clone())
+        .map_err(|error| format!("Scheduled job {} has an invalid op : {}", job_id, error))?;
+    let job = with_fields(
+        stored.value,
+        json!({ "status": "running", "startedAt": now_ms(), "executor": *EXECUTOR_ID }),
+    );
+    let cas = match update_job(connection, &job_id, job.clone(), stored.cas, keyspace).await? {
+        Some(cas) => cas,
+        None => return Ok(()),
+    };
+    let result = match mutation.apply(connection, OperationOptions::default()).await {
+        Ok(_) => {
+            log::info!("Applied scheduled job {} to {} in {}", job_id, mutation.key, mutation.keyspace());
+            json!({ "status": "done", "completedAt": now_ms() })
+        }
+        Err(error) => {
+            log::error!("Scheduled job {} on {} failed : {}", job_id, mutation.key, error);
+            json!({ "status": "failed", "completedAt": now_ms(), "error": error })
+        }
+    };
+    update_job(connection, &job_id, with_fields(job, result), cas, keyspace).await?;
+    Ok(())
+}
+
+// Applies every job that is due, if this process holds the leader lease. Returns how many were picked up
+pub async fn run_due_jobs(connection: &CouchbaseConnection) -> Result<usize, String> {
+    let keyspace = SCHEDULED_JOBS.keyspace()?;
+    if !hold_lease(connection, &keyspace).await? {
+        return Ok(0);
+    }
+    let statement = format!(
+        "SELECT RAW META(j).id FROM {} AS j WHERE j.type = $1 AND j.status = \"pending\" AND j.dueAt <= $2 ORDER BY j.dueAt LIMIT $3",
+        keyspace.query_path()
+    );
+    let rows = connection
+        .query(
+            statement,
+            vec![json!(JOB_TYPE), json!(now_ms()), json!(JOBS_PER_POLL)],
+            Vec::new(),
+            QuerySettings::default(),
+            OperationOptions::default(),
+        )
+        .await?;
+    let job_ids: Vec<String> = rows.into_iter().filter_map(|row| row.as_str().map(str::to_string)).collect();
+    for job_id in &job_ids {
+        if let Err(error) = run_job(connection, job_id.clone(), &keyspace).await {
+            log::error!("Unable to run scheduled job {} : {}", job_id, error);
+        }
+    }
+    Ok(job_ids.len())
+}
+
+// Polls every `interval` (config `pollSecs` by default) until stopped, replacing a running executor
+pub fn start_job_executor(connection: Arc<CouchbaseConnection>, interval: Option<Duration>) {
+    let interval = interval.unwrap_or(Duration::from_secs(SCHEDULED_JOBS.poll_secs.max(1)));
+    let task = tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            if let Err(error) = run_due_jobs(&connection).await {
+                log::error!("Scheduled job poll failed : {}", error);
+            }
+        }
+    });
+    if let Some(previous) = EXECUTOR_TASK.lock().unwrap().replace(task) {
+        previous.abort();
+    }
+    log::info!("Scheduled job executor started, polling every {:?}", interval);
+}
+
+// False when no executor was running
+pub fn stop_job_executor() -> bool {
+    match EXECUTOR_TASK.lock().unwrap().take() {
+        Some(task) => {
+            task.abort();
+            log::info!("Scheduled job executor stopped");
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,168 @@
+// Named document schemas, loaded from `couchbase.schemas` or registered at runtime, e.g.
+//   "UserProfile": { "fields": { "name": { "type": "string" }, "age": { "type": "integer", "required": false, "default": 0 } }, "unknownFields": "strip" }
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::configuration as config;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+    Any,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldSchema {
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default = "FieldSchema::required_by_default")]
+    pub required: bool,
+    // Filled in when an optional field is missing
+    pub default: Option<Value>,
+    #[serde(default)]
+    pub nullable: bool,
+}
+
+impl FieldSchema {
+    fn required_by_default() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum UnknownFields {
+    #[default]
+    Allow,
+    Strip,
+    Reject,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSchema {
+    pub fields: HashMap<String, FieldSchema>,
+    #[serde(default)]
+    pub unknown_fields: UnknownFields,
+}
+
+lazy_static! {
+    static ref SCHEMAS: RwLock<HashMap<String, DocumentSchema>> = RwLock::new(load_schemas());
+}
+
+fn load_schemas() -> HashMap<String, DocumentSchema> {
+    match config::get_res::<HashMap<String, DocumentSchema>>("couchbase.schemas") {
+        Ok(schemas) => {
+            log::info!("Loaded {} document schemas from config", schemas.len());
+            schemas
+        }
+        Err(_) => HashMap::new(),
+    }
+}
+
+pub fn register_schema(name: String, definition: Value) -> Result<(), String> {
+    let schema: DocumentSchema =
+        serde_json::from_value(definition).map_err(|error| format!("Invalid schema {} : {}", name, error))?;
+    SCHEMAS.write().unwrap().insert(name, schema);
+    Ok(())
+}
+
+// Checks `document` against the named schema and returns it with defaults applied and unknown fields handled
+pub fn validate_document(schema_name: &str, document: Value) -> Result<Value, String> {
+    let schema = SCHEMAS
+        .read()
+        .unwrap()
+        .get(schema_name)
+        .cloned()
+        .ok_or_else(|| format!("SCHEMA_NOT_FOUND: no schema registered as {}", schema_name))?;
+    let mut object = match document {
+        Value::Object(object) => object,
+        other => {
+            return Err(format!(
+                "SCHEMA_VALIDATION: {} expects an object, got {}",
+                schema_name,
+                type_name(&other)
+            ))
+        }
+    };
+
+    let mut normalized = Map::new();
+    for (field_name, field) in &schema.fields {
+        match object.remove(field_name) {
+            Some(Value::Null) if field.nullable => {
+                normalized.insert(field_name.to_owned(), Value::Null);
+            }
+            Some(value) => {
+                if !matches_type(field.field_type, &value) {
+                    return Err(format!(
+                        "SCHEMA_VALIDATION: {}.{} expects {:?}, got {}",
+                        schema_name,
+                        field_name,
+                        field.field_type,
+                        type_name(&value)
+                    ));
+                }
+                normalized.insert(field_name.to_owned(), value);
+            }
+            None => {
+                if let Some(default) = &field.default {
+                    normalized.insert(field_name.to_owned(), default.clone());
+                } else if field.required {
+                    return Err(format!(
+                        "SCHEMA_VALIDATION: {}.{} is required",
+                        schema_name, field_name
+                    ));
+                }
+            }
+        }
+    }
+
+    // Whatever is left in `object` is not described by the schema
+    match schema.unknown_fields {
+        UnknownFields::Allow => normalized.extend(object),
+        UnknownFields::Strip => {}
+        UnknownFields::Reject => {
+            if let Some(field_name) = object.keys().next() {
+                return Err(format!(
+                    "SCHEMA_VALIDATION: {}.{} is not part of the schema",
+                    schema_name, field_name
+                ));
+            }
+        }
+    }
+    Ok(Value::Object(normalized))
+}
+
+fn matches_type(field_type: FieldType, value: &Value) -> bool {
+    match field_type {
+        FieldType::String => value.is_string(),
+        FieldType::Number => value.is_number(),
+        FieldType::Integer => value.is_i64() || value.is_u64(),
+        FieldType::Boolean => value.is_boolean(),
+        FieldType::Array => value.is_array(),
+        FieldType::Object => value.is_object(),
+        FieldType::Any => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
@@ -0,0 +1,108 @@
+// Sessions kept as one document each with a sliding TTL: every read pushes the expiry out again, so a session
+// only disappears after `ttl` without activity
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::utils::couchbase_db::{is_document_not_found, uuid, CouchbaseConnection, Keyspace, OperationOptions};
+
+pub const DEFAULT_SESSION_PREFIX: &str = "session::";
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+
+pub struct SessionStore {
+    connection: Arc<CouchbaseConnection>,
+    keyspace: Keyspace,
+    key_prefix: String,
+    ttl: Duration,
+    options: OperationOptions,
+}
+
+impl SessionStore {
+    pub fn new(
+        connection: Arc<CouchbaseConnection>,
+        keyspace: Keyspace,
+        key_prefix: Option<String>,
+        ttl: Option<Duration>,
+        options: OperationOptions,
+    ) -> Self {
+        SessionStore {
+            connection,
+            keyspace,
+            key_prefix: key_prefix.unwrap_or_else(|| DEFAULT_SESSION_PREFIX.to_string()),
+            ttl: ttl.unwrap_or(DEFAULT_SESSION_TTL),
+            options,
+        }
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{}", self.key_prefix, id)
+    }
+
+    // Returns the new session id. `ttl` overrides the store's idle timeout for this session only
+    pub async fn create(&self, data: Value, ttl: Option<Duration>) -> Result<String, String> {
+        let ttl = ttl.unwrap_or(self.ttl);
+        if ttl.is_zero() {
+            return Err("Session TTL must be greater than zero".to_string());
+        }
+        let id = uuid().to_string();
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as u64)
+            .unwrap_or(0);
+        let document = json!({
+            "data": data,
+            "ttlSecs": ttl.as_secs(),
+            "createdAt": created_at,
+        });
+        let options = OperationOptions {
+            expiry: Some(ttl),
+            ..self.options.clone()
+        };
+        self.connection
+            .add_document(self.key(&id), document, self.keyspace.clone(), options, None)
+            .await?;
+        Ok(id)
+    }
+
+    // None once the session expired or was destroyed
+    pub async fn get(&self, id: &str) -> Result<Option<Value>, String> {
+        let key = self.key(id);
+        let mut document = match self
+            .connection
+            .get_and_touch(key.clone(), self.ttl, self.keyspace.clone(), self.options.clone())
+            .await
+        {
+            Ok(stored) => stored.value,
+            Err(error) if is_document_not_found(&error) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        // Sessions created with their own TTL get it back, the touch above used the store default
+        let ttl = document["ttlSecs"].as_u64().map(Duration::from_secs).unwrap_or(self.ttl);
+        if ttl != self.ttl {
+            match self
+                .connection
+                .touch(key, ttl, self.keyspace.clone(), self.options.clone())
+                .await
+            {
+                Ok(_) => {}
+                Err(error) if is_document_not_found(&error) => return Ok(None),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(Some(document["data"].take()))
+    }
+
+    // False when there was no such session
+    pub async fn destroy(&self, id: &str) -> Result<bool, String> {
+        match self
+            .connection
+            .delete_data(self.key(id), None, self.keyspace.clone(), self.options.clone())
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(error) if is_document_not_found(&error) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// Redis style sorted set kept as one small document per member, `{ zset, member, score }`, ranked through the
+// query service. Ranges and ranks need an index such as
+//   CREATE INDEX ix_zset ON `bucket`.`scope`.`collection`(zset, score, member)
+use std::sync::Arc;
+
+use couchbase::StoreSemantics;
+use serde_json::{json, Value};
+
+use crate::utils::couchbase_db::{
+    is_document_not_found, CouchbaseConnection, Keyspace, OperationOptions, QuerySettings, SubdocMutation,
+    SubdocOperation,
+};
+
+pub struct SortedSetEntry {
+    pub member: String,
+    pub score: f64,
+}
+
+pub struct SortedSet {
+    connection: Arc<CouchbaseConnection>,
+    name: String,
+    keyspace: Keyspace,
+    options: OperationOptions,
+}
+
+impl SortedSet {
+    pub fn new(connection: Arc<CouchbaseConnection>, name: String, keyspace: Keyspace, options: OperationOptions) -> Self {
+        SortedSet {
+            connection,
+            name,
+            keyspace,
+            options,
+        }
+    }
+
+    fn member_key(&self, member: &str) -> String {
+        format!("{}::{}", self.name, member)
+    }
+
+    // Ranges must see members added just before, so they wait for the index to catch up
+    fn query_settings(&self) -> QuerySettings {
+        QuerySettings {
+            scan_consistency: Some("request_plus".to_string()),
+            ..Default::default()
+        }
+    }
+
+    // Adds the member or moves it to the new score
+    pub async fn add(&self, member: &str, score: f64) -> Result<(), String> {
+        if !score.is_finite() {
+            return Err(format!("Score of {} must be a finite number", member));
+        }
+        let field = |path: &str, value: Value| SubdocMutation {
+            operation: SubdocOperation::Upsert,
+            path: path.to_string(),
+            value: Some(value),
+            create_path: false,
+            xattr: false,
+        };
+        let mutations = vec![
+            field("zset", json!(self.name)),
+            field("member", json!(member)),
+            field("score", json!(score)),
+        ];
+        self.connection
+            .mutate_in(
+                self.member_key(member),
+                mutations,
+                self.keyspace.clone(),
+                None,
+                Some(StoreSemantics::Upsert),
+                self.options.clone(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn count(&self) -> Result<u64, String> {
+        let statement = format!(
+            "SELECT RAW COUNT(*) FROM {} AS z WHERE z.zset = $1",
+            self.keyspace.query_path()
+        );
+        let rows = self
+            .connection
+            .query(statement, vec![json!(self.name)], Vec::new(), self.query_settings(), self.options.clone())
+            .await?;
+        Ok(rows.first().and_then(Value::as_u64).unwrap_or(0))
+    }
+
+    // Members between ranks `start` and `stop` inclusive, negative ranks count from the end like ZRANGE.
+    // Ties are ordered by member name
+    pub async fn range(&self, start: i64, stop: i64, reverse: bool) -> Result<Vec<SortedSetEntry>, String> {
+        let (start, stop) = if start < 0 || stop < 0 {
+            let count = self.count().await? as i64;
+            let resolve = |rank: i64| if rank < 0 { count + rank } else { rank };
+            (resolve(start).max(0), resolve(stop))
+        } else {
+            (start, stop)
+        };
+        if stop < start {
+            return Ok(Vec::new());
+        }
+        let order = if reverse { "DESC" } else { "ASC" };
+        let statement = format!(
+            "SELECT z.member, z.score FROM {} AS z WHERE z.zset = $1 ORDER BY z.score {}, z.member {} LIMIT $2 OFFSET $3",
+            self.keyspace.query_path(),
+            order,
+            order
+        );
+        let parameters = vec![json!(self.name), json!(stop - start + 1), json!(start)];
+        let rows = self
+            .connection
+            .query(statement, parameters, Vec::new(), self.query_settings(), self.options.clone())
+            .await?;
+        rows.into_iter()
+            .map(|row| match (row["member"].as_str(), row["score"].as_f64()) {
+                (Some(member), Some(score)) => Ok(SortedSetEntry {
+                    member: member.to_string(),
+                    score,
+                }),
+                _ => Err(format!("Unexpected sorted set row {}", row)),
+            })
+            .collect()
+    }
+
+    // Zero based rank of the member, None when it is not in the set
+    pub async fn rank(&self, member: &str, reverse: bool) -> Result<Option<u64>, String> {
+        let score = match self
+            .connection
+            .get_document(self.member_key(member), self.keyspace.clone(), self.options.clone())
+            .await
+        {
+            Ok(stored) => match stored.value["score"].as_f64() {
+                Some(score) => score,
+                None => return Ok(None),
+            },
+            Err(error) if is_document_not_found(&error) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let ahead = if reverse { ">" } else { "<" };
+        let statement = format!(
+            "SELECT RAW COUNT(*) FROM {} AS z WHERE z.zset = $1 AND (z.score {} $2 OR (z.score = $2 AND z.member {} $3))",
+            self.keyspace.query_path(),
+            ahead,
+            ahead
+        );
+        let parameters = vec![json!(self.name), json!(score), json!(member)];
+        let rows = self
+            .connection
+            .query(statement, parameters, Vec::new(), self.query_settings(), self.options.clone())
+            .await?;
+        Ok(rows.first().and_then(Value::as_u64))
+    }
+}
@@ -0,0 +1,65 @@
+// Time series points are sharded into one document per series and hour/day, `<series>::<bucket start ms>`,
+// holding `{ series, start, points: [[timestamp, value], ...] }`. Appends are subdoc array appends, so
+// concurrent writers never conflict on CAS
+use serde_json::Value;
+
+// A query may not span more bucket documents than this
+pub const MAX_TIME_SERIES_BUCKETS: i64 = 10_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeSeriesGranularity {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl TimeSeriesGranularity {
+    fn bucket_ms(&self) -> i64 {
+        match self {
+            TimeSeriesGranularity::Hour => 60 * 60 * 1000,
+            TimeSeriesGranularity::Day => 24 * 60 * 60 * 1000,
+        }
+    }
+
+    // Start of the bucket holding `timestamp`, in epoch milliseconds
+    pub fn bucket_start(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.bucket_ms())
+    }
+
+    // Starts of every bucket overlapping [from, to]
+    pub fn bucket_starts(&self, from: i64, to: i64) -> Result<Vec<i64>, String> {
+        if to < from {
+            return Err(format!("Time series range ends ({}) before it starts ({})", to, from));
+        }
+        let first = self.bucket_start(from);
+        let count = (self.bucket_start(to) - first) / self.bucket_ms() + 1;
+        if count > MAX_TIME_SERIES_BUCKETS {
+            return Err(format!(
+                "Time series range covers {} buckets, more than the {} allowed",
+                count, MAX_TIME_SERIES_BUCKETS
+            ));
+        }
+        Ok((0..count).map(|index| first + index * self.bucket_ms()).collect())
+    }
+}
+
+pub fn bucket_key(series_key: &str, bucket_start: i64) -> String {
+    format!("{}::{}", series_key, bucket_start)
+}
+
+// Points of one bucket document inside [from, to], malformed entries are skipped
+pub fn points_in_range(document: &Value, from: i64, to: i64) -> Vec<(i64, Value)> {
+    document["points"]
+        .as_array()
+        .map(|points| {
+            points
+                .iter()
+                .filter_map(|point| match point.as_array().map(Vec::as_slice) {
+                    Some([timestamp, value]) => timestamp.as_i64().map(|timestamp| (timestamp, value.clone())),
+                    _ => None,
+                })
+                .filter(|(timestamp, _)| *timestamp >= from && *timestamp <= to)
+                .collect()
+        })
+        .unwrap_or_default()
+}
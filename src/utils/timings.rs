@@ -0,0 +1,56 @@
+// Latency breakdown of a single call, filled in by the core operations when the caller asks for timings.
+// Time that is neither network nor deserialization (keyspace lookup, validation, retry backoff) counts as queue
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct OperationTimings {
+    started: Instant,
+    network_us: AtomicU64,
+    deserialize_us: AtomicU64,
+    attempts: AtomicU32,
+}
+
+pub struct TimingsBreakdown {
+    pub queue_ms: f64,
+    pub network_ms: f64,
+    pub deserialize_ms: f64,
+    pub attempts: u32,
+}
+
+fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+impl OperationTimings {
+    pub fn start() -> Arc<Self> {
+        Arc::new(OperationTimings {
+            started: Instant::now(),
+            network_us: AtomicU64::new(0),
+            deserialize_us: AtomicU64::new(0),
+            attempts: AtomicU32::new(0),
+        })
+    }
+
+    pub fn record_attempt(&self, network: Duration) {
+        self.network_us.fetch_add(network.as_micros() as u64, Ordering::Relaxed);
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_deserialize(&self, elapsed: Duration) {
+        self.deserialize_us.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) -> TimingsBreakdown {
+        let total_us = self.started.elapsed().as_micros() as u64;
+        let network_us = self.network_us.load(Ordering::Relaxed);
+        let deserialize_us = self.deserialize_us.load(Ordering::Relaxed);
+        TimingsBreakdown {
+            queue_ms: millis(total_us.saturating_sub(network_us + deserialize_us)),
+            network_ms: millis(network_us),
+            deserialize_ms: millis(deserialize_us),
+            attempts: self.attempts.load(Ordering::Relaxed),
+        }
+    }
+}
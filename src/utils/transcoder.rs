@@ -0,0 +1,179 @@
+// Transcoders turn the value handed to a write into what is stored, and the stored content back on reads.
+// Selected per call by name, else per bucket under `couchbase.transcoders`, e.g.
+//   "transcoders": { "reports": "string", "attachments": "binary" }
+// and JSON otherwise. The SDK writes every document with the JSON flag, so non-JSON payloads are carried as
+// JSON strings: "string" stores text as is, "binary" stores bytes given as base64 and hands base64 back
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+use crate::configuration as config;
+
+pub const DEFAULT_TRANSCODER: &str = "json";
+
+pub trait Transcoder: Send + Sync {
+    fn encode(&self, value: &Value) -> Result<Value, String>;
+    fn decode(&self, stored: Value) -> Result<Value, String>;
+}
+
+struct JsonTranscoder;
+
+impl Transcoder for JsonTranscoder {
+    fn encode(&self, value: &Value) -> Result<Value, String> {
+        Ok(value.clone())
+    }
+
+    fn decode(&self, stored: Value) -> Result<Value, String> {
+        Ok(stored)
+    }
+}
+
+struct StringTranscoder;
+
+impl Transcoder for StringTranscoder {
+    fn encode(&self, value: &Value) -> Result<Value, String> {
+        match value {
+            Value::String(_) => Ok(value.clone()),
+            _ => Err("string transcoder only writes strings".to_string()),
+        }
+    }
+
+    fn decode(&self, stored: Value) -> Result<Value, String> {
+        match stored {
+            Value::String(_) => Ok(stored),
+            _ => Err("string transcoder read a document that is not a string".to_string()),
+        }
+    }
+}
+
+struct BinaryTranscoder;
+
+impl BinaryTranscoder {
+    fn check(value: &Value) -> Result<(), String> {
+        let encoded = value
+            .as_str()
+            .ok_or_else(|| "binary transcoder expects base64 encoded bytes".to_string())?;
+        BASE64
+            .decode(encoded)
+            .map(|_| ())
+            .map_err(|error| format!("binary transcoder expects base64 encoded bytes : {}", error))
+    }
+}
+
+impl Transcoder for BinaryTranscoder {
+    fn encode(&self, value: &Value) -> Result<Value, String> {
+        Self::check(value)?;
+        Ok(value.clone())
+    }
+
+    fn decode(&self, stored: Value) -> Result<Value, String> {
+        Self::check(&stored)?;
+        Ok(stored)
+    }
+}
+
+fn builtin_transcoders() -> HashMap<String, Arc<dyn Transcoder>> {
+    let mut transcoders: HashMap<String, Arc<dyn Transcoder>> = HashMap::new();
+    transcoders.insert(DEFAULT_TRANSCODER.to_string(), Arc::new(JsonTranscoder));
+    transcoders.insert("string".to_string(), Arc::new(StringTranscoder));
+    transcoders.insert("binary".to_string(), Arc::new(BinaryTranscoder));
+    transcoders
+}
+
+lazy_static! {
+    static ref TRANSCODERS: RwLock<HashMap<String, Arc<dyn Transcoder>>> = RwLock::new(builtin_transcoders());
+    static ref BUCKET_TRANSCODERS: RwLock<HashMap<String, String>> = RwLock::new(
+        config::get_res::<HashMap<String, String>>("couchbase.transcoders").unwrap_or_default()
+    );
+}
+
+// Adds or replaces a transcoder, built-in names included
+pub fn register_transcoder(name: &str, transcoder: Arc<dyn Transcoder>) {
+    TRANSCODERS.write().unwrap().insert(name.to_string(), transcoder);
+}
+
+// Transcoder used for `bucket` when the call does not name one
+pub fn set_bucket_transcoder(bucket: String, name: String) -> Result<(), String> {
+    if !TRANSCODERS.read().unwrap().contains_key(&name) {
+        return Err(format!("UNKNOWN_TRANSCODER: {}", name));
+    }
+    BUCKET_TRANSCODERS.write().unwrap().insert(bucket, name);
+    Ok(())
+}
+
+pub fn transcoder_for(name: Option<&str>, bucket: &str) -> Result<Arc<dyn Transcoder>, String> {
+    let bucket_default = BUCKET_TRANSCODERS.read().unwrap().get(bucket).cloned();
+    let name = name
+        .map(str::to_string)
+        .or(bucket_default)
+        .unwrap_or_else(|| DEFAULT_TRANSCODER.to_string());
+    TRANSCODERS
+        .read()
+        .unwrap()
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| format!("UNKNOWN_TRANSCODER: {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_is_stored_as_is() {
+        let transcoder = transcoder_for(None, "test-json").unwrap();
+        let value = json!({ "a": [1, 2] });
+        assert_eq!(transcoder.decode(transcoder.encode(&value).unwrap()).unwrap(), value);
+    }
+
+    #[test]
+    fn string_transcoder_only_takes_strings() {
+        let transcoder = transcoder_for(Some("string"), "test-string").unwrap();
+        assert_eq!(transcoder.encode(&json!("text")).unwrap(), json!("text"));
+        assert!(transcoder.encode(&json!({ "a": 1 })).is_err());
+        assert!(transcoder.decode(json!(1)).is_err());
+    }
+
+    #[test]
+    fn binary_transcoder_only_takes_base64() {
+        let transcoder = transcoder_for(Some("binary"), "test-binary").unwrap();
+        assert_eq!(transcoder.encode(&json!("aGVsbG8=")).unwrap(), json!("aGVsbG8="));
+        assert!(transcoder.encode(&json!("not base64!")).is_err());
+        assert!(transcoder.decode(json!([1])).is_err());
+    }
+
+    #[test]
+    fn bucket_default_applies_when_the_call_names_none() {
+        assert!(set_bucket_transcoder("test-reports".to_string(), "missing".to_string())
+            .unwrap_err()
+            .starts_with("UNKNOWN_TRANSCODER"));
+        set_bucket_transcoder("test-reports".to_string(), "string".to_string()).unwrap();
+        assert!(transcoder_for(None, "test-reports").unwrap().encode(&json!(1)).is_err());
+        assert!(transcoder_for(Some("json"), "test-reports").unwrap().encode(&json!(1)).is_ok());
+        assert!(transcoder_for(Some("missing"), "test-reports").is_err());
+    }
+
+    struct Upper;
+
+    impl Transcoder for Upper {
+        fn encode(&self, value: &Value) -> Result<Value, String> {
+            Ok(Value::String(value.as_str().unwrap_or_default().to_uppercase()))
+        }
+
+        fn decode(&self, stored: Value) -> Result<Value, String> {
+            Ok(stored)
+        }
+    }
+
+    #[test]
+    fn registered_transcoders_are_found_by_name() {
+        register_transcoder("test-upper", Arc::new(Upper));
+        let transcoder = transcoder_for(Some("test-upper"), "test-bucket").unwrap();
+        assert_eq!(transcoder.encode(&json!("abc")).unwrap(), json!("ABC"));
+    }
+}
@@ -0,0 +1,237 @@
+// Per-bucket validation rules run before every write, configured under `couchbase.validation` or registered at
+// runtime, e.g.
+//   "validation": { "users": [
+//     { "rule": "maxSize", "bytes": 65536 },
+//     { "rule": "schema", "name": "UserProfile" },
+//     { "rule": "forbiddenFields", "fields": ["password", "card.number"] },
+//     { "rule": "keyConvention", "template": "user::{id}" },
+//     { "rule": "tenantMatch", "field": "tenantId" } ] }
+// Every rule runs and the failures are reported together in one VALIDATION_FAILED error. Partial writes
+// (mutateIn) skip the schema rule since the rest of the document is not at hand
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::configuration as config;
+use crate::utils::couchbase_db::{Keyspace, OperationOptions, SubdocMutation};
+use crate::utils::key_rules::key_pattern;
+use crate::utils::payload::payload_size;
+use crate::utils::schema::validate_document;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "rule", rename_all = "camelCase")]
+enum RuleSettings {
+    MaxSize {
+        bytes: usize,
+    },
+    Schema {
+        name: String,
+    },
+    ForbiddenFields {
+        fields: Vec<String>,
+    },
+    KeyConvention {
+        pattern: Option<String>,
+        template: Option<String>,
+    },
+    // The document field must hold the tenantId of the operation's context
+    TenantMatch {
+        #[serde(default = "RuleSettings::default_tenant_field")]
+        field: String,
+    },
+}
+
+impl RuleSettings {
+    fn default_tenant_field() -> String {
+        "tenantId".to_string()
+    }
+}
+
+enum ValidationRule {
+    MaxSize(usize),
+    Schema(String),
+    ForbiddenFields(Vec<String>),
+    KeyConvention(Regex, String),
+    TenantMatch(String),
+}
+
+impl ValidationRule {
+    fn compile(settings: RuleSettings) -> Result<Self, String> {
+        Ok(match settings {
+            RuleSettings::MaxSize { bytes } => ValidationRule::MaxSize(bytes),
+            RuleSettings::Schema { name } => ValidationRule::Schema(name),
+            RuleSettings::ForbiddenFields { fields } => ValidationRule::ForbiddenFields(fields),
+            RuleSettings::KeyConvention { pattern, template } => {
+                let (regex, description) = key_pattern(pattern.as_deref(), template.as_deref())?;
+                ValidationRule::KeyConvention(regex, description)
+            }
+            RuleSettings::TenantMatch { field } => ValidationRule::TenantMatch(field),
+        })
+    }
+}
+
+fn compile_rules(bucket: &str, definition: Value) -> Result<Vec<ValidationRule>, String> {
+    let settings: Vec<RuleSettings> = serde_json::from_value(definition)
+        .map_err(|error| format!("Invalid validation rules for bucket {} : {}", bucket, error))?;
+    settings
+        .into_iter()
+        .map(ValidationRule::compile)
+        .collect::<Result<Vec<ValidationRule>, String>>()
+        .map_err(|error| format!("Invalid validation rules for bucket {} : {}", bucket, error))
+}
+
+fn load_rules() -> HashMap<String, Vec<ValidationRule>> {
+    let definitions = match config::get_res::<HashMap<String, Value>>("couchbase.validation") {
+        Ok(definitions) => definitions,
+        Err(_) => return HashMap::new(),
+    };
+    let mut rules = HashMap::new();
+    for (bucket, definition) in definitions {
+        match compile_rules(&bucket, definition) {
+            Ok(compiled) => {
+                rules.insert(bucket, compiled);
+            }
+            Err(error) => log::error!("{}, no validation for this bucket", error),
+        }
+    }
+    rules
+}
+
+lazy_static! {
+    static ref VALIDATION_RULES: RwLock<HashMap<String, Vec<ValidationRule>>> = RwLock::new(load_rules());
+}
+
+// Replaces the rules of `bucket`, an empty list turns validation off for it
+pub fn register_validation_rules(bucket: String, definition: Value) -> Result<(), String> {
+    let rules = compile_rules(&bucket, definition)?;
+    VALIDATION_RULES.write().unwrap().insert(bucket, rules);
+    Ok(())
+}
+
+fn lookup_path<'a>(document: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(document, |current, segment| current.get(segment))
+}
+
+fn tenant_id(options: &OperationOptions) -> Option<&str> {
+    options.context.as_ref().and_then(|context| context.tenant_id.as_deref())
+}
+
+fn check_tenant(field: &str, value: Option<&Value>, options: &OperationOptions) -> Option<String> {
+    match (tenant_id(options), value) {
+        (None, _) => Some(format!("tenantMatch: {} needs a tenantId in the operation context", field)),
+        (Some(tenant), Some(Value::String(stored))) if stored == tenant => None,
+        (Some(tenant), _) => Some(format!("tenantMatch: {} must be {}", field, tenant)),
+    }
+}
+
+fn check_key_convention(key: &str, regex: &Regex, description: &str) -> Option<String> {
+    if regex.is_match(key) {
+        None
+    } else {
+        Some(format!("keyConvention: key does not follow {}", description))
+    }
+}
+
+fn report(key: &str, keyspace: &Keyspace, failures: Vec<String>) -> Result<(), String> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "VALIDATION_FAILED: {} failed {} rules of bucket {}: {}",
+        key,
+        failures.len(),
+        keyspace.bucket,
+        failures.join("; ")
+    ))
+}
+
+// Runs the bucket's rules against a whole document about to be inserted or replaced
+pub fn validate_write(key: &str, document: &Value, keyspace: &Keyspace, options: &OperationOptions) -> Result<(), String> {
+    let rules = VALIDATION_RULES.read().unwrap();
+    let rules = match rules.get(&keyspace.bucket) {
+        Some(rules) => rules,
+        None => return Ok(()),
+    };
+    let mut failures = Vec::new();
+    for rule in rules {
+        let failure = match rule {
+            ValidationRule::MaxSize(bytes) => {
+                let size = payload_size(document);
+                (size > *bytes).then(|| format!("maxSize: {} bytes, the limit is {} bytes", size, bytes))
+            }
+            ValidationRule::Schema(name) => validate_document(name, document.clone())
+                .err()
+                .map(|error| format!("schema: {}", error)),
+            ValidationRule::ForbiddenFields(fields) => {
+                let present: Vec<&str> = fields
+                    .iter()
+                    .filter(|field| lookup_path(document, field).is_some())
+                    .map(String::as_str)
+                    .collect();
+                (!present.is_empty()).then(|| format!("forbiddenFields: {} must not be stored", present.join(", ")))
+            }
+            ValidationRule::KeyConvention(regex, description) => check_key_convention(key, regex, description),
+            ValidationRule::TenantMatch(field) => check_tenant(field, lookup_path(document, field), options),
+        };
+        failures.extend(failure);
+    }
+    report(key, keyspace, failures)
+}
+
+// Same rules for mutateIn specs, `creates` when the store semantics can create the document
+pub fn validate_subdoc_write(
+    key: &str,
+    mutations: &[SubdocMutation],
+    creates: bool,
+    keyspace: &Keyspace,
+    options: &OperationOptions,
+) -> Result<(), String> {
+    let rules = VALIDATION_RULES.read().unwrap();
+    let rules = match rules.get(&keyspace.bucket) {
+        Some(rules) => rules,
+        None => return Ok(()),
+    };
+    let mut failures = Vec::new();
+    for rule in rules {
+        match rule {
+            ValidationRule::MaxSize(bytes) => {
+                let size: usize = mutations.iter().filter_map(|mutation| mutation.value.as_ref()).map(payload_size).sum();
+                if size > *bytes {
+                    failures.push(format!("maxSize: specs carry {} bytes, the limit is {} bytes", size, bytes));
+                }
+            }
+            ValidationRule::Schema(_) => {}
+            ValidationRule::ForbiddenFields(fields) => {
+                for mutation in mutations.iter().filter(|mutation| !mutation.xattr) {
+                    let path = mutation.path.as_str();
+                    // The spec writes the forbidden field itself, something inside it, or an object containing it
+                    let forbidden = fields.iter().find(|field| {
+                        path == field.as_str()
+                            || path.starts_with(&format!("{}.", field))
+                            || field.strip_prefix(&format!("{}.", path)).is_some_and(|rest| {
+                                mutation.value.as_ref().and_then(|value| lookup_path(value, rest)).is_some()
+                            })
+                    });
+                    if let Some(field) = forbidden {
+                        failures.push(format!("forbiddenFields: {} must not be stored, written through {}", field, path));
+                    }
+                }
+            }
+            ValidationRule::KeyConvention(regex, description) => {
+                if creates {
+                    failures.extend(check_key_convention(key, regex, description));
+                }
+            }
+            ValidationRule::TenantMatch(field) => {
+                for mutation in mutations.iter().filter(|mutation| !mutation.xattr && mutation.path == *field) {
+                    failures.extend(check_tenant(field, mutation.value.as_ref(), options));
+                }
+            }
+        }
+    }
+    report(key, keyspace, failures)
+}
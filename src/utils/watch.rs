@@ -0,0 +1,98 @@
+// Change notifications for single documents such as config docs. Each watch polls the document's CAS, which
+// costs no body transfer, and only fetches the document when the CAS moved. The CAS at the time the watch
+// starts is the baseline, so the listener hears about changes rather than the current state
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use tokio::task::JoinHandle;
+
+use crate::utils::couchbase_db::{is_document_not_found, uuid, CasDocument, CouchbaseConnection, Keyspace, OperationOptions};
+
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_millis(1000);
+
+// None when the document was removed (or expired)
+pub type WatchListener = Box<dyn Fn(Option<CasDocument>) + Send + Sync>;
+
+lazy_static! {
+    // Watch id -> polling task
+    static ref WATCHES: Mutex<HashMap<String, JoinHandle<()>>> = Mutex::new(HashMap::new());
+}
+
+async fn poll_cas(connection: &CouchbaseConnection, key: &str, keyspace: &Keyspace) -> Result<Option<u64>, String> {
+    connection
+        .document_cas(key.to_string(), keyspace.clone(), OperationOptions::default())
+        .await
+}
+
+// Returns the watch id for stop_watch
+pub fn start_watch(
+    connection: Arc<CouchbaseConnection>,
+    key: String,
+    keyspace: Keyspace,
+    interval: Option<Duration>,
+    listener: WatchListener,
+) -> String {
+    let id = uuid().to_string();
+    let interval = interval.unwrap_or(DEFAULT_WATCH_INTERVAL);
+    log::info!("Watch {} started on {} in {}, polling every {:?}", id, key, keyspace, interval);
+    let task_id = id.clone();
+    let task = tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last_cas: Option<Option<u64>> = None;
+        loop {
+            ticks.tick().await;
+            let cas = match poll_cas(&connection, &key, &keyspace).await {
+                Ok(cas) => cas,
+                Err(error) => {
+                    log::warn!("Watch {} on {} in {} could not poll : {}", task_id, key, keyspace, error);
+                    continue;
+                }
+            };
+            let baseline = match last_cas {
+                Some(baseline) => baseline,
+                None => {
+                    last_cas = Some(cas);
+                    continue;
+                }
+            };
+            if cas == baseline {
+                continue;
+            }
+            let change = match cas {
+                Some(_) => match connection
+                    .get_document(key.clone(), keyspace.clone(), OperationOptions::default())
+                    .await
+                {
+                    Ok(document) => Some(document),
+                    // Removed between the poll and the read, reported on the next poll
+                    Err(error) if is_document_not_found(&error) => continue,
+                    Err(error) => {
+                        log::warn!("Watch {} on {} in {} could not read the change : {}", task_id, key, keyspace, error);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            // The CAS of the body handed out, a write landing after the poll is then picked up next time
+            last_cas = Some(change.as_ref().map(|document| document.cas));
+            listener(change);
+        }
+    });
+    WATCHES.lock().unwrap().insert(id.clone(), task);
+    id
+}
+
+// False when no watch has that id
+pub fn stop_watch(id: &str) -> bool {
+    match WATCHES.lock().unwrap().remove(id) {
+        Some(task) => {
+            task.abort();
+            log::info!("Stopped watch {}", id);
+            true
+        }
+        None => false,
+    }
+}
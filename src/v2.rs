@@ -0,0 +1,628 @@
+// Options-object / typed-result API, exported to JS as `v2.*`.
+// The flat functions that shipped before it (getDocuments, addDocument, replaceDocument, removeDocument,
+// getBatchDocuments, getBatchDocumentsV2) are deprecated wrappers around these.
+use std::collections::HashMap;
+
+use napi::bindgen_prelude::{BigInt, Buffer, Either};
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction};
+use napi::Env;
+use serde_json::Value;
+
+use crate::bucket::BucketHandle;
+use crate::client::CouchbaseClient;
+use crate::configuration::{effective_config, encrypt_value};
+use crate::options::{
+  AddDocumentOptions, AnalyzeExpiryOptions, AppendTimeSeriesOptions, BlobOptions,
+  CasSnapshotOptions, CloudEventOptions, CoalesceWriteOptions, CounterOptions,
+  DeleteByPrefixOptions, EraseSubjectOptions, ExportDocumentsOptions, GetAndTouchOptions,
+  GetBatchOptions, GetDocumentOptions, ListKeysOptions, LogQueryOptions, LookupInOptions,
+  MergeDocumentOptions, MutateInOptions, PreloadCacheOptions, ProcessOnceOptions, QueryOptions,
+  QueryTimeSeriesOptions, RemoveDocumentOptions, ReplaceDocumentOptions, ScheduleMutationOptions,
+  SearchNearOptions, SearchWithinPolygonOptions, TouchDocumentOptions, UpsertDocumentOptions,
+  WatchOptions,
+};
+use crate::utils::document_cache::clear_document_cache as clear_cached_documents;
+use crate::utils::key_templates::{
+  build_key as build_template_key, parse_key as parse_template_key,
+  register_key_template as register_template,
+};
+use crate::utils::log_query::{query_logs, LogQuery};
+use crate::utils::named_queries::register_named_query as register_query;
+use crate::utils::query_cache::clear_query_cache as clear_cached_queries;
+use crate::utils::schema::register_schema as register_document_schema;
+use crate::utils::transcoder::set_bucket_transcoder as set_default_transcoder;
+use crate::utils::validation::register_validation_rules as register_bucket_rules;
+use crate::{
+  BatchDocumentsV2, BatchProgress, BlobResult, CasSnapshotResult, CloudEventMessage, ClusterInfo,
+  DeleteByPrefixResult, DocumentChange, DocumentWithMeta, EventSignature, ExistsResult,
+  ExpiryReport, ExportResult, GetResult, GetResultWithCas, HotKey, ListKeysResult, LogEntry,
+  MaintenanceEvent, MultiGetItem, MutationResult, MutationToken, PreloadCacheResult,
+  ProcessOnceResult, QueryColumns, QueuedWrite, ReadRepairStats, ReplaySummary, RetryQueueSummary,
+  RetryStats, SearchHit, TimeSeriesPoint,
+};
+
+#[napi(namespace = "v2", js_name = "getDocument")]
+pub async fn get_document(options: GetDocumentOptions) -> Result<GetResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_document(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getDocumentWithCas")]
+pub async fn get_document_with_cas(
+  options: GetDocumentOptions,
+) -> Result<GetResultWithCas, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_document_with_cas(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getDocumentTyped")]
+pub async fn get_document_typed(
+  options: GetDocumentOptions,
+  schema: String,
+) -> Result<GetResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_document_typed(options, schema)
+    .await
+}
+
+// Adds or replaces a named schema on top of the ones loaded from config
+#[napi(namespace = "v2", js_name = "registerSchema")]
+pub fn register_schema(name: String, definition: Value) -> Result<(), napi::Error> {
+  register_document_schema(name, definition).map_err(napi::Error::from_reason)
+}
+
+// Replaces the write validation rules of a bucket, see `couchbase.validation`
+#[napi(namespace = "v2", js_name = "registerValidationRules")]
+pub fn register_validation_rules(bucket: String, rules: Value) -> Result<(), napi::Error> {
+  register_bucket_rules(bucket, rules).map_err(napi::Error::from_reason)
+}
+
+// Transcoder used for the bucket's reads and writes when the call names none, see `couchbase.transcoders`
+#[napi(namespace = "v2", js_name = "setBucketTranscoder")]
+pub fn set_bucket_transcoder(bucket: String, transcoder: String) -> Result<(), napi::Error> {
+  set_default_transcoder(bucket, transcoder).map_err(napi::Error::from_reason)
+}
+
+#[napi(namespace = "v2", js_name = "getAndTouch")]
+pub async fn get_and_touch(
+  options: GetAndTouchOptions,
+) -> Result<Either<Value, GetResultWithCas>, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_and_touch(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "incrementCounter")]
+pub async fn increment_counter(options: CounterOptions) -> Result<BigInt, napi::Error> {
+  CouchbaseClient::default_client()
+    .increment_counter(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "decrementCounter")]
+pub async fn decrement_counter(options: CounterOptions) -> Result<BigInt, napi::Error> {
+  CouchbaseClient::default_client()
+    .decrement_counter(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "touchDocument")]
+pub async fn touch_document(options: TouchDocumentOptions) -> Result<BigInt, napi::Error> {
+  CouchbaseClient::default_client()
+    .touch_document(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "documentExists")]
+pub async fn document_exists(options: GetDocumentOptions) -> Result<ExistsResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .document_exists(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getDocumentExpiry")]
+pub async fn get_document_expiry(options: GetDocumentOptions) -> Result<Option<i64>, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_document_expiry(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getDocumentWithMeta")]
+pub async fn get_document_with_meta(
+  options: GetDocumentOptions,
+) -> Result<DocumentWithMeta, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_document_with_meta(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "addDocument")]
+pub async fn add_document(options: AddDocumentOptions) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .add_document(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "upsertDocument")]
+pub async fn upsert_document(
+  options: UpsertDocumentOptions,
+) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .upsert_document(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "replaceDocument")]
+pub async fn replace_document(
+  options: ReplaceDocumentOptions,
+) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .replace_document(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "lookupIn")]
+pub async fn lookup_in(options: LookupInOptions) -> Result<HashMap<String, Value>, napi::Error> {
+  CouchbaseClient::default_client().lookup_in(options).await
+}
+
+#[napi(namespace = "v2", js_name = "mutateIn")]
+pub async fn mutate_in(options: MutateInOptions) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client().mutate_in(options).await
+}
+
+#[napi(namespace = "v2", js_name = "coalesceWrite")]
+pub async fn coalesce_write(options: CoalesceWriteOptions) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .coalesce_write(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "mergeDocument")]
+pub async fn merge_document(options: MergeDocumentOptions) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .merge_document(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "removeDocument")]
+pub async fn remove_document(
+  options: RemoveDocumentOptions,
+) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .remove_document(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getBatch")]
+pub async fn get_batch(options: GetBatchOptions) -> Result<BatchDocumentsV2, napi::Error> {
+  CouchbaseClient::default_client().get_batch(options).await
+}
+
+#[napi(namespace = "v2", js_name = "queryConsistentWith")]
+pub async fn query_consistent_with(
+  tokens: Vec<MutationToken>,
+  statement: String,
+  options: Option<QueryOptions>,
+) -> Result<Either<Vec<Value>, QueryColumns>, napi::Error> {
+  CouchbaseClient::default_client()
+    .query_consistent_with(tokens, statement, options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "buildHealthReport")]
+pub async fn build_health_report() -> Value {
+  CouchbaseClient::default_client()
+    .build_health_report()
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getClusterInfo")]
+pub async fn get_cluster_info() -> Result<ClusterInfo, napi::Error> {
+  CouchbaseClient::default_client().get_cluster_info().await
+}
+
+#[napi(namespace = "v2", js_name = "runRetention")]
+pub async fn run_retention(dry_run: Option<bool>) -> Value {
+  CouchbaseClient::default_client()
+    .run_retention(dry_run)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "startRetention")]
+pub async fn start_retention(interval_secs: Option<u32>) {
+  CouchbaseClient::default_client()
+    .start_retention(interval_secs)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "stopRetention")]
+pub fn stop_retention() -> bool {
+  CouchbaseClient::default_client().stop_retention()
+}
+
+#[napi(namespace = "v2", js_name = "scheduleMutation")]
+pub async fn schedule_mutation(
+  at_ms: i64,
+  op: ScheduleMutationOptions,
+) -> Result<String, napi::Error> {
+  CouchbaseClient::default_client()
+    .schedule_mutation(at_ms, op)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "startJobExecutor")]
+pub async fn start_job_executor(poll_secs: Option<u32>) {
+  CouchbaseClient::default_client()
+    .start_job_executor(poll_secs)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "stopJobExecutor")]
+pub fn stop_job_executor() -> bool {
+  CouchbaseClient::default_client().stop_job_executor()
+}
+
+#[napi(namespace = "v2", js_name = "listQueuedWrites")]
+pub fn list_queued_writes() -> Vec<QueuedWrite> {
+  CouchbaseClient::default_client().list_queued_writes()
+}
+
+#[napi(namespace = "v2", js_name = "flushRetryQueue")]
+pub async fn flush_retry_queue() -> Result<RetryQueueSummary, napi::Error> {
+  CouchbaseClient::default_client().flush_retry_queue().await
+}
+
+#[napi(namespace = "v2", js_name = "discardQueuedWrite")]
+pub fn discard_queued_write(id: String) -> Result<bool, napi::Error> {
+  CouchbaseClient::default_client().discard_queued_write(id)
+}
+
+#[napi(namespace = "v2", js_name = "startRetryWorker")]
+pub async fn start_retry_worker(poll_secs: Option<u32>) {
+  CouchbaseClient::default_client()
+    .start_retry_worker(poll_secs)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "stopRetryWorker")]
+pub fn stop_retry_worker() -> bool {
+  CouchbaseClient::default_client().stop_retry_worker()
+}
+
+#[napi(namespace = "v2", js_name = "setReadOnlyMode")]
+pub fn set_read_only_mode(enabled: bool) {
+  CouchbaseClient::default_client().set_read_only_mode(enabled)
+}
+
+#[napi(namespace = "v2", js_name = "isReadOnlyMode")]
+pub fn is_read_only_mode() -> bool {
+  CouchbaseClient::default_client().is_read_only_mode()
+}
+
+#[napi(namespace = "v2", js_name = "getReadRepairStats")]
+pub fn get_read_repair_stats() -> ReadRepairStats {
+  CouchbaseClient::default_client().get_read_repair_stats()
+}
+
+#[napi(namespace = "v2", js_name = "getRetryStats")]
+pub fn get_retry_stats() -> RetryStats {
+  CouchbaseClient::default_client().get_retry_stats()
+}
+
+#[napi(namespace = "v2", js_name = "getHotKeys")]
+pub fn get_hot_keys(top_n: Option<u32>) -> Vec<HotKey> {
+  CouchbaseClient::default_client().get_hot_keys(top_n)
+}
+
+#[napi(namespace = "v2", js_name = "startMaintenanceScheduler")]
+pub fn start_maintenance_scheduler() -> Result<u32, napi::Error> {
+  CouchbaseClient::default_client().start_maintenance_scheduler()
+}
+
+#[napi(namespace = "v2", js_name = "stopMaintenanceScheduler")]
+pub fn stop_maintenance_scheduler() -> bool {
+  CouchbaseClient::default_client().stop_maintenance_scheduler()
+}
+
+#[napi(
+  namespace = "v2",
+  js_name = "onMaintenanceEvent",
+  ts_args_type = "callback: (event: MaintenanceEvent) => void"
+)]
+pub fn on_maintenance_event(
+  env: Env,
+  callback: ThreadsafeFunction<MaintenanceEvent, ErrorStrategy::Fatal>,
+) -> Result<(), napi::Error> {
+  CouchbaseClient::default_client().on_maintenance_event(env, callback)
+}
+
+#[napi(
+  namespace = "v2",
+  js_name = "watchDocument",
+  ts_args_type = "key: string, bucket: string, callback: (change: DocumentChange) => void, options?: WatchOptions | undefined | null"
+)]
+pub fn watch_document(
+  key: String,
+  bucket: String,
+  callback: ThreadsafeFunction<DocumentChange, ErrorStrategy::Fatal>,
+  options: Option<WatchOptions>,
+) -> String {
+  CouchbaseClient::default_client().watch_document(key, bucket, callback, options)
+}
+
+#[napi(namespace = "v2", js_name = "unwatchDocument")]
+pub fn unwatch_document(watch_id: String) -> bool {
+  CouchbaseClient::default_client().unwatch_document(watch_id)
+}
+
+#[napi(
+  namespace = "v2",
+  js_name = "getMulti",
+  ts_args_type = "options: GetBatchOptions, transform: (item: MultiGetItem) => any"
+)]
+pub async fn get_multi(
+  options: GetBatchOptions,
+  transform: ThreadsafeFunction<MultiGetItem, ErrorStrategy::Fatal>,
+) -> Result<BatchDocumentsV2, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_multi(options, transform)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "openBucket")]
+pub async fn open_bucket(
+  bucket_name: String,
+  scope: Option<String>,
+  collection: Option<String>,
+) -> Result<BucketHandle, napi::Error> {
+  CouchbaseClient::default_client()
+    .open_bucket(bucket_name, scope, collection)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "listKeys")]
+pub async fn list_keys(options: ListKeysOptions) -> Result<ListKeysResult, napi::Error> {
+  CouchbaseClient::default_client().list_keys(options).await
+}
+
+#[napi(namespace = "v2", js_name = "deleteByPrefix")]
+pub async fn delete_by_prefix(
+  options: DeleteByPrefixOptions,
+) -> Result<DeleteByPrefixResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .delete_by_prefix(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "putBlob")]
+pub async fn put_blob(bytes: Buffer, options: BlobOptions) -> Result<BlobResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .put_blob(bytes, options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getBlob")]
+pub async fn get_blob(hash: String, options: BlobOptions) -> Result<Buffer, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_blob(hash, options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "releaseBlob")]
+pub async fn release_blob(hash: String, options: BlobOptions) -> Result<i64, napi::Error> {
+  CouchbaseClient::default_client()
+    .release_blob(hash, options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "processOnce")]
+pub async fn process_once(options: ProcessOnceOptions) -> Result<ProcessOnceResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .process_once(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "getCasSnapshot")]
+pub async fn get_cas_snapshot(
+  options: CasSnapshotOptions,
+) -> Result<CasSnapshotResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .get_cas_snapshot(options)
+    .await
+}
+
+#[napi(
+  namespace = "v2",
+  js_name = "exportDocuments",
+  ts_args_type = "options: ExportDocumentsOptions, onProgress?: (progress: BatchProgress) => void"
+)]
+pub async fn export_documents(
+  options: ExportDocumentsOptions,
+  on_progress: Option<ThreadsafeFunction<BatchProgress, ErrorStrategy::Fatal>>,
+) -> Result<ExportResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .export_documents(options, on_progress)
+    .await
+}
+
+// Merged configuration this process runs with, secrets masked. Set `logger.logEffectiveConfig` to log it at startup
+#[napi(namespace = "v2", js_name = "dumpEffectiveConfig")]
+pub fn dump_effective_config() -> Value {
+  effective_config()
+}
+
+// ENC(...) form of a secret for config files, encrypted with CONFIG_ENCRYPTION_KEY
+#[napi(namespace = "v2", js_name = "encryptConfigValue")]
+pub fn encrypt_config_value(plaintext: String) -> Result<String, napi::Error> {
+  encrypt_value(&plaintext).map_err(napi::Error::from_reason)
+}
+
+// CloudEvents envelope of a mutation, source and type from couchbase.cloudEvents
+#[napi(namespace = "v2", js_name = "toCloudEvent")]
+pub fn to_cloud_event(options: CloudEventOptions) -> Result<CloudEventMessage, napi::Error> {
+  CouchbaseClient::default_client().to_cloud_event(options)
+}
+
+// Signs a webhook event body with the active couchbase.eventSigning key
+#[napi(namespace = "v2", js_name = "signEventPayload")]
+pub fn sign_event_payload(body: String) -> Result<EventSignature, napi::Error> {
+  CouchbaseClient::default_client().sign_event_payload(body)
+}
+
+// For receivers sharing the signing config, `body` must be the raw body as received
+#[napi(namespace = "v2", js_name = "verifyEventSignature")]
+pub fn verify_event_signature(
+  body: String,
+  signature: EventSignature,
+) -> Result<bool, napi::Error> {
+  CouchbaseClient::default_client().verify_event_signature(body, signature)
+}
+
+// Adds or replaces a key template on top of the ones loaded from couchbase.keyTemplates
+#[napi(namespace = "v2", js_name = "registerKeyTemplate")]
+pub fn register_key_template(name: String, template: String) -> Result<(), napi::Error> {
+  register_template(name, template).map_err(napi::Error::from_reason)
+}
+
+// e.g. buildKey("user", { tenantId: "acme", userId: 42 }) -> "usr::acme::42"
+#[napi(namespace = "v2", js_name = "buildKey")]
+pub fn build_key(name: String, params: Value) -> Result<String, napi::Error> {
+  let params = params.as_object().ok_or_else(|| {
+    napi::Error::from_reason(format!(
+      "Parameters of key template {} must be an object",
+      name
+    ))
+  })?;
+  build_template_key(&name, params).map_err(napi::Error::from_reason)
+}
+
+#[napi(namespace = "v2", js_name = "parseKey")]
+pub fn parse_key(name: String, key: String) -> Result<HashMap<String, String>, napi::Error> {
+  parse_template_key(&name, &key).map_err(napi::Error::from_reason)
+}
+
+// Adds or replaces a named query on top of the ones loaded from config
+#[napi(namespace = "v2", js_name = "registerNamedQuery")]
+pub fn register_named_query(name: String, definition: Value) -> Result<(), napi::Error> {
+  register_query(name, definition).map_err(napi::Error::from_reason)
+}
+
+#[napi(namespace = "v2", js_name = "runNamedQuery")]
+pub async fn run_named_query(
+  name: String,
+  params: Option<Value>,
+  options: Option<QueryOptions>,
+) -> Result<Either<Vec<Value>, QueryColumns>, napi::Error> {
+  CouchbaseClient::default_client()
+    .run_named_query(name, params, options)
+    .await
+}
+
+// Drops every cached query result, returns how many there were
+#[napi(namespace = "v2", js_name = "clearQueryCache")]
+pub fn clear_query_cache() -> u32 {
+  clear_cached_queries() as u32
+}
+
+#[napi(namespace = "v2", js_name = "preloadCache")]
+pub async fn preload_cache(
+  options: PreloadCacheOptions,
+) -> Result<PreloadCacheResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .preload_cache(options)
+    .await
+}
+
+// Drops every cached document, returns how many there were
+#[napi(namespace = "v2", js_name = "clearDocumentCache")]
+pub fn clear_document_cache() -> u32 {
+  clear_cached_documents() as u32
+}
+
+// Newest matching entries of the local JSON log files, the active file first then the rolled ones
+#[napi(namespace = "v2", js_name = "queryLocalLogs")]
+pub fn query_local_logs(options: Option<LogQueryOptions>) -> Result<Vec<LogEntry>, napi::Error> {
+  let query = match options {
+    Some(options) => options.log_query().map_err(napi::Error::from_reason)?,
+    None => LogQuery::default(),
+  };
+  let entries = query_logs(&query).map_err(napi::Error::from_reason)?;
+  Ok(
+    entries
+      .into_iter()
+      .map(|entry| LogEntry {
+        time: entry.time,
+        level: entry.level,
+        message: entry.message,
+        module_path: entry.module_path,
+        file: entry.file,
+        line: entry.line,
+      })
+      .collect(),
+  )
+}
+
+#[napi(namespace = "v2", js_name = "appendTimeSeries")]
+pub async fn append_time_series(
+  options: AppendTimeSeriesOptions,
+) -> Result<MutationResult, napi::Error> {
+  CouchbaseClient::default_client()
+    .append_time_series(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "queryTimeSeries")]
+pub async fn query_time_series(
+  options: QueryTimeSeriesOptions,
+) -> Result<Vec<TimeSeriesPoint>, napi::Error> {
+  CouchbaseClient::default_client()
+    .query_time_series(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "searchNear")]
+pub async fn search_near(options: SearchNearOptions) -> Result<Vec<SearchHit>, napi::Error> {
+  CouchbaseClient::default_client().search_near(options).await
+}
+
+#[napi(namespace = "v2", js_name = "searchWithinPolygon")]
+pub async fn search_within_polygon(
+  options: SearchWithinPolygonOptions,
+) -> Result<Vec<SearchHit>, napi::Error> {
+  CouchbaseClient::default_client()
+    .search_within_polygon(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "eraseSubjectData")]
+pub async fn erase_subject_data(options: EraseSubjectOptions) -> Result<Value, napi::Error> {
+  CouchbaseClient::default_client()
+    .erase_subject_data(options)
+    .await
+}
+
+#[napi(namespace = "v2", js_name = "analyzeExpiry")]
+pub async fn analyze_expiry(options: AnalyzeExpiryOptions) -> Result<ExpiryReport, napi::Error> {
+  CouchbaseClient::default_client()
+    .analyze_expiry(options)
+    .await
+}
+
+#[napi(
+  namespace = "v2",
+  js_name = "replayJournal",
+  ts_args_type = "path: string, toBucket: string, sinceMs?: number | undefined | null, onProgress?: (progress: BatchProgress) => void"
+)]
+pub async fn replay_journal(
+  path: String,
+  to_bucket: String,
+  since_ms: Option<i64>,
+  on_progress: Option<ThreadsafeFunction<BatchProgress, ErrorStrategy::Fatal>>,
+) -> Result<ReplaySummary, napi::Error> {
+  CouchbaseClient::default_client()
+    .replay_journal(path, to_bucket, since_ms, on_progress)
+    .await
+}
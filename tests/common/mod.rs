@@ -0,0 +1,99 @@
+// Boots a throwaway Couchbase Server in docker and provisions a single bucket for the integration suite
+use std::time::Duration;
+
+use testcontainers::{clients::Cli, core::WaitFor, Container, GenericImage, RunnableImage};
+
+pub const USERNAME: &str = "Administrator";
+pub const PASSWORD: &str = "password";
+pub const BUCKET: &str = "integration";
+
+const IMAGE: &str = "couchbase/server";
+const TAG: &str = "7.2.4";
+// The SDK bootstraps from the cluster map, which advertises the container ports, so they are mapped 1:1
+const PORTS: [u16; 7] = [8091, 8092, 8093, 8094, 8095, 8096, 11210];
+
+pub struct CouchbaseServer<'d> {
+  _container: Container<'d, GenericImage>,
+}
+
+impl<'d> CouchbaseServer<'d> {
+  pub async fn start(docker: &'d Cli) -> CouchbaseServer<'d> {
+    let image = GenericImage::new(IMAGE, TAG)
+      .with_wait_for(WaitFor::message_on_stdout("Starting Couchbase Server"));
+    let image = PORTS
+      .iter()
+      .fold(RunnableImage::from(image), |image, port| {
+        image.with_mapped_port((*port, *port))
+      });
+    let container = docker.run(image);
+
+    provision().await;
+    CouchbaseServer {
+      _container: container,
+    }
+  }
+
+  pub fn connection_string(&self) -> String {
+    "couchbase://127.0.0.1".to_string()
+  }
+}
+
+async fn provision() {
+  let http = reqwest::Client::new();
+  let rest = "http://127.0.0.1:8091";
+
+  // The REST API comes up a little after the process logs that it started
+  for _ in 0..60 {
+    if http.get(format!("{}/pools", rest)).send().await.is_ok() {
+      break;
+    }
+    tokio::time::sleep(Duration::from_secs(1)).await;
+  }
+
+  let steps: Vec<(&str, Vec<(&str, &str)>)> = vec![
+    (
+      "/node/controller/setupServices",
+      vec![("services", "kv,n1ql,index")],
+    ),
+    (
+      "/pools/default",
+      vec![("memoryQuota", "512"), ("indexMemoryQuota", "256")],
+    ),
+    (
+      "/settings/web",
+      vec![
+        ("port", "8091"),
+        ("username", USERNAME),
+        ("password", PASSWORD),
+      ],
+    ),
+    (
+      "/settings/indexes",
+      vec![("storageMode", "memory_optimized")],
+    ),
+    (
+      "/pools/default/buckets",
+      vec![
+        ("name", BUCKET),
+        ("bucketType", "couchbase"),
+        ("ramQuota", "256"),
+        ("flushEnabled", "1"),
+      ],
+    ),
+  ];
+  for (path, form) in steps {
+    let response = http
+      .post(format!("{}{}", rest, path))
+      .basic_auth(USERNAME, Some(PASSWORD))
+      .form(&form)
+      .send()
+      .await
+      .unwrap_or_else(|error| panic!("provisioning {} failed : {}", path, error));
+    assert!(
+      response.status().is_success(),
+      "provisioning {} failed : {}",
+      path,
+      response.text().await.unwrap_or_default()
+    );
+  }
+}
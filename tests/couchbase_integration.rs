@@ -0,0 +1,924 @@
+// Run with `cargo test --features integration-tests` (requires a local docker daemon)
+#![cfg(feature = "integration-tests")]
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use inventyv_datalayer_cb::utils::couchbase_db::{
+  get_next_counter_key, init_couchbase_connection_ready, is_document_not_found, is_write_conflict,
+  CouchbaseConnParams, CouchbaseConnection, CounterChange, Keyspace, OperationOptions,
+};
+use inventyv_datalayer_cb::utils::document_cache::{preload_cache, PreloadSource};
+use inventyv_datalayer_cb::utils::sessions::SessionStore;
+use inventyv_datalayer_cb::utils::time_series::TimeSeriesGranularity;
+use serde_json::json;
+use testcontainers::clients::Cli;
+
+use common::{CouchbaseServer, BUCKET, PASSWORD, USERNAME};
+
+fn keyspace() -> Keyspace {
+  Keyspace::bucket(BUCKET.to_string())
+}
+
+fn unique_key(prefix: &str) -> String {
+  format!("{}::{}", prefix, uuid::Uuid::new_v4())
+}
+
+// Reads the expiry back through META() and checks it lies `ttl` from now, give or take a few seconds
+async fn assert_expires_in(connection: &CouchbaseConnection, key: &str, ttl: Duration) -> u64 {
+  let now_secs = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap()
+    .as_secs();
+  let expires_at = connection
+    .get_document_expiry(key.to_string(), keyspace(), OperationOptions::default())
+    .await
+    .unwrap()
+    .expect("document has no expiry");
+  let expected = now_secs + ttl.as_secs();
+  assert!(
+    expires_at + 5 >= expected && expires_at <= expected + 5,
+    "{} expires at {}, expected about {}",
+    key,
+    expires_at,
+    expected
+  );
+  expires_at
+}
+
+// A single test drives every scenario so the container (slow to boot) is shared and torn down once
+#[tokio::test]
+async fn couchbase_integration() {
+  // Merges config/config-integration.json, read once on first use
+  std::env::set_var("RUN_MODE", "integration");
+  let docker = Cli::default();
+  let server = CouchbaseServer::start(&docker).await;
+
+  let connection = Arc::new(CouchbaseConnection::connect(CouchbaseConnParams {
+    connection_url: server.connection_string(),
+    username: USERNAME.to_string(),
+    password: PASSWORD.to_string(),
+  }));
+  connection
+    .wait_until_ready(vec![BUCKET.to_string()], Duration::from_secs(120))
+    .await
+    .expect("cluster never became ready");
+
+  get_add_replace_remove(&connection).await;
+  add_retries_until_key_is_free(&connection).await;
+  add_gives_up_after_retry_limit(&connection).await;
+  replace_rejects_stale_cas(&connection).await;
+  remove_rejects_stale_cas(&connection).await;
+  upsert_creates_and_overwrites(&connection).await;
+  add_with_ttl_sets_expiry(&connection).await;
+  replace_preserves_expiry(&connection).await;
+  touch_sets_expiry(&connection).await;
+  get_and_touch_returns_value_and_sets_expiry(&connection).await;
+  sessions_slide_their_expiry(&connection).await;
+  chunked_rewrites_switch_generations(&connection).await;
+  meta_read_matches_written_version(&connection).await;
+  lookup_returns_present_paths(&connection).await;
+  preloaded_cache_drops_written_keys(&connection).await;
+  batch_reports_missing_keys(&connection).await;
+  counters_are_atomic(&connection).await;
+  time_series_appends_create_their_bucket(&connection).await;
+
+  // The counter helpers still go through the env configured process-wide connection
+  std::env::set_var("COUCHBASE_CONNECTION_URL", server.connection_string());
+  std::env::set_var("COUCHBASE_USERNAME", USERNAME);
+  std::env::set_var("COUCHBASE_PASSWORD", PASSWORD);
+  init_couchbase_connection_ready(vec![BUCKET.to_string()], Duration::from_secs(120))
+    .await
+    .expect("default connection never became ready");
+  counter_increments().await;
+  counters_share_next_counter_keys(&connection).await;
+
+  connection.close().await;
+  assert!(connection.is_closed().await);
+}
+
+async fn get_add_replace_remove(connection: &CouchbaseConnection) {
+  let key = unique_key("crud");
+  let added = connection
+    .add_document(
+      key.clone(),
+      json!({ "n": 1 }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  assert!(added.cas > 0);
+
+  let fetched = connection
+    .get_document(key.clone(), keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(fetched.value, json!({ "n": 1 }));
+  assert_eq!(fetched.cas, added.cas);
+
+  let replaced = connection
+    .replace_document(
+      key.clone(),
+      json!({ "n": 2 }),
+      Some(fetched.cas),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  assert_ne!(replaced.cas, fetched.cas);
+
+  connection
+    .delete_data(key.clone(), None, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert!(connection
+    .get_document(key, keyspace(), OperationOptions::default())
+    .await
+    .is_err());
+}
+
+async fn add_retries_until_key_is_free(connection: &Arc<CouchbaseConnection>) {
+  let key = unique_key("retry");
+  connection
+    .add_document(
+      key.clone(),
+      json!("first"),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+
+  // Free the key while the second insert is sleeping between attempts
+  let remover = Arc::clone(connection);
+  let removed_key = key.clone();
+  tokio::spawn(async move {
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    remover
+      .delete_data(removed_key, None, keyspace(), OperationOptions::default())
+      .await
+      .unwrap();
+  });
+
+  connection
+    .add_document(
+      key.clone(),
+      json!("second"),
+      keyspace(),
+      OperationOptions::default(),
+      Some(5),
+    )
+    .await
+    .expect("insert should succeed once the key is removed");
+  let fetched = connection
+    .get_document(key, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(fetched.value, json!("second"));
+}
+
+async fn add_gives_up_after_retry_limit(connection: &CouchbaseConnection) {
+  let key = unique_key("exhausted");
+  connection
+    .add_document(
+      key.clone(),
+      json!(1),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  assert!(connection
+    .add_document(
+      key,
+      json!(2),
+      keyspace(),
+      OperationOptions::default(),
+      Some(2)
+    )
+    .await
+    .is_err());
+}
+
+async fn replace_rejects_stale_cas(connection: &CouchbaseConnection) {
+  let key = unique_key("cas");
+  let first = connection
+    .add_document(
+      key.clone(),
+      json!(1),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  connection
+    .replace_document(
+      key.clone(),
+      json!(2),
+      None,
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+
+  let conflict = connection
+    .replace_document(
+      key.clone(),
+      json!(3),
+      Some(first.cas),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await;
+  assert!(conflict.is_err());
+  let fetched = connection
+    .get_document(key, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(fetched.value, json!(2));
+}
+
+async fn remove_rejects_stale_cas(connection: &CouchbaseConnection) {
+  let key = unique_key("remove-cas");
+  let first = connection
+    .add_document(
+      key.clone(),
+      json!(1),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let second = connection
+    .replace_document(
+      key.clone(),
+      json!(2),
+      None,
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+
+  let conflict = connection
+    .delete_data(
+      key.clone(),
+      Some(first.cas),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap_err();
+  assert!(is_write_conflict(&conflict), "{}", conflict);
+  connection
+    .delete_data(
+      key.clone(),
+      Some(second.cas),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert!(connection
+    .get_document(key, keyspace(), OperationOptions::default())
+    .await
+    .is_err());
+}
+
+async fn upsert_creates_and_overwrites(connection: &CouchbaseConnection) {
+  let key = unique_key("upsert");
+  let created = connection
+    .upsert_document(
+      key.clone(),
+      json!({ "n": 1 }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let overwritten = connection
+    .upsert_document(
+      key.clone(),
+      json!({ "n": 2 }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  assert_ne!(overwritten.cas, created.cas);
+  let fetched = connection
+    .get_document(key, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(fetched.value, json!({ "n": 2 }));
+}
+
+async fn add_with_ttl_sets_expiry(connection: &CouchbaseConnection) {
+  let expiring = unique_key("ttl");
+  let lasting = unique_key("no-ttl");
+  let options = OperationOptions {
+    expiry: Some(Duration::from_secs(3600)),
+    ..Default::default()
+  };
+  connection
+    .add_document(
+      expiring.clone(),
+      json!({ "token": "t" }),
+      keyspace(),
+      options,
+      Some(0),
+    )
+    .await
+    .unwrap();
+  connection
+    .add_document(
+      lasting.clone(),
+      json!({ "token": "t" }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let now_secs = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap()
+    .as_secs();
+  let expires_at = connection
+    .get_document_expiry(expiring, keyspace(), OperationOptions::default())
+    .await
+    .unwrap()
+    .expect("document inserted with a TTL has no expiry");
+  assert!(expires_at > now_secs && expires_at <= now_secs + 3600 + 5);
+  assert_eq!(
+    connection
+      .get_document_expiry(lasting, keyspace(), OperationOptions::default())
+      .await
+      .unwrap(),
+    None
+  );
+}
+
+async fn touch_sets_expiry(connection: &CouchbaseConnection) {
+  let key = unique_key("touch");
+  let written = connection
+    .add_document(
+      key.clone(),
+      json!({ "n": 1 }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let cas = connection
+    .touch(
+      key.clone(),
+      Duration::from_secs(900),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_ne!(cas, written.cas);
+  assert_expires_in(connection, &key, Duration::from_secs(900)).await;
+  // Past 30 days the server would read a relative TTL as an epoch second
+  connection
+    .touch(
+      key.clone(),
+      Duration::from_secs(40 * 24 * 3600),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_expires_in(connection, &key, Duration::from_secs(40 * 24 * 3600)).await;
+
+  connection
+    .touch(
+      key.clone(),
+      Duration::ZERO,
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(
+    connection
+      .get_document_expiry(key.clone(), keyspace(), OperationOptions::default())
+      .await
+      .unwrap(),
+    None
+  );
+  let read = connection
+    .get_document(key, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(read.value, json!({ "n": 1 }));
+}
+
+async fn get_and_touch_returns_value_and_sets_expiry(connection: &CouchbaseConnection) {
+  let key = unique_key("get-and-touch");
+  let options = OperationOptions {
+    expiry: Some(Duration::from_secs(60)),
+    ..Default::default()
+  };
+  let written = connection
+    .add_document(key.clone(), json!({ "n": 1 }), keyspace(), options, Some(0))
+    .await
+    .unwrap();
+  let read = connection
+    .get_and_touch(
+      key.clone(),
+      Duration::from_secs(1800),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(read.value, json!({ "n": 1 }));
+  assert_ne!(read.cas, written.cas);
+  assert_expires_in(connection, &key, Duration::from_secs(1800)).await;
+
+  assert!(connection
+    .get_and_touch(
+      unique_key("get-and-touch"),
+      Duration::from_secs(1800),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .is_err_and(|error| is_document_not_found(&error)));
+}
+
+async fn sessions_slide_their_expiry(connection: &Arc<CouchbaseConnection>) {
+  let prefix = unique_key("session") + "::";
+  let store = SessionStore::new(
+    Arc::clone(connection),
+    keyspace(),
+    Some(prefix.clone()),
+    Some(Duration::from_secs(600)),
+    OperationOptions::default(),
+  );
+  let id = store.create(json!({ "user": "u1" }), None).await.unwrap();
+  let key = format!("{}{}", prefix, id);
+  let created = assert_expires_in(connection, &key, Duration::from_secs(600)).await;
+  tokio::time::sleep(Duration::from_secs(2)).await;
+  assert_eq!(store.get(&id).await.unwrap(), Some(json!({ "user": "u1" })));
+  let refreshed = assert_expires_in(connection, &key, Duration::from_secs(600)).await;
+  assert!(refreshed > created);
+
+  // A session created with its own TTL is pushed out by that TTL, not the store's
+  let short = store
+    .create(json!({ "user": "u2" }), Some(Duration::from_secs(120)))
+    .await
+    .unwrap();
+  assert!(store.get(&short).await.unwrap().is_some());
+  assert_expires_in(
+    connection,
+    &format!("{}{}", prefix, short),
+    Duration::from_secs(120),
+  )
+  .await;
+}
+
+// Generation the manifest under `key` points at
+async fn chunk_generation(connection: &CouchbaseConnection, key: &str) -> String {
+  let found = connection
+    .lookup_paths(
+      key.to_string(),
+      vec!["_chunks.generation".to_string()],
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  found["_chunks.generation"].as_str().unwrap().to_string()
+}
+
+async fn chunk_exists(connection: &CouchbaseConnection, key: &str, generation: &str) -> bool {
+  connection
+    .document_cas(
+      format!("{}::chunk::{}::0", key, generation),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap()
+    .is_some()
+}
+
+// config/config-integration.json chunks documents of BUCKET above 4 KiB
+async fn chunked_rewrites_switch_generations(connection: &CouchbaseConnection) {
+  let key = unique_key("chunked");
+  let version = |n: u64| json!({ "n": n, "text": "x".repeat(10_000) });
+  let first = connection
+    .add_document(
+      key.clone(),
+      version(1),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let first_generation = chunk_generation(connection, &key).await;
+  assert!(chunk_exists(connection, &key, &first_generation).await);
+
+  let second = connection
+    .replace_document(
+      key.clone(),
+      version(2),
+      Some(first.cas),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let second_generation = chunk_generation(connection, &key).await;
+  assert_ne!(first_generation, second_generation);
+  // The old generation goes once the manifest points at the new one
+  assert!(!chunk_exists(connection, &key, &first_generation).await);
+
+  // A rewrite losing the CAS race leaves the stored version and its chunks as they were
+  let stale = connection
+    .replace_document(
+      key.clone(),
+      version(3),
+      Some(first.cas),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await;
+  assert!(stale.is_err_and(|error| is_write_conflict(&error)));
+  assert_eq!(chunk_generation(connection, &key).await, second_generation);
+  let read = connection
+    .get_document(key.clone(), keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(read.value, version(2));
+  assert_eq!(read.cas, second.cas);
+
+  connection
+    .delete_data(key.clone(), None, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert!(!chunk_exists(connection, &key, &second_generation).await);
+}
+
+async fn meta_read_matches_written_version(connection: &CouchbaseConnection) {
+  let key = unique_key("meta");
+  let options = OperationOptions {
+    expiry: Some(Duration::from_secs(600)),
+    ..Default::default()
+  };
+  let written = connection
+    .add_document(key.clone(), json!({ "n": 1 }), keyspace(), options, Some(0))
+    .await
+    .unwrap();
+  let read = connection
+    .get_document_with_meta(key, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(read.value, json!({ "n": 1 }));
+  assert_eq!(read.cas, written.cas);
+  assert!(read.expiry.is_some());
+}
+
+async fn lookup_returns_present_paths(connection: &CouchbaseConnection) {
+  let key = unique_key("lookup");
+  let document = json!({ "name": "Ada", "address": { "city": "London" }, "tags": ["a", "b"] });
+  connection
+    .add_document(
+      key.clone(),
+      document,
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let paths = vec![
+    "address.city".to_string(),
+    "tags[1]".to_string(),
+    "missing".to_string(),
+  ];
+  let values = connection
+    .lookup_paths(key, paths, keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(values.len(), 2);
+  assert_eq!(values["address.city"], json!("London"));
+  assert_eq!(values["tags[1]"], json!("b"));
+}
+
+async fn preloaded_cache_drops_written_keys(connection: &CouchbaseConnection) {
+  let key = unique_key("cached");
+  connection
+    .add_document(
+      key.clone(),
+      json!({ "v": 1 }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let summary = preload_cache(
+    connection,
+    PreloadSource::Keys(vec![key.clone(), unique_key("cached-missing")]),
+    keyspace(),
+    OperationOptions::default(),
+  )
+  .await
+  .unwrap();
+  assert_eq!((summary.loaded, summary.missing), (1, 1));
+  let cached = OperationOptions {
+    cached: true,
+    ..Default::default()
+  };
+  connection
+    .upsert_document(
+      key.clone(),
+      json!({ "v": 2 }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let read = connection
+    .get_document(key, keyspace(), cached)
+    .await
+    .unwrap();
+  assert_eq!(read.value, json!({ "v": 2 }));
+}
+
+async fn replace_preserves_expiry(connection: &CouchbaseConnection) {
+  let key = unique_key("preserve-ttl");
+  let with_ttl = OperationOptions {
+    expiry: Some(Duration::from_secs(3600)),
+    ..Default::default()
+  };
+  connection
+    .add_document(
+      key.clone(),
+      json!({ "v": 1 }),
+      keyspace(),
+      with_ttl,
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let expires_at = connection
+    .get_document_expiry(key.clone(), keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  let preserving = OperationOptions {
+    preserve_expiry: true,
+    ..Default::default()
+  };
+  connection
+    .replace_document(
+      key.clone(),
+      json!({ "v": 2 }),
+      None,
+      keyspace(),
+      preserving,
+      Some(0),
+    )
+    .await
+    .unwrap();
+  let preserved = connection
+    .get_document_expiry(key.clone(), keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert!(preserved.is_some());
+  assert!(preserved.unwrap().abs_diff(expires_at.unwrap()) <= 2);
+  // A plain replace clears the expiry
+  connection
+    .replace_document(
+      key.clone(),
+      json!({ "v": 3 }),
+      None,
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+  assert_eq!(
+    connection
+      .get_document_expiry(key, keyspace(), OperationOptions::default())
+      .await
+      .unwrap(),
+    None
+  );
+}
+
+async fn batch_reports_missing_keys(connection: &CouchbaseConnection) {
+  let present = unique_key("batch");
+  let missing = unique_key("batch-missing");
+  connection
+    .add_document(
+      present.clone(),
+      json!({ "ok": true }),
+      keyspace(),
+      OperationOptions::default(),
+      Some(0),
+    )
+    .await
+    .unwrap();
+
+  let batch = connection
+    .get_documents_v2(
+      vec![present.clone(), missing.clone()],
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(batch.docs[&present].value, json!({ "ok": true }));
+  assert!(batch.errors.contains_key(&missing));
+
+  // The strict variant fails the whole batch on any missing key
+  assert!(connection
+    .get_documents(
+      vec![present, missing],
+      keyspace(),
+      OperationOptions::default()
+    )
+    .await
+    .is_err());
+}
+
+async fn counters_are_atomic(connection: &CouchbaseConnection) {
+  let key = unique_key("counter");
+  assert!(connection
+    .counter(
+      key.clone(),
+      CounterChange::Increment(1),
+      None,
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .is_err());
+  let increments = (0..20).map(|_| {
+    connection.counter(
+      key.clone(),
+      CounterChange::Increment(1),
+      Some(1),
+      keyspace(),
+      OperationOptions::default(),
+    )
+  });
+  let mut values: Vec<u64> = futures::future::join_all(increments)
+    .await
+    .into_iter()
+    .map(|outcome| outcome.unwrap().value)
+    .collect();
+  values.sort();
+  // The first call creates the counter at 1, every other one sees a distinct value
+  assert_eq!(values, (1..=20).collect::<Vec<u64>>());
+  let decremented = connection
+    .counter(
+      key,
+      CounterChange::Decrement(50),
+      None,
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(decremented.value, 0);
+}
+
+async fn time_series_appends_create_their_bucket(connection: &CouchbaseConnection) {
+  let series = unique_key("series");
+  let hour = 60 * 60 * 1000;
+  for (timestamp, value) in [(hour, 1), (hour + 1, 2)] {
+    connection
+      .append_time_series(
+        series.clone(),
+        timestamp,
+        json!(value),
+        TimeSeriesGranularity::Hour,
+        keyspace(),
+        OperationOptions::default(),
+      )
+      .await
+      .expect("append failed");
+  }
+
+  let points = connection
+    .query_time_series(
+      series,
+      0,
+      2 * hour,
+      TimeSeriesGranularity::Hour,
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .expect("query failed");
+  assert_eq!(points, vec![(hour, json!(1)), (hour + 1, json!(2))]);
+}
+
+async fn counter_increments() {
+  let key = unique_key("counter");
+  assert_eq!(
+    get_next_counter_key(BUCKET.to_string(), key.clone(), None)
+      .await
+      .unwrap(),
+    "1"
+  );
+  assert_eq!(
+    get_next_counter_key(BUCKET.to_string(), key.clone(), None)
+      .await
+      .unwrap(),
+    "2"
+  );
+  assert_eq!(
+    get_next_counter_key(BUCKET.to_string(), key.clone(), Some(10))
+      .await
+      .unwrap(),
+    "10"
+  );
+  assert_eq!(
+    get_next_counter_key(BUCKET.to_string(), key, None)
+      .await
+      .unwrap(),
+    "11"
+  );
+}
+
+// Both counter APIs read and write the bare number getNextCounterKey stores
+async fn counters_share_next_counter_keys(connection: &CouchbaseConnection) {
+  let key = unique_key("shared-counter");
+  assert_eq!(
+    get_next_counter_key(BUCKET.to_string(), key.clone(), Some(5))
+      .await
+      .unwrap(),
+    "5"
+  );
+  let incremented = connection
+    .counter(
+      key.clone(),
+      CounterChange::Increment(3),
+      None,
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(incremented.value, 8);
+  let stored = connection
+    .get_document(key.clone(), keyspace(), OperationOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(stored.value, json!(8));
+  assert_eq!(
+    get_next_counter_key(BUCKET.to_string(), key.clone(), None)
+      .await
+      .unwrap(),
+    "9"
+  );
+
+  // Counters created by incrementCounter continue under getNextCounterKey too
+  let created = unique_key("shared-counter");
+  connection
+    .counter(
+      created.clone(),
+      CounterChange::Increment(1),
+      Some(1),
+      keyspace(),
+      OperationOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(
+    get_next_counter_key(BUCKET.to_string(), created, None)
+      .await
+      .unwrap(),
+    "2"
+  );
+}